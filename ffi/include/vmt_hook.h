@@ -0,0 +1,50 @@
+#ifndef VMT_HOOK_H
+#define VMT_HOOK_H
+
+#include <stddef.h>
+
+#ifdef __cplusplus
+extern "C" {
+#endif
+
+typedef struct VmtHook VmtHook;
+
+typedef enum VmtHookStatus {
+    VMT_HOOK_OK = 0,
+    VMT_HOOK_NULL_POINTER = 1,
+    VMT_HOOK_OUT_OF_BOUNDS = 2,
+    VMT_HOOK_INVALID_OBJECT = 3,
+    VMT_HOOK_FAILED = 4,
+} VmtHookStatus;
+
+/* Hooks the VTable of `object`, detecting the method count when `count` is 0.
+ * The object and its VTable are probed first, up to the null terminator or `count` methods, failing
+ * with VMT_HOOK_INVALID_OBJECT if either isn't readable. */
+VmtHookStatus vmt_hook_create(void *object, size_t count, VmtHook **hook);
+
+/* Restores the original VTable and frees the hook. NULL is ignored. */
+void vmt_hook_destroy(VmtHook *hook);
+
+/* Replaces the method at `index` with `func`, writing the original method to `original` if not NULL. */
+VmtHookStatus vmt_hook_replace(VmtHook *hook, size_t index, const void *func, const void **original);
+
+/* Restores the original method at `index`. */
+VmtHookStatus vmt_hook_restore(VmtHook *hook, size_t index);
+
+/* Restores every method to its original address. */
+VmtHookStatus vmt_hook_restore_all(VmtHook *hook);
+
+/* Writes the original method at `index` to `original`. */
+VmtHookStatus vmt_hook_get_original(const VmtHook *hook, size_t index, const void **original);
+
+/* Returns the number of methods in the hooked VTable, or 0 for NULL. */
+size_t vmt_hook_count(const VmtHook *hook);
+
+/* Returns a static, null-terminated description of a status code. */
+const char *vmt_hook_status_string(VmtHookStatus status);
+
+#ifdef __cplusplus
+}
+#endif
+
+#endif
@@ -0,0 +1,149 @@
+//! Stable C API over [`vmt_hook::VTableHook`].
+//!
+//! Build this crate to get a `vmt_hook_ffi` shared or static library; the declarations are in `include/vmt_hook.h`.
+
+#![allow(clippy::missing_safety_doc)]
+
+use std::ffi::{c_char, c_void};
+
+use vmt_hook::{Error, HookBackend, VTableHook};
+
+/// Opaque hook handle.
+pub struct VmtHook(VTableHook<usize>);
+
+/// Status codes returned by every function.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VmtHookStatus {
+    Ok = 0,
+    /// A required pointer argument was null.
+    NullPointer = 1,
+    /// The method index is outside the VTable.
+    OutOfBounds = 2,
+    /// The object or its VTable isn't readable, or its method count couldn't be detected.
+    InvalidObject = 3,
+    /// The hooked VTable couldn't be allocated or installed.
+    Failed = 4,
+}
+
+/// Hooks the VTable of `object`, detecting the method count when `count` is 0.
+///
+/// Detection reads the object and its VTable with [`VTableHook::try_new`], so it only succeeds for a
+/// readable, null-terminated VTable. A given count is checked with [`VTableHook::try_with_count`].
+#[no_mangle]
+pub unsafe extern "C" fn vmt_hook_create(object: *mut c_void, count: usize, hook: *mut *mut VmtHook) -> VmtHookStatus {
+    if object.is_null() || hook.is_null() {
+        return VmtHookStatus::NullPointer;
+    }
+    let inner = if count == 0 {
+        VTableHook::try_new(object as usize)
+    } else {
+        VTableHook::try_with_count(object as usize, count)
+    };
+    match inner {
+        Ok(inner) => {
+            *hook = Box::into_raw(Box::new(VmtHook(inner)));
+            VmtHookStatus::Ok
+        }
+        Err(Error::Invalid(_)) => VmtHookStatus::InvalidObject,
+        Err(_) => VmtHookStatus::Failed,
+    }
+}
+
+/// Restores the original VTable and frees the hook. Null is ignored.
+#[no_mangle]
+pub unsafe extern "C" fn vmt_hook_destroy(hook: *mut VmtHook) {
+    if !hook.is_null() {
+        drop(Box::from_raw(hook));
+    }
+}
+
+unsafe fn checked<'a>(hook: *const VmtHook, index: usize) -> Result<&'a VmtHook, VmtHookStatus> {
+    let hook = hook.as_ref().ok_or(VmtHookStatus::NullPointer)?;
+    if index >= hook.0.backend().count() {
+        return Err(VmtHookStatus::OutOfBounds);
+    }
+    Ok(hook)
+}
+
+/// Replaces the method at `index` with `func`, writing the original method to `original` if not null.
+#[no_mangle]
+pub unsafe extern "C" fn vmt_hook_replace(
+    hook: *mut VmtHook,
+    index: usize,
+    func: *const c_void,
+    original: *mut *const c_void,
+) -> VmtHookStatus {
+    match checked(hook, index) {
+        Ok(hook) => {
+            hook.0.replace_method(index, func as usize);
+            if !original.is_null() {
+                *original = hook.0.get_original_method(index) as *const c_void;
+            }
+            VmtHookStatus::Ok
+        }
+        Err(status) => status,
+    }
+}
+
+/// Restores the original method at `index`.
+#[no_mangle]
+pub unsafe extern "C" fn vmt_hook_restore(hook: *mut VmtHook, index: usize) -> VmtHookStatus {
+    match checked(hook, index) {
+        Ok(hook) => {
+            hook.0.restore_method(index);
+            VmtHookStatus::Ok
+        }
+        Err(status) => status,
+    }
+}
+
+/// Restores every method to its original address.
+#[no_mangle]
+pub unsafe extern "C" fn vmt_hook_restore_all(hook: *mut VmtHook) -> VmtHookStatus {
+    match hook.as_ref() {
+        Some(hook) => {
+            hook.0.restore_all_methods();
+            VmtHookStatus::Ok
+        }
+        None => VmtHookStatus::NullPointer,
+    }
+}
+
+/// Writes the original method at `index` to `original`.
+#[no_mangle]
+pub unsafe extern "C" fn vmt_hook_get_original(
+    hook: *const VmtHook,
+    index: usize,
+    original: *mut *const c_void,
+) -> VmtHookStatus {
+    if original.is_null() {
+        return VmtHookStatus::NullPointer;
+    }
+    match checked(hook, index) {
+        Ok(hook) => {
+            *original = hook.0.get_original_method(index) as *const c_void;
+            VmtHookStatus::Ok
+        }
+        Err(status) => status,
+    }
+}
+
+/// Returns the number of methods in the hooked VTable, or 0 for null.
+#[no_mangle]
+pub unsafe extern "C" fn vmt_hook_count(hook: *const VmtHook) -> usize {
+    hook.as_ref().map_or(0, |hook| hook.0.backend().count())
+}
+
+/// Returns a static, null-terminated description of a status code.
+#[no_mangle]
+pub extern "C" fn vmt_hook_status_string(status: VmtHookStatus) -> *const c_char {
+    let message: &'static [u8] = match status {
+        VmtHookStatus::Ok => b"ok\0",
+        VmtHookStatus::NullPointer => b"null pointer argument\0",
+        VmtHookStatus::OutOfBounds => b"method index out of bounds\0",
+        VmtHookStatus::InvalidObject => b"object or vtable isn't readable\0",
+        VmtHookStatus::Failed => b"failed to install the hook\0",
+    };
+    message.as_ptr().cast()
+}
@@ -0,0 +1,86 @@
+//! Registry of the live shadow tables and generated code, for diagnosing hooks that were never dropped.
+//!
+//! Every allocation made by the crate for a [`VTableHook`](crate::VTableHook), a thunk or a stub is
+//! registered until it is freed, so a tool that loads and unloads plugins can call [`report`] and see
+//! what keeps piling up.
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// What an allocation holds.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AllocationKind {
+    /// A shadow VTable objects are pointed at.
+    ShadowTable,
+    /// Generated code wrapping a method, such as a call counter or a logger.
+    Thunk,
+    /// A generated stub returning a constant, which is never freed.
+    Stub,
+}
+
+/// A live allocation, returned by [`report`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LiveAllocation {
+    /// What the allocation holds.
+    pub kind: AllocationKind,
+    /// Address of the copied methods or of the first instruction.
+    pub address: usize,
+    /// Size of the allocation in bytes.
+    pub size: usize,
+    /// Address of the VTable pointer of the object using the allocation, if it has a single one.
+    pub owner: Option<usize>,
+    /// The VTable a shadow table was copied from.
+    pub vtable: Option<usize>,
+    /// Time since the allocation was made.
+    pub age: Duration,
+}
+
+struct Entry {
+    kind: AllocationKind,
+    size: usize,
+    owner: Option<usize>,
+    vtable: Option<usize>,
+    created: Instant,
+}
+
+/// Live allocations by address.
+static LIVE: Mutex<BTreeMap<usize, Entry>> = Mutex::new(BTreeMap::new());
+
+/// Returns the live allocations, the oldest first.
+pub fn report() -> Vec<LiveAllocation> {
+    let live = LIVE.lock().unwrap_or_else(|e| e.into_inner());
+    let now = Instant::now();
+    let mut report: Vec<_> = live
+        .iter()
+        .map(|(&address, entry)| LiveAllocation {
+            kind: entry.kind,
+            address,
+            size: entry.size,
+            owner: entry.owner,
+            vtable: entry.vtable,
+            age: now.duration_since(entry.created),
+        })
+        .collect();
+    report.sort_by_key(|allocation| std::cmp::Reverse(allocation.age));
+    report
+}
+
+/// Registers an allocation of `size` bytes at `address`.
+pub(crate) fn register(kind: AllocationKind, address: usize, size: usize, vtable: Option<usize>) {
+    let entry = Entry { kind, size, owner: None, vtable, created: Instant::now() };
+    LIVE.lock().unwrap_or_else(|e| e.into_inner()).insert(address, entry);
+}
+
+/// Records the object whose VTable pointer was pointed at the allocation at `address`.
+pub(crate) fn set_owner(address: usize, owner: usize) {
+    if let Some(entry) = LIVE.lock().unwrap_or_else(|e| e.into_inner()).get_mut(&address) {
+        entry.owner = Some(owner);
+    }
+}
+
+/// Forgets the allocation at `address` once it is freed.
+pub(crate) fn unregister(address: usize) {
+    LIVE.lock().unwrap_or_else(|e| e.into_inner()).remove(&address);
+}
@@ -0,0 +1,150 @@
+//! In-memory audit log of what the hooks did.
+//!
+//! Recording is off until [`enable`] is called. Every install, replacement, restore, release and
+//! detected tampering of a [`VTableHook`](crate::VTableHook) or an [`InPlaceVmtHook`](crate::InPlaceVmtHook)
+//! is then kept, the oldest entries being dropped past the capacity, so the log can be dumped when something
+//! goes wrong in the field.
+
+use std::collections::VecDeque;
+use std::fmt;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::thread::ThreadId;
+use std::time::SystemTime;
+
+/// A lifecycle operation of a hook.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Operation {
+    /// The hook was installed over the `count` methods of `vtable`.
+    Install { vtable: usize, count: usize },
+    /// The method at `id` was changed from `old` to `new`.
+    Replace { id: usize, old: usize, new: usize },
+    /// The method at `id` was changed from `old` back to the original `new`.
+    Restore { id: usize, old: usize, new: usize },
+    /// Every method was restored to its original address.
+    RestoreAll,
+    /// The hook was removed, restoring the original VTable.
+    Uninstall,
+    /// The hook was released without touching the object, which was already destroyed.
+    Detach,
+    /// A verification found the listed slots modified by someone else.
+    Tamper { slots: Vec<usize> },
+    /// A guarded hook of the method at `id` panicked and its fallback was used.
+    Panic { id: usize, message: String },
+}
+
+/// A recorded operation.
+#[derive(Debug, Clone)]
+pub struct Entry {
+    /// When the operation happened.
+    pub time: SystemTime,
+    /// Thread that performed it.
+    pub thread: ThreadId,
+    /// Address of the hooked object, or of the VTable for class-wide hooks.
+    pub object: usize,
+    /// What was done.
+    pub operation: Operation,
+    /// Backtrace of the call, if enabled.
+    pub backtrace: Option<String>,
+}
+
+impl fmt::Display for Entry {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let time = self.time.duration_since(SystemTime::UNIX_EPOCH).unwrap_or_default();
+        write!(f, "[{}.{:06}] {:?} {:#x} ", time.as_secs(), time.subsec_micros(), self.thread, self.object)?;
+        match &self.operation {
+            Operation::Install { vtable, count } => write!(f, "install {count} methods of {vtable:#x}")?,
+            Operation::Replace { id, old, new } => write!(f, "replace {id}: {old:#x} -> {new:#x}")?,
+            Operation::Restore { id, old, new } => write!(f, "restore {id}: {old:#x} -> {new:#x}")?,
+            Operation::RestoreAll => write!(f, "restore all")?,
+            Operation::Uninstall => write!(f, "uninstall")?,
+            Operation::Detach => write!(f, "detach")?,
+            Operation::Tamper { slots } => write!(f, "tampered slots {slots:?}")?,
+            Operation::Panic { id, message } => write!(f, "hook of method {id} panicked: {message}")?,
+        }
+        if let Some(backtrace) = &self.backtrace {
+            write!(f, "\n{backtrace}")?;
+        }
+        Ok(())
+    }
+}
+
+struct Log {
+    entries: VecDeque<Entry>,
+    capacity: usize,
+    backtraces: bool,
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static LOG: Mutex<Option<Log>> = Mutex::new(None);
+
+fn with_log<R>(f: impl FnOnce(&mut Option<Log>) -> R) -> R {
+    f(&mut LOG.lock().unwrap_or_else(|e| e.into_inner()))
+}
+
+/// Starts recording, keeping at most `capacity` entries, with a backtrace for each if `backtraces` is set.
+/// Entries recorded before are kept, the oldest ones dropped past the new capacity.
+pub fn enable(capacity: usize, backtraces: bool) {
+    with_log(|log| {
+        let mut entries = log.take().map(|log| log.entries).unwrap_or_default();
+        entries.drain(..entries.len().saturating_sub(capacity));
+        *log = Some(Log {
+            entries,
+            capacity,
+            backtraces,
+        });
+    });
+    ENABLED.store(true, Ordering::SeqCst);
+}
+
+/// Stops recording; the entries recorded so far are kept.
+pub fn disable() {
+    ENABLED.store(false, Ordering::SeqCst);
+}
+
+/// Returns `true` if operations are being recorded.
+pub fn is_enabled() -> bool {
+    ENABLED.load(Ordering::Relaxed)
+}
+
+/// Returns the recorded entries, oldest first.
+pub fn entries() -> Vec<Entry> {
+    with_log(|log| log.as_ref().map(|log| log.entries.iter().cloned().collect()).unwrap_or_default())
+}
+
+/// Removes and returns the recorded entries, oldest first.
+pub fn drain() -> Vec<Entry> {
+    with_log(|log| log.as_mut().map(|log| log.entries.drain(..).collect()).unwrap_or_default())
+}
+
+/// Returns the recorded entries formatted one per line, oldest first.
+pub fn dump() -> String {
+    entries().iter().map(|entry| format!("{entry}\n")).collect()
+}
+
+/// Records `operation` on `object` if recording is enabled.
+pub(crate) fn record(object: usize, operation: Operation) {
+    if !is_enabled() {
+        return;
+    }
+    with_log(|log| {
+        let Some(log) = log else {
+            return;
+        };
+        if log.capacity == 0 {
+            return;
+        }
+        let backtrace = log.backtraces.then(|| std::backtrace::Backtrace::force_capture().to_string());
+        if log.entries.len() == log.capacity {
+            log.entries.pop_front();
+        }
+        log.entries.push_back(Entry {
+            time: SystemTime::now(),
+            thread: std::thread::current().id(),
+            object,
+            operation,
+            backtrace,
+        });
+    });
+}
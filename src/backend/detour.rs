@@ -0,0 +1,85 @@
+//! Class-wide backend inline-detouring the original methods with `retour`.
+
+use std::cell::UnsafeCell;
+
+use retour::RawDetour;
+
+use super::HookBackend;
+
+/// Leaves the VTable and the object's VTable pointer untouched and instead patches the code
+/// of each hooked method with a `retour` detour.
+///
+/// Useful for class-wide hooks when the table itself is shared and integrity-checked.
+/// Every call to a hooked method is intercepted, not only virtual calls through this object.
+/// [`get_original_method`](crate::VTableHook::get_original_method) returns the detour's trampoline
+/// once a method is replaced, so fetch it after every replacement.
+///
+/// # Panics
+///
+/// Replacing a method panics if `retour` can't detour it.
+pub struct InlineDetour {
+    /// Pointer to the original VTable.
+    original_vtbl: &'static [usize],
+    /// Detours of the replaced methods with their replacement.
+    detours: UnsafeCell<Vec<Option<(usize, RawDetour)>>>,
+}
+
+impl InlineDetour {
+    #[allow(clippy::mut_from_ref)]
+    fn detours(&self) -> &mut Vec<Option<(usize, RawDetour)>> {
+        unsafe { &mut *self.detours.get() }
+    }
+}
+
+unsafe impl HookBackend for InlineDetour {
+    unsafe fn install(_vptr: *mut *const usize, vtable: *const usize, count: usize) -> Self {
+        let original_vtbl = std::slice::from_raw_parts(vtable, count);
+
+        Self {
+            original_vtbl,
+            detours: UnsafeCell::new((0..count).map(|_| None).collect()),
+        }
+    }
+
+    unsafe fn uninstall(&mut self, _vptr: *mut *const usize) {}
+
+    unsafe fn is_installed(&self, vptr: *const *const usize) -> bool {
+        std::ptr::eq(*vptr, self.original_vtbl.as_ptr())
+    }
+
+    fn count(&self) -> usize {
+        self.original_vtbl.len()
+    }
+
+    fn original(&self, id: usize) -> usize {
+        match &self.detours()[id] {
+            Some((_, detour)) => detour.trampoline() as *const () as usize,
+            None => self.original_vtbl[id],
+        }
+    }
+
+    fn replaced(&self, id: usize) -> usize {
+        match &self.detours()[id] {
+            Some((func, _)) => *func,
+            None => self.original_vtbl[id],
+        }
+    }
+
+    unsafe fn replace(&self, id: usize, func: usize) {
+        let target = self.original_vtbl[id];
+        let slot = &mut self.detours()[id];
+        if func == target || slot.as_ref().is_some_and(|(_, detour)| func == detour.trampoline() as *const () as usize) {
+            // Dropping the detour disables it.
+            *slot = None;
+            return;
+        }
+        if slot.as_ref().is_some_and(|(replaced, _)| *replaced == func) {
+            return;
+        }
+
+        *slot = None;
+        let detour = RawDetour::new(target as *const (), func as *const ()).expect("failed to create detour");
+        detour.enable().expect("failed to enable detour");
+        *slot = Some((func, detour));
+    }
+}
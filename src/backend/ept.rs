@@ -0,0 +1,334 @@
+//! Hypervisor-assisted backend hiding hooks in execute-only shadow pages.
+//!
+//! A companion hypervisor splits the EPT view of a code page: instruction fetches see a
+//! shadow copy patched with a jump to the replacement, while reads and writes keep seeing
+//! the untouched original. The hypervisor is reached through an [`EptChannel`] registered
+//! with [`set_channel`].
+
+use std::cell::UnsafeCell;
+use std::collections::HashMap;
+use std::io;
+use std::sync::{Mutex, MutexGuard};
+
+use super::HookBackend;
+use crate::sys::{self, Protection};
+use crate::x86;
+
+/// Bytes reserved for each trampoline: the relocated prologue and a jump back.
+const TRAMPOLINE_SIZE: usize = 64;
+
+/// Connection to a hypervisor able to split the EPT view of a page.
+pub unsafe trait EptChannel: Send {
+    /// Makes instruction fetches from the page at `page` read the page-sized copy at `shadow`.
+    /// Reads and writes of `page` must keep reaching the original memory.
+    unsafe fn split(&self, page: usize, shadow: usize) -> io::Result<()>;
+
+    /// Removes the split of `page`; `shadow` is freed afterwards.
+    unsafe fn merge(&self, page: usize) -> io::Result<()>;
+}
+
+/// A shadow copy of a split code page.
+struct Shadow {
+    /// Address of the copy.
+    address: usize,
+    /// Number of hooked methods on the page.
+    hooks: usize,
+}
+
+#[derive(Default)]
+struct State {
+    channel: Option<Box<dyn EptChannel>>,
+    /// Split pages mapped to their shadows.
+    shadows: HashMap<usize, Shadow>,
+}
+
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+
+fn with_state<R>(f: impl FnOnce(&mut State) -> R) -> R {
+    let mut state: MutexGuard<'static, Option<State>> = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    f(state.get_or_insert_with(State::default))
+}
+
+/// Registers the channel used by every [`Ept`] hook, returning the previous one.
+pub fn set_channel(channel: impl EptChannel + 'static) -> Option<Box<dyn EptChannel>> {
+    with_state(|state| state.channel.replace(Box::new(channel)))
+}
+
+/// Writes a jump to `func` at `target` in the execute view of its page.
+unsafe fn patch(target: usize, func: usize) -> io::Result<()> {
+    let page_size = sys::page_size();
+    let page = target & !(page_size - 1);
+    let offset = target - page;
+    if offset + x86::JUMP_LEN > page_size {
+        return Err(io::Error::new(io::ErrorKind::Unsupported, "method prologue crosses a page boundary"));
+    }
+
+    with_state(|state| {
+        let channel = state.channel.as_ref().ok_or_else(|| io::Error::other("no EPT channel registered"))?;
+        let shadow = match state.shadows.get_mut(&page) {
+            Some(shadow) => shadow,
+            None => {
+                let address = sys::alloc_pages(page_size, Protection::ReadWrite)?;
+                std::ptr::copy_nonoverlapping(page as *const u8, address as *mut u8, page_size);
+                state.shadows.entry(page).or_insert(Shadow { address, hooks: 0 })
+            }
+        };
+
+        let code = std::slice::from_raw_parts_mut((shadow.address + offset) as *mut u8, x86::JUMP_LEN);
+        x86::write_jump(code, target, func);
+        shadow.hooks += 1;
+        if shadow.hooks == 1 {
+            if let Err(error) = channel.split(page, shadow.address) {
+                sys::free_pages(shadow.address, page_size);
+                state.shadows.remove(&page);
+                return Err(error);
+            }
+        }
+        Ok(())
+    })
+}
+
+/// Restores the execute view of the prologue at `target`.
+unsafe fn unpatch(target: usize) -> io::Result<()> {
+    let page_size = sys::page_size();
+    let page = target & !(page_size - 1);
+    let offset = target - page;
+
+    with_state(|state| {
+        let Some(shadow) = state.shadows.get_mut(&page) else {
+            return Ok(());
+        };
+        std::ptr::copy_nonoverlapping(target as *const u8, (shadow.address + offset) as *mut u8, x86::JUMP_LEN);
+        shadow.hooks -= 1;
+        if shadow.hooks == 0 {
+            let address = shadow.address;
+            state.shadows.remove(&page);
+            if let Some(channel) = &state.channel {
+                channel.merge(page)?;
+            }
+            sys::free_pages(address, page_size);
+        }
+        Ok(())
+    })
+}
+
+/// Leaves the VTable, the object's VTable pointer and the readable code untouched and instead
+/// patches an execute-only shadow of each hooked method's code page through the hypervisor.
+///
+/// Calls must reach the original through [`get_original_method`](crate::VTableHook::get_original_method),
+/// which returns a trampoline running the relocated prologue. Every call to a hooked method
+/// is intercepted, not only virtual calls through this object.
+///
+/// # Panics
+///
+/// Replacing a method panics if no channel is registered, the hypervisor refuses the split
+/// or the method's prologue can't be relocated.
+pub struct Ept {
+    /// Pointer to the original VTable.
+    original_vtbl: &'static [usize],
+    /// Methods that calls currently reach.
+    replaced: UnsafeCell<Box<[usize]>>,
+    /// Executable memory holding one trampoline per method.
+    trampolines: usize,
+    /// Whether the trampoline of each method has been written.
+    ready: UnsafeCell<Vec<bool>>,
+}
+
+unsafe impl Send for Ept {}
+
+impl Ept {
+    #[allow(clippy::mut_from_ref)]
+    fn replaced_mut(&self) -> &mut [usize] {
+        unsafe { &mut *self.replaced.get() }
+    }
+
+    /// Returns the trampoline of a method, writing it on first use.
+    fn trampoline(&self, id: usize) -> Option<usize> {
+        let target = self.original_vtbl[id];
+        let trampoline = self.trampolines + id * TRAMPOLINE_SIZE;
+        let ready = unsafe { &mut *self.ready.get() };
+        if !ready[id] {
+            unsafe {
+                // A method at the end of its region is only readable up to the end of its page.
+                let wanted = TRAMPOLINE_SIZE - x86::JUMP_LEN;
+                let page_end = (target | (sys::page_size() - 1)) + 1;
+                let available = match sys::is_readable(target, wanted) {
+                    true => wanted,
+                    false => (page_end - target).min(wanted),
+                };
+                if available < x86::JUMP_LEN || !sys::is_readable(target, available) {
+                    return None;
+                }
+                let prologue = std::slice::from_raw_parts(target as *const u8, available);
+                let len = x86::relocatable_len(prologue, x86::JUMP_LEN)?;
+                let code = std::slice::from_raw_parts_mut(trampoline as *mut u8, TRAMPOLINE_SIZE);
+                code[..len].copy_from_slice(&prologue[..len]);
+                x86::write_jump(&mut code[len..], trampoline + len, target + len);
+            }
+            ready[id] = true;
+        }
+        Some(trampoline)
+    }
+}
+
+impl Drop for Ept {
+    fn drop(&mut self) {
+        unsafe {
+            self.restore_all();
+            sys::free_pages(self.trampolines, self.original_vtbl.len() * TRAMPOLINE_SIZE);
+        }
+    }
+}
+
+unsafe impl HookBackend for Ept {
+    unsafe fn install(_vptr: *mut *const usize, vtable: *const usize, count: usize) -> Self {
+        let original_vtbl = std::slice::from_raw_parts(vtable, count);
+        let trampolines = sys::alloc_pages((count * TRAMPOLINE_SIZE).max(1), Protection::ReadWriteExecute)
+            .expect("failed to allocate trampolines");
+
+        Self {
+            original_vtbl,
+            replaced: UnsafeCell::new(original_vtbl.into()),
+            trampolines,
+            ready: UnsafeCell::new(vec![false; count]),
+        }
+    }
+
+    unsafe fn uninstall(&mut self, _vptr: *mut *const usize) {}
+
+    unsafe fn is_installed(&self, vptr: *const *const usize) -> bool {
+        std::ptr::eq(*vptr, self.original_vtbl.as_ptr())
+    }
+
+    fn count(&self) -> usize {
+        self.original_vtbl.len()
+    }
+
+    fn original(&self, id: usize) -> usize {
+        self.trampoline(id).unwrap_or(self.original_vtbl[id])
+    }
+
+    fn replaced(&self, id: usize) -> usize {
+        self.replaced_mut()[id]
+    }
+
+    unsafe fn replace(&self, id: usize, func: usize) {
+        let target = self.original_vtbl[id];
+        let func = if Some(func) == self.trampoline(id) { target } else { func };
+        let replaced = &mut self.replaced_mut()[id];
+        if *replaced == func {
+            return;
+        }
+
+        if *replaced != target {
+            unpatch(target).expect("failed to merge EPT page");
+        }
+        if func != target {
+            self.trampoline(id).expect("method prologue can't be relocated");
+            patch(target, func).expect("failed to split EPT page");
+        }
+        *replaced = func;
+    }
+
+    unsafe fn restore_all(&self) {
+        for id in 0..self.count() {
+            self.replace(id, self.original_vtbl[id]);
+        }
+    }
+}
+
+/// Reference [`EptChannel`] talking to a driver through `DeviceIoControl`.
+///
+/// Both requests send an [`EptRequest`] to the device with the control codes given to [`DeviceChannel::open`].
+#[cfg(windows)]
+pub struct DeviceChannel {
+    device: usize,
+    split_code: u32,
+    merge_code: u32,
+}
+
+/// Input buffer of the requests sent by [`DeviceChannel`].
+#[cfg(windows)]
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct EptRequest {
+    /// Id of the process owning the pages.
+    pub process_id: u32,
+    /// Virtual address of the original page.
+    pub page: u64,
+    /// Virtual address of the shadow page, or 0 for merge requests.
+    pub shadow: u64,
+}
+
+#[cfg(windows)]
+impl DeviceChannel {
+    /// Opens the device at `path`, such as `\\.\MyHypervisor`.
+    pub fn open(path: &str, split_code: u32, merge_code: u32) -> io::Result<Self> {
+        use windows_sys::Win32::Foundation::{GENERIC_READ, GENERIC_WRITE, INVALID_HANDLE_VALUE};
+        use windows_sys::Win32::Storage::FileSystem::{CreateFileA, FILE_SHARE_READ, FILE_SHARE_WRITE, OPEN_EXISTING};
+
+        let path = std::ffi::CString::new(path).map_err(|e| io::Error::new(io::ErrorKind::InvalidInput, e))?;
+        let device = unsafe {
+            CreateFileA(
+                path.as_ptr().cast(),
+                GENERIC_READ | GENERIC_WRITE,
+                FILE_SHARE_READ | FILE_SHARE_WRITE,
+                std::ptr::null(),
+                OPEN_EXISTING,
+                0,
+                std::ptr::null_mut(),
+            )
+        };
+        if device == INVALID_HANDLE_VALUE {
+            return Err(io::Error::last_os_error());
+        }
+
+        Ok(Self {
+            device: device as usize,
+            split_code,
+            merge_code,
+        })
+    }
+
+    unsafe fn send(&self, code: u32, request: EptRequest) -> io::Result<()> {
+        use windows_sys::Win32::System::IO::DeviceIoControl;
+
+        let mut returned = 0;
+        let ok = DeviceIoControl(
+            self.device as _,
+            code,
+            &request as *const EptRequest as *const _,
+            std::mem::size_of::<EptRequest>() as u32,
+            std::ptr::null_mut(),
+            0,
+            &mut returned,
+            std::ptr::null_mut(),
+        );
+        if ok == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+#[cfg(windows)]
+impl Drop for DeviceChannel {
+    fn drop(&mut self) {
+        unsafe {
+            windows_sys::Win32::Foundation::CloseHandle(self.device as _);
+        }
+    }
+}
+
+#[cfg(windows)]
+unsafe impl EptChannel for DeviceChannel {
+    unsafe fn split(&self, page: usize, shadow: usize) -> io::Result<()> {
+        let process_id = windows_sys::Win32::System::Threading::GetCurrentProcessId();
+        self.send(self.split_code, EptRequest { process_id, page: page as u64, shadow: shadow as u64 })
+    }
+
+    unsafe fn merge(&self, page: usize) -> io::Result<()> {
+        let process_id = windows_sys::Win32::System::Threading::GetCurrentProcessId();
+        self.send(self.merge_code, EptRequest { process_id, page: page as u64, shadow: 0 })
+    }
+}
@@ -0,0 +1,124 @@
+//! Class-wide backend replacing the original methods with frida-gum's `Interceptor`.
+
+use std::cell::UnsafeCell;
+use std::ffi::c_void;
+
+use frida_gum::interceptor::Interceptor;
+use frida_gum::{Gum, NativePointer};
+
+use super::HookBackend;
+
+/// Leaves the VTable and the object's VTable pointer untouched and instead replaces each
+/// hooked method through frida-gum's `Interceptor`.
+///
+/// Every call to a hooked method is intercepted, not only virtual calls through this object.
+/// [`get_original_method`](crate::VTableHook::get_original_method) returns the interceptor's
+/// trampoline once a method is replaced, so fetch it after every replacement.
+///
+/// # Panics
+///
+/// Replacing a method panics if the interceptor refuses it.
+pub struct FridaInterceptor {
+    /// Pointer to the original VTable.
+    original_vtbl: &'static [usize],
+    interceptor: UnsafeCell<Interceptor>,
+    /// Replacements of the replaced methods with the trampoline to the original.
+    replaced: UnsafeCell<Vec<Option<(usize, usize)>>>,
+}
+
+impl FridaInterceptor {
+    #[allow(clippy::mut_from_ref)]
+    fn interceptor(&self) -> &mut Interceptor {
+        unsafe { &mut *self.interceptor.get() }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    fn replaced_mut(&self) -> &mut Vec<Option<(usize, usize)>> {
+        unsafe { &mut *self.replaced.get() }
+    }
+
+    /// Runs `f` inside an interceptor transaction, so the code pages are patched once at the end.
+    pub fn transaction<R>(&self, f: impl FnOnce() -> R) -> R {
+        self.interceptor().begin_transaction();
+        let result = f();
+        self.interceptor().end_transaction();
+        result
+    }
+}
+
+impl Drop for FridaInterceptor {
+    fn drop(&mut self) {
+        unsafe {
+            self.restore_all();
+        }
+    }
+}
+
+unsafe impl HookBackend for FridaInterceptor {
+    unsafe fn install(_vptr: *mut *const usize, vtable: *const usize, count: usize) -> Self {
+        let original_vtbl = std::slice::from_raw_parts(vtable, count);
+        let gum = Gum::obtain();
+
+        Self {
+            original_vtbl,
+            interceptor: UnsafeCell::new(Interceptor::obtain(&gum)),
+            replaced: UnsafeCell::new(vec![None; count]),
+        }
+    }
+
+    unsafe fn uninstall(&mut self, _vptr: *mut *const usize) {}
+
+    unsafe fn is_installed(&self, vptr: *const *const usize) -> bool {
+        std::ptr::eq(*vptr, self.original_vtbl.as_ptr())
+    }
+
+    fn count(&self) -> usize {
+        self.original_vtbl.len()
+    }
+
+    fn original(&self, id: usize) -> usize {
+        match self.replaced_mut()[id] {
+            Some((_, trampoline)) => trampoline,
+            None => self.original_vtbl[id],
+        }
+    }
+
+    fn replaced(&self, id: usize) -> usize {
+        match self.replaced_mut()[id] {
+            Some((func, _)) => func,
+            None => self.original_vtbl[id],
+        }
+    }
+
+    unsafe fn replace(&self, id: usize, func: usize) {
+        let target = NativePointer(self.original_vtbl[id] as *mut c_void);
+        let slot = &mut self.replaced_mut()[id];
+        if let Some((replaced, trampoline)) = *slot {
+            if replaced == func {
+                return;
+            }
+            self.interceptor().revert(target);
+            *slot = None;
+            if func == trampoline {
+                return;
+            }
+        }
+        if func == self.original_vtbl[id] {
+            return;
+        }
+
+        let trampoline = self
+            .interceptor()
+            .replace(target, NativePointer(func as *mut c_void), NativePointer(std::ptr::null_mut()))
+            .expect("failed to replace method");
+        *slot = Some((func, trampoline.0 as usize));
+    }
+
+    unsafe fn restore_all(&self) {
+        self.transaction(|| {
+            for id in 0..self.count() {
+                self.replace(id, self.original_vtbl[id]);
+            }
+        });
+    }
+}
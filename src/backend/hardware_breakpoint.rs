@@ -0,0 +1,208 @@
+//! Stealth backend intercepting calls with debug register execution breakpoints.
+
+use std::cell::UnsafeCell;
+use std::sync::{Mutex, MutexGuard};
+
+use windows_sys::Win32::Foundation::{CloseHandle, STATUS_SINGLE_STEP};
+use windows_sys::Win32::System::Diagnostics::Debug::{GetThreadContext, SetThreadContext, CONTEXT, EXCEPTION_RECORD};
+use windows_sys::Win32::System::Threading::{
+    GetCurrentThreadId, OpenThread, ResumeThread, SuspendThread, THREAD_GET_CONTEXT, THREAD_SET_CONTEXT,
+    THREAD_SUSPEND_RESUME,
+};
+
+use super::HookBackend;
+use crate::sys;
+use crate::veh;
+
+#[cfg(target_arch = "x86_64")]
+use windows_sys::Win32::System::Diagnostics::Debug::CONTEXT_DEBUG_REGISTERS_AMD64 as CONTEXT_DEBUG_REGISTERS;
+#[cfg(target_arch = "x86")]
+use windows_sys::Win32::System::Diagnostics::Debug::CONTEXT_DEBUG_REGISTERS_X86 as CONTEXT_DEBUG_REGISTERS;
+
+/// Number of debug address registers (DR0–DR3).
+pub const MAX_BREAKPOINTS: usize = 4;
+
+/// Resume flag of EFLAGS, suppressing instruction breakpoints for the next instruction.
+const RESUME_FLAG: u32 = 0x10000;
+
+/// Leaves memory untouched and instead sets DR0–DR3 execution breakpoints on hooked methods,
+/// redirecting execution from the exception handler.
+///
+/// Debug registers are shared by all hooks in the process, so at most [`MAX_BREAKPOINTS`]
+/// methods can be hooked at once. Breakpoints are applied to the threads that exist when a
+/// method is replaced; call [`HardwareBreakpoint::apply_to_threads`] to cover threads created later.
+/// Every call to a hooked method is intercepted, not only virtual calls through this object.
+/// Replacements must return normally: unwinding out of them skips the return-address bookkeeping.
+///
+/// # Panics
+///
+/// Replacing a method panics if all debug registers are in use.
+pub struct HardwareBreakpoint {
+    /// Pointer to the original VTable.
+    original_vtbl: &'static [usize],
+    /// Methods that calls currently reach.
+    replaced: UnsafeCell<Box<[usize]>>,
+}
+
+unsafe impl Send for HardwareBreakpoint {}
+
+#[derive(Default)]
+struct State {
+    /// Methods watched by each debug register.
+    breakpoints: [Option<usize>; MAX_BREAKPOINTS],
+}
+
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+
+fn with_state<R>(f: impl FnOnce(&mut State) -> R) -> R {
+    let mut state: MutexGuard<'static, Option<State>> = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    f(state.get_or_insert_with(State::default))
+}
+
+/// `CONTEXT` must be 16-byte aligned on x86_64.
+#[repr(C, align(16))]
+struct AlignedContext(CONTEXT);
+
+/// Writes the breakpoints into the debug registers of a suspended thread.
+unsafe fn set_debug_registers(thread: *mut std::ffi::c_void, breakpoints: &[Option<usize>; MAX_BREAKPOINTS]) {
+    let mut context = AlignedContext(std::mem::zeroed());
+    context.0.ContextFlags = CONTEXT_DEBUG_REGISTERS;
+    if GetThreadContext(thread, &mut context.0) == 0 {
+        return;
+    }
+
+    let context = &mut context.0;
+    let mut dr7 = context.Dr7 as usize;
+    for (index, breakpoint) in breakpoints.iter().enumerate() {
+        let address = breakpoint.unwrap_or_default();
+        match index {
+            0 => context.Dr0 = address as _,
+            1 => context.Dr1 = address as _,
+            2 => context.Dr2 = address as _,
+            _ => context.Dr3 = address as _,
+        }
+        // Local enable bit; zero condition and length bits select an execution breakpoint.
+        dr7 &= !((1 << (index * 2)) | (0b1111 << (16 + index * 4)));
+        if breakpoint.is_some() {
+            dr7 |= 1 << (index * 2);
+        }
+    }
+    context.Dr7 = dr7 as _;
+    SetThreadContext(thread, context);
+}
+
+/// Applies the current breakpoints to every thread of the process.
+///
+/// Runs on a helper thread, since a thread can't change its own debug registers while running.
+fn apply() {
+    let breakpoints = with_state(|state| state.breakpoints);
+    std::thread::spawn(move || unsafe {
+        let current = GetCurrentThreadId();
+        sys::threads(|id| {
+            if id == current {
+                return;
+            }
+            let thread = OpenThread(THREAD_GET_CONTEXT | THREAD_SET_CONTEXT | THREAD_SUSPEND_RESUME, 0, id);
+            if thread.is_null() {
+                return;
+            }
+            if SuspendThread(thread) != u32::MAX {
+                set_debug_registers(thread, &breakpoints);
+                ResumeThread(thread);
+            }
+            CloseHandle(thread);
+        });
+    })
+    .join()
+    .unwrap_or_default();
+}
+
+/// Handles the single-step exceptions raised by the breakpoints.
+pub(crate) unsafe fn handle(record: &EXCEPTION_RECORD, context: &mut CONTEXT) -> bool {
+    if record.ExceptionCode != STATUS_SINGLE_STEP {
+        return false;
+    }
+    let address = record.ExceptionAddress as usize;
+    if !with_state(|state| state.breakpoints.contains(&Some(address))) {
+        return false;
+    }
+    if !veh::enter(context) {
+        // Called from its own replacement: run the original without breaking again.
+        context.EFlags |= RESUME_FLAG;
+    }
+    true
+}
+
+impl HardwareBreakpoint {
+    /// Applies the breakpoints of all hooks to every thread of the process, including new ones.
+    pub fn apply_to_threads() {
+        apply();
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    fn replaced_mut(&self) -> &mut [usize] {
+        unsafe { &mut *self.replaced.get() }
+    }
+}
+
+impl Drop for HardwareBreakpoint {
+    fn drop(&mut self) {
+        unsafe { self.restore_all() };
+    }
+}
+
+unsafe impl HookBackend for HardwareBreakpoint {
+    unsafe fn install(_vptr: *mut *const usize, vtable: *const usize, count: usize) -> Self {
+        veh::acquire();
+        let original_vtbl = std::slice::from_raw_parts(vtable, count);
+
+        Self {
+            original_vtbl,
+            replaced: UnsafeCell::new(original_vtbl.into()),
+        }
+    }
+
+    unsafe fn uninstall(&mut self, _vptr: *mut *const usize) {}
+
+    unsafe fn is_installed(&self, vptr: *const *const usize) -> bool {
+        std::ptr::eq(*vptr, self.original_vtbl.as_ptr())
+    }
+
+    fn count(&self) -> usize {
+        self.original_vtbl.len()
+    }
+
+    fn original(&self, id: usize) -> usize {
+        self.original_vtbl[id]
+    }
+
+    fn replaced(&self, id: usize) -> usize {
+        self.replaced_mut()[id]
+    }
+
+    unsafe fn replace(&self, id: usize, func: usize) {
+        let target = self.original_vtbl[id];
+        let replaced = &mut self.replaced_mut()[id];
+        if *replaced == func {
+            return;
+        }
+
+        if *replaced != target {
+            veh::remove(target);
+            with_state(|state| {
+                if let Some(breakpoint) = state.breakpoints.iter_mut().find(|b| **b == Some(target)) {
+                    *breakpoint = None;
+                }
+            });
+        }
+        if func != target {
+            with_state(|state| {
+                let breakpoint = state.breakpoints.iter_mut().find(|b| b.is_none());
+                *breakpoint.expect("all debug registers are in use") = Some(target);
+            });
+            veh::redirect(target, func);
+        }
+        *replaced = func;
+        apply();
+    }
+}
@@ -0,0 +1,99 @@
+use std::sync::OnceLock;
+
+use super::HookBackend;
+use crate::allocations;
+use crate::shadow::{ShadowTable, VTableCopyOptions};
+
+/// Copies the VTable like [`CopySwap`](super::CopySwap), but only once a method is first replaced.
+///
+/// Until then the object keeps pointing at its original VTable and nothing is allocated, so wrapping
+/// many objects in case they need hooking costs nothing for the ones that never do.
+///
+/// # Panics
+///
+/// Replacing the first method panics if the copy can't be allocated.
+pub struct LazyCopySwap {
+    /// Address of the object's VTable pointer, swapped on the first replacement.
+    vptr: *mut *const usize,
+    /// Pointer the object held before it was hooked, reaching the words around the methods too.
+    vtable: *const usize,
+    /// Pointer to the original VTable.
+    original_vtbl: &'static [usize],
+    /// New VTable containing hooked function address, once a method was replaced.
+    new_vtbl: OnceLock<ShadowTable>,
+}
+
+impl LazyCopySwap {
+    /// Returns `true` once the copy has been allocated and the object points at it.
+    pub fn is_allocated(&self) -> bool {
+        self.new_vtbl.get().is_some()
+    }
+
+    /// Returns the copy, allocating it and pointing the object at it on first use.
+    unsafe fn table(&self) -> &ShadowTable {
+        self.new_vtbl.get_or_init(|| {
+            let table = ShadowTable::new(self.vtable, self.original_vtbl.len(), &VTableCopyOptions::default())
+                .expect("failed to allocate vtable");
+            *self.vptr = table.as_ptr();
+            allocations::set_owner(table.as_ptr() as usize, self.vptr as usize);
+            table
+        })
+    }
+}
+
+// The original VTable is only read, the copy is `Send` and `Sync` itself and allocated once.
+unsafe impl Send for LazyCopySwap {}
+unsafe impl Sync for LazyCopySwap {}
+
+unsafe impl HookBackend for LazyCopySwap {
+    unsafe fn install(vptr: *mut *const usize, vtable: *const usize, count: usize) -> Self {
+        Self {
+            vptr,
+            vtable,
+            original_vtbl: std::slice::from_raw_parts(vtable, count),
+            new_vtbl: OnceLock::new(),
+        }
+    }
+
+    unsafe fn uninstall(&mut self, vptr: *mut *const usize) {
+        if self.is_allocated() {
+            *vptr = self.vtable;
+        }
+    }
+
+    unsafe fn is_installed(&self, vptr: *const *const usize) -> bool {
+        match self.new_vtbl.get() {
+            Some(table) => *vptr == table.as_ptr(),
+            None => *vptr == self.vtable,
+        }
+    }
+
+    fn count(&self) -> usize {
+        self.original_vtbl.len()
+    }
+
+    fn original(&self, id: usize) -> usize {
+        self.original_vtbl[id]
+    }
+
+    fn replaced(&self, id: usize) -> usize {
+        match self.new_vtbl.get() {
+            Some(table) => table.methods()[id],
+            None => self.original_vtbl[id],
+        }
+    }
+
+    unsafe fn replace(&self, id: usize, func: usize) {
+        // Restoring a method that was never replaced leaves the object alone.
+        if !self.is_allocated() && func == self.original_vtbl[id] {
+            return;
+        }
+        self.table().write(id, &[func]).expect("failed to patch vtable");
+    }
+
+    unsafe fn restore_all(&self) {
+        if let Some(table) = self.new_vtbl.get() {
+            table.write(0, self.original_vtbl).expect("failed to patch vtable");
+        }
+    }
+}
@@ -0,0 +1,256 @@
+//! Mechanisms used by [`VTableHook`](crate::VTableHook) to redirect virtual calls.
+
+#[cfg(feature = "std")]
+use std::io;
+
+use crate::shadow::ShadowTable;
+#[cfg(feature = "std")]
+use crate::shadow::{IntegrityCheck, VTableCopyOptions};
+#[cfg(feature = "std")]
+use crate::{allocations, Error, InPlaceVmtHook, Result};
+
+#[cfg(feature = "retour")]
+mod detour;
+#[cfg(feature = "frida-gum")]
+mod frida;
+#[cfg(all(feature = "ept", any(target_arch = "x86", target_arch = "x86_64")))]
+pub mod ept;
+#[cfg(all(feature = "std", windows, any(target_arch = "x86", target_arch = "x86_64")))]
+pub(crate) mod hardware_breakpoint;
+#[cfg(all(feature = "std", windows, any(target_arch = "x86", target_arch = "x86_64")))]
+pub(crate) mod page_guard;
+#[cfg(feature = "std")]
+mod lazy;
+#[cfg(feature = "std")]
+mod shared;
+#[cfg(feature = "retour")]
+pub use detour::InlineDetour;
+#[cfg(feature = "frida-gum")]
+pub use frida::FridaInterceptor;
+#[cfg(all(feature = "ept", any(target_arch = "x86", target_arch = "x86_64")))]
+pub use ept::Ept;
+#[cfg(all(feature = "std", windows, any(target_arch = "x86", target_arch = "x86_64")))]
+pub use hardware_breakpoint::{HardwareBreakpoint, MAX_BREAKPOINTS};
+#[cfg(all(feature = "std", windows, any(target_arch = "x86", target_arch = "x86_64")))]
+pub use page_guard::PageGuard;
+#[cfg(feature = "std")]
+pub use lazy::LazyCopySwap;
+#[cfg(feature = "std")]
+pub use shared::SharedCopySwap;
+
+/// A strategy for redirecting the methods of an object's VTable.
+///
+/// `vptr` is the address of the object's VTable pointer.
+pub unsafe trait HookBackend: Sized {
+    /// Installs the backend over the `count` methods of `vtable`.
+    unsafe fn install(vptr: *mut *const usize, vtable: *const usize, count: usize) -> Self;
+
+    /// Undoes the installation on the object; resources are released on drop.
+    unsafe fn uninstall(&mut self, vptr: *mut *const usize);
+
+    /// Returns `true` if calls through `vptr` still go through the backend.
+    unsafe fn is_installed(&self, vptr: *const *const usize) -> bool;
+
+    /// Returns the number of methods covered by the backend.
+    fn count(&self) -> usize;
+
+    /// Returns the original method address at the specified index.
+    fn original(&self, id: usize) -> usize;
+
+    /// Returns the method address that calls at the specified index currently reach.
+    fn replaced(&self, id: usize) -> usize;
+
+    /// Redirects calls at the specified index to `func`.
+    unsafe fn replace(&self, id: usize, func: usize);
+
+    /// Restores all methods to their original address.
+    unsafe fn restore_all(&self) {
+        for id in 0..self.count() {
+            self.replace(id, self.original(id));
+        }
+    }
+}
+
+/// Copies the VTable and points the object at the copy.
+///
+/// Only the hooked object is affected. This is the default backend.
+/// Where the copy lives is controlled by `VTableCopyOptions`; without `std` it is always on the heap.
+///
+/// # Panics
+///
+/// Replacing a method panics if the copy is frozen or in a module cave and its page protection can't be changed.
+pub struct CopySwap {
+    /// Pointer the object held before it was hooked, reaching the words around the methods too.
+    vtable: *const usize,
+    /// Pointer to the original VTable.
+    original_vtbl: &'static [usize],
+    /// New VTable containing hooked function address.
+    new_vtbl: ShadowTable,
+}
+
+impl CopySwap {
+    /// Points the object at `new_vtbl`, a copy of the `count` methods of `vtable`.
+    unsafe fn swap(vptr: *mut *const usize, vtable: *const usize, count: usize, new_vtbl: ShadowTable) -> Self {
+        let original_vtbl = core::slice::from_raw_parts(vtable, count);
+
+        *vptr = new_vtbl.as_ptr();
+        #[cfg(feature = "std")]
+        allocations::set_owner(new_vtbl.as_ptr() as usize, vptr as usize);
+
+        Self {
+            vtable,
+            original_vtbl,
+            new_vtbl,
+        }
+    }
+}
+
+#[cfg(feature = "std")]
+impl CopySwap {
+    /// Installs a copy of the `count` methods of `vtable` allocated as described by `options`.
+    pub unsafe fn with_options(
+        vptr: *mut *const usize,
+        vtable: *const usize,
+        count: usize,
+        options: &VTableCopyOptions,
+    ) -> io::Result<Self> {
+        let new_vtbl = ShadowTable::new(vtable, count, options)?;
+        Ok(Self::swap(vptr, vtable, count, new_vtbl))
+    }
+
+    /// Makes the copy read-only, like the `.rdata` section the original usually lives in.
+    ///
+    /// Replacing methods keeps working: the pages are made writable for the duration of each write.
+    /// Fails for copies allocated on the heap, which share their pages with other data.
+    pub fn freeze(&self) -> io::Result<()> {
+        self.new_vtbl.freeze()
+    }
+
+    /// Makes a frozen copy writable again, for a batch of replacements.
+    /// Copies in a module cave keep the protection of their section.
+    pub fn thaw(&self) -> io::Result<()> {
+        self.new_vtbl.thaw()
+    }
+
+    /// Returns `true` if the copy is read-only between writes.
+    pub fn is_frozen(&self) -> bool {
+        self.new_vtbl.is_frozen()
+    }
+
+    /// Checks that nothing but this hook wrote into the copy, reporting the changed methods as
+    /// [`Error::Tampered`].
+    pub fn verify(&self) -> Result<()> {
+        let tampered = self.new_vtbl.tampered_slots();
+        if tampered.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::Tampered(tampered))
+        }
+    }
+
+    /// Returns a check of the copy that can run on other threads, e.g. from a [`Watchdog`](crate::watchdog::Watchdog).
+    pub fn integrity_check(&self) -> IntegrityCheck {
+        self.new_vtbl.integrity_check()
+    }
+}
+
+// The original VTable is only read, and the copy is `Send` and `Sync` itself.
+unsafe impl Send for CopySwap {}
+unsafe impl Sync for CopySwap {}
+
+unsafe impl HookBackend for CopySwap {
+    unsafe fn install(vptr: *mut *const usize, vtable: *const usize, count: usize) -> Self {
+        #[cfg(feature = "std")]
+        let new_vtbl =
+            ShadowTable::new(vtable, count, &VTableCopyOptions::default()).expect("failed to allocate vtable");
+        #[cfg(not(feature = "std"))]
+        let new_vtbl = ShadowTable::on_heap(vtable, count);
+        Self::swap(vptr, vtable, count, new_vtbl)
+    }
+
+    unsafe fn uninstall(&mut self, vptr: *mut *const usize) {
+        // Checks stop first, so a watchdog doesn't report the restored vptr as a violation.
+        #[cfg(feature = "std")]
+        self.new_vtbl.release();
+        *vptr = self.vtable;
+    }
+
+    unsafe fn is_installed(&self, vptr: *const *const usize) -> bool {
+        *vptr == self.new_vtbl.as_ptr()
+    }
+
+    fn count(&self) -> usize {
+        self.original_vtbl.len()
+    }
+
+    fn original(&self, id: usize) -> usize {
+        self.original_vtbl[id]
+    }
+
+    fn replaced(&self, id: usize) -> usize {
+        self.new_vtbl.methods()[id]
+    }
+
+    unsafe fn replace(&self, id: usize, func: usize) {
+        self.new_vtbl.write(id, &[func]).expect("failed to patch vtable");
+    }
+
+    unsafe fn restore_all(&self) {
+        self.new_vtbl.write(0, self.original_vtbl).expect("failed to patch vtable");
+    }
+}
+
+#[cfg(feature = "std")]
+/// Patches the original VTable in place through an [`InPlaceVmtHook`].
+///
+/// Every instance of the class is affected; the object's VTable pointer is left untouched.
+///
+/// # Panics
+///
+/// Replacing a method panics if the page protection of the VTable can't be changed.
+pub struct InPlacePatch {
+    hook: InPlaceVmtHook,
+}
+
+#[cfg(feature = "std")]
+impl InPlacePatch {
+    /// Returns the underlying in-place hook.
+    pub fn hook(&self) -> &InPlaceVmtHook {
+        &self.hook
+    }
+}
+
+#[cfg(feature = "std")]
+unsafe impl HookBackend for InPlacePatch {
+    unsafe fn install(_vptr: *mut *const usize, vtable: *const usize, count: usize) -> Self {
+        Self {
+            hook: InPlaceVmtHook::unaudited(vtable as *mut usize, count),
+        }
+    }
+
+    unsafe fn uninstall(&mut self, _vptr: *mut *const usize) {}
+
+    unsafe fn is_installed(&self, vptr: *const *const usize) -> bool {
+        std::ptr::eq(*vptr, self.hook.vtable())
+    }
+
+    fn count(&self) -> usize {
+        self.hook.len()
+    }
+
+    fn original(&self, id: usize) -> usize {
+        self.hook.get_original_method(id)
+    }
+
+    fn replaced(&self, id: usize) -> usize {
+        unsafe { self.hook.get_replaced_method(id) }
+    }
+
+    unsafe fn replace(&self, id: usize, func: usize) {
+        self.hook.replace_method(id, func).expect("failed to patch vtable");
+    }
+
+    unsafe fn restore_all(&self) {
+        self.hook.restore_all_methods().expect("failed to patch vtable");
+    }
+}
@@ -0,0 +1,186 @@
+//! Stealth backend intercepting calls with guard pages and a vectored exception handler.
+
+use std::cell::UnsafeCell;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard};
+
+use windows_sys::Win32::Foundation::{STATUS_GUARD_PAGE_VIOLATION, STATUS_SINGLE_STEP};
+use windows_sys::Win32::System::Diagnostics::Debug::{CONTEXT, EXCEPTION_RECORD};
+use windows_sys::Win32::System::Memory::PAGE_GUARD;
+
+use super::HookBackend;
+use crate::sys::{self, RawProtection};
+use crate::veh;
+
+/// Leaves the VTable and the object's VTable pointer untouched and instead marks the code
+/// pages of hooked methods with `PAGE_GUARD`, redirecting execution from the exception handler.
+///
+/// Every call to a hooked method is intercepted, not only virtual calls through this object.
+/// Other code sharing a page with a hooked method runs single-stepped, so hooked calls are much
+/// slower than with [`CopySwap`](super::CopySwap). Replacements must return normally: unwinding
+/// out of them skips the return-address bookkeeping.
+///
+/// # Panics
+///
+/// Replacing a method panics if the page protection can't be changed.
+pub struct PageGuard {
+    /// Pointer to the original VTable.
+    original_vtbl: &'static [usize],
+    /// Methods that calls currently reach.
+    replaced: UnsafeCell<Box<[usize]>>,
+}
+
+unsafe impl Send for PageGuard {}
+
+#[derive(Default)]
+struct State {
+    /// Guarded pages with the number of hooked methods on them and their protection.
+    pages: HashMap<usize, (usize, RawProtection)>,
+}
+
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+
+thread_local! {
+    /// Guarded page that the thread is stepping through.
+    static STEPPING: std::cell::Cell<Option<usize>> = const { std::cell::Cell::new(None) };
+}
+
+fn with_state<R>(f: impl FnOnce(&mut State) -> R) -> R {
+    let mut state: MutexGuard<'static, Option<State>> = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    f(state.get_or_insert_with(State::default))
+}
+
+fn page_of(address: usize) -> usize {
+    address & !(sys::page_size() - 1)
+}
+
+/// Marks the page containing `address` with `PAGE_GUARD`.
+unsafe fn guard(address: usize) {
+    let page = page_of(address);
+    with_state(|state| {
+        let (count, raw) = state.pages.entry(page).or_insert((0, 0));
+        if *count == 0 {
+            *raw = sys::query_protection(page).expect("failed to query page protection");
+            sys::protect_raw(page, 1, *raw | PAGE_GUARD).expect("failed to guard page");
+        }
+        *count += 1;
+    });
+}
+
+/// Removes `PAGE_GUARD` from the page containing `address` once no hooked method uses it.
+unsafe fn unguard(address: usize) {
+    let page = page_of(address);
+    with_state(|state| {
+        if let Some((count, raw)) = state.pages.get_mut(&page) {
+            *count -= 1;
+            if *count == 0 {
+                let _ = sys::protect_raw(page, 1, *raw);
+                state.pages.remove(&page);
+            }
+        }
+    });
+}
+
+/// Re-arms `PAGE_GUARD` on a page after the system cleared it, returning `false` if it isn't ours.
+unsafe fn rearm(page: usize) -> bool {
+    with_state(|state| match state.pages.get(&page) {
+        Some(&(_, raw)) => {
+            let _ = sys::protect_raw(page, 1, raw | PAGE_GUARD);
+            true
+        }
+        None => false,
+    })
+}
+
+/// Handles guard page violations and the single steps used to re-arm the guard.
+pub(crate) unsafe fn handle(record: &EXCEPTION_RECORD, context: &mut CONTEXT) -> bool {
+    match record.ExceptionCode {
+        STATUS_GUARD_PAGE_VIOLATION => {
+            if veh::enter(context) {
+                return rearm(page_of(record.ExceptionInformation[1]));
+            }
+            let page = page_of(record.ExceptionInformation[1]);
+            if !with_state(|state| state.pages.contains_key(&page)) {
+                return false;
+            }
+            STEPPING.set(Some(page));
+            veh::set_trap_flag(context, true);
+            true
+        }
+        STATUS_SINGLE_STEP => {
+            let Some(page) = STEPPING.get() else {
+                return false;
+            };
+            if veh::enter(context) {
+                STEPPING.set(None);
+                rearm(page);
+                veh::set_trap_flag(context, false);
+            } else if page_of(*veh::ip(context)) == page {
+                // Still running on the unguarded page.
+                veh::set_trap_flag(context, true);
+            } else {
+                STEPPING.set(None);
+                rearm(page);
+            }
+            true
+        }
+        _ => false,
+    }
+}
+
+impl PageGuard {
+    #[allow(clippy::mut_from_ref)]
+    fn replaced_mut(&self) -> &mut [usize] {
+        unsafe { &mut *self.replaced.get() }
+    }
+}
+
+impl Drop for PageGuard {
+    fn drop(&mut self) {
+        unsafe { self.restore_all() };
+    }
+}
+
+unsafe impl HookBackend for PageGuard {
+    unsafe fn install(_vptr: *mut *const usize, vtable: *const usize, count: usize) -> Self {
+        veh::acquire();
+        let original_vtbl = std::slice::from_raw_parts(vtable, count);
+
+        Self {
+            original_vtbl,
+            replaced: UnsafeCell::new(original_vtbl.into()),
+        }
+    }
+
+    unsafe fn uninstall(&mut self, _vptr: *mut *const usize) {}
+
+    unsafe fn is_installed(&self, vptr: *const *const usize) -> bool {
+        std::ptr::eq(*vptr, self.original_vtbl.as_ptr())
+    }
+
+    fn count(&self) -> usize {
+        self.original_vtbl.len()
+    }
+
+    fn original(&self, id: usize) -> usize {
+        self.original_vtbl[id]
+    }
+
+    fn replaced(&self, id: usize) -> usize {
+        self.replaced_mut()[id]
+    }
+
+    unsafe fn replace(&self, id: usize, func: usize) {
+        let target = self.original_vtbl[id];
+        let replaced = &mut self.replaced_mut()[id];
+        if *replaced != target {
+            unguard(target);
+            veh::remove(target);
+        }
+        if func != target {
+            veh::redirect(target, func);
+            guard(target);
+        }
+        *replaced = func;
+    }
+}
@@ -0,0 +1,118 @@
+use std::cell::RefCell;
+use std::sync::{Arc, Mutex, Weak};
+
+use super::HookBackend;
+use crate::allocations;
+use crate::shadow::{ShadowTable, VTableCopyOptions};
+
+/// Shared shadow VTables by original VTable and method count.
+static SHARED: Mutex<Vec<(usize, usize, Weak<ShadowTable>)>> = Mutex::new(Vec::new());
+
+/// Returns the shared copy of the `count` methods of `vtable`, allocating it if no hook uses it yet.
+unsafe fn shared_table(vtable: *const usize, count: usize) -> Arc<ShadowTable> {
+    let mut shared = SHARED.lock().unwrap_or_else(|e| e.into_inner());
+    shared.retain(|(_, _, table)| table.strong_count() != 0);
+    let existing = shared
+        .iter()
+        .find(|&&(original, len, _)| original == vtable as usize && len == count)
+        .and_then(|(_, _, table)| table.upgrade());
+    existing.unwrap_or_else(|| {
+        let table = Arc::new(
+            ShadowTable::new(vtable, count, &VTableCopyOptions::default()).expect("failed to allocate vtable"),
+        );
+        shared.push((vtable as usize, count, Arc::downgrade(&table)));
+        table
+    })
+}
+
+/// Copies the VTable like [`CopySwap`](super::CopySwap), sharing one copy between every object hooked
+/// with the same original VTable and method count.
+///
+/// Hooking hundreds of instances of a class takes a single allocation. Replacements apply to every
+/// object sharing the copy, which is freed when the last of their hooks is dropped.
+/// An object that needs replacements of its own gets a private copy with [`SharedCopySwap::fork`],
+/// while the others keep sharing.
+///
+/// # Panics
+///
+/// Installing panics if the copy can't be allocated.
+pub struct SharedCopySwap {
+    /// Pointer the object held before it was hooked, reaching the words around the methods too.
+    vtable: *const usize,
+    /// Pointer to the original VTable.
+    original_vtbl: &'static [usize],
+    /// Copy shared with the other hooks of the VTable, or private once forked.
+    new_vtbl: RefCell<Arc<ShadowTable>>,
+}
+
+impl SharedCopySwap {
+    /// Returns the number of hooks sharing the copy, this one included.
+    pub fn sharers(&self) -> usize {
+        Arc::strong_count(&self.new_vtbl.borrow())
+    }
+
+    /// Returns `true` if other hooks share the copy.
+    pub fn is_shared(&self) -> bool {
+        self.sharers() > 1
+    }
+
+    /// Gives the object at `vptr` a private copy of the shared VTable, replacements included,
+    /// so that later replacements only affect it. Does nothing if the copy isn't shared.
+    pub unsafe fn fork(&self, vptr: *mut *const usize) {
+        if !self.is_shared() {
+            return;
+        }
+        let shared = self.new_vtbl.borrow().clone();
+        let private = ShadowTable::new(shared.as_ptr(), shared.methods().len(), &VTableCopyOptions::default())
+            .expect("failed to allocate vtable");
+        *vptr = private.as_ptr();
+        allocations::set_owner(private.as_ptr() as usize, vptr as usize);
+        *self.new_vtbl.borrow_mut() = Arc::new(private);
+    }
+}
+
+// The original VTable is only read, and the copy is shared through an `Arc`.
+unsafe impl Send for SharedCopySwap {}
+
+unsafe impl HookBackend for SharedCopySwap {
+    unsafe fn install(vptr: *mut *const usize, vtable: *const usize, count: usize) -> Self {
+        let original_vtbl = std::slice::from_raw_parts(vtable, count);
+        let new_vtbl = shared_table(vtable, count);
+
+        *vptr = new_vtbl.as_ptr();
+
+        Self {
+            vtable,
+            original_vtbl,
+            new_vtbl: RefCell::new(new_vtbl),
+        }
+    }
+
+    unsafe fn uninstall(&mut self, vptr: *mut *const usize) {
+        *vptr = self.vtable;
+    }
+
+    unsafe fn is_installed(&self, vptr: *const *const usize) -> bool {
+        *vptr == self.new_vtbl.borrow().as_ptr()
+    }
+
+    fn count(&self) -> usize {
+        self.original_vtbl.len()
+    }
+
+    fn original(&self, id: usize) -> usize {
+        self.original_vtbl[id]
+    }
+
+    fn replaced(&self, id: usize) -> usize {
+        self.new_vtbl.borrow().methods()[id]
+    }
+
+    unsafe fn replace(&self, id: usize, func: usize) {
+        self.new_vtbl.borrow().write(id, &[func]).expect("failed to patch vtable");
+    }
+
+    unsafe fn restore_all(&self) {
+        self.new_vtbl.borrow().write(0, self.original_vtbl).expect("failed to patch vtable");
+    }
+}
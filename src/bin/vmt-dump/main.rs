@@ -0,0 +1,122 @@
+//! Dumps a VTable of another process or of a minidump.
+//!
+//! ```text
+//! vmt-dump (--pid <id> | --minidump <file>) (--vtable <address> | --class <name> [--module <name>])
+//!          [--count <methods>] [--format text|json|ida|ghidra]
+//! ```
+
+use std::process::ExitCode;
+
+use vmt_hook::dump::{Memory, TableDump};
+use vmt_hook::rtti;
+
+mod minidump;
+#[cfg(target_os = "linux")]
+mod process;
+
+const USAGE: &str = "usage: vmt-dump (--pid <id> | --minidump <file>) (--vtable <address> | --class <name> \
+                     [--module <name>]) [--count <methods>] [--format text|json|ida|ghidra]";
+
+#[derive(Default)]
+struct Options {
+    pid: Option<u32>,
+    minidump: Option<String>,
+    vtable: Option<usize>,
+    class: Option<String>,
+    module: Option<String>,
+    count: Option<usize>,
+    format: Option<String>,
+}
+
+fn parse_number(text: &str) -> Result<usize, String> {
+    let parsed = match text.strip_prefix("0x").or_else(|| text.strip_prefix("0X")) {
+        Some(hex) => usize::from_str_radix(hex, 16),
+        None => text.parse(),
+    };
+    parsed.map_err(|_| format!("'{text}' isn't a number"))
+}
+
+fn parse_options(mut args: impl Iterator<Item = String>) -> Result<Options, String> {
+    let mut options = Options::default();
+    while let Some(flag) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{flag} needs a value"));
+        match flag.as_str() {
+            "--pid" => options.pid = Some(parse_number(&value()?)? as u32),
+            "--minidump" => options.minidump = Some(value()?),
+            "--vtable" => options.vtable = Some(parse_number(&value()?)?),
+            "--class" => options.class = Some(value()?),
+            "--module" => options.module = Some(value()?),
+            "--count" => options.count = Some(parse_number(&value()?)?),
+            "--format" => options.format = Some(value()?),
+            "--help" | "-h" => return Err(USAGE.to_owned()),
+            _ => return Err(format!("unknown argument '{flag}'\n{USAGE}")),
+        }
+    }
+    Ok(options)
+}
+
+fn open(options: &Options) -> Result<Box<dyn Memory>, String> {
+    match (options.pid, &options.minidump) {
+        (Some(pid), None) => open_process(pid),
+        (None, Some(path)) => Ok(Box::new(minidump::Minidump::open(path).map_err(|e| format!("{path}: {e}"))?)),
+        _ => Err(format!("either --pid or --minidump is needed\n{USAGE}")),
+    }
+}
+
+#[cfg(windows)]
+fn open_process(pid: u32) -> Result<Box<dyn Memory>, String> {
+    let process = vmt_hook::remote::Process::open(pid).map_err(|e| format!("process {pid}: {e}"))?;
+    Ok(Box::new(process))
+}
+
+#[cfg(target_os = "linux")]
+fn open_process(pid: u32) -> Result<Box<dyn Memory>, String> {
+    Ok(Box::new(process::ProcessMemory::open(pid).map_err(|e| format!("process {pid}: {e}"))?))
+}
+
+#[cfg(not(any(windows, target_os = "linux")))]
+fn open_process(_pid: u32) -> Result<Box<dyn Memory>, String> {
+    Err("attaching to processes isn't supported on this platform".to_owned())
+}
+
+/// Finds the VTable of `class` in the modules of `memory` matching `module`.
+fn find_class(memory: &dyn Memory, class: &str, module: Option<&str>) -> Result<usize, String> {
+    let modules = memory.modules().map_err(|e| format!("failed to list modules: {e}"))?;
+    modules
+        .iter()
+        .filter(|mapped| module.is_none_or(|module| mapped.name.eq_ignore_ascii_case(module)))
+        .find_map(|mapped| rtti::find_vtable_in(memory, mapped, class))
+        .ok_or_else(|| format!("no vtable for {class} in {}", module.unwrap_or("any module")))
+}
+
+fn run(options: Options) -> Result<String, String> {
+    let memory = open(&options)?;
+    let vtable = match (options.vtable, &options.class) {
+        (Some(vtable), None) => vtable,
+        (None, Some(class)) => find_class(memory.as_ref(), class, options.module.as_deref())?,
+        _ => return Err(format!("either --vtable or --class is needed\n{USAGE}")),
+    };
+    let mut dump = TableDump::read_from(memory.as_ref(), vtable, options.count)
+        .map_err(|e| format!("failed to read the vtable at {vtable:#x}: {e}"))?;
+    dump.class = options.class.map(|class| rtti::demangle(&class).unwrap_or(class));
+    match options.format.as_deref().unwrap_or("text") {
+        "text" => Ok(dump.to_string()),
+        "json" => Ok(dump.export_json() + "\n"),
+        "ida" => Ok(dump.ida_script()),
+        "ghidra" => Ok(dump.ghidra_script()),
+        format => Err(format!("unknown format '{format}'")),
+    }
+}
+
+fn main() -> ExitCode {
+    match parse_options(std::env::args().skip(1)).and_then(run) {
+        Ok(output) => {
+            print!("{output}");
+            ExitCode::SUCCESS
+        }
+        Err(error) => {
+            eprintln!("vmt-dump: {error}");
+            ExitCode::FAILURE
+        }
+    }
+}
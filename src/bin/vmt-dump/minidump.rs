@@ -0,0 +1,137 @@
+//! Memory captured in a Windows minidump.
+
+use std::io;
+
+use vmt_hook::dump::{MappedModule, Memory};
+
+const SIGNATURE: &[u8; 4] = b"MDMP";
+const MODULE_LIST_STREAM: u32 = 4;
+const MEMORY_LIST_STREAM: u32 = 5;
+const MEMORY64_LIST_STREAM: u32 = 9;
+/// Size of a `MINIDUMP_MODULE`.
+const MODULE_SIZE: usize = 108;
+
+/// A minidump read into memory.
+pub struct Minidump {
+    data: Vec<u8>,
+    /// Address, file offset and size of each captured range.
+    ranges: Vec<(usize, usize, usize)>,
+    modules: Vec<MappedModule>,
+}
+
+fn invalid(what: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, format!("invalid minidump: {what}"))
+}
+
+impl Minidump {
+    pub fn open(path: &str) -> io::Result<Self> {
+        let data = std::fs::read(path)?;
+        if data.get(..4) != Some(SIGNATURE) {
+            return Err(invalid("bad signature"));
+        }
+        let mut dump = Self { data, ranges: Vec::new(), modules: Vec::new() };
+        let (count, directory) = (dump.u32(8)? as usize, dump.u32(12)? as usize);
+        for id in 0..count {
+            let entry = directory + id * 12;
+            let (kind, location) = (dump.u32(entry)?, dump.u32(entry + 8)? as usize);
+            match kind {
+                MEMORY_LIST_STREAM => dump.read_memory_list(location)?,
+                MEMORY64_LIST_STREAM => dump.read_memory64_list(location)?,
+                _ => {}
+            }
+        }
+        for id in 0..count {
+            let entry = directory + id * 12;
+            if dump.u32(entry)? == MODULE_LIST_STREAM {
+                dump.read_module_list(dump.u32(entry + 8)? as usize)?;
+            }
+        }
+        Ok(dump)
+    }
+
+    fn bytes(&self, offset: usize, size: usize) -> io::Result<&[u8]> {
+        let end = offset.checked_add(size).ok_or_else(|| invalid("offset overflow"))?;
+        self.data.get(offset..end).ok_or_else(|| invalid("truncated"))
+    }
+
+    fn u32(&self, offset: usize) -> io::Result<u32> {
+        Ok(u32::from_le_bytes(self.bytes(offset, 4)?.try_into().unwrap()))
+    }
+
+    fn u64(&self, offset: usize) -> io::Result<u64> {
+        Ok(u64::from_le_bytes(self.bytes(offset, 8)?.try_into().unwrap()))
+    }
+
+    fn address(&self, offset: usize) -> io::Result<usize> {
+        usize::try_from(self.u64(offset)?).map_err(|_| invalid("address beyond the pointer width"))
+    }
+
+    /// Reads a `MINIDUMP_MEMORY_LIST`, each range stored at its own offset.
+    fn read_memory_list(&mut self, location: usize) -> io::Result<()> {
+        for id in 0..self.u32(location)? as usize {
+            let descriptor = location + 4 + id * 16;
+            let (start, size) = (self.address(descriptor)?, self.u32(descriptor + 8)? as usize);
+            self.ranges.push((start, self.u32(descriptor + 12)? as usize, size));
+        }
+        Ok(())
+    }
+
+    /// Reads a `MINIDUMP_MEMORY64_LIST`, the ranges of full dumps stored one after another.
+    fn read_memory64_list(&mut self, location: usize) -> io::Result<()> {
+        let mut offset = self.address(location + 8)?;
+        for id in 0..self.address(location)? {
+            let descriptor = location + 16 + id * 16;
+            let (start, size) = (self.address(descriptor)?, self.address(descriptor + 8)?);
+            self.ranges.push((start, offset, size));
+            offset += size;
+        }
+        Ok(())
+    }
+
+    /// Reads a `MINIDUMP_MODULE_LIST`, keeping the captured parts of each image.
+    fn read_module_list(&mut self, location: usize) -> io::Result<()> {
+        for id in 0..self.u32(location)? as usize {
+            let module = location + 4 + id * MODULE_SIZE;
+            let (base, size) = (self.address(module)?, self.u32(module + 8)? as usize);
+            let name = self.u32(module + 20)? as usize;
+            let units: Vec<u16> = self
+                .bytes(name + 4, self.u32(name)? as usize)?
+                .chunks_exact(2)
+                .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+                .collect();
+            let path = String::from_utf16_lossy(&units);
+            let image = base..base + size;
+            let ranges = self
+                .ranges
+                .iter()
+                .map(|&(start, _, len)| start.max(image.start)..(start + len).min(image.end))
+                .filter(|range| !range.is_empty())
+                .collect();
+            let name = path.rsplit(['\\', '/']).next().unwrap_or(&path).to_owned();
+            self.modules.push(MappedModule { name, base, ranges });
+        }
+        Ok(())
+    }
+}
+
+impl Memory for Minidump {
+    fn read(&self, mut address: usize, mut buffer: &mut [u8]) -> io::Result<()> {
+        // A read may span ranges captured next to each other.
+        while !buffer.is_empty() {
+            let &(start, offset, size) = self
+                .ranges
+                .iter()
+                .find(|&&(start, _, size)| (start..start + size).contains(&address))
+                .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, format!("{address:#x} isn't in the minidump")))?;
+            let len = buffer.len().min(start + size - address);
+            buffer[..len].copy_from_slice(self.bytes(offset + (address - start), len)?);
+            buffer = &mut buffer[len..];
+            address += len;
+        }
+        Ok(())
+    }
+
+    fn modules(&self) -> io::Result<Vec<MappedModule>> {
+        Ok(self.modules.clone())
+    }
+}
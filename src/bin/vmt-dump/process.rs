@@ -0,0 +1,83 @@
+//! Memory of a live process, read through procfs.
+
+use std::fs::File;
+use std::io;
+use std::ops::Range;
+use std::os::unix::fs::FileExt;
+use std::sync::OnceLock;
+
+use vmt_hook::dump::{MappedModule, Memory};
+
+/// A process whose memory is read through `/proc/<id>/mem`, which needs the rights to trace it.
+pub struct ProcessMemory {
+    id: u32,
+    memory: File,
+    /// Executable mappings, read once; `None` if the maps couldn't be read.
+    executable: OnceLock<Option<Vec<Range<usize>>>>,
+}
+
+/// A line of `/proc/<id>/maps`.
+struct Mapping<'a> {
+    range: Range<usize>,
+    permissions: &'a str,
+    path: &'a str,
+}
+
+fn parse_mapping(line: &str) -> Option<Mapping<'_>> {
+    let fields: Vec<&str> = line.splitn(6, ' ').collect();
+    let [range, permissions, _, _, _, path] = fields[..] else {
+        return None;
+    };
+    let (start, end) = range.split_once('-')?;
+    let range = usize::from_str_radix(start, 16).ok()?..usize::from_str_radix(end, 16).ok()?;
+    Some(Mapping { range, permissions, path: path.trim_start() })
+}
+
+impl ProcessMemory {
+    pub fn open(id: u32) -> io::Result<Self> {
+        Ok(Self { id, memory: File::open(format!("/proc/{id}/mem"))?, executable: OnceLock::new() })
+    }
+
+    fn maps(&self) -> io::Result<String> {
+        std::fs::read_to_string(format!("/proc/{}/maps", self.id))
+    }
+}
+
+impl Memory for ProcessMemory {
+    fn read(&self, address: usize, buffer: &mut [u8]) -> io::Result<()> {
+        self.memory.read_exact_at(buffer, address as u64)
+    }
+
+    /// Groups the file mappings of `/proc/<id>/maps` by file.
+    fn modules(&self) -> io::Result<Vec<MappedModule>> {
+        let maps = self.maps()?;
+        let mut modules: Vec<(&str, MappedModule)> = Vec::new();
+        for Mapping { range, permissions, path } in maps.lines().filter_map(parse_mapping) {
+            if !path.starts_with('/') {
+                continue;
+            }
+            let module = match modules.iter().position(|&(known, _)| known == path) {
+                Some(id) => &mut modules[id].1,
+                None => {
+                    let name = path.rsplit('/').next().unwrap_or(path).to_owned();
+                    modules.push((path, MappedModule { name, base: range.start, ranges: Vec::new() }));
+                    &mut modules.last_mut().unwrap().1
+                }
+            };
+            module.base = module.base.min(range.start);
+            if permissions.starts_with('r') {
+                module.ranges.push(range);
+            }
+        }
+        Ok(modules.into_iter().map(|(_, module)| module).collect())
+    }
+
+    fn is_executable(&self, address: usize) -> Option<bool> {
+        let executable = self.executable.get_or_init(|| {
+            let maps = self.maps().ok()?;
+            let mappings = maps.lines().filter_map(parse_mapping);
+            Some(mappings.filter(|mapping| mapping.permissions.contains('x')).map(|mapping| mapping.range).collect())
+        });
+        Some(executable.as_ref()?.iter().any(|range| range.contains(&address)))
+    }
+}
@@ -0,0 +1,134 @@
+//! Building artificial objects with VTables entirely in Rust.
+//!
+//! [`VTableBuilder`] lays out a VTable whose slots point at provided functions and an object whose
+//! vptr points at it, so hook logic can be exercised on any platform without a real COM or C++ object.
+
+use std::ffi::c_void;
+
+use crate::slot::FnPtr;
+
+/// Builds a [`SyntheticObject`] slot by slot.
+///
+/// The VTable gets the words of the platform ABI before the first method, zeroed unless set with
+/// [`prefix`](Self::prefix), and a null terminator after the last method, so the method count of
+/// [`VTableHook::new`](crate::VTableHook::new) is detected.
+#[derive(Debug, Clone)]
+pub struct VTableBuilder {
+    prefix: Vec<usize>,
+    methods: Vec<usize>,
+    fields: usize,
+}
+
+impl Default for VTableBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl VTableBuilder {
+    /// Creates a builder for a VTable without methods and an object without fields.
+    pub fn new() -> Self {
+        let words = if cfg!(windows) { crate::shadow::MSVC_PREFIX } else { crate::shadow::ITANIUM_PREFIX };
+        Self {
+            prefix: vec![0; words],
+            methods: Vec::new(),
+            fields: 0,
+        }
+    }
+
+    /// Appends a method at the function address `func`.
+    pub fn method(mut self, func: usize) -> Self {
+        self.methods.push(func);
+        self
+    }
+
+    /// Appends a method.
+    pub fn function<F: FnPtr>(self, func: F) -> Self {
+        self.method(func.to_address())
+    }
+
+    /// Appends `count` methods all at the function address `func`, e.g. a stub panicking when called.
+    pub fn methods(mut self, count: usize, func: usize) -> Self {
+        self.methods.extend(std::iter::repeat_n(func, count));
+        self
+    }
+
+    /// Sets the words before the first method, such as an RTTI pointer last.
+    pub fn prefix(mut self, words: &[usize]) -> Self {
+        self.prefix = words.to_vec();
+        self
+    }
+
+    /// Gives the object `words` zeroed words of fields after its vptr.
+    pub fn fields(mut self, words: usize) -> Self {
+        self.fields = words;
+        self
+    }
+
+    /// Builds the VTable and an object pointing at it.
+    pub fn build(self) -> SyntheticObject {
+        let mut table = self.prefix.clone();
+        table.extend_from_slice(&self.methods);
+        table.push(0);
+        let table = table.into_boxed_slice();
+
+        let mut object = vec![0; 1 + self.fields].into_boxed_slice();
+        object[0] = table[self.prefix.len()..].as_ptr() as usize;
+        SyntheticObject {
+            object,
+            table,
+            prefix: self.prefix.len(),
+        }
+    }
+}
+
+/// An object built by a [`VTableBuilder`], owning its VTable.
+pub struct SyntheticObject {
+    /// The vptr, then the fields.
+    object: Box<[usize]>,
+    /// The prefix, the methods and the null terminator.
+    table: Box<[usize]>,
+    prefix: usize,
+}
+
+impl SyntheticObject {
+    /// Returns the object, to be hooked or passed to code calling its methods.
+    pub fn as_ptr(&self) -> *mut c_void {
+        self.object.as_ptr() as *mut c_void
+    }
+
+    /// Returns the VTable the object was built with, at its first method.
+    pub fn vtable(&self) -> *const usize {
+        self.table[self.prefix..].as_ptr()
+    }
+
+    /// Returns the VTable the object's vptr currently points at.
+    pub fn current_vtable(&self) -> *const usize {
+        self.object[0] as *const usize
+    }
+
+    /// Returns the number of methods.
+    pub fn len(&self) -> usize {
+        self.table.len() - self.prefix - 1
+    }
+
+    /// Returns `true` if the VTable has no methods.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns a pointer to the fields after the vptr.
+    pub fn fields(&self) -> *mut usize {
+        self.object[1..].as_ptr() as *mut usize
+    }
+
+    /// Returns the method the object's current VTable holds at the specified index.
+    pub unsafe fn method(&self, id: usize) -> usize {
+        *self.current_vtable().add(id)
+    }
+
+    /// Returns the method the object's current VTable holds at the specified index as a function pointer.
+    pub unsafe fn get<F: FnPtr>(&self, id: usize) -> F {
+        F::from_address(self.method(id))
+    }
+}
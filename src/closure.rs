@@ -0,0 +1,213 @@
+//! Installing Rust closures as hooks (x86_64 only).
+//!
+//! Every [`ClosureHook`] generates a thunk that stores the address of its closure in a thread-local
+//! before jumping to a shim with the signature of the hooked method. The shim takes the address back
+//! first thing and calls the closure with the arguments, so any state the hook needs can be captured
+//! instead of living in globals.
+//!
+//! Closures installed with [`ClosureHook::install_guarded`] have their panics caught instead of
+//! aborting the process, and replaced by a [`Fallback`].
+
+use std::cell::Cell;
+use std::io;
+use std::marker::PhantomData;
+use std::panic::{self, AssertUnwindSafe};
+
+use crate::slot::{FnPtr, Slot};
+use crate::thunk::{Thunk, ARGUMENTS};
+use crate::{audit, observer, HookBackend, VTableHook};
+
+thread_local! {
+    /// The closure of the thunk that was entered last on this thread.
+    static CONTEXT: Cell<usize> = const { Cell::new(0) };
+}
+
+extern "C" fn set_context(context: usize) {
+    CONTEXT.set(context);
+}
+
+fn take_context() -> usize {
+    CONTEXT.replace(0)
+}
+
+/// Function pointer types a closure can be installed behind.
+///
+/// Implemented for `extern "C"` and `extern "system"` function pointers with up to 12 arguments.
+pub unsafe trait ClosureFn: FnPtr {
+    /// The closure type, `dyn Fn` of the same arguments and return type.
+    type Closure: ?Sized;
+
+    /// The return type.
+    type Output;
+
+    /// Returns the shim calling the closure of the thunk that was entered last.
+    fn shim() -> Self;
+}
+
+macro_rules! impl_closure_fn {
+    ($abi:literal: $($arg:ident),*) => {
+        unsafe impl<R: 'static, $($arg: 'static),*> ClosureFn for extern $abi fn($($arg),*) -> R {
+            type Closure = dyn Fn($($arg),*) -> R + Send + Sync;
+            type Output = R;
+
+            fn shim() -> Self {
+                #[allow(non_snake_case)]
+                extern $abi fn shim<R, $($arg),*>($($arg: $arg),*) -> R {
+                    let closure = take_context() as *const Box<dyn Fn($($arg),*) -> R + Send + Sync>;
+                    unsafe { (*closure)($($arg),*) }
+                }
+                shim::<R, $($arg),*>
+            }
+        }
+
+        unsafe impl<R, $($arg),*> GuardFn for extern $abi fn($($arg),*) -> R
+        where
+            R: Clone + Send + Sync + 'static,
+            $($arg: Copy + 'static),*
+        {
+            #[allow(non_snake_case)]
+            fn guard(
+                closure: Box<Self::Closure>,
+                original: Self,
+                fallback: Fallback<R>,
+                source: (usize, usize),
+            ) -> Box<Self::Closure> {
+                Box::new(move |$($arg),*| match panic::catch_unwind(AssertUnwindSafe(|| closure($($arg),*))) {
+                    Ok(value) => value,
+                    Err(payload) => {
+                        report(source, &*payload);
+                        match &fallback {
+                            Fallback::CallOriginal => original($($arg),*),
+                            Fallback::Return(value) => value.clone(),
+                        }
+                    }
+                })
+            }
+        }
+    };
+}
+
+macro_rules! impl_closure_fn_all {
+    ($abi:literal) => {
+        impl_closure_fn!($abi:);
+        impl_closure_fn!($abi: A0);
+        impl_closure_fn!($abi: A0, A1);
+        impl_closure_fn!($abi: A0, A1, A2);
+        impl_closure_fn!($abi: A0, A1, A2, A3);
+        impl_closure_fn!($abi: A0, A1, A2, A3, A4);
+        impl_closure_fn!($abi: A0, A1, A2, A3, A4, A5);
+        impl_closure_fn!($abi: A0, A1, A2, A3, A4, A5, A6);
+        impl_closure_fn!($abi: A0, A1, A2, A3, A4, A5, A6, A7);
+        impl_closure_fn!($abi: A0, A1, A2, A3, A4, A5, A6, A7, A8);
+        impl_closure_fn!($abi: A0, A1, A2, A3, A4, A5, A6, A7, A8, A9);
+        impl_closure_fn!($abi: A0, A1, A2, A3, A4, A5, A6, A7, A8, A9, A10);
+        impl_closure_fn!($abi: A0, A1, A2, A3, A4, A5, A6, A7, A8, A9, A10, A11);
+    };
+}
+
+impl_closure_fn_all!("C");
+impl_closure_fn_all!("system");
+
+/// Function pointer types whose closures can be guarded against panics.
+///
+/// Implemented for the [`ClosureFn`] types with `Copy` arguments and a `Clone` return type.
+pub unsafe trait GuardFn: ClosureFn {
+    /// Wraps `closure` to catch its panics, report them as coming from `source`, the object and the
+    /// slot, and use `fallback` instead.
+    fn guard(
+        closure: Box<Self::Closure>,
+        original: Self,
+        fallback: Fallback<Self::Output>,
+        source: (usize, usize),
+    ) -> Box<Self::Closure>;
+}
+
+/// What a guarded hook does after its closure panicked.
+#[derive(Debug, Clone)]
+pub enum Fallback<R> {
+    /// Calls the method the slot reached before the hook with the same arguments.
+    CallOriginal,
+    /// Returns a value, such as an error `HRESULT`.
+    Return(R),
+}
+
+/// Reports a caught panic of the hook of `(object, id)` to the observers.
+fn report((object, id): (usize, usize), payload: &(dyn std::any::Any + Send)) {
+    let message = match (payload.downcast_ref::<&str>(), payload.downcast_ref::<String>()) {
+        (Some(message), _) => message.to_string(),
+        (_, Some(message)) => message.clone(),
+        _ => "unknown panic payload".to_owned(),
+    };
+    observer::emit(object, audit::Operation::Panic { id, message });
+}
+
+/// A closure callable through a function pointer of type `F`.
+///
+/// A panic in the closure aborts the process, as it can't unwind into the caller of the hook, unless the
+/// closure was installed with [`install_guarded`](Self::install_guarded).
+pub struct ClosureHook<F: ClosureFn> {
+    thunk: Thunk,
+    _closure: Box<Box<F::Closure>>,
+    _func: PhantomData<F>,
+}
+
+impl<F: ClosureFn> ClosureHook<F> {
+    /// Generates a thunk calling `closure`.
+    ///
+    /// The thunk is freed with the hook, by the [lifetime](crate::thunk#lifetime) rule of thunks.
+    pub fn new(closure: Box<F::Closure>) -> io::Result<Self> {
+        let closure = Box::new(closure);
+        let context = &*closure as *const Box<F::Closure> as usize;
+        let shim = F::shim().to_address();
+        let thunk = unsafe {
+            Thunk::new(|code| {
+                code.call_preserving(set_context as *const () as usize, |code| {
+                    code.mov_imm(ARGUMENTS[0], context);
+                });
+                code.mov_r11_imm(shim).jmp_r11();
+            })?
+        };
+        Ok(Self {
+            thunk,
+            _closure: closure,
+            _func: PhantomData,
+        })
+    }
+
+    /// Replaces the method of `slot` in `hook` with `closure`, which can capture the original method.
+    pub unsafe fn install<T, B: HookBackend>(
+        hook: &VTableHook<T, B>,
+        slot: Slot<F>,
+        closure: Box<F::Closure>,
+    ) -> io::Result<Self> {
+        let closure = Self::new(closure)?;
+        hook.replace(slot, closure.get());
+        Ok(closure)
+    }
+
+    /// Replaces the method of `slot` in `hook` with `closure`, catching its panics.
+    ///
+    /// A panic is reported to the [`observer`]s and the [`audit`] log, then `fallback` is used for that call.
+    pub unsafe fn install_guarded<T, B: HookBackend>(
+        hook: &VTableHook<T, B>,
+        slot: Slot<F>,
+        closure: Box<F::Closure>,
+        fallback: Fallback<F::Output>,
+    ) -> io::Result<Self>
+    where
+        F: GuardFn,
+    {
+        let source = (hook.vptr() as usize, slot.index());
+        Self::install(hook, slot, F::guard(closure, hook.get_replaced(slot), fallback, source))
+    }
+
+    /// Returns the address of the thunk.
+    pub fn address(&self) -> usize {
+        self.thunk.address()
+    }
+
+    /// Returns the thunk as a function pointer.
+    pub fn get(&self) -> F {
+        unsafe { F::from_address(self.address()) }
+    }
+}
@@ -0,0 +1,109 @@
+//! Handling of .NET COM-callable wrappers (CCWs).
+//!
+//! The VTables of a CCW are generated by the CLR: `IUnknown` points into the runtime module and the
+//! interface methods point at stubs in the runtime's stub heaps. They aren't null-terminated, and the
+//! CLR back-patches the stubs once a method is jitted, so a copied VTable can go stale.
+//! [`CcwHook`] counts methods up to the first entry that isn't executable code, which the stubs and runtime
+//! methods are and the data following the table usually isn't, and picks up slots the runtime rewrites.
+
+use crate::{detect, sys, VTableHook};
+
+/// Counts the methods at the start of a buffer the way [`count_ccw_methods`] does, for tables read out of
+/// another process or a dump.
+pub use crate::detect::code_count_in as count_ccw_methods_in;
+
+/// Modules of the .NET runtimes that generate CCWs.
+const RUNTIME_MODULES: [(&str, ClrRuntime); 3] = [
+    ("coreclr.dll", ClrRuntime::Core),
+    ("clr.dll", ClrRuntime::Framework),
+    ("mscorwks.dll", ClrRuntime::Framework),
+];
+
+/// The .NET runtime that created a CCW.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ClrRuntime {
+    /// .NET Framework.
+    Framework,
+    /// .NET Core and .NET 5+.
+    Core,
+}
+
+/// Returns the runtime that created the object if it's a COM-callable wrapper.
+///
+/// An object is a CCW when its `QueryInterface` is implemented by a loaded .NET runtime module.
+pub unsafe fn ccw_runtime<T>(object: *const T) -> Option<ClrRuntime> {
+    let object = object as usize;
+    if !sys::is_readable(object, std::mem::size_of::<usize>()) {
+        return None;
+    }
+    let vtable = *(object as *const usize);
+    if !sys::is_readable(vtable, std::mem::size_of::<usize>()) {
+        return None;
+    }
+    let module = sys::module_of(*(vtable as *const usize))?;
+    RUNTIME_MODULES
+        .iter()
+        .find(|(name, _)| sys::module_handle(name) == Some(module))
+        .map(|&(_, runtime)| runtime)
+}
+
+/// Returns `true` if the object is a COM-callable wrapper.
+pub unsafe fn is_ccw<T>(object: *const T) -> bool {
+    ccw_runtime(object).is_some()
+}
+
+/// Counts the entries of the VTable at `vtable` up to the first one that isn't executable code, with
+/// [`detect::probe_code_count`].
+///
+/// The stub heaps themselves aren't consulted: an adjacent table of code pointers is counted along, so pass
+/// the count to [`CcwHook::with_count`] when the interface is known.
+pub unsafe fn count_ccw_methods(vtable: *const usize) -> usize {
+    detect::probe_code_count(vtable)
+}
+
+/// A [`VTableHook`] for COM-callable wrappers that follows the runtime's rewrites of its stubs.
+pub struct CcwHook<T> {
+    hook: VTableHook<T>,
+    /// Original methods as copied into the hooked VTable.
+    snapshot: Vec<usize>,
+}
+
+impl<T> CcwHook<T> {
+    /// Hooks the CCW. The count of methods is determined by [`count_ccw_methods`] instead of a null terminator.
+    pub unsafe fn new(object: T) -> Self {
+        let vtable = *std::mem::transmute_copy::<T, *const *const usize>(&object);
+        Self::with_count(object, count_ccw_methods(vtable))
+    }
+
+    /// Hooks the CCW with a specified method count.
+    pub unsafe fn with_count(object: T, count: usize) -> Self {
+        let hook = VTableHook::with_count(object, count);
+        let snapshot = (0..count).map(|id| hook.get_original_method(id)).collect();
+        Self { hook, snapshot }
+    }
+
+    /// Copies the methods the runtime rewrote in the original VTable into the hooked one.
+    ///
+    /// Replaced methods are left alone; their originals are always read from the live table.
+    /// Returns the indices of the methods that changed.
+    pub unsafe fn refresh(&mut self) -> Vec<usize> {
+        let mut changed = Vec::new();
+        for (id, snapshot) in self.snapshot.iter_mut().enumerate() {
+            let original = self.hook.get_original_method(id);
+            if original == *snapshot {
+                continue;
+            }
+            if self.hook.get_replaced_method(id) == *snapshot {
+                self.hook.replace_method(id, original);
+            }
+            *snapshot = original;
+            changed.push(id);
+        }
+        changed
+    }
+
+    /// Returns the underlying hook.
+    pub fn hook(&self) -> &VTableHook<T> {
+        &self.hook
+    }
+}
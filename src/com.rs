@@ -0,0 +1,395 @@
+//! Hooking COM objects from outside their apartment.
+//!
+//! Objects living in a single-threaded apartment expect to be touched only by the apartment's thread.
+//! [`Apartment`] runs code on that thread, either through the object context captured with
+//! [`ObjectContext::current`] or by posting to the thread's message queue, and [`ApartmentHook`]
+//! uses it to swap and restore the VTable pointer there. [`InterfaceHooks`] hooks every interface of an
+//! object at once.
+
+use std::ffi::c_void;
+use std::io;
+use std::sync::mpsc;
+use std::sync::OnceLock;
+use std::time::{Duration, Instant};
+
+use windows_sys::core::{GUID, HRESULT};
+use windows_sys::Win32::Foundation::{CloseHandle, LPARAM, LRESULT, S_OK, WAIT_OBJECT_0, WPARAM};
+use windows_sys::Win32::System::Com::{CoGetObjectContext, ComCallData};
+use windows_sys::Win32::System::Threading::{GetCurrentThreadId, OpenThread, WaitForSingleObject, THREAD_SYNCHRONIZE};
+use windows_sys::Win32::UI::WindowsAndMessaging::{
+    CallNextHookEx, PostThreadMessageW, RegisterWindowMessageW, SetWindowsHookExW, UnhookWindowsHookEx, MSG, PM_REMOVE,
+    WH_GETMESSAGE, WM_NULL,
+};
+
+use crate::slot::FnPtr;
+use crate::VTableHook;
+
+const IID_ICONTEXT_CALLBACK: GUID = GUID::from_u128(0x000001da_0000_0000_c000_000000000046);
+const IID_ICALLBACK_WITH_NO_REENTRANCY_TO_APPLICATION_STA: GUID =
+    GUID::from_u128(0x0a299774_3e4e_fc42_1d9d_72cee105ca57);
+
+const RELEASE: usize = 2;
+const CONTEXT_CALLBACK: usize = 3;
+
+/// How often a thread-routed call checks that the target thread is still alive.
+const POLL_INTERVAL: Duration = Duration::from_millis(50);
+
+/// How long a thread-routed call waits for the target thread to retrieve it.
+const CALL_TIMEOUT: Duration = Duration::from_secs(10);
+
+type QueryInterfaceFn = unsafe extern "system" fn(*mut c_void, *const GUID, *mut *mut c_void) -> HRESULT;
+type ReleaseFn = unsafe extern "system" fn(*mut c_void) -> u32;
+type ContextCallbackFn = unsafe extern "system" fn(
+    *mut c_void,
+    Option<unsafe extern "system" fn(*mut ComCallData) -> HRESULT>,
+    *mut ComCallData,
+    *const GUID,
+    i32,
+    *mut c_void,
+) -> HRESULT;
+
+/// Work sent to another apartment.
+type Job = Box<dyn FnOnce() + Send>;
+
+/// Moves a value that isn't `Send`, such as an interface pointer, to the apartment owning it.
+struct AssertSend<T>(T);
+
+unsafe impl<T> Send for AssertSend<T> {}
+
+impl<T> AssertSend<T> {
+    fn into_inner(self) -> T {
+        self.0
+    }
+}
+
+/// The COM object context of a thread, through which code can be run in its apartment.
+pub struct ObjectContext {
+    /// `IContextCallback` of the context.
+    callback: *mut c_void,
+}
+
+// Object contexts are agile.
+unsafe impl Send for ObjectContext {}
+unsafe impl Sync for ObjectContext {}
+
+impl Drop for ObjectContext {
+    fn drop(&mut self) {
+        unsafe {
+            let vtable = *(self.callback as *const *const usize);
+            ReleaseFn::from_address(*vtable.add(RELEASE))(self.callback);
+        }
+    }
+}
+
+impl ObjectContext {
+    /// Captures the object context of the current thread, which must have initialized COM.
+    pub fn current() -> io::Result<Self> {
+        let mut callback = std::ptr::null_mut();
+        let result = unsafe { CoGetObjectContext(&IID_ICONTEXT_CALLBACK, &mut callback) };
+        if result < 0 {
+            return Err(io::Error::from_raw_os_error(result));
+        }
+        Ok(Self { callback })
+    }
+
+    /// Runs `job` in the context, waiting for it to finish.
+    fn call(&self, job: Job) -> io::Result<()> {
+        unsafe extern "system" fn context_call(data: *mut ComCallData) -> HRESULT {
+            if let Some(job) = (*((*data).pUserDefined as *mut Option<Job>)).take() {
+                job();
+            }
+            S_OK
+        }
+
+        let mut job = Some(job);
+        let mut data = ComCallData {
+            pUserDefined: (&mut job as *mut Option<Job>).cast(),
+            ..Default::default()
+        };
+        let result = unsafe {
+            let vtable = *(self.callback as *const *const usize);
+            ContextCallbackFn::from_address(*vtable.add(CONTEXT_CALLBACK))(
+                self.callback,
+                Some(context_call),
+                &mut data,
+                &IID_ICALLBACK_WITH_NO_REENTRANCY_TO_APPLICATION_STA,
+                5,
+                std::ptr::null_mut(),
+            )
+        };
+        // A call that didn't run is leaked along with whatever it owns.
+        std::mem::forget(job);
+        if result < 0 {
+            return Err(io::Error::from_raw_os_error(result));
+        }
+        Ok(())
+    }
+}
+
+/// The apartment owning a COM object.
+pub enum Apartment {
+    /// Calls are made through the object context of the apartment.
+    Context(ObjectContext),
+    /// Calls are posted to the message queue of the apartment's thread, which must pump messages.
+    Thread(u32),
+}
+
+/// Returns the message used to post calls to other threads.
+fn call_message() -> u32 {
+    static MESSAGE: OnceLock<u32> = OnceLock::new();
+    *MESSAGE.get_or_init(|| {
+        let name: Vec<u16> = "vmt-hook apartment call\0".encode_utf16().collect();
+        unsafe { RegisterWindowMessageW(name.as_ptr()) }
+    })
+}
+
+/// Runs calls posted by [`post`] when the target thread retrieves them.
+unsafe extern "system" fn get_message(code: i32, wparam: WPARAM, lparam: LPARAM) -> LRESULT {
+    if code >= 0 && wparam == PM_REMOVE as WPARAM {
+        let msg = &mut *(lparam as *mut MSG);
+        if msg.hwnd.is_null() && msg.message == call_message() {
+            let job = Box::from_raw(msg.lParam as *mut Job);
+            msg.message = WM_NULL;
+            job();
+        }
+    }
+    CallNextHookEx(std::ptr::null_mut(), code, wparam, lparam)
+}
+
+/// Runs `job` on the thread when it next retrieves a message, waiting for it to finish.
+///
+/// A call that doesn't run is leaked along with whatever it owns, as dropping it here could run
+/// destructors that belong in the apartment.
+fn post(thread_id: u32, job: Job) -> io::Result<()> {
+    let (done, finished) = mpsc::channel();
+    let job: *mut Job = Box::into_raw(Box::new(Box::new(move || {
+        job();
+        let _ = done.send(());
+    })));
+
+    unsafe {
+        let thread = OpenThread(THREAD_SYNCHRONIZE, 0, thread_id);
+        if thread.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        let hook = SetWindowsHookExW(WH_GETMESSAGE, Some(get_message), std::ptr::null_mut(), thread_id);
+        if hook.is_null() {
+            let error = io::Error::last_os_error();
+            CloseHandle(thread);
+            return Err(error);
+        }
+
+        let result = if PostThreadMessageW(thread_id, call_message(), 0, job as LPARAM) == 0 {
+            Err(io::Error::last_os_error())
+        } else {
+            let deadline = Instant::now() + CALL_TIMEOUT;
+            loop {
+                match finished.recv_timeout(POLL_INTERVAL) {
+                    Ok(()) => break Ok(()),
+                    // The call is never run once the thread is gone.
+                    Err(_) if WaitForSingleObject(thread, 0) == WAIT_OBJECT_0 => {
+                        break Err(io::Error::other("apartment thread exited"))
+                    }
+                    Err(_) if Instant::now() >= deadline => {
+                        break Err(io::Error::new(io::ErrorKind::TimedOut, "apartment thread doesn't pump messages"))
+                    }
+                    Err(_) => {}
+                }
+            }
+        };
+
+        UnhookWindowsHookEx(hook);
+        CloseHandle(thread);
+        result
+    }
+}
+
+impl Apartment {
+    /// Returns the apartment of the current thread: its object context if COM is initialized,
+    /// otherwise the thread itself.
+    pub fn current() -> Self {
+        match ObjectContext::current() {
+            Ok(context) => Self::Context(context),
+            Err(_) => Self::Thread(unsafe { GetCurrentThreadId() }),
+        }
+    }
+
+    /// Runs `f` in the apartment and returns its result, waiting for it to finish.
+    ///
+    /// Calls from the apartment's own thread run directly. The apartment's thread must not be
+    /// waiting on the caller, or both threads deadlock. A call posted to a thread that doesn't retrieve
+    /// it within 10 seconds fails with [`io::ErrorKind::TimedOut`].
+    pub fn run<R: Send + 'static>(&self, f: impl FnOnce() -> R + Send + 'static) -> io::Result<R> {
+        if let Self::Thread(thread_id) = *self {
+            if thread_id == unsafe { GetCurrentThreadId() } {
+                return Ok(f());
+            }
+        }
+
+        let (result, received) = mpsc::channel();
+        let job: Job = Box::new(move || {
+            let _ = result.send(f());
+        });
+        match self {
+            Self::Context(context) => context.call(job)?,
+            Self::Thread(thread_id) => post(*thread_id, job)?,
+        }
+        received.try_recv().map_err(|_| io::Error::other("apartment call didn't complete"))
+    }
+}
+
+/// A [`VTableHook`] installed and removed in the apartment owning the object.
+///
+/// Replacing methods only writes to the hook's own copy of the VTable, so it can be done from any thread.
+/// If the apartment can't be reached on drop, the hook is leaked rather than restored from the wrong thread.
+pub struct ApartmentHook<T: 'static> {
+    hook: Option<VTableHook<T>>,
+    apartment: Apartment,
+}
+
+impl<T: 'static> Drop for ApartmentHook<T> {
+    /// Restoring the original VTable in the apartment.
+    fn drop(&mut self) {
+        let hook = AssertSend(self.hook.take());
+        // The hook stays installed if the call is lost.
+        let _ = self.apartment.run(move || drop(hook));
+    }
+}
+
+impl<T: 'static> ApartmentHook<T> {
+    /// Hooks the object in its apartment with a specified method count.
+    pub unsafe fn with_count(object: T, count: usize, apartment: Apartment) -> io::Result<Self> {
+        let object = AssertSend(object);
+        let hook = apartment.run(move || {
+            AssertSend(VTableHook::with_count(object.into_inner(), count))
+        })?;
+        Ok(Self {
+            hook: Some(hook.into_inner()),
+            apartment,
+        })
+    }
+
+    /// Returns the underlying hook.
+    pub fn hook(&self) -> &VTableHook<T> {
+        self.hook.as_ref().unwrap()
+    }
+
+    /// Returns the apartment the hook was installed in.
+    pub fn apartment(&self) -> &Apartment {
+        &self.apartment
+    }
+}
+
+fn same_iid(a: &GUID, b: &GUID) -> bool {
+    (a.data1, a.data2, a.data3, a.data4) == (b.data1, b.data2, b.data3, b.data4)
+}
+
+/// An interface of an [`InterfaceHooks`] object.
+struct Interface {
+    /// IIDs that returned this interface pointer.
+    iids: Vec<GUID>,
+    pointer: *mut c_void,
+    hook: Option<VTableHook<*mut c_void>>,
+}
+
+/// Hooks of several interfaces of one COM object, installed and restored together.
+///
+/// Every IID is queried through `IUnknown::QueryInterface`; IIDs returning the same interface pointer
+/// share one hook covering the largest requested method count. All hooks are restored before any of the
+/// interface references is released.
+pub struct InterfaceHooks {
+    interfaces: Vec<Interface>,
+}
+
+impl Drop for InterfaceHooks {
+    /// Restoring every original VTable, then releasing the interfaces.
+    fn drop(&mut self) {
+        for interface in &mut self.interfaces {
+            interface.hook = None;
+        }
+        for interface in &self.interfaces {
+            unsafe {
+                let vtable = *(interface.pointer as *const *const usize);
+                ReleaseFn::from_address(*vtable.add(RELEASE))(interface.pointer);
+            }
+        }
+    }
+}
+
+impl InterfaceHooks {
+    /// Queries each `(iid, method count)` pair on the object and hooks the returned interfaces.
+    ///
+    /// IIDs the object doesn't implement are skipped.
+    pub unsafe fn new(unknown: *mut c_void, interfaces: &[(GUID, usize)]) -> Self {
+        let vtable = *(unknown as *const *const usize);
+        let query_interface = QueryInterfaceFn::from_address(*vtable);
+
+        let mut found: Vec<(Interface, usize)> = Vec::new();
+        for (iid, count) in interfaces {
+            let mut pointer = std::ptr::null_mut();
+            if query_interface(unknown, iid, &mut pointer) < 0 || pointer.is_null() {
+                continue;
+            }
+            match found.iter_mut().find(|(interface, _)| interface.pointer == pointer) {
+                Some((interface, max_count)) => {
+                    let vtable = *(pointer as *const *const usize);
+                    ReleaseFn::from_address(*vtable.add(RELEASE))(pointer);
+                    interface.iids.push(*iid);
+                    *max_count = (*max_count).max(*count);
+                }
+                None => found.push((
+                    Interface {
+                        iids: vec![*iid],
+                        pointer,
+                        hook: None,
+                    },
+                    *count,
+                )),
+            }
+        }
+
+        let interfaces = found
+            .into_iter()
+            .map(|(mut interface, count)| {
+                interface.hook = Some(VTableHook::with_count(interface.pointer, count));
+                interface
+            })
+            .collect();
+        Self { interfaces }
+    }
+
+    fn find(&self, iid: &GUID) -> Option<&Interface> {
+        self.interfaces
+            .iter()
+            .find(|interface| interface.iids.iter().any(|other| same_iid(other, iid)))
+    }
+
+    /// Returns the interface pointer returned for the IID.
+    pub fn interface(&self, iid: &GUID) -> Option<*mut c_void> {
+        self.find(iid).map(|interface| interface.pointer)
+    }
+
+    /// Returns the hook of the interface returned for the IID.
+    pub fn hook(&self, iid: &GUID) -> Option<&VTableHook<*mut c_void>> {
+        self.find(iid).and_then(|interface| interface.hook.as_ref())
+    }
+
+    /// Returns the hooks of every distinct interface.
+    pub fn hooks(&self) -> impl Iterator<Item = &VTableHook<*mut c_void>> {
+        self.interfaces.iter().filter_map(|interface| interface.hook.as_ref())
+    }
+
+    /// Hooks the method at the specified index in every interface, typically one of `IUnknown`.
+    pub unsafe fn replace_in_all(&self, id: usize, func: usize) {
+        for hook in self.hooks() {
+            hook.replace_method(id, func);
+        }
+    }
+
+    /// Restores all methods of every interface to their original address.
+    pub unsafe fn restore_all_methods(&self) {
+        for hook in self.hooks() {
+            hook.restore_all_methods();
+        }
+    }
+}
@@ -0,0 +1,215 @@
+//! Support for Delphi/C++Builder classes, whose VMTs carry metadata before the first virtual method.
+//!
+//! The VMT pointer of a Delphi object points at its first user-defined virtual method; the
+//! class name, instance size, parent class and the `TObject` virtual methods are stored at
+//! negative offsets from it. [`DelphiCopySwap`] copies that metadata along with the methods,
+//! so the RTL can still read the class name, size and parent of hooked objects. The VMT pointer is
+//! also the class reference, though, so a hooked object no longer is an instance of its exact class
+//! as far as class comparisons are concerned; see [`DelphiCopySwap`].
+
+use std::cell::UnsafeCell;
+
+use crate::{sys, HookBackend};
+
+/// Slot indices of the VMT fields, relative to the VMT pointer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VmtLayout {
+    /// `vmtSelfPtr`, the first field of the VMT.
+    pub self_ptr: isize,
+    /// `vmtClassName`, a pointer to a `ShortString`.
+    pub class_name: isize,
+    /// `vmtInstanceSize`.
+    pub instance_size: isize,
+    /// `vmtParent`, a pointer to the parent's VMT pointer.
+    pub parent: isize,
+}
+
+impl VmtLayout {
+    /// Layout of Delphi 2009 and later, on both x86 and x64.
+    pub const DELPHI_2009: Self = Self {
+        self_ptr: -22,
+        class_name: -14,
+        instance_size: -13,
+        parent: -12,
+    };
+
+    /// Layout of Delphi 7 up to Delphi 2007.
+    pub const DELPHI_7: Self = Self {
+        self_ptr: -19,
+        class_name: -11,
+        instance_size: -10,
+        parent: -9,
+    };
+
+    /// Returns the layout whose `vmtSelfPtr` points back at `vmt`, if any.
+    pub unsafe fn detect(vmt: *const usize) -> Option<Self> {
+        [Self::DELPHI_2009, Self::DELPHI_7].into_iter().find(|layout| {
+            let slot = vmt.wrapping_offset(layout.self_ptr);
+            sys::is_readable(slot as usize, std::mem::size_of::<usize>()) && *slot == vmt as usize
+        })
+    }
+
+    /// Returns the number of words stored before the first virtual method.
+    pub fn prefix(&self) -> usize {
+        self.self_ptr.unsigned_abs()
+    }
+}
+
+/// A Delphi class, identified by its VMT.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Vmt {
+    vmt: *const usize,
+    layout: VmtLayout,
+}
+
+impl Vmt {
+    /// Inspects the VMT at `vmt`, detecting its layout.
+    pub unsafe fn new(vmt: *const usize) -> Option<Self> {
+        Some(Self::with_layout(vmt, VmtLayout::detect(vmt)?))
+    }
+
+    /// Inspects the VMT at `vmt` with a specified layout.
+    pub unsafe fn with_layout(vmt: *const usize, layout: VmtLayout) -> Self {
+        Self { vmt, layout }
+    }
+
+    /// Inspects the class of a Delphi object.
+    pub unsafe fn of<T>(object: *const T) -> Option<Self> {
+        Self::new(*(object as *const *const usize))
+    }
+
+    /// Returns the VMT pointer.
+    pub fn vmt(&self) -> *const usize {
+        self.vmt
+    }
+
+    /// Returns the layout of the VMT.
+    pub fn layout(&self) -> VmtLayout {
+        self.layout
+    }
+
+    unsafe fn field(&self, index: isize) -> usize {
+        *self.vmt.offset(index)
+    }
+
+    /// Returns the class name, such as `TForm1`.
+    pub unsafe fn class_name(&self) -> String {
+        let name = self.field(self.layout.class_name) as *const u8;
+        let bytes = std::slice::from_raw_parts(name.add(1), *name as usize);
+        String::from_utf8_lossy(bytes).into_owned()
+    }
+
+    /// Returns the size of an instance of the class in bytes.
+    pub unsafe fn instance_size(&self) -> usize {
+        *(self.vmt.offset(self.layout.instance_size) as *const i32) as usize
+    }
+
+    /// Returns the parent class, or `None` for `TObject`.
+    pub unsafe fn parent(&self) -> Option<Self> {
+        let parent = self.field(self.layout.parent) as *const *const usize;
+        if parent.is_null() || (*parent).is_null() {
+            return None;
+        }
+        Some(Self::with_layout(*parent, self.layout))
+    }
+
+    /// Returns `true` if the class is or inherits from the class with the given name.
+    pub unsafe fn inherits_from(&self, class_name: &str) -> bool {
+        let mut class = Some(*self);
+        while let Some(current) = class {
+            if current.class_name().eq_ignore_ascii_case(class_name) {
+                return true;
+            }
+            class = current.parent();
+        }
+        false
+    }
+
+    /// Returns the virtual method at the specified index.
+    pub unsafe fn method(&self, id: usize) -> usize {
+        *self.vmt.add(id)
+    }
+}
+
+/// Copies the VMT together with its metadata and points the object at the copy.
+///
+/// Only the hooked object is affected. `vmtSelfPtr` of the copy points at the copy.
+/// Delphi VMTs aren't null-terminated, so the method count must be specified.
+/// Falls back to copying the methods only if the layout isn't detected.
+///
+/// # Class identity
+///
+/// The class of a hooked object is the copy, not its original class. Virtual calls, `ClassName`,
+/// `InstanceSize`, `ClassParent` and tests against its ancestors keep working, since the parent of the
+/// copy is the original parent. Everything comparing the class itself with the original breaks:
+/// `obj is TExactClass` and `obj.InheritsFrom(TExactClass)` return `False`, `obj as TExactClass`
+/// raises `EInvalidCast`, and `obj.ClassType = TExactClass` fails. Use this backend only for objects
+/// the hooked code doesn't downcast to their exact class.
+pub struct DelphiCopySwap {
+    /// Pointer to the original VMT methods.
+    original_vtbl: &'static [usize],
+    /// Number of metadata words stored before the methods.
+    prefix: usize,
+    /// Metadata followed by the hooked methods.
+    new_vtbl: UnsafeCell<Box<[usize]>>,
+}
+
+impl DelphiCopySwap {
+    /// Returns our hooked VMT, metadata included.
+    #[allow(clippy::mut_from_ref)]
+    fn vtbl(&self) -> &mut [usize] {
+        unsafe { &mut *self.new_vtbl.get() }
+    }
+
+    /// Returns the VMT pointer of the hooked object.
+    fn methods(&self) -> *const usize {
+        self.vtbl()[self.prefix..].as_ptr()
+    }
+}
+
+unsafe impl HookBackend for DelphiCopySwap {
+    unsafe fn install(vptr: *mut *const usize, vtable: *const usize, count: usize) -> Self {
+        let prefix = VmtLayout::detect(vtable).map_or(0, |layout| layout.prefix());
+        let original_vtbl = std::slice::from_raw_parts(vtable, count);
+        let new_vtbl: Box<[usize]> = std::slice::from_raw_parts(vtable.sub(prefix), prefix + count).into();
+
+        let backend = Self {
+            original_vtbl,
+            prefix,
+            new_vtbl: UnsafeCell::new(new_vtbl),
+        };
+        if prefix != 0 {
+            backend.vtbl()[0] = backend.methods() as usize;
+        }
+        *vptr = backend.methods();
+        backend
+    }
+
+    unsafe fn uninstall(&mut self, vptr: *mut *const usize) {
+        *vptr = self.original_vtbl.as_ptr();
+    }
+
+    unsafe fn is_installed(&self, vptr: *const *const usize) -> bool {
+        *vptr == self.methods()
+    }
+
+    fn count(&self) -> usize {
+        self.original_vtbl.len()
+    }
+
+    fn original(&self, id: usize) -> usize {
+        self.original_vtbl[id]
+    }
+
+    fn replaced(&self, id: usize) -> usize {
+        self.vtbl()[self.prefix + id]
+    }
+
+    unsafe fn replace(&self, id: usize, func: usize) {
+        self.vtbl()[self.prefix + id] = func;
+    }
+
+    unsafe fn restore_all(&self) {
+        self.vtbl()[self.prefix..].copy_from_slice(self.original_vtbl);
+    }
+}
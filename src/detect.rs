@@ -0,0 +1,326 @@
+//! Detecting hooks installed by other tools before installing ours, and seeing through the jump
+//! stubs compilers and linkers put in front of functions.
+
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicU8, Ordering};
+
+use crate::sys;
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+use crate::x86::{decode_jump, Jump};
+
+/// A VTable that doesn't live in the read-only data of a loaded module.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ForeignVTable {
+    /// Address the object's vptr points at.
+    pub vtable: usize,
+    /// Base address of the module containing the table, `None` for heap and other anonymous memory.
+    pub module: Option<usize>,
+    /// Path of that module.
+    pub module_path: Option<PathBuf>,
+}
+
+/// Reports whether the vptr of `object` points outside the read-only sections of every loaded module,
+/// as it does once someone else has swapped in a shadow VTable.
+///
+/// Compilers emit VTables into read-only data, so a table on the heap or in writable module memory
+/// was almost certainly built at runtime. Shadow tables of our own [`VTableHook`](crate::VTableHook)s
+/// are reported as well, except those in module caves, which keep the protection of their section.
+pub unsafe fn detect_existing_hook<T>(object: *const T) -> Option<ForeignVTable> {
+    let vtable = *(object as *const usize);
+    let module = sys::module_of(vtable);
+    if module.is_some() && !sys::is_writable(vtable) {
+        return None;
+    }
+    Some(ForeignVTable {
+        vtable,
+        module: module.map(|base| base as usize),
+        module_path: module.and_then(|base| sys::module_path(base)),
+    })
+}
+
+/// Returns the version of Wine if the process runs under it, Proton included.
+///
+/// Module lookups, and with them [`detect_existing_hook`] and [`check_owner`], then ask the loader instead of
+/// `VirtualQuery`, whose region types differ from Windows. Checks whether an address in a module is executable
+/// or writable, as count detection and [`detect_existing_hook`] make them, read the module's section headers,
+/// since Wine maps some images read-write-execute in one piece. [`set_wine_mode`] overrides the detection.
+pub fn wine_version() -> Option<&'static str> {
+    #[cfg(windows)]
+    {
+        sys::wine_version()
+    }
+    #[cfg(not(windows))]
+    {
+        None
+    }
+}
+
+/// When module lookups and region checks work around Wine; see [`wine_version`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum WineMode {
+    /// When running under Wine.
+    #[default]
+    Detect,
+    /// Always, e.g. under a Wine build hiding `wine_get_version`.
+    Force,
+    /// Never, e.g. under a Wine version reporting regions like Windows.
+    Disable,
+}
+
+static WINE_MODE: AtomicU8 = AtomicU8::new(WineMode::Detect as u8);
+
+/// Sets when module lookups and region checks work around Wine. Has no effect outside Windows.
+pub fn set_wine_mode(mode: WineMode) {
+    WINE_MODE.store(mode as u8, Ordering::Relaxed);
+}
+
+/// Returns `true` if module lookups and region checks work around Wine.
+#[cfg(windows)]
+pub(crate) fn wine_compat() -> bool {
+    match WINE_MODE.load(Ordering::Relaxed) {
+        mode if mode == WineMode::Force as u8 => true,
+        mode if mode == WineMode::Disable as u8 => false,
+        _ => wine_version().is_some(),
+    }
+}
+
+/// Counts the methods at the start of `words` the way [`VTableHook::new`](crate::VTableHook::new) does:
+/// up to the first null entry, or all of them if there is none.
+///
+/// Works on a buffer supplied by the caller, such as a table read from another process or fuzzer input,
+/// so it can be checked against layouts that would fault when scanned in place.
+pub fn detect_count_in(words: &[usize]) -> usize {
+    words.iter().position(|&word| word == 0).unwrap_or(words.len())
+}
+
+/// Most methods [`probe_count`] walks before giving up on finding the terminator.
+pub const MAX_PROBED_METHODS: usize = 4096;
+
+/// Counts the methods of `vtable` up to the first null entry like [`detect_count_in`], reading every entry in
+/// a way that reports faults instead of raising them. Returns `None` if the walk runs into unreadable memory
+/// or past [`MAX_PROBED_METHODS`], as it does for a pointer to something that isn't an object.
+pub unsafe fn probe_count(vtable: *const usize) -> Option<usize> {
+    (0..MAX_PROBED_METHODS).find_map(|id| match sys::probe(vtable.wrapping_add(id)) {
+        Some(0) => Some(Some(id)),
+        Some(_) => None,
+        None => Some(None),
+    })?
+}
+
+/// Counts the methods of `vtable` up to the first entry that doesn't point at executable memory, reading every
+/// entry like [`probe_count`]. Finds the end of MSVC tables, which aren't null-terminated but followed by the
+/// RTTI pointer of the next table.
+pub unsafe fn probe_code_count(vtable: *const usize) -> usize {
+    let entries = (0..).map_while(|id| sys::probe(vtable.wrapping_add(id)));
+    count_code(entries, |entry| sys::is_executable(entry))
+}
+
+/// Counts the methods at the start of `words` the way [`probe_code_count`] does, with `is_code` telling
+/// whether an address points at executable memory.
+///
+/// Works on a table read from another process or a dump, where executability has to be looked up in
+/// that process's memory map.
+pub fn code_count_in(words: &[usize], is_code: impl Fn(usize) -> bool) -> usize {
+    count_code(words.iter().copied(), is_code)
+}
+
+/// Counts the leading `entries` that are non-null and code, up to [`MAX_PROBED_METHODS`].
+fn count_code(entries: impl Iterator<Item = usize>, is_code: impl Fn(usize) -> bool) -> usize {
+    entries.take(MAX_PROBED_METHODS).take_while(|&entry| entry != 0 && is_code(entry)).count()
+}
+
+/// Where an object's vptr points, relative to the VTable it was hooked with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum VptrState {
+    /// At the hooked VTable.
+    Hooked,
+    /// Back at the original VTable, restored by someone else.
+    Original,
+    /// At a VTable of a base class of the original, as C++ destructors set it while the object dies.
+    Destroying {
+        /// The base class VTable.
+        vtable: usize,
+    },
+    /// Anywhere else, usually another tool's shadow VTable.
+    Foreign {
+        /// The unknown VTable.
+        vtable: usize,
+    },
+}
+
+/// Classifies a vptr that no longer points at the hooked VTable, using RTTI to recognize the base class
+/// VTables destructors switch to. Tables without RTTI are reported as [`VptrState::Foreign`].
+pub unsafe fn classify_vptr(vtable: usize, original: usize) -> VptrState {
+    if vtable == original {
+        VptrState::Original
+    } else if crate::rtti::is_base_of(vtable as *const usize, original as *const usize) == Some(true) {
+        VptrState::Destroying { vtable }
+    } else {
+        VptrState::Foreign { vtable }
+    }
+}
+
+/// A method that doesn't lie in the module owning its VTable.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Outlier {
+    /// Index of the method.
+    pub id: usize,
+    /// Address of the method.
+    pub address: usize,
+    /// Base address of the module containing the method, `None` outside every module.
+    pub module: Option<usize>,
+}
+
+/// Result of [`check_owner`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnerReport {
+    /// Base address of the module containing the VTable, `None` if it isn't in a module.
+    pub module: Option<usize>,
+    /// Methods outside that module.
+    pub outliers: Vec<Outlier>,
+}
+
+impl OwnerReport {
+    /// Returns `true` if every method is in the module owning the VTable.
+    pub fn is_clean(&self) -> bool {
+        self.module.is_some() && self.outliers.is_empty()
+    }
+}
+
+/// Checks that `methods`, as read from `vtable`, lie in the module containing `vtable`.
+///
+/// Outliers usually mean a count that runs past the end of the table or methods hooked before ours.
+/// Tables inheriting methods from a base class exported by another module have legitimate outliers.
+pub unsafe fn check_owner(vtable: *const usize, methods: &[usize]) -> OwnerReport {
+    let module = sys::module_of(vtable as usize);
+    let outliers = methods
+        .iter()
+        .enumerate()
+        .filter_map(|(id, &address)| {
+            let owner = sys::module_of(address);
+            (module.is_none() || owner != module).then_some(Outlier {
+                id,
+                address,
+                module: owner.map(|base| base as usize),
+            })
+        })
+        .collect();
+    OwnerReport { module: module.map(|base| base as usize), outliers }
+}
+
+/// How an inline trampoline leaves the hooked function.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TrampolineKind {
+    /// `jmp rel8` or `jmp rel32`, as written by MinHook and Detours.
+    RelativeJump,
+    /// `jmp [mem]`, the absolute jump of x86_64 hooking libraries.
+    IndirectJump,
+    /// `mov reg, imm` followed by `jmp reg`.
+    RegisterJump,
+    /// `push imm` followed by `ret`.
+    PushReturn,
+}
+
+/// An inline hook found at the start of a function.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Trampoline {
+    /// The first jump of the function.
+    pub kind: TrampolineKind,
+    /// Where calls end up after following every chained jump, like a hot-patch jump into a `jmp rel32`.
+    pub destination: usize,
+    /// `true` if the destination is in the same module as the function, as for incremental-linking thunks.
+    pub same_module: bool,
+}
+
+/// Maximum number of chained jumps followed to the destination of a trampoline.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+const MAX_JUMPS: usize = 8;
+
+/// Returns the code at `func`, long enough for the longest jump decoded,
+/// `endbr64; push; mov [rsp + 4]; ret`, unless it ends right before an unmapped page.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+unsafe fn code_at(func: usize) -> Option<&'static [u8]> {
+    const WINDOW: usize = 18;
+    let len = if sys::is_readable(func, WINDOW) {
+        WINDOW
+    } else if sys::is_readable(func, 1) {
+        WINDOW.min(sys::page_size() - func % sys::page_size())
+    } else {
+        return None;
+    };
+    Some(std::slice::from_raw_parts(func as *const u8, len))
+}
+
+/// Reads the pointer an indirect jump goes through.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+unsafe fn read_pointer(pointer: usize) -> Option<usize> {
+    sys::is_readable(pointer, std::mem::size_of::<usize>()).then(|| std::ptr::read_unaligned(pointer as *const usize))
+}
+
+/// Decodes the jump at the start of `func` and returns where it goes.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+unsafe fn jump_at(func: usize) -> Option<(TrampolineKind, usize)> {
+    match decode_jump(code_at(func)?, func)? {
+        Jump::Relative(to) => Some((TrampolineKind::RelativeJump, to)),
+        Jump::Indirect(pointer) => Some((TrampolineKind::IndirectJump, read_pointer(pointer)?)),
+        Jump::Register(to) => Some((TrampolineKind::RegisterJump, to)),
+        Jump::PushReturn(to) => Some((TrampolineKind::PushReturn, to)),
+    }
+}
+
+/// Returns the trampoline at the start of `func` if it begins with an unconditional jump,
+/// meaning calling it reaches someone's detour instead of the function.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub unsafe fn detect_trampoline(func: usize) -> Option<Trampoline> {
+    let (kind, mut destination) = jump_at(func)?;
+    for _ in 1..MAX_JUMPS {
+        match jump_at(destination) {
+            Some((_, next)) => destination = next,
+            None => break,
+        }
+    }
+    let module = sys::module_of(func);
+    Some(Trampoline {
+        kind,
+        destination,
+        same_module: module.is_some() && module == sys::module_of(destination),
+    })
+}
+
+/// Returns the indices and trampolines of the first `count` methods of `vtable` that are inline-hooked.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub unsafe fn detect_trampolines(vtable: *const usize, count: usize) -> Vec<(usize, Trampoline)> {
+    (0..count)
+        .filter_map(|id| detect_trampoline(*vtable.add(id)).map(|trampoline| (id, trampoline)))
+        .collect()
+}
+
+/// Follows the import and incremental-linking stubs at `func` to the function they forward to.
+///
+/// Only jumps that stay inside the stub's module (ILT `jmp rel32` stubs) and indirect jumps through
+/// a pointer inside the stub's module (`jmp [iat]`, PLT entries) are followed, so an inline hook
+/// leading out of the module isn't mistaken for a stub. Returns `func` itself if it isn't a stub.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub unsafe fn resolve_thunk(func: usize) -> usize {
+    let mut resolved = func;
+    for _ in 0..MAX_JUMPS {
+        let Some(module) = sys::module_of(resolved) else {
+            break;
+        };
+        let Some(jump) = code_at(resolved).and_then(|code| decode_jump(code, resolved)) else {
+            break;
+        };
+        let next = match jump {
+            Jump::Relative(to) if sys::module_of(to) == Some(module) => to,
+            Jump::Indirect(pointer) if sys::module_of(pointer) == Some(module) => match read_pointer(pointer) {
+                Some(to) => to,
+                None => break,
+            },
+            _ => break,
+        };
+        resolved = next;
+    }
+    resolved
+}
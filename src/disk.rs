@@ -0,0 +1,93 @@
+//! Comparing VTables against the image of their module on disk.
+//!
+//! A tool that hooked a table before us leaves no trace of the original methods in memory, but the
+//! module's file still has them. The file is laid out as the loader would map it, relocated to the
+//! module's base address, and the table is read at the same offset.
+
+use std::io;
+
+use crate::{pe, sys};
+
+/// A VTable slot whose value in memory differs from the module's file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotDiff {
+    /// Index of the method.
+    pub id: usize,
+    /// Value of the slot in the file, relocated to the module's base.
+    pub disk: usize,
+    /// Value of the slot in memory.
+    pub memory: usize,
+}
+
+/// The file of a loaded module, mapped and relocated like the module.
+pub struct DiskImage {
+    base: usize,
+    mapped: Vec<u8>,
+}
+
+impl DiskImage {
+    /// Reads the file of the module containing `address`.
+    pub unsafe fn of(address: usize) -> io::Result<Self> {
+        let module = sys::module_of(address)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "address isn't inside a module"))?;
+        let path = sys::module_path(module)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "module has no file"))?;
+        let file = std::fs::read(path)?;
+        let mapped = pe::map_file(&file, module as usize)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidData, "module file isn't a valid PE image"))?;
+        Ok(Self {
+            base: module as usize,
+            mapped,
+        })
+    }
+
+    /// Returns the base address of the module.
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    /// Returns the pointer-sized word the file holds at `address` of the loaded module.
+    pub fn read(&self, address: usize) -> Option<usize> {
+        let offset = address.checked_sub(self.base)?;
+        let bytes = self.mapped.get(offset..offset.checked_add(std::mem::size_of::<usize>())?)?;
+        Some(usize::from_le_bytes(bytes.try_into().ok()?))
+    }
+
+    /// Returns the `count` methods the file holds for the VTable at `vtable`.
+    pub fn vtable(&self, vtable: *const usize, count: usize) -> Option<Vec<usize>> {
+        (0..count).map(|id| self.read(vtable.wrapping_add(id) as usize)).collect()
+    }
+
+    /// Returns the slots of the first `count` methods of `vtable` whose value in memory differs from the file.
+    pub unsafe fn diff(&self, vtable: *const usize, count: usize) -> io::Result<Vec<SlotDiff>> {
+        let memory = std::slice::from_raw_parts(vtable, count);
+        self.diff_methods(vtable, memory)
+    }
+
+    /// Returns the slots of `methods`, as read from `vtable`, whose value differs from the file.
+    pub fn diff_methods(&self, vtable: *const usize, methods: &[usize]) -> io::Result<Vec<SlotDiff>> {
+        let disk = self
+            .vtable(vtable, methods.len())
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "vtable isn't inside the module image"))?;
+        Ok(disk
+            .into_iter()
+            .zip(methods)
+            .enumerate()
+            .filter(|(_, (disk, memory))| disk != *memory)
+            .map(|(id, (disk, &memory))| SlotDiff { id, disk, memory })
+            .collect())
+    }
+}
+
+/// Returns the `count` methods of `vtable` as stored in its module's file, the true originals
+/// even if someone patched the table in memory.
+pub unsafe fn disk_vtable(vtable: *const usize, count: usize) -> io::Result<Vec<usize>> {
+    DiskImage::of(vtable as usize)?
+        .vtable(vtable, count)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "vtable isn't inside the module image"))
+}
+
+/// Returns the slots of the first `count` methods of `vtable` that differ from its module's file.
+pub unsafe fn diff_with_disk(vtable: *const usize, count: usize) -> io::Result<Vec<SlotDiff>> {
+    DiskImage::of(vtable as usize)?.diff(vtable, count)
+}
@@ -0,0 +1,442 @@
+//! Readable dumps of VTables, with each method named after the module it lies in.
+//!
+//! With the `symbols` feature the symbol containing each method is looked up as well, through dbghelp on
+//! Windows, which loads PDBs from the symbol path and symbol servers, and through `dladdr` elsewhere.
+//! Original methods are also named after the exports of their module and, with the `pdb` feature, the
+//! public symbols of its matching PDB. With the `serde` feature dumps can be exported as JSON.
+//!
+//! Tables of other processes and minidumps are dumped through [`Memory`], naming methods after their module.
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::c_void;
+use std::fmt;
+use std::io;
+use std::ops::Range;
+
+use crate::{rtti, symbol, sys, HookBackend};
+
+/// Where an address lies, as `module!symbol+0xOFFSET` or `module+0xOFFSET`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Location {
+    /// The address.
+    pub address: usize,
+    /// File name of the module containing the address, `None` if it isn't in a module.
+    pub module: Option<String>,
+    /// Offset of the address from the base of the module.
+    pub offset: usize,
+    /// The symbol containing the address, if known.
+    pub symbol: Option<Symbol>,
+}
+
+/// A named symbol and the offset of an address into it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct Symbol {
+    /// Name of the symbol, demangled where possible.
+    pub name: String,
+    /// Offset of the address from the start of the symbol.
+    pub displacement: usize,
+}
+
+/// One method of a dumped VTable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct SlotDump {
+    /// Index of the method.
+    pub id: usize,
+    /// Where the original method lies.
+    pub original: Location,
+    /// Where the method the table holds now lies.
+    pub current: Location,
+    /// Demangled name of the original method, if its module has a symbol for it.
+    pub method: Option<String>,
+}
+
+/// The methods of a VTable.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize))]
+pub struct TableDump {
+    /// Name of the class from its RTTI, demangled where possible.
+    pub class: Option<String>,
+    /// Address of the hooked object, if the table was dumped through a hook.
+    pub object: Option<usize>,
+    /// Address of the original VTable.
+    pub vtable: usize,
+    /// Where the original VTable lies.
+    pub location: Location,
+    /// The methods, in table order.
+    pub slots: Vec<SlotDump>,
+}
+
+/// Memory of another process or of a minidump, read by [`TableDump::read_from`] and
+/// [`rtti::find_vtable_in`].
+pub trait Memory {
+    /// Reads `buffer.len()` bytes at `address`.
+    fn read(&self, address: usize, buffer: &mut [u8]) -> io::Result<()>;
+
+    /// Returns the modules mapped in the memory.
+    fn modules(&self) -> io::Result<Vec<MappedModule>>;
+
+    /// Returns whether `address` lies in executable memory, `None` if that isn't known.
+    fn is_executable(&self, _address: usize) -> Option<bool> {
+        None
+    }
+
+    /// Reads a pointer-sized value at `address`.
+    fn read_usize(&self, address: usize) -> io::Result<usize> {
+        let mut bytes = [0; std::mem::size_of::<usize>()];
+        self.read(address, &mut bytes)?;
+        Ok(usize::from_ne_bytes(bytes))
+    }
+}
+
+/// A module mapped in the memory read by a [`Memory`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct MappedModule {
+    /// File name of the module.
+    pub name: String,
+    /// Base address of the module.
+    pub base: usize,
+    /// Address ranges the module is mapped at.
+    pub ranges: Vec<Range<usize>>,
+}
+
+impl MappedModule {
+    /// Returns `true` if `address` lies in the module.
+    pub fn contains(&self, address: usize) -> bool {
+        self.ranges.iter().any(|range| range.contains(&address))
+    }
+}
+
+impl SlotDump {
+    /// Returns `true` if the method has been replaced.
+    pub fn is_replaced(&self) -> bool {
+        self.original.address != self.current.address
+    }
+}
+
+impl TableDump {
+    /// Dumps the `count` methods of the VTable at `vtable`.
+    pub unsafe fn of_table(vtable: *const usize, count: usize) -> Self {
+        let methods: Vec<usize> = (0..count).map(|id| *vtable.add(id)).collect();
+        Self::new(None, vtable as usize, &methods, &methods)
+    }
+
+    /// Dumps the VTable at `vtable` from `memory`, with `count` methods or, by default, up to the first entry
+    /// that isn't executable, or outside of every module if `memory` can't tell. Methods are named after their
+    /// module only.
+    pub fn read_from(memory: &dyn Memory, vtable: usize, count: Option<usize>) -> io::Result<Self> {
+        let modules = memory.modules()?;
+        let locate = |address: usize| match modules.iter().find(|module| module.contains(address)) {
+            Some(module) => Location {
+                address,
+                module: Some(module.name.clone()),
+                offset: address - module.base,
+                symbol: None,
+            },
+            None => Location { address, module: None, offset: 0, symbol: None },
+        };
+        let entry = |id: usize| memory.read_usize(vtable + id * std::mem::size_of::<usize>());
+        let is_method = |method: usize| {
+            let code = memory.is_executable(method);
+            code.unwrap_or_else(|| modules.iter().any(|module| module.contains(method)))
+        };
+        let methods: Vec<usize> = match count {
+            Some(count) => (0..count).map(entry).collect::<io::Result<_>>()?,
+            None => (0..crate::detect::MAX_PROBED_METHODS)
+                .map_while(|id| entry(id).ok().filter(|&method| is_method(method)))
+                .collect(),
+        };
+        let slots = methods
+            .into_iter()
+            .enumerate()
+            .map(|(id, method)| SlotDump { id, original: locate(method), current: locate(method), method: None })
+            .collect();
+        Ok(Self { class: None, object: None, vtable, location: locate(vtable), slots })
+    }
+
+    /// Dumps a table whose methods were `original` and now are `current`.
+    pub(crate) unsafe fn new(object: Option<usize>, vtable: usize, original: &[usize], current: &[usize]) -> Self {
+        let mut names = MethodNames::default();
+        let slots = original
+            .iter()
+            .zip(current)
+            .enumerate()
+            .map(|(id, (&original, &current))| {
+                let method = names.name(original);
+                let original = locate(original);
+                let current = if current == original.address { original.clone() } else { locate(current) };
+                SlotDump { id, original, current, method }
+            })
+            .collect();
+        // Tables outside of modules, built at runtime, have no RTTI to read.
+        let class = sys::module_of(vtable)
+            .and_then(|_| rtti::class_name(vtable as *const usize))
+            .map(|class| rtti::demangle(&class).unwrap_or(class));
+        Self { class, object, vtable, location: locate(vtable), slots }
+    }
+
+    /// Returns the replaced methods.
+    pub fn replaced(&self) -> impl Iterator<Item = &SlotDump> {
+        self.slots.iter().filter(|slot| slot.is_replaced())
+    }
+
+    /// Returns the dump as a JSON object, for tools that track hook state.
+    #[cfg(feature = "serde")]
+    pub fn export_json(&self) -> String {
+        serde_json::to_string(self).expect("dumps always serialize")
+    }
+
+    /// Returns an IDAPython script that names the original VTable and the methods of its module in an IDA
+    /// database of that module, and comments each slot with its method and whether it is hooked.
+    pub fn ida_script(&self) -> String {
+        let mut script = format!("# IDAPython script generated by vmt-hook from {}.\n", self.describe());
+        script.push_str("import ida_bytes\nimport ida_name\nimport idaapi\n\n");
+        let base = if self.location.module.is_some() { "idaapi.get_imagebase()" } else { "0" };
+        script.push_str(&format!("base = {base}\n"));
+        script.push_str(&self.python_annotations());
+        script.push_str("flags = ida_name.SN_NOCHECK | ida_name.SN_NOWARN | ida_name.SN_FORCE\n");
+        script.push_str("for offset, name, comment in annotations:\n");
+        script.push_str("    if name:\n");
+        script.push_str("        ida_name.set_name(base + offset, name, flags)\n");
+        script.push_str("    if comment:\n");
+        script.push_str("        ida_bytes.set_cmt(base + offset, comment, False)\n");
+        script
+    }
+
+    /// Returns a Ghidra Python script doing what [`ida_script`](Self::ida_script) does in IDA.
+    pub fn ghidra_script(&self) -> String {
+        let mut script = format!("# Ghidra script generated by vmt-hook from {}.\n", self.describe());
+        script.push_str("# @category vmt-hook\n");
+        script.push_str("from ghidra.program.model.symbol import SourceType\n\n");
+        let base = if self.location.module.is_some() { "currentProgram.getImageBase()" } else { "toAddr(0)" };
+        script.push_str(&format!("base = {base}\n"));
+        script.push_str(&self.python_annotations());
+        script.push_str("for offset, name, comment in annotations:\n");
+        script.push_str("    address = base.add(offset)\n");
+        script.push_str("    if name:\n");
+        script.push_str("        createLabel(address, name, True, SourceType.USER_DEFINED)\n");
+        script.push_str("    if comment:\n");
+        script.push_str("        setEOLComment(address, comment)\n");
+        script
+    }
+
+    fn describe(&self) -> String {
+        let class = self.class.as_deref().map(|class| format!(" of {class}")).unwrap_or_default();
+        format!("a dump of the vtable{class} at {}", self.location)
+    }
+
+    /// Returns the annotations of the scripts as a Python list of `(offset, name, comment)` tuples, offsets
+    /// being relative to the base of the VTable's module, or absolute addresses without one.
+    fn python_annotations(&self) -> String {
+        let module = self.location.module.as_ref();
+        let offset_of = |location: &Location| match module {
+            Some(module) => (location.module.as_ref() == Some(module)).then_some(location.offset),
+            None => Some(location.address),
+        };
+        let mut annotations: BTreeMap<usize, (Option<String>, Vec<String>)> = BTreeMap::new();
+        let vtable = offset_of(&self.location).unwrap_or(self.vtable);
+        let class = self.class.clone().unwrap_or_else(|| format!("{:x}", self.vtable));
+        let table = annotations.entry(vtable).or_default();
+        table.0 = Some(format!("vtable_{class}"));
+        table.1.push(format!("vtable of {class} ({} methods)", self.slots.len()));
+
+        for slot in &self.slots {
+            let symbol = slot.original.symbol.as_ref().filter(|symbol| symbol.displacement == 0);
+            let name = slot.method.clone().or_else(|| symbol.map(|symbol| symbol.name.clone()));
+            let described = name.clone().unwrap_or_else(|| slot.original.to_string());
+            let mut comment = format!("[{}] {described}", slot.id);
+            if slot.is_replaced() {
+                comment.push_str(&format!(" (hooked: {})", slot.current));
+            }
+            let entry = vtable + slot.id * std::mem::size_of::<usize>();
+            annotations.entry(entry).or_default().1.push(comment);
+            if let (Some(name), Some(method)) = (name, offset_of(&slot.original)) {
+                annotations.entry(method).or_default().0.get_or_insert(name);
+            }
+        }
+
+        let mut list = String::from("annotations = [\n");
+        for (offset, (name, comments)) in annotations {
+            let name = name.map_or_else(|| "None".to_owned(), |name| python_string(&label(&name)));
+            list.push_str(&format!("    ({offset:#x}, {name}, {}),\n", python_string(&comments.join("\n"))));
+        }
+        list.push_str("]\n\n");
+        list
+    }
+}
+
+impl<'a> IntoIterator for &'a TableDump {
+    type Item = &'a SlotDump;
+    type IntoIter = std::slice::Iter<'a, SlotDump>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.slots.iter()
+    }
+}
+
+impl fmt::Display for Location {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (&self.module, &self.symbol) {
+            (Some(module), Some(symbol)) => write!(f, "{module}!{}+{:#x}", symbol.name, symbol.displacement),
+            (Some(module), None) => write!(f, "{module}+{:#x}", self.offset),
+            (None, _) => write!(f, "{:#x}", self.address),
+        }
+    }
+}
+
+impl fmt::Display for SlotDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:4}: {:#x} {}", self.id, self.original.address, self.original)?;
+        if let Some(method) = &self.method {
+            write!(f, " [{method}]")?;
+        }
+        if self.is_replaced() {
+            write!(f, " -> {:#x} {} (replaced)", self.current.address, self.current)?;
+        }
+        Ok(())
+    }
+}
+
+/// Formats addresses in hexadecimal in `Debug` output.
+pub(crate) struct Hex(pub(crate) usize);
+
+impl fmt::Debug for Hex {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:#x}", self.0)
+    }
+}
+
+/// Formats the slots of a backend in `Debug` output as `module+0xOFFSET`, followed by the replacement of
+/// replaced ones. Only modules are looked up, so formatting stays cheap; symbols are left to [`TableDump`].
+pub(crate) struct DebugSlots<'a, B>(pub(crate) &'a B);
+
+impl<B: HookBackend> fmt::Debug for DebugSlots<'_, B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let slots = (0..self.0.count()).map(|id| DebugSlot {
+            original: self.0.original(id),
+            current: self.0.replaced(id),
+        });
+        f.debug_list().entries(slots).finish()
+    }
+}
+
+struct DebugSlot {
+    original: usize,
+    current: usize,
+}
+
+impl fmt::Debug for DebugSlot {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", unsafe { locate_module(self.original) })?;
+        if self.current != self.original {
+            write!(f, " -> {} (replaced)", unsafe { locate_module(self.current) })?;
+        }
+        Ok(())
+    }
+}
+
+impl fmt::Display for TableDump {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "vtable {:#x}", self.vtable)?;
+        if let Some(class) = &self.class {
+            write!(f, " of {class}")?;
+        }
+        write!(f, " ({} methods", self.slots.len())?;
+        if let Some(object) = self.object {
+            write!(f, ", object {object:#x}")?;
+        }
+        writeln!(f, ")")?;
+        for slot in &self.slots {
+            writeln!(f, "{slot}")?;
+        }
+        Ok(())
+    }
+}
+
+/// Turns a qualified name into a label both IDA and Ghidra accept.
+fn label(name: &str) -> String {
+    name.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect()
+}
+
+/// Quotes `text` as a Python string literal, in the unicode form Ghidra's Python 2 needs too.
+fn python_string(text: &str) -> String {
+    let mut quoted = String::from("u\"");
+    for c in text.chars() {
+        match c {
+            '"' | '\\' => quoted.extend(['\\', c]),
+            '\n' => quoted.push_str("\\n"),
+            ' '..='~' => quoted.push(c),
+            c => quoted.push_str(&format!("\\U{:08x}", c as u32)),
+        }
+    }
+    quoted.push('"');
+    quoted
+}
+
+/// Names of the functions of the modules seen so far, by address.
+#[derive(Default)]
+struct MethodNames(HashMap<usize, HashMap<usize, String>>);
+
+impl MethodNames {
+    unsafe fn name(&mut self, address: usize) -> Option<String> {
+        let base = sys::module_of(address)?;
+        let names = self.0.entry(base as usize).or_insert_with(|| module_names(base));
+        if let Some(name) = names.get(&address) {
+            return Some(name.clone());
+        }
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        return names.get(&crate::detect::resolve_thunk(address)).cloned();
+        #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+        None
+    }
+}
+
+unsafe fn module_names(base: *mut c_void) -> HashMap<usize, String> {
+    symbol::module_symbols(base)
+        .into_iter()
+        .map(|(name, address)| (address, symbol::demangle_function(&name).unwrap_or(name)))
+        .collect()
+}
+
+/// Returns where `address` lies.
+pub unsafe fn locate(address: usize) -> Location {
+    let location = locate_module(address);
+    match location.module {
+        Some(_) => Location { symbol: find_symbol(address), ..location },
+        None => location,
+    }
+}
+
+/// Returns the module `address` lies in, without looking up a symbol.
+unsafe fn locate_module(address: usize) -> Location {
+    let Some(base) = sys::module_of(address) else {
+        return Location { address, module: None, offset: 0, symbol: None };
+    };
+    let module = sys::module_path(base)
+        .and_then(|path| Some(path.file_name()?.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| format!("{:#x}", base as usize));
+    Location { address, module: Some(module), offset: address - base as usize, symbol: None }
+}
+
+#[cfg(feature = "symbols")]
+unsafe fn find_symbol(address: usize) -> Option<Symbol> {
+    let (name, displacement) = sys::symbolize(address)?;
+    let name = symbol::demangle_function(&name).unwrap_or(name);
+    Some(Symbol { name, displacement })
+}
+
+#[cfg(not(feature = "symbols"))]
+unsafe fn find_symbol(_address: usize) -> Option<Symbol> {
+    None
+}
+
+/// Sets the paths dbghelp searches for PDBs, in its `_NT_SYMBOL_PATH` syntax. `srv*cache*url` entries
+/// download them from a symbol server, provided `symsrv.dll` sits next to the `dbghelp.dll` in use.
+/// Without a call the `_NT_SYMBOL_PATH` environment variable is used.
+#[cfg(all(feature = "symbols", windows))]
+pub fn set_symbol_path(path: &str) -> crate::error::Result<()> {
+    unsafe { sys::set_symbol_path(path) }
+        .map_err(|error| crate::error::Error::Invalid(format!("failed to set the symbol path: {error}")))
+}
@@ -0,0 +1,166 @@
+//! Automatic hooking of swapchains created through a hooked `IDXGIFactory`.
+//!
+//! [`FactoryHook`] watches the `CreateSwapChain*` methods of a factory through a [`FactoryWatcher`] and
+//! installs the registered replacements (typically `Present` and `ResizeBuffers`) on every swapchain it
+//! creates. The hook of a swapchain is released together with its last reference.
+
+use std::ffi::c_void;
+
+use windows_sys::core::{GUID, HRESULT};
+
+use crate::factory::{self as factory_watcher, FactoryOutput, FactoryWatcher};
+use crate::slot::{FnPtr, Slot};
+
+type QueryInterfaceFn = unsafe extern "system" fn(*mut c_void, *const GUID, *mut *mut c_void) -> HRESULT;
+type ReleaseFn = unsafe extern "system" fn(*mut c_void) -> u32;
+pub type CreateSwapChainFn = unsafe extern "system" fn(*mut c_void, *mut c_void, *mut c_void, *mut *mut c_void) -> HRESULT;
+pub type CreateSwapChainForHwndFn = unsafe extern "system" fn(
+    *mut c_void,
+    *mut c_void,
+    *mut c_void,
+    *const c_void,
+    *const c_void,
+    *mut c_void,
+    *mut *mut c_void,
+) -> HRESULT;
+pub type CreateSwapChainForCoreWindowFn = unsafe extern "system" fn(
+    *mut c_void,
+    *mut c_void,
+    *mut c_void,
+    *const c_void,
+    *mut c_void,
+    *mut *mut c_void,
+) -> HRESULT;
+pub type CreateSwapChainForCompositionFn =
+    unsafe extern "system" fn(*mut c_void, *mut c_void, *const c_void, *mut c_void, *mut *mut c_void) -> HRESULT;
+
+const IID_IDXGI_FACTORY2: GUID = GUID::from_u128(0x50c83a1c_e072_4c48_87b0_3630fa36a6d0);
+const IID_IDXGI_SWAP_CHAIN1: GUID = GUID::from_u128(0x790a45f7_0d42_4876_983a_0a55cfe6f4aa);
+
+const RELEASE: usize = 2;
+
+/// Slots of `IDXGIFactory` and `IDXGIFactory2`.
+pub mod factory {
+    use super::*;
+
+    pub const CREATE_SWAP_CHAIN: Slot<CreateSwapChainFn> = Slot::new(10);
+    pub const CREATE_SWAP_CHAIN_FOR_HWND: Slot<CreateSwapChainForHwndFn> = Slot::new(15);
+    pub const CREATE_SWAP_CHAIN_FOR_CORE_WINDOW: Slot<CreateSwapChainForCoreWindowFn> = Slot::new(16);
+    pub const CREATE_SWAP_CHAIN_FOR_COMPOSITION: Slot<CreateSwapChainForCompositionFn> = Slot::new(24);
+
+    /// Method count of `IDXGIFactory`.
+    pub const COUNT: usize = 12;
+    /// Method count of `IDXGIFactory2`.
+    pub const COUNT2: usize = 25;
+}
+
+/// Slots of `IDXGISwapChain` and `IDXGISwapChain1`.
+pub mod swap_chain {
+    use super::*;
+
+    pub type PresentFn = unsafe extern "system" fn(*mut c_void, u32, u32) -> HRESULT;
+    pub type ResizeBuffersFn = unsafe extern "system" fn(*mut c_void, u32, u32, u32, i32, u32) -> HRESULT;
+    pub type Present1Fn = unsafe extern "system" fn(*mut c_void, u32, u32, *const c_void) -> HRESULT;
+
+    pub const PRESENT: Slot<PresentFn> = Slot::new(8);
+    pub const RESIZE_BUFFERS: Slot<ResizeBuffersFn> = Slot::new(13);
+    pub const PRESENT1: Slot<Present1Fn> = Slot::new(22);
+
+    /// Method count of `IDXGISwapChain`.
+    pub const COUNT: usize = 18;
+    /// Method count of `IDXGISwapChain1`.
+    pub const COUNT1: usize = 29;
+}
+
+/// Returns `true` if the object implements the interface.
+unsafe fn supports(object: *mut c_void, iid: &GUID) -> bool {
+    let vtable = *(object as *const *const usize);
+    let query_interface = QueryInterfaceFn::from_address(*vtable);
+    let mut interface = std::ptr::null_mut();
+    if query_interface(object, iid, &mut interface) < 0 || interface.is_null() {
+        return false;
+    }
+    let vtable = *(interface as *const *const usize);
+    ReleaseFn::from_address(*vtable.add(RELEASE))(interface);
+    true
+}
+
+fn swap_chain_count(swap_chain: *mut c_void) -> usize {
+    if unsafe { supports(swap_chain, &IID_IDXGI_SWAP_CHAIN1) } {
+        swap_chain::COUNT1
+    } else {
+        swap_chain::COUNT
+    }
+}
+
+/// Returns the original method of a swapchain hooked by a [`FactoryHook`].
+pub fn original_method(swap_chain: *mut c_void, id: usize) -> Option<usize> {
+    factory_watcher::product_original_method(swap_chain, id)
+}
+
+/// Returns the original method of the slot of a swapchain hooked by a [`FactoryHook`].
+pub unsafe fn get_original<F: FnPtr>(swap_chain: *mut c_void, slot: Slot<F>) -> Option<F> {
+    factory_watcher::get_product_original(swap_chain, slot)
+}
+
+/// Hooks the swapchain creation methods of a factory.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use vmt_hook::dxgi::{self, swap_chain, FactoryHook};
+///
+/// unsafe extern "system" fn hk_present(this: *mut c_void, sync_interval: u32, flags: u32) -> HRESULT {
+///     // Your code.
+///
+///     let original_present = dxgi::get_original(this, swap_chain::PRESENT).unwrap();
+///     original_present(this, sync_interval, flags)
+/// }
+///
+/// let hook = FactoryHook::new(factory);
+/// hook.replace(swap_chain::PRESENT, hk_present);
+/// ```
+pub struct FactoryHook {
+    watcher: FactoryWatcher,
+}
+
+impl FactoryHook {
+    /// Hooks the factory. Swapchains it creates from now on are hooked automatically.
+    pub unsafe fn new(factory: *mut c_void) -> Self {
+        let factory2 = supports(factory, &IID_IDXGI_FACTORY2);
+        let count = if factory2 { factory::COUNT2 } else { factory::COUNT };
+
+        let watcher = FactoryWatcher::with_count(factory, count);
+        watcher.product_count(swap_chain_count);
+        watcher.com_lifetime();
+        const WATCHABLE: &str = "swapchain factory slots are distinct and watchable";
+        watcher.watch(factory::CREATE_SWAP_CHAIN, FactoryOutput::Argument(3)).expect(WATCHABLE);
+        if factory2 {
+            watcher.watch(factory::CREATE_SWAP_CHAIN_FOR_HWND, FactoryOutput::Argument(6)).expect(WATCHABLE);
+            watcher.watch(factory::CREATE_SWAP_CHAIN_FOR_CORE_WINDOW, FactoryOutput::Argument(5)).expect(WATCHABLE);
+            watcher.watch(factory::CREATE_SWAP_CHAIN_FOR_COMPOSITION, FactoryOutput::Argument(4)).expect(WATCHABLE);
+        }
+
+        Self { watcher }
+    }
+
+    /// Registers a swapchain method replacement, applied to existing and future swapchains of the factory.
+    pub unsafe fn replace_method(&self, id: usize, func: usize) {
+        self.watcher.replace_method(id, func);
+    }
+
+    /// Registers a replacement of the slot, applied to existing and future swapchains of the factory.
+    pub unsafe fn replace<F: FnPtr>(&self, slot: Slot<F>, func: F) {
+        self.watcher.replace(slot, func);
+    }
+
+    /// Hooks a swapchain created before the factory was hooked.
+    pub unsafe fn adopt(&self, swap_chain: *mut c_void) {
+        self.watcher.adopt(swap_chain);
+    }
+
+    /// Returns the addresses of all currently hooked swapchains of the factory.
+    pub fn swap_chains(&self) -> Vec<usize> {
+        self.watcher.products()
+    }
+}
@@ -0,0 +1,177 @@
+//! Hooking the VTables of Rust trait objects.
+//!
+//! A `dyn Trait` pointer is a data pointer followed by a VTable pointer. The VTable starts with a
+//! header of three words, the drop glue, the size and the alignment of the type, followed by the
+//! methods of the trait, supertraits first, in declaration order in practice. The layout isn't a
+//! stable guarantee of the compiler, so [`DynTraitHook::new`] checks the header against the object.
+//!
+//! Replacements are Rust functions taking the data pointer first, such as
+//! `fn(this: *const MyType, arg: u32) -> u32` for `fn method(&self, arg: u32) -> u32`.
+
+use std::marker::PhantomData;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::slot::{FnPtr, Slot};
+use crate::{audit, observer};
+
+/// Words of the VTable before the first method: drop glue, size and alignment.
+pub const HEADER: usize = 3;
+
+const WORD: usize = std::mem::size_of::<usize>();
+
+/// Splits a trait object pointer into its data and VTable pointers.
+unsafe fn parts<T: ?Sized>(object: *const T) -> [usize; 2] {
+    assert_eq!(std::mem::size_of::<*const T>(), 2 * WORD, "not a trait object pointer");
+    std::mem::transmute_copy(&object)
+}
+
+/// A copy of the VTable of a `dyn Trait` type with replaced methods, attached to trait object pointers.
+///
+/// The VTable of a trait object lives in the pointer rather than in the object, so hooking produces
+/// new pointers with [`wrap`](Self::wrap), or rewrites stored ones with [`attach`](Self::attach).
+/// Method indices count from the first method; the header is reached through
+/// [`replace_drop`](Self::replace_drop).
+pub struct DynTraitHook<T: ?Sized> {
+    /// Pointer to the original VTable.
+    original: *const usize,
+    /// The header, then the methods.
+    table: Box<[AtomicUsize]>,
+    _trait: PhantomData<*const T>,
+}
+
+unsafe impl<T: ?Sized> Send for DynTraitHook<T> {}
+unsafe impl<T: ?Sized> Sync for DynTraitHook<T> {}
+
+impl<T: ?Sized> Drop for DynTraitHook<T> {
+    fn drop(&mut self) {
+        observer::emit(self.vtable() as usize, audit::Operation::Uninstall);
+    }
+}
+
+impl<T: ?Sized> DynTraitHook<T> {
+    /// Creates a hook for the VTable of `object`, a `dyn Trait` pointer, with `count` methods.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `object` isn't a trait object pointer, or the header doesn't hold its size and alignment.
+    pub unsafe fn new(object: *const T, count: usize) -> Self {
+        let [_, vtable] = parts(object);
+        let original = vtable as *const usize;
+        let (size, align) = (std::mem::size_of_val(&*object), std::mem::align_of_val(&*object));
+        assert!(
+            *original.add(1) == size && *original.add(2) == align,
+            "unexpected trait object VTable layout"
+        );
+
+        let table = std::slice::from_raw_parts(original, HEADER + count).iter().map(|&entry| AtomicUsize::new(entry));
+        let hook = Self {
+            original,
+            table: table.collect(),
+            _trait: PhantomData,
+        };
+        observer::emit(hook.vtable() as usize, audit::Operation::Install { vtable, count });
+        hook
+    }
+
+    /// Returns the address of the hooked VTable copy.
+    pub fn vtable(&self) -> *const usize {
+        self.table.as_ptr() as *const usize
+    }
+
+    /// Returns the address of the original VTable.
+    pub fn original_vtable(&self) -> *const usize {
+        self.original
+    }
+
+    /// Returns the number of methods covered by the hook.
+    pub fn len(&self) -> usize {
+        self.table.len() - HEADER
+    }
+
+    /// Returns `true` if the hook covers no methods.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns `true` if `object` points at the hooked VTable copy.
+    pub unsafe fn is_attached(&self, object: *const T) -> bool {
+        parts(object)[1] == self.vtable() as usize
+    }
+
+    /// Returns a pointer to the same data as `object` whose calls go through the hooked VTable.
+    ///
+    /// The hook must outlive every use of the pointer.
+    pub unsafe fn wrap(&self, object: *const T) -> *const T {
+        let [data, _] = parts(object);
+        std::mem::transmute_copy(&[data, self.vtable() as usize])
+    }
+
+    /// Rewrites the trait object pointer stored at `object`, such as the field behind a `Box<dyn Trait>`,
+    /// to go through the hooked VTable.
+    pub unsafe fn attach(&self, object: *mut *const T) {
+        *object = self.wrap(*object);
+    }
+
+    /// Rewrites the trait object pointer stored at `object` back to the original VTable.
+    pub unsafe fn detach(&self, object: *mut *const T) {
+        let [data, _] = parts(*object);
+        *object = std::mem::transmute_copy(&[data, self.original as usize]);
+    }
+
+    /// Returns the original method address at the specified index.
+    pub fn get_original_method(&self, id: usize) -> usize {
+        assert!(id < self.len(), "slot {id} is out of bounds");
+        unsafe { *self.original.add(HEADER + id) }
+    }
+
+    /// Returns the method address currently stored at the specified index in the hooked VTable.
+    pub fn get_replaced_method(&self, id: usize) -> usize {
+        self.table[HEADER + id].load(Ordering::SeqCst)
+    }
+
+    /// Hooks the method at the specified index with a new function address.
+    pub unsafe fn replace_method(&self, id: usize, func: usize) {
+        let old = self.table[HEADER + id].swap(func, Ordering::SeqCst);
+        observer::emit(self.vtable() as usize, audit::Operation::Replace { id, old, new: func });
+    }
+
+    /// Restores the original method at the specified index.
+    pub unsafe fn restore_method(&self, id: usize) {
+        let new = self.get_original_method(id);
+        let old = self.table[HEADER + id].swap(new, Ordering::SeqCst);
+        observer::emit(self.vtable() as usize, audit::Operation::Restore { id, old, new });
+    }
+
+    /// Restores all methods and the drop glue to their original address.
+    pub unsafe fn restore_all_methods(&self) {
+        for (i, entry) in self.table.iter().enumerate() {
+            entry.store(*self.original.add(i), Ordering::SeqCst);
+        }
+        observer::emit(self.vtable() as usize, audit::Operation::RestoreAll);
+    }
+
+    /// Returns the original drop glue, `unsafe fn(*mut Data)`.
+    pub fn get_original_drop(&self) -> usize {
+        unsafe { *self.original }
+    }
+
+    /// Replaces the drop glue run when a `Box<dyn Trait>` going through the hooked VTable is dropped.
+    pub unsafe fn replace_drop(&self, func: unsafe fn(*mut ())) {
+        self.table[0].store(func as usize, Ordering::SeqCst);
+    }
+
+    /// Returns the original method of the slot.
+    pub unsafe fn get_original<F: FnPtr>(&self, slot: Slot<F>) -> F {
+        F::from_address(self.get_original_method(slot.index()))
+    }
+
+    /// Hooks the method of the slot with a new function.
+    pub unsafe fn replace<F: FnPtr>(&self, slot: Slot<F>, func: F) {
+        self.replace_method(slot.index(), func.to_address());
+    }
+
+    /// Restores the original method of the slot.
+    pub unsafe fn restore<F>(&self, slot: Slot<F>) {
+        self.restore_method(slot.index());
+    }
+}
@@ -0,0 +1,46 @@
+//! Errors returned by hooks that modify protected memory.
+
+use std::fmt;
+use std::io;
+
+/// Error returned by fallible hook operations.
+#[derive(Debug)]
+pub enum Error {
+    /// Changing the page protection of a VTable failed.
+    Protection(io::Error),
+    /// Allocating the shadow VTable failed.
+    Allocation(io::Error),
+    /// The listed VTable slots no longer hold the values written by the hook.
+    Tampered(Vec<usize>),
+    /// The hook can't be installed as described.
+    Invalid(String),
+}
+
+/// Result type used by fallible hook operations.
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Protection(error) => write!(f, "failed to change page protection: {error}"),
+            Error::Allocation(error) => write!(f, "failed to allocate shadow vtable: {error}"),
+            Error::Tampered(slots) => write!(f, "vtable slots were modified externally: {slots:?}"),
+            Error::Invalid(reason) => write!(f, "invalid hook: {reason}"),
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Protection(error) | Error::Allocation(error) => Some(error),
+            Error::Tampered(_) | Error::Invalid(_) => None,
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Protection(error)
+    }
+}
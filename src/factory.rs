@@ -0,0 +1,406 @@
+//! Automatic hooking of objects returned by factory methods.
+//!
+//! [`FactoryWatcher`] replaces designated factory methods of one object. Every object (product) the
+//! factory hands out is hooked with the registered replacements, which covers entity factories,
+//! COM class factories and swapchain factories alike.
+
+use std::collections::HashMap;
+use std::ffi::c_void;
+use std::sync::{Mutex, MutexGuard};
+
+use crate::error::{Error, Result};
+use crate::slot::{FnPtr, Slot};
+use crate::VTableHook;
+
+/// Where a factory method stores the created object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FactoryOutput {
+    /// The object is the return value.
+    Return,
+    /// The object is written through the pointer passed as the argument at this index, `this` being 0.
+    Argument(usize),
+}
+
+/// Number of leading slots of a factory whose methods can be watched.
+pub const WATCHABLE_SLOTS: usize = 128;
+
+/// Factory method signatures that can be watched.
+///
+/// Implemented for `unsafe extern` function pointers taking `this: *mut c_void` and up to 8 more arguments.
+pub unsafe trait FactoryFn: FnPtr + 'static {
+    /// Replacements of every watchable slot, forwarding to the original method and hooking the returned object.
+    #[doc(hidden)]
+    const HOOKS: [Self; WATCHABLE_SLOTS];
+}
+
+type ReleaseFn = unsafe extern "system" fn(*mut c_void) -> u32;
+
+const RELEASE: Slot<ReleaseFn> = Slot::new(2);
+
+struct Watcher {
+    hook: VTableHook<usize>,
+    replacements: Vec<(usize, usize)>,
+    product_count: Option<fn(*mut c_void) -> usize>,
+    com_lifetime: bool,
+}
+
+struct Product {
+    factory: usize,
+    hook: VTableHook<usize>,
+}
+
+#[derive(Default)]
+struct State {
+    watchers: HashMap<usize, Watcher>,
+    products: HashMap<usize, Product>,
+    // Removed only after the factory is restored, so that calls already inside our replacements are forwarded.
+    routes: HashMap<(usize, usize), (usize, FactoryOutput)>,
+    // The method the `Release` hook of a product with a COM lifetime forwards to.
+    // Removed only after the product is restored, like `routes`.
+    releases: HashMap<usize, usize>,
+}
+
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+
+fn with_state<R>(f: impl FnOnce(&mut State) -> R) -> R {
+    let mut state: MutexGuard<'static, Option<State>> = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    f(state.get_or_insert_with(State::default))
+}
+
+/// Returns the bits of a pointer-sized value, or 0 for values of other sizes.
+#[doc(hidden)]
+pub fn word<V>(value: &V) -> usize {
+    if std::mem::size_of::<V>() == std::mem::size_of::<usize>() {
+        unsafe { std::mem::transmute_copy(value) }
+    } else {
+        0
+    }
+}
+
+/// Looks up the original factory method and output of a watched slot.
+#[doc(hidden)]
+pub fn route<F: FactoryFn>(factory: *mut c_void, slot: usize) -> (F, FactoryOutput) {
+    let route = with_state(|state| state.routes.get(&(factory as usize, slot)).copied());
+    match route {
+        Some((original, output)) => (unsafe { F::from_address(original) }, output),
+        // Only our replacements call this, so the route always exists.
+        None => std::process::abort(),
+    }
+}
+
+/// Hooks the object produced by a call of a watched factory method.
+#[doc(hidden)]
+pub unsafe fn produced(factory: *mut c_void, output: FactoryOutput, result: usize, args: &[usize]) {
+    let product = match output {
+        FactoryOutput::Return => result,
+        FactoryOutput::Argument(index) => match args.get(index) {
+            Some(&out) if out != 0 => *(out as *const usize),
+            _ => 0,
+        },
+    };
+    if product != 0 {
+        adopt_product(factory as usize, product as *mut c_void);
+    }
+}
+
+/// Builds an array of `$at!(slot)` for every watchable slot.
+macro_rules! slot_table {
+    ($at:ident) => {
+        slot_table!(@ $at;
+            0 1 2 3 4 5 6 7 8 9 10 11 12 13 14 15
+            16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31
+            32 33 34 35 36 37 38 39 40 41 42 43 44 45 46 47
+            48 49 50 51 52 53 54 55 56 57 58 59 60 61 62 63
+            64 65 66 67 68 69 70 71 72 73 74 75 76 77 78 79
+            80 81 82 83 84 85 86 87 88 89 90 91 92 93 94 95
+            96 97 98 99 100 101 102 103 104 105 106 107 108 109 110 111
+            112 113 114 115 116 117 118 119 120 121 122 123 124 125 126 127
+        )
+    };
+    (@ $at:ident; $($slot:literal)*) => {
+        [$($at!($slot)),*]
+    };
+}
+
+macro_rules! impl_factory_fn {
+    ($abi:literal: $($arg:ident),*) => {
+        unsafe impl<R: 'static, $($arg: 'static),*> FactoryFn for unsafe extern $abi fn(*mut c_void, $($arg),*) -> R {
+            const HOOKS: [Self; WATCHABLE_SLOTS] = {
+                #[allow(non_snake_case)]
+                unsafe extern $abi fn hook<const SLOT: usize, R: 'static, $($arg: 'static),*>(
+                    this: *mut c_void,
+                    $($arg: $arg),*
+                ) -> R {
+                    let (original, output) = route::<unsafe extern $abi fn(*mut c_void, $($arg),*) -> R>(this, SLOT);
+                    let args = [this as usize, $(word(&$arg)),*];
+                    let result = original(this, $($arg),*);
+                    produced(this, output, word(&result), &args);
+                    result
+                }
+                macro_rules! at {
+                    ($slot:literal) => {
+                        hook::<$slot, R, $($arg),*>
+                    };
+                }
+                slot_table!(at)
+            };
+        }
+    };
+}
+
+macro_rules! impl_factory_fn_all {
+    ($abi:literal) => {
+        impl_factory_fn!($abi:);
+        impl_factory_fn!($abi: A1);
+        impl_factory_fn!($abi: A1, A2);
+        impl_factory_fn!($abi: A1, A2, A3);
+        impl_factory_fn!($abi: A1, A2, A3, A4);
+        impl_factory_fn!($abi: A1, A2, A3, A4, A5);
+        impl_factory_fn!($abi: A1, A2, A3, A4, A5, A6);
+        impl_factory_fn!($abi: A1, A2, A3, A4, A5, A6, A7);
+        impl_factory_fn!($abi: A1, A2, A3, A4, A5, A6, A7, A8);
+    };
+}
+
+impl_factory_fn_all!("C");
+impl_factory_fn_all!("system");
+#[cfg(target_arch = "x86")]
+impl_factory_fn_all!("stdcall");
+#[cfg(target_arch = "x86")]
+impl_factory_fn_all!("fastcall");
+#[cfg(target_arch = "x86")]
+impl_factory_fn_all!("thiscall");
+
+/// Returns the original method of an object created by a watched factory.
+pub fn product_original_method(product: *mut c_void, id: usize) -> Option<usize> {
+    with_state(|state| {
+        state
+            .products
+            .get(&(product as usize))
+            .map(|product| product.hook.get_original_method(id))
+    })
+}
+
+/// Returns the original method of the slot of an object created by a watched factory.
+pub unsafe fn get_product_original<F: FnPtr>(product: *mut c_void, slot: Slot<F>) -> Option<F> {
+    product_original_method(product, slot.index()).map(|address| F::from_address(address))
+}
+
+unsafe fn adopt_product(factory: usize, product: *mut c_void) {
+    let Some(product_count) = with_state(|state| {
+        if state.products.contains_key(&(product as usize)) {
+            return None;
+        }
+        state.watchers.get(&factory).map(|watcher| watcher.product_count)
+    }) else {
+        return;
+    };
+    // Counting may call into the object, so it is done without holding the lock.
+    let count = product_count.map(|count| count(product));
+
+    with_state(|state| {
+        let Some(watcher) = state.watchers.get(&factory) else {
+            return;
+        };
+        let hook = match count {
+            Some(count) => VTableHook::with_count(product as usize, count),
+            None => VTableHook::new(product as usize),
+        };
+        let mut release = hook.get_original_method(RELEASE.index());
+        for &(id, func) in &watcher.replacements {
+            if watcher.com_lifetime && id == RELEASE.index() {
+                release = func;
+            } else {
+                hook.replace_method(id, func);
+            }
+        }
+        if watcher.com_lifetime {
+            // A replacement of `Release` itself is called by our hook instead of being overwritten by it.
+            state.releases.insert(product as usize, release);
+            hook.replace(RELEASE, product_release);
+        }
+        state.products.insert(product as usize, Product { factory, hook });
+    });
+}
+
+unsafe extern "system" fn product_release(this: *mut c_void) -> u32 {
+    let release = with_state(|state| {
+        let product = state.products.get(&(this as usize));
+        let original = || product.map(|product| product.hook.get_original_method(RELEASE.index()));
+        state.releases.get(&(this as usize)).copied().or_else(original)
+    });
+    // Without an entry the object is already restored, so its own VTable holds the original.
+    let release = release.unwrap_or_else(|| *(*(this as *const *const usize)).add(RELEASE.index()));
+    let count = ReleaseFn::from_address(release)(this);
+    if count == 0 {
+        // The object is destroyed, so its original VTable must not be written back.
+        forget_product(this as usize);
+    }
+    count
+}
+
+/// Drops the hook of a destroyed product without writing its original VTable back.
+fn forget_product(address: usize) {
+    if let Some(product) = with_state(|state| state.products.remove(&address)) {
+        unsafe { product.hook.detach() };
+    }
+    with_state(|state| state.releases.remove(&address));
+}
+
+/// Hooks factory methods of an object and every object they create.
+///
+/// # Example
+///
+/// ```rust,ignore
+/// use vmt_hook::factory::{FactoryOutput, FactoryWatcher};
+///
+/// // IClassFactory::CreateInstance(this, outer, riid, ppv).
+/// let watcher = FactoryWatcher::with_count(class_factory, 5);
+/// watcher.watch(CREATE_INSTANCE, FactoryOutput::Argument(3))?;
+/// watcher.com_lifetime();
+/// watcher.replace_method(7, hk_method as usize);
+/// ```
+pub struct FactoryWatcher {
+    factory: usize,
+}
+
+impl FactoryWatcher {
+    /// Hooks the factory object. The count of methods is automatically determined.
+    pub unsafe fn new(factory: *mut c_void) -> Self {
+        Self::init(VTableHook::new(factory as usize))
+    }
+
+    /// Hooks the factory object with a specified method count.
+    pub unsafe fn with_count(factory: *mut c_void, count: usize) -> Self {
+        Self::init(VTableHook::with_count(factory as usize, count))
+    }
+
+    fn init(hook: VTableHook<usize>) -> Self {
+        let factory = *hook.object();
+        let watcher = Watcher {
+            hook,
+            replacements: Vec::new(),
+            product_count: None,
+            com_lifetime: false,
+        };
+        with_state(|state| state.watchers.insert(factory, watcher));
+        Self { factory }
+    }
+
+    /// Replaces the factory method of the slot, hooking every object it creates.
+    ///
+    /// The slot must be one of the first [`WATCHABLE_SLOTS`] and can be watched only once.
+    pub unsafe fn watch<F: FactoryFn>(&self, slot: Slot<F>, output: FactoryOutput) -> Result<()> {
+        let Some(&hook) = F::HOOKS.get(slot.index()) else {
+            return Err(Error::Invalid(format!("slot {} is not one of the watchable slots", slot.index())));
+        };
+        with_state(|state| {
+            let Some(watcher) = state.watchers.get(&self.factory) else {
+                return Ok(());
+            };
+            let key = (self.factory, slot.index());
+            if state.routes.contains_key(&key) {
+                return Err(Error::Invalid(format!("slot {} is already watched", slot.index())));
+            }
+            state.routes.insert(key, (watcher.hook.get_original_method(slot.index()), output));
+            watcher.hook.replace(slot, hook);
+            Ok(())
+        })
+    }
+
+    /// Sets the function determining the method count of created objects instead of automatic detection.
+    pub fn product_count(&self, count: fn(*mut c_void) -> usize) {
+        with_state(|state| {
+            if let Some(watcher) = state.watchers.get_mut(&self.factory) {
+                watcher.product_count = Some(count);
+            }
+        });
+    }
+
+    /// Treats created objects as COM objects, releasing their hooks together with their last reference.
+    ///
+    /// A registered replacement of `Release` is still called, by the hook tracking the references.
+    pub fn com_lifetime(&self) {
+        with_state(|state| {
+            if let Some(watcher) = state.watchers.get_mut(&self.factory) {
+                watcher.com_lifetime = true;
+            }
+        });
+    }
+
+    /// Registers a replacement, applied to existing and future objects created by the factory.
+    pub unsafe fn replace_method(&self, id: usize, func: usize) {
+        with_state(|state| {
+            if let Some(watcher) = state.watchers.get_mut(&self.factory) {
+                watcher.replacements.retain(|&(replaced, _)| replaced != id);
+                watcher.replacements.push((id, func));
+            }
+            for (address, product) in state.products.iter().filter(|(_, product)| product.factory == self.factory) {
+                match state.releases.get_mut(address) {
+                    Some(release) if id == RELEASE.index() => *release = func,
+                    _ => product.hook.replace_method(id, func),
+                }
+            }
+        });
+    }
+
+    /// Registers a replacement of the slot, applied to existing and future objects created by the factory.
+    pub unsafe fn replace<F: FnPtr>(&self, slot: Slot<F>, func: F) {
+        self.replace_method(slot.index(), func.to_address());
+    }
+
+    /// Hooks an object created before the factory was watched.
+    pub unsafe fn adopt(&self, product: *mut c_void) {
+        adopt_product(self.factory, product);
+    }
+
+    /// Releases the hook of a destroyed object without restoring its original VTable.
+    pub unsafe fn forget(&self, product: *mut c_void) {
+        forget_product(product as usize);
+    }
+
+    /// Returns the addresses of all hooked objects created by the factory.
+    pub fn products(&self) -> Vec<usize> {
+        with_state(|state| {
+            state
+                .products
+                .iter()
+                .filter(|(_, product)| product.factory == self.factory)
+                .map(|(&address, _)| address)
+                .collect()
+        })
+    }
+
+    /// Returns the original factory method at the specified index.
+    pub fn get_original_method(&self, id: usize) -> Option<usize> {
+        with_state(|state| state.watchers.get(&self.factory).map(|watcher| watcher.hook.get_original_method(id)))
+    }
+}
+
+impl Drop for FactoryWatcher {
+    /// Restoring the factory and all of its objects.
+    fn drop(&mut self) {
+        let (watcher, keys, products) = with_state(|state| {
+            let watcher = state.watchers.remove(&self.factory);
+            let keys = state
+                .products
+                .iter()
+                .filter(|(_, product)| product.factory == self.factory)
+                .map(|(&address, _)| address)
+                .collect::<Vec<_>>();
+            let products = keys
+                .iter()
+                .filter_map(|address| state.products.remove(address))
+                .collect::<Vec<_>>();
+            (watcher, keys, products)
+        });
+        drop(products);
+        drop(watcher);
+        with_state(|state| {
+            state.routes.retain(|&(factory, _), _| factory != self.factory);
+            for address in &keys {
+                state.releases.remove(address);
+            }
+        });
+    }
+}
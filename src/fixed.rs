@@ -0,0 +1,209 @@
+//! Hooks keeping their shadow VTable inline, for contexts where the heap can't be used.
+
+use core::ffi::c_void;
+use core::marker::PhantomPinned;
+use core::pin::Pin;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+use crate::slot::{FnPtr, Slot};
+
+/// Copies the VTable of an object into room for `N` methods inside the hook itself, never allocating,
+/// so that hooks can be installed from `DllMain`, APCs or early loader stages.
+///
+/// The hooked object points into the hook, which is why it is installed through a [`Pin`]: declare it
+/// with [`static_hook!`](crate::static_hook) and install it with [`install_static`](Self::install_static),
+/// or pin it in a frame that outlives its use. The hook is restored on drop or by
+/// [`uninstall`](Self::uninstall), after which it can be installed again with the table at the same address.
+/// Observers and the audit log don't see these hooks, as recording allocates.
+pub struct FixedVTableHook<const N: usize> {
+    /// Address of the hooked object's VTable pointer, null while not installed.
+    vptr: AtomicPtr<*const usize>,
+    /// The VTable the object pointed at when it was hooked.
+    original_vtable: AtomicPtr<usize>,
+    /// Number of methods copied into the table.
+    count: AtomicUsize,
+    /// New VTable containing hooked function address.
+    table: [AtomicUsize; N],
+    _pinned: PhantomPinned,
+}
+
+impl<const N: usize> Default for FixedVTableHook<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const N: usize> Drop for FixedVTableHook<N> {
+    /// Restoring the original VTable.
+    fn drop(&mut self) {
+        unsafe {
+            self.uninstall();
+        }
+    }
+}
+
+impl<const N: usize> FixedVTableHook<N> {
+    /// Creates a hook that isn't installed on any object yet.
+    pub const fn new() -> Self {
+        Self {
+            vptr: AtomicPtr::new(ptr::null_mut()),
+            original_vtable: AtomicPtr::new(ptr::null_mut()),
+            count: AtomicUsize::new(0),
+            table: [const { AtomicUsize::new(0) }; N],
+            _pinned: PhantomPinned,
+        }
+    }
+
+    /// Copies the first `count` methods of the VTable of `object` and points the object at the copy.
+    /// Returns `false` if the hook is already installed.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `count` exceeds the capacity `N`.
+    pub unsafe fn install(self: Pin<&Self>, object: *mut c_void, count: usize) -> bool {
+        assert!(count <= N, "method count exceeds the capacity of the fixed hook");
+        let vptr = object as *mut *const usize;
+        let claimed = self.vptr.compare_exchange(ptr::null_mut(), vptr, Ordering::SeqCst, Ordering::SeqCst);
+        if claimed.is_err() {
+            return false;
+        }
+        let original = *vptr;
+        for (id, method) in self.table[..count].iter().enumerate() {
+            method.store(*original.add(id), Ordering::SeqCst);
+        }
+        self.original_vtable.store(original.cast_mut(), Ordering::SeqCst);
+        self.count.store(count, Ordering::SeqCst);
+        *vptr = self.table.as_ptr().cast();
+        true
+    }
+
+    /// Installs a hook living in static memory; see [`install`](Self::install).
+    pub unsafe fn install_static(&'static self, object: *mut c_void, count: usize) -> bool {
+        Pin::static_ref(self).install(object, count)
+    }
+
+    /// Points the object back at its original VTable. Returns `false` if the hook wasn't installed.
+    pub unsafe fn uninstall(&self) -> bool {
+        let vptr = self.vptr.swap(ptr::null_mut(), Ordering::SeqCst);
+        if vptr.is_null() {
+            return false;
+        }
+        *vptr = self.original_vtable.load(Ordering::SeqCst);
+        true
+    }
+
+    /// Forgets the object without restoring its VTable, e.g. once it has been destroyed.
+    /// Returns `false` if the hook wasn't installed.
+    pub fn detach(&self) -> bool {
+        !self.vptr.swap(ptr::null_mut(), Ordering::SeqCst).is_null()
+    }
+
+    /// Returns `true` if the hook is installed and the object still points at its table.
+    pub unsafe fn is_installed(&self) -> bool {
+        let vptr = self.vptr.load(Ordering::SeqCst);
+        !vptr.is_null() && ptr::eq(*vptr, self.table.as_ptr().cast())
+    }
+
+    /// Returns the number of methods the table has room for.
+    pub const fn capacity(&self) -> usize {
+        N
+    }
+
+    /// Returns the number of methods copied by the last installation.
+    pub fn len(&self) -> usize {
+        self.count.load(Ordering::SeqCst)
+    }
+
+    /// Returns `true` if no methods were copied.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the address of the VTable the object pointed at when it was hooked.
+    pub fn original_vtable(&self) -> usize {
+        self.original_vtable.load(Ordering::SeqCst) as usize
+    }
+
+    /// Returns the original method address at the specified index in the VTable.
+    pub fn get_original_method(&self, id: usize) -> usize {
+        assert!(id < self.len(), "method index out of bounds");
+        unsafe { *self.original_vtable.load(Ordering::SeqCst).add(id) }
+    }
+
+    /// Returns the replaced method address at the specified index in the VTable.
+    pub fn get_replaced_method(&self, id: usize) -> usize {
+        self.table[..self.len()][id].load(Ordering::SeqCst)
+    }
+
+    /// Hooks the method at the specified index in the VTable with a new function address.
+    pub unsafe fn replace_method(&self, id: usize, func: usize) {
+        self.table[..self.len()][id].store(func, Ordering::SeqCst);
+    }
+
+    /// Restores the original method at the specified index in the VTable.
+    pub unsafe fn restore_method(&self, id: usize) {
+        self.replace_method(id, self.get_original_method(id));
+    }
+
+    /// Points the method at index `dst` at the original implementation of the method at index `src`.
+    pub unsafe fn alias_method(&self, dst: usize, src: usize) {
+        self.replace_method(dst, self.get_original_method(src));
+    }
+
+    /// Restores all methods in the VTable to their original address.
+    pub unsafe fn restore_all_methods(&self) {
+        for id in 0..self.len() {
+            self.restore_method(id);
+        }
+    }
+
+    /// Returns the original method of the slot.
+    pub unsafe fn get_original<F: FnPtr>(&self, slot: Slot<F>) -> F {
+        F::from_address(self.get_original_method(slot.index()))
+    }
+
+    /// Returns the replaced method of the slot.
+    pub unsafe fn get_replaced<F: FnPtr>(&self, slot: Slot<F>) -> F {
+        F::from_address(self.get_replaced_method(slot.index()))
+    }
+
+    /// Hooks the method of the slot with a new function.
+    pub unsafe fn replace<F: FnPtr>(&self, slot: Slot<F>, func: F) {
+        self.replace_method(slot.index(), func.to_address());
+    }
+
+    /// Points the slot `dst` at the original implementation of the slot `src` of the same type.
+    pub unsafe fn alias<F>(&self, dst: Slot<F>, src: Slot<F>) {
+        self.alias_method(dst.index(), src.index());
+    }
+
+    /// Restores the original method of the slot.
+    pub unsafe fn restore<F>(&self, slot: Slot<F>) {
+        self.restore_method(slot.index());
+    }
+}
+
+/// Declares [`FixedVTableHook`]s in static memory with room for the methods of known classes, so that
+/// installing them performs no allocation and their tables keep the same address across installs.
+///
+/// ```rust,ignore
+/// vmt_hook::static_hook! {
+///     /// Hook of `IDirect3DDevice9`, which has 119 methods.
+///     pub static DEVICE_HOOK: 119;
+/// }
+///
+/// unsafe fn on_attach(device: *mut c_void) {
+///     DEVICE_HOOK.install_static(device, 119);
+///     DEVICE_HOOK.replace_method(17, hk_present as usize);
+/// }
+/// ```
+#[macro_export]
+macro_rules! static_hook {
+    ($($(#[$attr:meta])* $vis:vis static $name:ident: $count:expr;)*) => {
+        $(
+            $(#[$attr])*
+            $vis static $name: $crate::FixedVTableHook<{ $count }> = $crate::FixedVTableHook::new();
+        )*
+    };
+}
@@ -0,0 +1,99 @@
+//! Locating and hooking live instances of a class.
+//!
+//! A per-instance hook only affects the object it was installed on. When a game keeps dozens of
+//! objects of a class, the heaps are scanned for objects whose first word is the class VTable.
+//! On Windows the process heaps are walked block by block and only block starts are considered.
+//! Elsewhere every pointer-aligned word of the writable anonymous mappings is a candidate, so
+//! results can include stale copies of the pointer and should be validated with a filter.
+
+use crate::{sys, SharedCopySwap, VTableHook};
+
+/// Returns the addresses of heap objects whose vptr is `vtable`.
+pub unsafe fn find_instances(vtable: usize) -> Vec<usize> {
+    #[cfg(windows)]
+    loop {
+        // Allocating would change the heap being walked, so the instances are counted first and
+        // then stored without growing the vector, starting over if more appeared in between.
+        let is_instance = |address: usize, size: usize| {
+            size >= std::mem::size_of::<usize>()
+                && address.is_multiple_of(std::mem::align_of::<usize>())
+                && std::ptr::read(address as *const usize) == vtable
+        };
+        let mut count = 0;
+        sys::heap_blocks(|address, size| count += usize::from(is_instance(address, size)));
+
+        let mut instances = Vec::with_capacity(count);
+        let mut complete = true;
+        sys::heap_blocks(|address, size| {
+            if !is_instance(address, size) {
+                return;
+            }
+            if instances.len() < instances.capacity() {
+                instances.push(address);
+            } else {
+                complete = false;
+            }
+        });
+        if complete {
+            return instances;
+        }
+    }
+
+    #[cfg(unix)]
+    {
+        let mut instances = Vec::new();
+        sys::heap_regions(|address, size| {
+            let words = size / std::mem::size_of::<usize>();
+            for i in 0..words {
+                let address = address + i * std::mem::size_of::<usize>();
+                if std::ptr::read(address as *const usize) == vtable {
+                    instances.push(address);
+                }
+            }
+        });
+        instances
+    }
+}
+
+/// Hooks every heap object whose vptr is `vtable` and which passes `filter`,
+/// installing the same replacements on each of them.
+pub unsafe fn hook_all_instances(
+    vtable: usize,
+    count: usize,
+    replacements: &[(usize, usize)],
+    mut filter: impl FnMut(usize) -> bool,
+) -> Vec<VTableHook<usize>> {
+    find_instances(vtable)
+        .into_iter()
+        .filter(|&object| filter(object))
+        .map(|object| {
+            let hook = VTableHook::with_count(object, count);
+            for &(id, func) in replacements {
+                hook.replace_method(id, func);
+            }
+            hook
+        })
+        .collect()
+}
+
+/// Like [`hook_all_instances`], with every object pointed at a single shared copy of the VTable;
+/// see [`SharedCopySwap`].
+pub unsafe fn hook_all_instances_shared(
+    vtable: usize,
+    count: usize,
+    replacements: &[(usize, usize)],
+    mut filter: impl FnMut(usize) -> bool,
+) -> Vec<VTableHook<usize, SharedCopySwap>> {
+    let hooks: Vec<VTableHook<usize, SharedCopySwap>> = find_instances(vtable)
+        .into_iter()
+        .filter(|&object| filter(object))
+        .map(|object| VTableHook::with_backend_and_count(object, count))
+        .collect();
+    // The replacements land in the shared copy, so applying them through one hook is enough.
+    if let Some(hook) = hooks.first() {
+        for &(id, func) in replacements {
+            hook.replace_method(id, func);
+        }
+    }
+    hooks
+}
@@ -0,0 +1,277 @@
+//! Class-wide hooking by patching the original VTable in place.
+//!
+//! Unlike [`VTableHook`](crate::VTableHook), which swaps the vptr of a single object,
+//! [`InPlaceVmtHook`] writes into the shared table itself, so every instance of the class
+//! is affected, including ones created later.
+
+use std::cell::UnsafeCell;
+use std::mem::ManuallyDrop;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::audit;
+use crate::error::{Error, Result};
+use crate::observer;
+use crate::rtti;
+use crate::slot::{FnPtr, Slot};
+use crate::sys;
+
+/// Hooks a VTable by patching its entries directly.
+///
+/// Dropping the hook restores every slot that still holds the value written by the hook.
+pub struct InPlaceVmtHook {
+    /// Pointer to the patched VTable.
+    vtable: *mut usize,
+    /// Entries of the VTable at the time the hook was created, or when it was last revalidated.
+    original: UnsafeCell<Box<[usize]>>,
+    /// Entries the VTable is expected to contain.
+    expected: UnsafeCell<Box<[usize]>>,
+    /// Whether operations are recorded in the [`audit`] log and reported to observers; the hooks of
+    /// [`InPlacePatch`](crate::InPlacePatch) are recorded by their [`VTableHook`](crate::VTableHook) instead.
+    audited: bool,
+    /// Whether the VTable is in writable memory and written without changing page protections.
+    writable: bool,
+}
+
+unsafe impl Send for InPlaceVmtHook {}
+
+impl Drop for InPlaceVmtHook {
+    /// Restoring the original entries.
+    fn drop(&mut self) {
+        unsafe {
+            let _ = self.restore_owned();
+        }
+        self.record(audit::Operation::Uninstall);
+    }
+}
+
+impl InPlaceVmtHook {
+    /// Creates a hook for the VTable at `vtable` with `count` methods.
+    pub unsafe fn new(vtable: *mut usize, count: usize) -> Self {
+        let mut hook = Self::unaudited(vtable, count);
+        hook.audited = true;
+        hook.record(audit::Operation::Install { vtable: vtable as usize, count });
+        hook
+    }
+
+    /// Creates a hook whose operations aren't recorded in the [`audit`] log nor reported to observers.
+    pub(crate) unsafe fn unaudited(vtable: *mut usize, count: usize) -> Self {
+        let original: Box<[usize]> = std::slice::from_raw_parts(vtable, count).into();
+
+        Self {
+            vtable,
+            expected: UnsafeCell::new(original.clone()),
+            original: UnsafeCell::new(original),
+            audited: false,
+            writable: false,
+        }
+    }
+
+    /// Creates a hook for a VTable an engine built at runtime in writable memory, such as a JIT or
+    /// script runtime. Slots are written atomically without touching page protections, and
+    /// [`revalidate`](Self::revalidate) puts the replacements back after the engine rebuilds the table.
+    ///
+    /// Fails with [`Error::Invalid`] if the VTable isn't writable.
+    pub unsafe fn new_writable(vtable: *mut usize, count: usize) -> Result<Self> {
+        if !sys::is_writable(vtable as usize) {
+            return Err(Error::Invalid("vtable isn't in writable memory".into()));
+        }
+        let mut hook = Self::new(vtable, count);
+        hook.writable = true;
+        Ok(hook)
+    }
+
+    fn record(&self, operation: audit::Operation) {
+        if self.audited {
+            observer::emit(self.vtable as usize, operation);
+        }
+    }
+
+    /// Creates a hook for the VTable of the provided object with `count` methods.
+    pub unsafe fn from_object<T>(object: &T, count: usize) -> Self {
+        Self::new(*std::mem::transmute_copy::<T, *const *mut usize>(object), count)
+    }
+
+    /// Creates a hook for the VTable of the class named `class` in the loaded module `module`, found through
+    /// its RTTI by [`rtti::find_vtable`] without needing an instance.
+    ///
+    /// Fails with [`Error::Invalid`] if the module isn't loaded or has no VTable for the class.
+    pub unsafe fn for_class(class: &str, module: &str) -> Result<Self> {
+        let found = rtti::find_vtable(module, class)
+            .ok_or_else(|| Error::Invalid(format!("no vtable for {class} in {module}")))?;
+        Ok(Self::new(found.vtable as *mut usize, found.count))
+    }
+
+    /// Returns the address of the patched VTable.
+    pub fn vtable(&self) -> *mut usize {
+        self.vtable
+    }
+
+    /// Returns the number of methods covered by the hook.
+    pub fn len(&self) -> usize {
+        self.original().len()
+    }
+
+    /// Returns `true` if the hook covers no methods.
+    pub fn is_empty(&self) -> bool {
+        self.original().is_empty()
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    fn original(&self) -> &mut [usize] {
+        unsafe { &mut *self.original.get() }
+    }
+
+    #[allow(clippy::mut_from_ref)]
+    fn expected(&self) -> &mut [usize] {
+        unsafe { &mut *self.expected.get() }
+    }
+
+    unsafe fn entry(&self, id: usize) -> &AtomicUsize {
+        assert!(id < self.len(), "slot {id} is out of bounds");
+        AtomicUsize::from_ptr(self.vtable.add(id))
+    }
+
+    unsafe fn write(&self, id: usize, func: usize) -> Result<()> {
+        let entry = self.entry(id);
+        if self.writable {
+            entry.store(func, Ordering::SeqCst);
+        } else {
+            sys::with_writable(entry.as_ptr() as usize, std::mem::size_of::<usize>(), || {
+                entry.store(func, Ordering::SeqCst)
+            })?;
+        }
+        self.expected()[id] = func;
+        Ok(())
+    }
+
+    /// Returns the original method address at the specified index in the VTable.
+    pub fn get_original_method(&self, id: usize) -> usize {
+        self.original()[id]
+    }
+
+    /// Returns the original method address at the specified index with import and incremental-linking
+    /// stubs followed to the function they forward to; see [`resolve_thunk`](crate::detect::resolve_thunk).
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    pub unsafe fn get_resolved_original_method(&self, id: usize) -> usize {
+        crate::detect::resolve_thunk(self.get_original_method(id))
+    }
+
+    /// Returns the method address currently stored at the specified index in the VTable.
+    pub unsafe fn get_replaced_method(&self, id: usize) -> usize {
+        self.entry(id).load(Ordering::SeqCst)
+    }
+
+    /// Hooks the method at the specified index in the VTable with a new function address.
+    pub unsafe fn replace_method(&self, id: usize, func: usize) -> Result<()> {
+        let old = self.get_replaced_method(id);
+        self.write(id, func)?;
+        self.record(audit::Operation::Replace { id, old, new: func });
+        Ok(())
+    }
+
+    /// Restores the original method at the specified index in the VTable.
+    pub unsafe fn restore_method(&self, id: usize) -> Result<()> {
+        let old = self.get_replaced_method(id);
+        self.write(id, self.original()[id])?;
+        self.record(audit::Operation::Restore { id, old, new: self.original()[id] });
+        Ok(())
+    }
+
+    /// Points the method at index `dst` at the original implementation of the method at index `src`.
+    pub unsafe fn alias_method(&self, dst: usize, src: usize) -> Result<()> {
+        self.replace_method(dst, self.original()[src])
+    }
+
+    /// Restores all methods in the VTable to their original address.
+    pub unsafe fn restore_all_methods(&self) -> Result<()> {
+        for id in 0..self.len() {
+            if self.expected()[id] != self.original()[id] {
+                self.write(id, self.original()[id])?;
+            }
+        }
+        self.record(audit::Operation::RestoreAll);
+        Ok(())
+    }
+
+    /// Returns the original method at the given slot.
+    pub unsafe fn get_original<F: FnPtr>(&self, slot: Slot<F>) -> F {
+        F::from_address(self.get_original_method(slot.index()))
+    }
+
+    /// Hooks the method at the given slot.
+    pub unsafe fn replace<F: FnPtr>(&self, slot: Slot<F>, func: F) -> Result<()> {
+        self.replace_method(slot.index(), func.to_address())
+    }
+
+    /// Restores the original method at the given slot.
+    pub unsafe fn restore<F>(&self, slot: Slot<F>) -> Result<()> {
+        self.restore_method(slot.index())
+    }
+
+    /// Checks that every slot still holds the value written by the hook.
+    pub unsafe fn verify(&self) -> Result<()> {
+        let tampered = self.tampered_slots();
+        if tampered.is_empty() {
+            Ok(())
+        } else {
+            self.record(audit::Operation::Tamper { slots: tampered.clone() });
+            Err(Error::Tampered(tampered))
+        }
+    }
+
+    /// Adopts the entries the engine rewrote since they were last written as the new originals, and writes
+    /// the replacements over the hooked ones again. Returns the slots whose replacement was put back.
+    pub unsafe fn revalidate(&self) -> Result<Vec<usize>> {
+        let mut reapplied = Vec::new();
+        for id in 0..self.len() {
+            let live = self.get_replaced_method(id);
+            let expected = self.expected()[id];
+            if live == expected {
+                continue;
+            }
+            if expected == self.original()[id] {
+                self.expected()[id] = live;
+            } else {
+                self.write(id, expected)?;
+                self.record(audit::Operation::Replace { id, old: live, new: expected });
+                reapplied.push(id);
+            }
+            self.original()[id] = live;
+        }
+        Ok(reapplied)
+    }
+
+    unsafe fn tampered_slots(&self) -> Vec<usize> {
+        (0..self.len()).filter(|&id| self.get_replaced_method(id) != self.expected()[id]).collect()
+    }
+
+    /// Restores the original entries and checks that the table matches its original contents byte for byte.
+    ///
+    /// Slots changed by someone else since they were hooked are left alone and reported as [`Error::Tampered`].
+    pub unsafe fn unhook(self) -> Result<()> {
+        // The entries are restored here instead of on drop, but the copies still have to be freed.
+        let mut hook = ManuallyDrop::new(self);
+        let result = hook.restore_owned();
+        hook.record(audit::Operation::Uninstall);
+        drop(std::ptr::read(&hook.original));
+        drop(std::ptr::read(&hook.expected));
+        result
+    }
+
+    unsafe fn restore_owned(&mut self) -> Result<()> {
+        let tampered = self.tampered_slots();
+        for id in 0..self.len() {
+            if !tampered.contains(&id) && self.expected()[id] != self.original()[id] {
+                self.write(id, self.original()[id])?;
+            }
+        }
+
+        let table = std::slice::from_raw_parts(self.vtable as *const u8, std::mem::size_of_val(self.original()));
+        let original = std::slice::from_raw_parts(self.original().as_ptr() as *const u8, table.len());
+        if table == original {
+            Ok(())
+        } else {
+            Err(Error::Tampered(tampered))
+        }
+    }
+}
@@ -1,13 +1,139 @@
 //! This library provides the ability to hook Virtual Method Tables (VMT).
-//! It works by copying the original VMT and then swapping it out with the modified version.
+//! By default it works by copying the original VMT and then swapping it out with the modified version;
+//! other mechanisms can be selected through [`HookBackend`].
+//!
+//! Without the default `std` feature the crate is `no_std`, needing only `alloc`, and offers just the
+//! copy-and-swap [`VTableHook`], the allocation-free [`FixedVTableHook`] and the typed [`slot`]s.
 
-use std::cell::UnsafeCell;
+#![cfg_attr(not(feature = "std"), no_std)]
+#![allow(clippy::missing_safety_doc)]
+
+extern crate alloc;
+
+#[cfg(feature = "std")]
+pub mod allocations;
+#[cfg(feature = "std")]
+pub mod audit;
+pub mod backend;
+#[cfg(feature = "std")]
+pub mod builder;
+#[cfg(feature = "std")]
+pub mod detect;
+#[cfg(feature = "std")]
+pub mod dump;
+#[cfg(feature = "std")]
+pub mod dyn_trait;
+#[cfg(feature = "std")]
+pub mod error;
+#[cfg(feature = "std")]
+pub mod factory;
+pub mod fixed;
+#[cfg(feature = "std")]
+pub mod heap;
+#[cfg(feature = "std")]
+pub mod in_place;
+#[cfg(feature = "std")]
+pub mod memory_map;
+#[cfg(feature = "std")]
+pub mod metrics;
+#[cfg(feature = "std")]
+pub mod minhook;
+#[cfg(feature = "std")]
+pub mod multi;
+#[cfg(feature = "std")]
+pub mod observer;
+#[cfg(feature = "std")]
+pub mod pattern;
+#[cfg(feature = "std")]
+pub mod reentrancy;
+#[cfg(feature = "std")]
+pub mod rehook;
+#[cfg(feature = "std")]
+pub mod rtti;
+pub mod shadow;
+pub mod slot;
+#[cfg(feature = "std")]
+pub mod symbol;
+#[cfg(feature = "std")]
+pub mod transaction;
+#[cfg(feature = "std")]
+pub mod watchdog;
+#[cfg(all(feature = "std", windows))]
+pub mod clr;
+#[cfg(all(feature = "closures", target_arch = "x86_64"))]
+pub mod closure;
+#[cfg(all(windows, feature = "com"))]
+pub mod com;
+#[cfg(feature = "delphi")]
+pub mod delphi;
+#[cfg(all(feature = "std", windows))]
+pub mod disk;
+#[cfg(all(windows, feature = "dxgi"))]
+pub mod dxgi;
+#[cfg(feature = "mlua")]
+pub mod lua;
+#[cfg(all(feature = "std", target_vendor = "apple"))]
+pub mod objc;
+#[cfg(all(feature = "std", target_arch = "x86_64"))]
+pub mod proxy;
+#[cfg(feature = "pyo3")]
+pub mod python;
+#[cfg(all(feature = "std", windows))]
+pub mod remote;
+#[cfg(feature = "source")]
+pub mod source;
+#[cfg(feature = "serde")]
+pub mod spec;
+#[cfg(feature = "steam")]
+pub mod steam;
+#[cfg(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))]
+pub mod stub;
+#[cfg(all(feature = "std", target_arch = "x86_64"))]
+pub mod thunk;
+#[cfg(feature = "uefi")]
+pub mod uefi;
+#[cfg(feature = "unreal")]
+pub mod unreal;
+#[cfg(feature = "vulkan")]
+pub mod vulkan;
+
+#[cfg(all(feature = "std", windows))]
+mod pe;
+#[cfg(feature = "std")]
+mod sys;
+#[cfg(feature = "tracing")]
+mod telemetry;
+#[cfg(all(feature = "std", windows, any(target_arch = "x86", target_arch = "x86_64")))]
+mod veh;
+#[cfg(all(feature = "std", any(target_arch = "x86", target_arch = "x86_64")))]
+mod x86;
+
+pub use backend::{CopySwap, HookBackend};
+#[cfg(feature = "std")]
+pub use backend::{InPlacePatch, LazyCopySwap, SharedCopySwap};
+#[cfg(all(feature = "std", windows, any(target_arch = "x86", target_arch = "x86_64")))]
+pub use backend::{HardwareBreakpoint, PageGuard};
+#[cfg(feature = "std")]
+pub use error::{Error, Result};
+pub use fixed::FixedVTableHook;
+#[cfg(feature = "std")]
+pub use in_place::InPlaceVmtHook;
+#[cfg(feature = "std")]
+pub use shadow::VTableCopyOptions;
+
+/// Reports an operation on a hook to the observers and the audit log, which need `std`.
+macro_rules! emit {
+    ($object:expr, $operation:expr) => {
+        #[cfg(feature = "std")]
+        observer::emit($object as usize, $operation);
+    };
+}
 
 /// Represents a structure responsible for hooking and managing the virtual function table (VTable) of a given type.
 ///
 /// # Example
 ///
-/// ```rust
+/// ```rust,ignore
 /// use vmt_hook::VTableHook;
 ///
 /// use windows::{
@@ -62,21 +188,36 @@ use std::cell::UnsafeCell;
 ///     hook.replace_method(17, hk_present as usize);
 /// }
 /// ````
-pub struct VTableHook<T> {
+pub struct VTableHook<T, B: HookBackend = CopySwap> {
     /// Pointer to the object whose VTable is being hooked.
     object: T,
-    /// Pointer to the original VTable.
-    original_vtbl: &'static [usize],
-    /// New VTable containing hooked function address.
-    new_vtbl: UnsafeCell<Vec<usize>>,
+    /// Mechanism redirecting the calls.
+    backend: B,
+    /// Address of the VTable the object pointed at when it was hooked.
+    original_vtable: usize,
 }
 
-impl<T> Drop for VTableHook<T> {
+impl<T, B: HookBackend> Drop for VTableHook<T, B> {
     /// Restoring the original VTable.
     fn drop(&mut self) {
         unsafe {
-            *std::mem::transmute_copy::<_, *mut *const usize>(&self.object) = self.original_vtbl.as_ptr();
+            self.backend.uninstall(self.vptr());
         }
+        emit!(self.vptr(), audit::Operation::Uninstall);
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T, B: HookBackend> std::fmt::Debug for VTableHook<T, B> {
+    /// Printing the object, the original VTable and every slot as `module+0xOFFSET`; see
+    /// [`dump`](Self::dump) for class and method names.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("VTableHook")
+            .field("object", &dump::Hex(self.vptr() as usize))
+            .field("vtable", &dump::Hex(self.original_vtable))
+            .field("count", &self.backend.count())
+            .field("slots", &dump::DebugSlots(&self.backend))
+            .finish()
     }
 }
 
@@ -84,12 +225,115 @@ impl<T> VTableHook<T> {
     /// Creates a new VTableHook instance for the provided object and replaces its VTable with the hooked VTable.
     /// The count of methods is automatically determined.
     pub unsafe fn new(object: T) -> Self {
-        Self::init(object, |vtable| Self::detect_vtable_methods_count(vtable))
+        Self::with_backend(object)
     }
 
     /// Creates a new VTableHook instance for the provided object with a specified method count
     /// and replaces its VTable with the hooked VTable.
     pub unsafe fn with_count(object: T, count: usize) -> Self {
+        Self::with_backend_and_count(object, count)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> VTableHook<T> {
+    /// Creates a new VTableHook instance like [`new`](Self::new), reading the object and its VTable with
+    /// [`probe_count`](detect::probe_count) first, so a pointer to something that isn't an object fails with
+    /// [`Error::Invalid`] instead of crashing the process.
+    pub unsafe fn try_new(object: T) -> Result<Self> {
+        let (vptr, vtable) = Self::probe_object(&object)?;
+        let count = detect::probe_count(vtable)
+            .ok_or_else(|| Error::Invalid(format!("vtable {vtable:p} of object {vptr:p} isn't readable")))?;
+        Self::with_count_and_options(object, count, &VTableCopyOptions::default())
+    }
+
+    /// Creates a new VTableHook instance like [`with_count`](Self::with_count), checking first that the object
+    /// and the `count` methods of its VTable are readable, so a bad pointer fails with [`Error::Invalid`].
+    pub unsafe fn try_with_count(object: T, count: usize) -> Result<Self> {
+        let (vptr, vtable) = Self::probe_object(&object)?;
+        if !sys::is_readable(vtable as usize, count * std::mem::size_of::<usize>()) {
+            let error = format!("{count} methods of vtable {vtable:p} of object {vptr:p} aren't readable");
+            return Err(Error::Invalid(error));
+        }
+        Self::with_count_and_options(object, count, &VTableCopyOptions::default())
+    }
+
+    /// Reads the vptr of `object`, failing if it isn't readable.
+    unsafe fn probe_object(object: &T) -> Result<(*const *const usize, *const usize)> {
+        let vptr = std::mem::transmute_copy::<T, *const *const usize>(object);
+        if sys::probe(vptr.cast()).is_none() {
+            return Err(Error::Invalid(format!("object {vptr:p} isn't readable")));
+        }
+        Ok((vptr, *vptr))
+    }
+
+    /// Creates a new VTableHook instance whose VTable copy is allocated as described by `options`.
+    /// The count of methods is automatically determined.
+    pub unsafe fn with_options(object: T, options: &VTableCopyOptions) -> Result<Self> {
+        Self::try_init(object, |vtable| Self::detect_vtable_methods_count(vtable), |vptr, vtable, count| {
+            CopySwap::with_options(vptr, vtable, count, options).map_err(Error::Allocation)
+        })
+    }
+
+    /// Creates a new VTableHook instance with a specified method count
+    /// whose VTable copy is allocated as described by `options`.
+    pub unsafe fn with_count_and_options(object: T, count: usize, options: &VTableCopyOptions) -> Result<Self> {
+        Self::try_init(object, |_| count, |vptr, vtable, count| {
+            CopySwap::with_options(vptr, vtable, count, options).map_err(Error::Allocation)
+        })
+    }
+
+    /// Makes the hooked VTable read-only; see [`CopySwap::freeze`].
+    pub fn freeze(&self) -> Result<()> {
+        Ok(self.backend.freeze()?)
+    }
+
+    /// Makes a frozen hooked VTable writable again; see [`CopySwap::thaw`].
+    pub fn thaw(&self) -> Result<()> {
+        Ok(self.backend.thaw()?)
+    }
+
+    /// Checks that nothing but this hook wrote into the hooked VTable; see [`CopySwap::verify`].
+    pub fn verify(&self) -> Result<()> {
+        let result = self.backend.verify();
+        if let Err(Error::Tampered(slots)) = &result {
+            observer::emit(self.vptr() as usize, audit::Operation::Tamper { slots: slots.clone() });
+        }
+        result
+    }
+
+    /// Returns a check of the hooked VTable that can run on other threads; see [`CopySwap::integrity_check`].
+    /// The check also watches the object's vptr, see [`IntegrityCheck::vptr_state`](shadow::IntegrityCheck::vptr_state).
+    pub fn integrity_check(&self) -> shadow::IntegrityCheck {
+        self.backend.integrity_check().with_object(self.vptr() as usize, self.original_vtable)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T> VTableHook<T, SharedCopySwap> {
+    /// Hooks the method for this object only, forking a private copy of the shared VTable first;
+    /// see [`SharedCopySwap::fork`].
+    pub unsafe fn replace_method_own(&self, id: usize, func: usize) {
+        self.backend.fork(self.vptr());
+        self.replace_method(id, func);
+    }
+
+    /// Restores the original method for this object only, forking a private copy of the shared VTable first.
+    pub unsafe fn restore_method_own(&self, id: usize) {
+        self.backend.fork(self.vptr());
+        self.restore_method(id);
+    }
+}
+
+impl<T, B: HookBackend> VTableHook<T, B> {
+    /// Creates a new VTableHook instance using the backend `B`.
+    /// The count of methods is automatically determined.
+    pub unsafe fn with_backend(object: T) -> Self {
+        Self::init(object, |vtable| Self::detect_vtable_methods_count(vtable))
+    }
+
+    /// Creates a new VTableHook instance using the backend `B` with a specified method count.
+    pub unsafe fn with_backend_and_count(object: T, count: usize) -> Self {
         Self::init(object, |_| count)
     }
 
@@ -97,19 +341,13 @@ impl<T> VTableHook<T> {
     where
         F: FnOnce(*const usize) -> usize
     {
-        let object_ptr = std::mem::transmute_copy::<T, *mut *const usize>(&object);
+        let object_ptr = core::mem::transmute_copy::<T, *mut *const usize>(&object);
         let original_vtbl = *object_ptr;
         let count = count_fn(original_vtbl);
-        let original_vtbl = std::slice::from_raw_parts(original_vtbl, count);
-        let new_vtbl = original_vtbl.to_vec();
-
-        *object_ptr = new_vtbl.as_ptr();
+        let backend = B::install(object_ptr, original_vtbl, count);
+        emit!(object_ptr, audit::Operation::Install { vtable: original_vtbl as usize, count });
 
-        Self {
-            object,
-            original_vtbl,
-            new_vtbl: UnsafeCell::new(new_vtbl),
-        }
+        Self { object, backend, original_vtable: original_vtbl as usize }
     }
 
     /// Detects the number of methods in the provided VTable.
@@ -117,45 +355,150 @@ impl<T> VTableHook<T> {
         let mut vmt = vtable;
 
         // Todo: Maybe add a memory region length check?
-        while std::ptr::read(vmt) != 0 {
+        while core::ptr::read(vmt) != 0 {
             vmt = vmt.add(1);
         }
 
-        (vmt as usize - vtable as usize) / std::mem::size_of::<usize>()
+        (vmt as usize - vtable as usize) / core::mem::size_of::<usize>()
     }
 
-    /// Returns our hooked vtable.
-    fn vtbl(&self) -> &mut Vec<usize> {
-        unsafe { &mut *self.new_vtbl.get() }
+    /// Returns the address of the object's VTable pointer.
+    pub(crate) fn vptr(&self) -> *mut *const usize {
+        unsafe { core::mem::transmute_copy::<T, *mut *const usize>(&self.object) }
     }
 
     /// Returns the original method address at the specified index in the VTable.
     pub fn get_original_method(&self, id: usize) -> usize {
-        self.original_vtbl[id]
+        self.backend.original(id)
     }
 
     /// Returns the replaced method address at the specified index in the VTable.
     pub fn get_replaced_method(&self, id: usize) -> usize {
-        self.vtbl()[id]
+        self.backend.replaced(id)
+    }
+
+    /// Returns the method stored at the specified index in the original VTable right now, including hooks
+    /// others patched into it after this one was installed.
+    ///
+    /// Backends keeping their own copy of the original entries return that copy from
+    /// [`get_original_method`](Self::get_original_method) instead. With [`InPlacePatch`] it is the hook itself.
+    pub unsafe fn get_live_original_method(&self, id: usize) -> usize {
+        core::ptr::read_volatile((self.original_vtable as *const usize).add(id))
     }
 
     /// Hooks the method at the specified index in the VTable with a new function address.
     pub unsafe fn replace_method(&self, id: usize, func: usize) {
-        self.vtbl()[id] = func;
+        #[cfg(feature = "std")]
+        let old = self.backend.replaced(id);
+        self.backend.replace(id, func);
+        emit!(self.vptr(), audit::Operation::Replace { id, old, new: func });
     }
 
     /// Restores the original method at the specified index in the VTable.
     pub unsafe fn restore_method(&self, id: usize) {
-        self.vtbl()[id] = self.get_original_method(id);
+        #[cfg(feature = "std")]
+        let old = self.backend.replaced(id);
+        let new = self.get_original_method(id);
+        self.backend.replace(id, new);
+        emit!(self.vptr(), audit::Operation::Restore { id, old, new });
+    }
+
+    /// Points the method at index `dst` at the original implementation of the method at index `src`,
+    /// e.g. to redirect `PresentEx` to `Present`.
+    pub unsafe fn alias_method(&self, dst: usize, src: usize) {
+        self.replace_method(dst, self.get_original_method(src));
     }
 
     /// Restores all methods in the VTable to their original address.
     pub unsafe fn restore_all_methods(&self) {
-        self.vtbl().copy_from_slice(self.original_vtbl);
+        self.backend.restore_all();
+        emit!(self.vptr(), audit::Operation::RestoreAll);
+    }
+
+    /// Returns the address of the VTable the object pointed at when it was hooked.
+    pub fn original_vtable(&self) -> usize {
+        self.original_vtable
     }
 
     /// Returns the original object.
     pub fn object(&self) -> &T {
         &self.object
     }
+
+    /// Returns the backend redirecting the calls.
+    pub fn backend(&self) -> &B {
+        &self.backend
+    }
+
+    /// Returns `true` if the object still points at our hooked VTable.
+    pub unsafe fn is_installed(&self) -> bool {
+        self.backend.is_installed(self.vptr())
+    }
+
+    /// Releases the hook without restoring the original VTable and returns the object.
+    /// Used when the object has already been destroyed.
+    pub unsafe fn detach(self) -> T {
+        emit!(self.vptr(), audit::Operation::Detach);
+        let this = core::mem::ManuallyDrop::new(self);
+        drop(core::ptr::read(&this.backend));
+        core::ptr::read(&this.object)
+    }
+}
+
+#[cfg(feature = "std")]
+impl<T, B: HookBackend> VTableHook<T, B> {
+    unsafe fn try_init<F, I>(object: T, count_fn: F, install: I) -> Result<Self>
+    where
+        F: FnOnce(*const usize) -> usize,
+        I: FnOnce(*mut *const usize, *const usize, usize) -> Result<B>
+    {
+        let object_ptr = std::mem::transmute_copy::<T, *mut *const usize>(&object);
+        let original_vtbl = *object_ptr;
+        let count = count_fn(original_vtbl);
+        let backend = install(object_ptr, original_vtbl, count)?;
+        observer::emit(object_ptr as usize, audit::Operation::Install { vtable: original_vtbl as usize, count });
+
+        Ok(Self { object, backend, original_vtable: original_vtbl as usize })
+    }
+
+    /// Returns the original method address at the specified index with import and incremental-linking
+    /// stubs followed to the function they forward to; see [`resolve_thunk`](crate::detect::resolve_thunk).
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    pub unsafe fn get_resolved_original_method(&self, id: usize) -> usize {
+        crate::detect::resolve_thunk(self.get_original_method(id))
+    }
+
+    /// Dumps the original and current methods of the table, named after the modules and symbols they lie in;
+    /// see [`dump`](crate::dump).
+    pub unsafe fn dump(&self) -> dump::TableDump {
+        let count = self.backend.count();
+        let original: Vec<usize> = (0..count).map(|id| self.backend.original(id)).collect();
+        let current: Vec<usize> = (0..count).map(|id| self.backend.replaced(id)).collect();
+        dump::TableDump::new(Some(self.vptr() as usize), self.original_vtable, &original, &current)
+    }
+
+    /// Checks that every original method lies in the module containing the original VTable;
+    /// see [`check_owner`](crate::detect::check_owner).
+    pub unsafe fn check_owner(&self) -> detect::OwnerReport {
+        let methods: Vec<usize> = (0..self.backend.count()).map(|id| self.backend.original(id)).collect();
+        detect::check_owner(self.original_vtable as *const usize, &methods)
+    }
+
+    /// Returns the original methods that differ from the file of the module owning the VTable,
+    /// revealing hooks installed by others before this one; see [`disk`](crate::disk).
+    #[cfg(windows)]
+    pub unsafe fn diff_with_disk(&self) -> std::io::Result<Vec<disk::SlotDiff>> {
+        let methods: Vec<usize> = (0..self.backend.count()).map(|id| self.backend.original(id)).collect();
+        disk::DiskImage::of(self.original_vtable)?.diff_methods(self.original_vtable as *const usize, &methods)
+    }
+
+    /// Returns where the object's vptr points, telling a destructor switching to base class VTables
+    /// apart from another tool replacing ours.
+    pub unsafe fn vptr_state(&self) -> detect::VptrState {
+        if self.is_installed() {
+            detect::VptrState::Hooked
+        } else {
+            detect::classify_vptr(*self.vptr() as usize, self.original_vtable)
+        }
+    }
 }
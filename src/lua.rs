@@ -0,0 +1,170 @@
+//! Lua bindings letting embedded scripts declare VTable hooks.
+//!
+//! Scripts can only install replacements that Rust registered by name with [`register_detour`],
+//! and the hooks they create are owned by Rust-side userdata that restores the table when it is
+//! collected or explicitly unhooked. Method indices are 0-based, like everywhere else in the crate.
+//!
+//! `call_original` passes pointer-sized integer arguments. On 32-bit x86 methods are called as `thiscall`
+//! on Windows and `cdecl` elsewhere, matching the C++ compilers of the platform; methods with another
+//! convention, such as the `stdcall` methods of COM interfaces, must be declared with `abi` first.
+//! Elsewhere there is only one convention and `abi` has no effect.
+//!
+//! ```lua
+//! local hook = vmt_hook.hook(device, 119)
+//! hook:replace(17, "present")
+//! hook:abi(17, "stdcall")
+//! local result = hook:call_original(17, 0, 0, 0, 0)
+//! hook:unhook()
+//! ```
+
+use std::collections::HashMap;
+
+use mlua::{Lua, Result, Table, UserData, UserDataMethods, Variadic};
+
+use crate::slot::FnPtr;
+use crate::{HookBackend, VTableHook};
+
+/// Replacements registered from Rust, by name.
+#[derive(Default)]
+struct Detours(HashMap<String, usize>);
+
+/// Makes `func` installable by scripts under `name`.
+pub fn register_detour<F: FnPtr>(lua: &Lua, name: &str, func: F) {
+    if lua.app_data_ref::<Detours>().is_none() {
+        lua.set_app_data(Detours::default());
+    }
+    if let Some(mut detours) = lua.app_data_mut::<Detours>() {
+        detours.0.insert(name.to_owned(), func.to_address());
+    }
+}
+
+/// A hook created by a script, with the calling conventions declared for its methods.
+struct LuaHook(Option<VTableHook<usize>>, HashMap<usize, Abi>);
+
+impl LuaHook {
+    fn get(&self) -> Result<&VTableHook<usize>> {
+        self.0.as_ref().ok_or_else(|| mlua::Error::runtime("hook was removed"))
+    }
+
+    fn checked(&self, index: usize) -> Result<&VTableHook<usize>> {
+        let hook = self.get()?;
+        if index >= hook.backend().count() {
+            return Err(mlua::Error::runtime(format!("method index {index} is out of bounds")));
+        }
+        Ok(hook)
+    }
+}
+
+/// Calling convention of a method called by a script.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Abi {
+    Cdecl,
+    Stdcall,
+    Thiscall,
+}
+
+impl Abi {
+    /// Convention of virtual methods compiled by the platform's C++ compiler.
+    const METHOD: Self = if cfg!(all(windows, target_arch = "x86")) { Self::Thiscall } else { Self::Cdecl };
+
+    fn parse(name: &str) -> Result<Self> {
+        match name {
+            "C" | "cdecl" => Ok(Self::Cdecl),
+            "stdcall" | "system" => Ok(Self::Stdcall),
+            "thiscall" => Ok(Self::Thiscall),
+            _ => Err(mlua::Error::runtime(format!("unknown calling convention '{name}'"))),
+        }
+    }
+}
+
+macro_rules! call_as {
+    ($abi:literal, $func:expr, $this:expr, $args:expr) => {{
+        type Original0 = unsafe extern $abi fn(usize) -> usize;
+        type Original1 = unsafe extern $abi fn(usize, usize) -> usize;
+        type Original2 = unsafe extern $abi fn(usize, usize, usize) -> usize;
+        type Original3 = unsafe extern $abi fn(usize, usize, usize, usize) -> usize;
+        type Original4 = unsafe extern $abi fn(usize, usize, usize, usize, usize) -> usize;
+        type Original5 = unsafe extern $abi fn(usize, usize, usize, usize, usize, usize) -> usize;
+        type Original6 = unsafe extern $abi fn(usize, usize, usize, usize, usize, usize, usize) -> usize;
+
+        let (func, this) = ($func, $this);
+        match *$args {
+            [] => Original0::from_address(func)(this),
+            [a] => Original1::from_address(func)(this, a),
+            [a, b] => Original2::from_address(func)(this, a, b),
+            [a, b, c] => Original3::from_address(func)(this, a, b, c),
+            [a, b, c, d] => Original4::from_address(func)(this, a, b, c, d),
+            [a, b, c, d, e] => Original5::from_address(func)(this, a, b, c, d, e),
+            [a, b, c, d, e, f] => Original6::from_address(func)(this, a, b, c, d, e, f),
+            _ => return Err(mlua::Error::runtime("at most 6 arguments are supported")),
+        }
+    }};
+}
+
+/// Calls `func` with the calling convention `abi`, `this` first and pointer-sized integer arguments.
+unsafe fn call(abi: Abi, func: usize, this: usize, args: &[usize]) -> Result<usize> {
+    Ok(match abi {
+        #[cfg(target_arch = "x86")]
+        Abi::Stdcall => call_as!("stdcall", func, this, args),
+        #[cfg(target_arch = "x86")]
+        Abi::Thiscall => call_as!("thiscall", func, this, args),
+        // Outside of x86 every convention is the C one.
+        _ => call_as!("C", func, this, args),
+    })
+}
+
+impl UserData for LuaHook {
+    fn add_methods<M: UserDataMethods<Self>>(methods: &mut M) {
+        methods.add_method("replace", |lua, this, (index, name): (usize, String)| {
+            let hook = this.checked(index)?;
+            let detours = lua.app_data_ref::<Detours>();
+            let func = detours
+                .as_ref()
+                .and_then(|detours| detours.0.get(&name).copied())
+                .ok_or_else(|| mlua::Error::runtime(format!("no detour named '{name}'")))?;
+            unsafe { hook.replace_method(index, func) };
+            Ok(())
+        });
+        methods.add_method("restore", |_, this, index: usize| {
+            unsafe { this.checked(index)?.restore_method(index) };
+            Ok(())
+        });
+        methods.add_method("restore_all", |_, this, ()| {
+            unsafe { this.get()?.restore_all_methods() };
+            Ok(())
+        });
+        methods.add_method("original", |_, this, index: usize| Ok(this.checked(index)?.get_original_method(index)));
+        methods.add_method("count", |_, this, ()| Ok(this.get()?.backend().count()));
+        methods.add_method_mut("abi", |_, this, (index, name): (usize, String)| {
+            this.checked(index)?;
+            this.1.insert(index, Abi::parse(&name)?);
+            Ok(())
+        });
+        methods.add_method("call_original", |_, this, (index, args): (usize, Variadic<usize>)| {
+            let hook = this.checked(index)?;
+            let abi = this.1.get(&index).copied().unwrap_or(Abi::METHOD);
+            unsafe { call(abi, hook.get_original_method(index), *hook.object(), &args) }
+        });
+        methods.add_method_mut("unhook", |_, this, ()| {
+            this.0 = None;
+            Ok(())
+        });
+    }
+}
+
+/// Creates the `vmt_hook` module table. Store it in a global or return it from a `require` loader.
+pub fn module(lua: &Lua) -> Result<Table> {
+    let module = lua.create_table()?;
+    module.set(
+        "hook",
+        lua.create_function(|_, (object, count): (usize, Option<usize>)| {
+            let hook = match count {
+                None => unsafe { VTableHook::try_new(object) },
+                Some(count) => unsafe { VTableHook::try_with_count(object, count) },
+            };
+            let hook = hook.map_err(|error| mlua::Error::runtime(error.to_string()))?;
+            Ok(LuaHook(Some(hook), HashMap::new()))
+        })?,
+    )?;
+    Ok(module)
+}
@@ -0,0 +1,113 @@
+//! A cached map of the process's memory regions, for installing many hooks at once.
+//!
+//! Detection and validation check object and VTable pointers against the memory map, which costs a
+//! `VirtualQuery` or a read of `/proc/self/maps` each time. Once [`enable`]d, those checks answer from a
+//! snapshot taken on first use instead. Addresses outside of it, such as memory mapped since, are still
+//! looked up, and protection changes made by the crate are looked up again too; [`invalidate`] drops the
+//! snapshot after others unmap memory or change protections, as unloading a module does.
+
+use std::ops::Range;
+use std::sync::Mutex;
+
+use crate::sys;
+
+/// Ranges changed since the snapshot was taken after which it is taken again.
+const MAX_STALE: usize = 64;
+
+/// A committed region of the address space.
+#[derive(Debug, Clone, Copy)]
+// Only Windows looks up executable pages and modules through the cache.
+#[cfg_attr(not(windows), allow(dead_code))]
+pub(crate) struct Region {
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    pub(crate) readable: bool,
+    pub(crate) writable: bool,
+    pub(crate) executable: bool,
+    /// Base of the module image the region belongs to, where the platform reports it.
+    pub(crate) module: Option<usize>,
+}
+
+struct Cache {
+    enabled: bool,
+    /// Regions sorted by address, taken on the first lookup.
+    regions: Option<Vec<Region>>,
+    /// Ranges whose protection the crate changed since.
+    stale: Vec<Range<usize>>,
+}
+
+static CACHE: Mutex<Cache> = Mutex::new(Cache { enabled: false, regions: None, stale: Vec::new() });
+
+/// Makes memory queries answer from a snapshot of the memory map.
+pub fn enable() {
+    CACHE.lock().unwrap_or_else(|e| e.into_inner()).enabled = true;
+}
+
+/// Makes memory queries ask the platform again, dropping the snapshot.
+pub fn disable() {
+    let mut cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    cache.enabled = false;
+    cache.regions = None;
+    cache.stale.clear();
+}
+
+/// Returns `true` if memory queries answer from the snapshot.
+pub fn is_enabled() -> bool {
+    CACHE.lock().unwrap_or_else(|e| e.into_inner()).enabled
+}
+
+/// Drops the snapshot, so that the next query takes it again.
+pub fn invalidate() {
+    let mut cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    cache.regions = None;
+    cache.stale.clear();
+}
+
+/// Records that the crate changed the protection of `address..address + size` or unmapped it.
+pub(crate) fn invalidate_range(address: usize, size: usize) {
+    let mut cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    if cache.regions.is_none() {
+        return;
+    }
+    if cache.stale.len() == MAX_STALE {
+        cache.regions = None;
+        cache.stale.clear();
+    } else {
+        cache.stale.push(address..address.saturating_add(size.max(1)));
+    }
+}
+
+/// Calls `f` with the regions covering `address..address + size`, or returns `None` if the cache is
+/// disabled or part of the range isn't covered by the snapshot.
+fn lookup<R>(address: usize, size: usize, f: impl FnOnce(&[Region]) -> R) -> Option<R> {
+    let mut cache = CACHE.lock().unwrap_or_else(|e| e.into_inner());
+    if !cache.enabled {
+        return None;
+    }
+    let end = address.saturating_add(size.max(1));
+    if cache.stale.iter().any(|stale| stale.start < end && address < stale.end) {
+        return None;
+    }
+    let regions = cache.regions.get_or_insert_with(|| unsafe { sys::regions() });
+    let first = regions.partition_point(|region| region.end <= address);
+    let mut at = address;
+    let mut last = first;
+    while at < end {
+        match regions.get(last) {
+            Some(region) if region.start <= at => at = region.end,
+            _ => return None,
+        }
+        last += 1;
+    }
+    Some(f(&regions[first..last]))
+}
+
+/// Returns whether every page of `address..address + size` is readable, if the snapshot knows.
+pub(crate) fn is_readable(address: usize, size: usize) -> Option<bool> {
+    lookup(address, size, |regions| regions.iter().all(|region| region.readable))
+}
+
+/// Returns the region containing `address`, if the snapshot knows it.
+pub(crate) fn region_of(address: usize) -> Option<Region> {
+    lookup(address, 1, |regions| regions[0])
+}
@@ -0,0 +1,121 @@
+//! Counters summarizing the hooks of the process, e.g. for a debug overlay.
+//!
+//! The counts are kept from the operations of every [`VTableHook`](crate::VTableHook) and
+//! [`InPlaceVmtHook`](crate::InPlaceVmtHook), whether or not the [`audit`](crate::audit) log is enabled.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::audit::Operation;
+
+/// A snapshot of the hook counters, returned by [`stats`].
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct Stats {
+    /// Number of hooks currently installed.
+    pub installed_hooks: usize,
+    /// Number of methods currently replaced across all hooks.
+    pub replaced_slots: usize,
+    /// Number of verifications that found tampered slots since the process started.
+    pub tamper_events: usize,
+    /// Call counts of the called methods instrumented by a live [`CallCounter`](crate::thunk::CallCounter).
+    pub calls: Vec<SlotCalls>,
+    /// Durations of the called methods instrumented by a live [`CallTimer`](crate::thunk::CallTimer).
+    pub timings: Vec<SlotTiming>,
+}
+
+/// The number of calls to one method.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotCalls {
+    /// Address of the object's VTable pointer.
+    pub object: usize,
+    /// Index of the method in the VTable.
+    pub slot: usize,
+    /// Calls counted since the method was instrumented or the counter was reset.
+    pub calls: u64,
+}
+
+/// The durations of the calls to one method.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SlotTiming {
+    /// Address of the object's VTable pointer.
+    pub object: usize,
+    /// Index of the method in the VTable.
+    pub slot: usize,
+    /// Calls timed since the method was instrumented or the timer was reset.
+    pub calls: u64,
+    /// Combined duration of the calls.
+    pub total: Duration,
+    /// Duration of the shortest call.
+    pub min: Duration,
+    /// Duration of the longest call.
+    pub max: Duration,
+}
+
+impl SlotTiming {
+    /// Returns the mean duration of the calls.
+    pub fn mean(&self) -> Duration {
+        match self.calls {
+            0 => Duration::ZERO,
+            calls => Duration::from_nanos((self.total.as_nanos() / calls as u128) as u64),
+        }
+    }
+}
+
+struct Counters {
+    /// Replaced slots of every installed hook, by object.
+    hooks: BTreeMap<usize, BTreeSet<usize>>,
+    tamper_events: usize,
+}
+
+static COUNTERS: Mutex<Counters> = Mutex::new(Counters {
+    hooks: BTreeMap::new(),
+    tamper_events: 0,
+});
+
+/// Returns the current hook counters.
+pub fn stats() -> Stats {
+    let counters = COUNTERS.lock().unwrap_or_else(|e| e.into_inner());
+    Stats {
+        installed_hooks: counters.hooks.len(),
+        replaced_slots: counters.hooks.values().map(BTreeSet::len).sum(),
+        tamper_events: counters.tamper_events,
+        #[cfg(target_arch = "x86_64")]
+        calls: crate::thunk::slot_calls(),
+        #[cfg(not(target_arch = "x86_64"))]
+        calls: Vec::new(),
+        #[cfg(target_arch = "x86_64")]
+        timings: crate::thunk::slot_timings(),
+        #[cfg(not(target_arch = "x86_64"))]
+        timings: Vec::new(),
+    }
+}
+
+/// Updates the counters with `operation` on `object`.
+pub(crate) fn record(object: usize, operation: &Operation) {
+    let mut counters = COUNTERS.lock().unwrap_or_else(|e| e.into_inner());
+    match *operation {
+        Operation::Install { .. } => {
+            counters.hooks.insert(object, BTreeSet::new());
+        }
+        Operation::Replace { id, .. } => {
+            counters.hooks.entry(object).or_default().insert(id);
+        }
+        Operation::Restore { id, .. } => {
+            if let Some(slots) = counters.hooks.get_mut(&object) {
+                slots.remove(&id);
+            }
+        }
+        Operation::RestoreAll => {
+            if let Some(slots) = counters.hooks.get_mut(&object) {
+                slots.clear();
+            }
+        }
+        Operation::Uninstall | Operation::Detach => {
+            counters.hooks.remove(&object);
+        }
+        Operation::Tamper { .. } => counters.tamper_events += 1,
+        Operation::Panic { .. } => {}
+    }
+}
@@ -0,0 +1,110 @@
+//! Interop with MinHook-managed hooks for codebases migrating from minhook-rs.
+//!
+//! MinHook describes a hook as a `(target, detour, original)` triple, where `original` is the
+//! address to call to reach the unhooked function. [`MhHook`] uses the same shape, so hooks
+//! can move between both libraries and mixed C/Rust projects can keep a single inventory.
+
+use std::ffi::c_void;
+
+use crate::{HookBackend, VTableHook};
+
+/// Status codes of MinHook's `MH_STATUS`.
+#[repr(i32)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MhStatus {
+    Unknown = -1,
+    Ok = 0,
+    ErrorAlreadyInitialized = 1,
+    ErrorNotInitialized = 2,
+    ErrorAlreadyCreated = 3,
+    ErrorNotCreated = 4,
+    ErrorEnabled = 5,
+    ErrorDisabled = 6,
+    ErrorNotExecutable = 7,
+    ErrorUnsupportedFunction = 8,
+    ErrorMemoryAlloc = 9,
+    ErrorMemoryProtect = 10,
+    ErrorModuleNotFound = 11,
+    ErrorFunctionNotFound = 12,
+}
+
+/// A hook in MinHook's `(target, detour, original)` shape.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MhHook {
+    /// Address of the hooked function.
+    pub target: *mut c_void,
+    /// Address of the function called instead.
+    pub detour: *mut c_void,
+    /// Address to call to reach the original function.
+    pub original: *mut c_void,
+}
+
+/// Hooks the method at the specified index the way `MH_CreateHook` does:
+/// `detour` replaces it and the original method is written to `original`, if not null.
+///
+/// Returns [`MhStatus::ErrorAlreadyCreated`] if the method is already replaced
+/// and [`MhStatus::ErrorFunctionNotFound`] if the index is out of bounds.
+pub unsafe fn create_hook<T, B: HookBackend>(
+    hook: &VTableHook<T, B>,
+    id: usize,
+    detour: *mut c_void,
+    original: *mut *mut c_void,
+) -> MhStatus {
+    if id >= hook.backend().count() {
+        return MhStatus::ErrorFunctionNotFound;
+    }
+    if hook.get_replaced_method(id) != hook.get_original_method(id) {
+        return MhStatus::ErrorAlreadyCreated;
+    }
+
+    hook.replace_method(id, detour as usize);
+    if !original.is_null() {
+        *original = hook.get_original_method(id) as *mut c_void;
+    }
+    MhStatus::Ok
+}
+
+/// Restores the method at the specified index the way `MH_RemoveHook` does.
+pub unsafe fn remove_hook<T, B: HookBackend>(hook: &VTableHook<T, B>, id: usize) -> MhStatus {
+    if id >= hook.backend().count() {
+        return MhStatus::ErrorFunctionNotFound;
+    }
+    if hook.get_replaced_method(id) == hook.get_original_method(id) {
+        return MhStatus::ErrorNotCreated;
+    }
+
+    hook.restore_method(id);
+    MhStatus::Ok
+}
+
+/// Returns every replaced method of the hook in MinHook's shape.
+pub fn export<T, B: HookBackend>(hook: &VTableHook<T, B>) -> Vec<MhHook> {
+    (0..hook.backend().count())
+        .filter(|&id| hook.get_replaced_method(id) != hook.get_original_method(id))
+        .map(|id| MhHook {
+            target: hook.get_original_method(id) as *mut c_void,
+            detour: hook.get_replaced_method(id) as *mut c_void,
+            original: hook.get_original_method(id) as *mut c_void,
+        })
+        .collect()
+}
+
+/// Creates a hook for the object that takes over MinHook hooks of its methods.
+///
+/// Each hook whose `target` is a method of the object's VTable has its detour installed in that slot.
+/// Returns the slot index of every hook, or `None` for targets that aren't in the table.
+/// The MinHook hooks must be removed afterwards, and callers should reach the original methods
+/// through [`VTableHook::get_original_method`] instead of the MinHook trampolines.
+pub unsafe fn adopt<T>(object: T, count: usize, hooks: &[MhHook]) -> (VTableHook<T>, Vec<Option<usize>>) {
+    let hook = VTableHook::with_count(object, count);
+    let ids = hooks
+        .iter()
+        .map(|mh| {
+            let id = (0..count).find(|&id| hook.get_original_method(id) == mh.target as usize)?;
+            hook.replace_method(id, mh.detour as usize);
+            Some(id)
+        })
+        .collect();
+    (hook, ids)
+}
@@ -0,0 +1,155 @@
+//! Hooking a batch of objects with the same replacements.
+//!
+//! [`MultiInstanceHook`] suits entity lists: every object is hooked with the registered replacements,
+//! objects with the same VTable share one copy of it, and all of them are restored together.
+
+use std::ffi::c_void;
+
+use crate::{SharedCopySwap, VTableHook};
+
+/// A set of replacements applied to many objects, whose hooks live and die together.
+///
+/// Objects are hooked with [`SharedCopySwap`], so instances of the same class take a single copy of
+/// the VTable. Dropping the set restores every object still in it.
+pub struct MultiInstanceHook {
+    hooks: Vec<VTableHook<usize, SharedCopySwap>>,
+    count: Option<usize>,
+    replacements: Vec<(usize, usize)>,
+}
+
+impl MultiInstanceHook {
+    /// Hooks every object with the replacements, given as method index and function address pairs.
+    /// The counts of methods are automatically determined.
+    pub unsafe fn install(objects: &[*mut c_void], replacements: &[(usize, usize)]) -> Self {
+        Self::with_replacements(objects, None, replacements)
+    }
+
+    /// Hooks every object with the replacements, for VTables with a specified method count.
+    pub unsafe fn install_with_count(objects: &[*mut c_void], count: usize, replacements: &[(usize, usize)]) -> Self {
+        Self::with_replacements(objects, Some(count), replacements)
+    }
+
+    unsafe fn with_replacements(
+        objects: &[*mut c_void],
+        count: Option<usize>,
+        replacements: &[(usize, usize)],
+    ) -> Self {
+        let mut hooks = Self {
+            hooks: Vec::with_capacity(objects.len()),
+            count,
+            replacements: Vec::new(),
+        };
+        for &(id, func) in replacements {
+            hooks.replacements.retain(|&(replaced, _)| replaced != id);
+            hooks.replacements.push((id, func));
+        }
+        for &object in objects {
+            hooks.add(object);
+        }
+        hooks
+    }
+
+    /// Hooks one more object with the replacements, e.g. a newly spawned entity.
+    ///
+    /// Returns `false` if the object is already in the set.
+    pub unsafe fn add(&mut self, object: *mut c_void) -> bool {
+        if self.contains(object) {
+            return false;
+        }
+        let hook = match self.count {
+            Some(count) => VTableHook::with_backend_and_count(object as usize, count),
+            None => VTableHook::with_backend(object as usize),
+        };
+        for &(id, func) in &self.replacements {
+            hook.replace_method(id, func);
+        }
+        self.hooks.push(hook);
+        true
+    }
+
+    fn position(&self, object: *mut c_void) -> Option<usize> {
+        self.hooks.iter().position(|hook| *hook.object() == object as usize)
+    }
+
+    /// Removes an object from the set, restoring its original VTable.
+    ///
+    /// Returns `false` if the object isn't in the set.
+    pub fn remove(&mut self, object: *mut c_void) -> bool {
+        match self.position(object) {
+            Some(index) => {
+                self.hooks.swap_remove(index);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Removes a destroyed object from the set without touching its memory.
+    ///
+    /// Returns `false` if the object isn't in the set.
+    pub unsafe fn forget(&mut self, object: *mut c_void) -> bool {
+        match self.position(object) {
+            Some(index) => {
+                self.hooks.swap_remove(index).detach();
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Registers a replacement and applies it to every object.
+    pub unsafe fn replace_method(&mut self, id: usize, func: usize) {
+        self.replacements.retain(|&(replaced, _)| replaced != id);
+        self.replacements.push((id, func));
+        for hook in &self.hooks {
+            hook.replace_method(id, func);
+        }
+    }
+
+    /// Unregisters a replacement and restores the original method of every object.
+    pub unsafe fn restore_method(&mut self, id: usize) {
+        self.replacements.retain(|&(replaced, _)| replaced != id);
+        for hook in &self.hooks {
+            hook.restore_method(id);
+        }
+    }
+
+    /// Returns `true` if the object is in the set.
+    pub fn contains(&self, object: *mut c_void) -> bool {
+        self.position(object).is_some()
+    }
+
+    /// Returns the objects in the set.
+    pub fn objects(&self) -> impl Iterator<Item = *mut c_void> + '_ {
+        self.hooks.iter().map(|hook| *hook.object() as *mut c_void)
+    }
+
+    /// Returns the hooks of the objects in the set.
+    pub fn hooks(&self) -> &[VTableHook<usize, SharedCopySwap>] {
+        &self.hooks
+    }
+
+    /// Returns the number of objects in the set.
+    pub fn len(&self) -> usize {
+        self.hooks.len()
+    }
+
+    /// Returns `true` if the set holds no objects.
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// Returns the number of distinct VTable copies the objects point at.
+    pub fn tables(&self) -> usize {
+        let mut tables: Vec<usize> =
+            self.hooks.iter().map(|hook| unsafe { *(*hook.object() as *const usize) }).collect();
+        tables.sort_unstable();
+        tables.dedup();
+        tables.len()
+    }
+
+    /// Returns the original method of `object` at the specified index.
+    pub fn get_original_method(&self, object: *mut c_void, id: usize) -> Option<usize> {
+        Some(self.hooks[self.position(object)?].get_original_method(id))
+    }
+}
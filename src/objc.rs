@@ -0,0 +1,152 @@
+//! Objective-C method swizzling, the counterpart of VTable hooking for Objective-C classes.
+//!
+//! Methods are resolved by selector in the class method lists of the Objective-C runtime
+//! instead of by index in a C++ VTable. [`ClassSwizzle`] mirrors [`VTableHook`](crate::VTableHook):
+//! it remembers the original implementations and restores them on drop.
+
+use std::collections::HashMap;
+use std::ffi::{c_char, c_void, CString};
+
+use crate::slot::FnPtr;
+
+type Class = *mut c_void;
+type Sel = *const c_void;
+type Method = *mut c_void;
+type Imp = *const c_void;
+
+#[link(name = "objc", kind = "dylib")]
+extern "C" {
+    fn objc_getClass(name: *const c_char) -> Class;
+    fn object_getClass(object: *const c_void) -> Class;
+    fn sel_registerName(name: *const c_char) -> Sel;
+    fn class_getInstanceMethod(class: Class, selector: Sel) -> Method;
+    fn class_addMethod(class: Class, selector: Sel, imp: Imp, types: *const c_char) -> i8;
+    fn method_getImplementation(method: Method) -> Imp;
+    fn method_setImplementation(method: Method, imp: Imp) -> Imp;
+    fn method_getTypeEncoding(method: Method) -> *const c_char;
+}
+
+/// Returns the class with the given name.
+pub fn class(name: &str) -> Option<*mut c_void> {
+    let name = CString::new(name).ok()?;
+    let class = unsafe { objc_getClass(name.as_ptr()) };
+    (!class.is_null()).then_some(class)
+}
+
+/// Returns the metaclass of a class, whose methods are the class methods.
+pub unsafe fn metaclass(class: *mut c_void) -> *mut c_void {
+    object_getClass(class)
+}
+
+/// Returns the selector with the given name, such as `viewDidLoad` or `setObject:forKey:`.
+pub fn selector(name: &str) -> Option<*const c_void> {
+    let name = CString::new(name).ok()?;
+    Some(unsafe { sel_registerName(name.as_ptr()) })
+}
+
+/// Swizzles the instance methods of an Objective-C class.
+///
+/// Methods the class inherits are overridden on the class itself, so the superclass is left untouched.
+/// Swizzle the metaclass returned by [`metaclass`] to hook class methods.
+pub struct ClassSwizzle {
+    class: *mut c_void,
+    /// Swizzled methods with their original implementation.
+    originals: HashMap<usize, (Method, Imp)>,
+}
+
+unsafe impl Send for ClassSwizzle {}
+
+impl Drop for ClassSwizzle {
+    /// Restoring the original implementations.
+    fn drop(&mut self) {
+        unsafe {
+            self.restore_all_methods();
+        }
+    }
+}
+
+impl ClassSwizzle {
+    /// Creates a swizzle for the given class.
+    pub unsafe fn new(class: *mut c_void) -> Self {
+        Self {
+            class,
+            originals: HashMap::new(),
+        }
+    }
+
+    /// Creates a swizzle for the class with the given name.
+    pub unsafe fn for_class(name: &str) -> Option<Self> {
+        Some(Self::new(class(name)?))
+    }
+
+    /// Returns the swizzled class.
+    pub fn class(&self) -> *mut c_void {
+        self.class
+    }
+
+    /// Returns the method of the class itself, overriding an inherited one if needed.
+    unsafe fn own_method(&self, selector: Sel) -> Option<Method> {
+        let method = class_getInstanceMethod(self.class, selector);
+        if method.is_null() {
+            return None;
+        }
+        // Fails when the class already implements the method itself.
+        class_addMethod(
+            self.class,
+            selector,
+            method_getImplementation(method),
+            method_getTypeEncoding(method),
+        );
+        let method = class_getInstanceMethod(self.class, selector);
+        (!method.is_null()).then_some(method)
+    }
+
+    /// Returns the original implementation of the method.
+    pub unsafe fn get_original_method(&self, selector: *const c_void) -> Option<usize> {
+        match self.originals.get(&(selector as usize)) {
+            Some(&(_, imp)) => Some(imp as usize),
+            None => {
+                let method = class_getInstanceMethod(self.class, selector);
+                (!method.is_null()).then(|| method_getImplementation(method) as usize)
+            }
+        }
+    }
+
+    /// Replaces the implementation of the method, returning the original one.
+    /// Returns `None` if the class doesn't respond to the selector.
+    pub unsafe fn replace_method(&mut self, selector: *const c_void, imp: usize) -> Option<usize> {
+        let original = match self.originals.get(&(selector as usize)) {
+            Some(&(method, original)) => {
+                method_setImplementation(method, imp as Imp);
+                original
+            }
+            None => {
+                let method = self.own_method(selector)?;
+                let original = method_setImplementation(method, imp as Imp);
+                self.originals.insert(selector as usize, (method, original));
+                original
+            }
+        };
+        Some(original as usize)
+    }
+
+    /// Replaces the implementation of the method with a typed function, returning the original one.
+    pub unsafe fn replace<F: FnPtr>(&mut self, selector: *const c_void, func: F) -> Option<F> {
+        self.replace_method(selector, func.to_address())
+            .map(|original| F::from_address(original))
+    }
+
+    /// Restores the original implementation of the method.
+    pub unsafe fn restore_method(&mut self, selector: *const c_void) {
+        if let Some((method, original)) = self.originals.remove(&(selector as usize)) {
+            method_setImplementation(method, original);
+        }
+    }
+
+    /// Restores all swizzled methods to their original implementation.
+    pub unsafe fn restore_all_methods(&mut self) {
+        for (_, (method, original)) in self.originals.drain() {
+            method_setImplementation(method, original);
+        }
+    }
+}
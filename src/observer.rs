@@ -0,0 +1,92 @@
+//! Callbacks notified of what every hook does.
+//!
+//! Registered [`HookObserver`]s see the operations of every [`VTableHook`](crate::VTableHook) and
+//! [`InPlaceVmtHook`](crate::InPlaceVmtHook), so logging, metrics and safety policies can live in
+//! one place instead of around every call site. Callbacks run on the thread performing the operation.
+
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use crate::audit::{self, Operation};
+use crate::metrics;
+
+/// Receives hook events; every method does nothing by default.
+///
+/// `object` is the address of the hooked object, or of the VTable for class-wide hooks.
+#[allow(unused_variables)]
+pub trait HookObserver: Send + Sync {
+    /// A hook was installed over the `count` methods of `vtable`.
+    fn on_install(&self, object: usize, vtable: usize, count: usize) {}
+
+    /// The method at `id` was changed from `old` to `new`.
+    fn on_replace(&self, object: usize, id: usize, old: usize, new: usize) {}
+
+    /// The method at `id` was changed from `old` back to the original `new`.
+    fn on_restore(&self, object: usize, id: usize, old: usize, new: usize) {}
+
+    /// Every method was restored to its original address.
+    fn on_restore_all(&self, object: usize) {}
+
+    /// The hook was removed, or released without touching the already destroyed object.
+    fn on_uninstall(&self, object: usize) {}
+
+    /// A verification found the listed slots modified by someone else.
+    fn on_tamper(&self, object: usize, slots: &[usize]) {}
+
+    /// A guarded hook of the method at `id` panicked with `message`, and its fallback was used.
+    fn on_panic(&self, object: usize, id: usize, message: &str) {}
+}
+
+/// Identifies a registered observer for [`remove_observer`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ObserverId(u64);
+
+static NEXT_ID: AtomicU64 = AtomicU64::new(0);
+static COUNT: AtomicUsize = AtomicUsize::new(0);
+static OBSERVERS: Mutex<Vec<(ObserverId, Arc<dyn HookObserver>)>> = Mutex::new(Vec::new());
+
+/// Registers an observer notified of the operations of every hook.
+pub fn add_observer(observer: Arc<dyn HookObserver>) -> ObserverId {
+    let id = ObserverId(NEXT_ID.fetch_add(1, Ordering::Relaxed));
+    let mut observers = OBSERVERS.lock().unwrap_or_else(|e| e.into_inner());
+    observers.push((id, observer));
+    COUNT.store(observers.len(), Ordering::SeqCst);
+    id
+}
+
+/// Unregisters an observer; returns `false` if it wasn't registered.
+pub fn remove_observer(id: ObserverId) -> bool {
+    let mut observers = OBSERVERS.lock().unwrap_or_else(|e| e.into_inner());
+    let len = observers.len();
+    observers.retain(|(other, _)| *other != id);
+    COUNT.store(observers.len(), Ordering::SeqCst);
+    observers.len() != len
+}
+
+/// Records `operation` on `object` in the audit log and the metrics and notifies the observers.
+pub(crate) fn emit(object: usize, operation: Operation) {
+    metrics::record(object, &operation);
+    if COUNT.load(Ordering::Relaxed) != 0 {
+        // Cloned so that observers can register or remove observers from their callbacks.
+        let observers: Vec<_> = OBSERVERS
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .iter()
+            .map(|(_, observer)| Arc::clone(observer))
+            .collect();
+        for observer in observers {
+            match &operation {
+                &Operation::Install { vtable, count } => observer.on_install(object, vtable, count),
+                &Operation::Replace { id, old, new } => observer.on_replace(object, id, old, new),
+                &Operation::Restore { id, old, new } => observer.on_restore(object, id, old, new),
+                Operation::RestoreAll => observer.on_restore_all(object),
+                Operation::Uninstall | Operation::Detach => observer.on_uninstall(object),
+                Operation::Tamper { slots } => observer.on_tamper(object, slots),
+                Operation::Panic { id, message } => observer.on_panic(object, *id, message),
+            }
+        }
+    }
+    #[cfg(feature = "tracing")]
+    crate::telemetry::trace(object, &operation);
+    audit::record(object, operation);
+}
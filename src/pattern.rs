@@ -0,0 +1,47 @@
+//! Byte signatures in the common IDA style, e.g. `"48 89 5C 24 ?? 57"`.
+
+/// A byte signature where `None` matches any byte.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Pattern {
+    bytes: Vec<Option<u8>>,
+}
+
+impl Pattern {
+    /// Parses a signature of space separated hex bytes, using `?` or `??` as wildcards.
+    pub fn parse(signature: &str) -> Option<Self> {
+        let bytes = signature
+            .split_whitespace()
+            .map(|byte| match byte {
+                "?" | "??" => Some(None),
+                _ => u8::from_str_radix(byte, 16).ok().map(Some),
+            })
+            .collect::<Option<Vec<_>>>()?;
+        (!bytes.is_empty()).then_some(Self { bytes })
+    }
+
+    /// Returns the length of the signature in bytes.
+    pub fn len(&self) -> usize {
+        self.bytes.len()
+    }
+
+    /// Returns `true` if the signature has no bytes.
+    pub fn is_empty(&self) -> bool {
+        self.bytes.is_empty()
+    }
+
+    /// Returns `true` if `data` starts with the signature.
+    pub fn matches(&self, data: &[u8]) -> bool {
+        data.len() >= self.bytes.len()
+            && self.bytes.iter().zip(data).all(|(expected, byte)| expected.is_none_or(|e| e == *byte))
+    }
+
+    /// Returns the offset of the first occurrence of the signature in `data`.
+    pub fn find(&self, data: &[u8]) -> Option<usize> {
+        (0..data.len().saturating_sub(self.bytes.len() - 1)).find(|&i| self.matches(&data[i..]))
+    }
+
+    /// Returns `true` if the memory at `address` starts with the signature.
+    pub unsafe fn matches_at(&self, address: usize) -> bool {
+        self.matches(std::slice::from_raw_parts(address as *const u8, self.bytes.len()))
+    }
+}
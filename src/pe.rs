@@ -0,0 +1,259 @@
+//! Minimal parsing of PE images mapped into memory.
+
+// Not every helper is used by every combination of features.
+#![allow(dead_code)]
+
+use std::ffi::CStr;
+use std::ops::Range;
+
+const IMAGE_DOS_SIGNATURE: u16 = 0x5A4D;
+const IMAGE_NT_SIGNATURE: u32 = 0x0000_4550;
+const IMAGE_NT_OPTIONAL_HDR32_MAGIC: u16 = 0x10B;
+const IMAGE_NT_OPTIONAL_HDR64_MAGIC: u16 = 0x20B;
+
+const IMAGE_SCN_MEM_DISCARDABLE: u32 = 0x0200_0000;
+pub(crate) const IMAGE_SCN_MEM_EXECUTE: u32 = 0x2000_0000;
+pub(crate) const IMAGE_SCN_MEM_WRITE: u32 = 0x8000_0000;
+
+/// Index of the export table in the data directories.
+pub(crate) const IMAGE_DIRECTORY_ENTRY_EXPORT: usize = 0;
+/// Index of the base relocation table in the data directories.
+pub(crate) const IMAGE_DIRECTORY_ENTRY_BASERELOC: usize = 5;
+/// Index of the debug directory in the data directories.
+pub(crate) const IMAGE_DIRECTORY_ENTRY_DEBUG: usize = 6;
+
+const IMAGE_DEBUG_TYPE_CODEVIEW: u32 = 2;
+
+const IMAGE_REL_BASED_HIGHLOW: u16 = 3;
+const IMAGE_REL_BASED_DIR64: u16 = 10;
+
+/// A PE image mapped at `base`.
+#[derive(Clone, Copy)]
+pub(crate) struct Image {
+    base: *const u8,
+}
+
+impl Image {
+    /// Validates the headers of the image mapped at `base`.
+    pub(crate) unsafe fn new(base: *const u8) -> Option<Self> {
+        if base.is_null() || read::<u16>(base, 0) != IMAGE_DOS_SIGNATURE {
+            return None;
+        }
+        let image = Self { base };
+        if read::<u32>(base, image.nt_offset()) != IMAGE_NT_SIGNATURE {
+            return None;
+        }
+        match image.magic() {
+            IMAGE_NT_OPTIONAL_HDR32_MAGIC | IMAGE_NT_OPTIONAL_HDR64_MAGIC => Some(image),
+            _ => None,
+        }
+    }
+
+    unsafe fn nt_offset(&self) -> usize {
+        read::<u32>(self.base, 0x3C) as usize
+    }
+
+    unsafe fn optional_header_offset(&self) -> usize {
+        self.nt_offset() + 4 + 20
+    }
+
+    unsafe fn magic(&self) -> u16 {
+        read::<u16>(self.base, self.optional_header_offset())
+    }
+
+    /// Returns the RVA and the size of the data directory at `index`.
+    pub(crate) unsafe fn data_directory(&self, index: usize) -> Option<(usize, usize)> {
+        let (count_offset, directories_offset) = match self.magic() {
+            IMAGE_NT_OPTIONAL_HDR64_MAGIC => (108, 112),
+            _ => (92, 96),
+        };
+        let header = self.optional_header_offset();
+        if index >= read::<u32>(self.base, header + count_offset) as usize {
+            return None;
+        }
+        let entry = header + directories_offset + index * 8;
+        let rva = read::<u32>(self.base, entry) as usize;
+        let size = read::<u32>(self.base, entry + 4) as usize;
+        (rva != 0).then_some((rva, size))
+    }
+
+    /// Returns the preferred load address from the optional header.
+    pub(crate) unsafe fn image_base(&self) -> usize {
+        match self.magic() {
+            IMAGE_NT_OPTIONAL_HDR64_MAGIC => read::<u64>(self.base, self.optional_header_offset() + 24) as usize,
+            _ => read::<u32>(self.base, self.optional_header_offset() + 28) as usize,
+        }
+    }
+
+    /// Returns the size of the image once mapped.
+    pub(crate) unsafe fn size_of_image(&self) -> usize {
+        read::<u32>(self.base, self.optional_header_offset() + 56) as usize
+    }
+
+    /// Returns the offset of the section table.
+    unsafe fn section_table(&self) -> (usize, usize) {
+        let file_header = self.nt_offset() + 4;
+        let count = read::<u16>(self.base, file_header + 2) as usize;
+        let optional_size = read::<u16>(self.base, file_header + 16) as usize;
+        (self.optional_header_offset() + optional_size, count)
+    }
+
+    /// Returns the RVA, the virtual size and the characteristics of every section.
+    unsafe fn sections(&self) -> Vec<(usize, usize, u32)> {
+        let (sections, count) = self.section_table();
+        (0..count)
+            .map(|i| {
+                let section = sections + i * 40;
+                let size = read::<u32>(self.base, section + 8) as usize;
+                let rva = read::<u32>(self.base, section + 12) as usize;
+                (rva, size, read::<u32>(self.base, section + 36))
+            })
+            .filter(|&(_, size, _)| size != 0)
+            .collect()
+    }
+
+    /// Returns the unused space between the end of each non-executable section and the next section,
+    /// which is mapped but never referenced by the image.
+    pub(crate) unsafe fn section_slack(&self) -> Vec<Range<usize>> {
+        let alignment = read::<u32>(self.base, self.optional_header_offset() + 32) as usize;
+        self.sections()
+            .into_iter()
+            .filter(|&(_, _, characteristics)| characteristics & (IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_DISCARDABLE) == 0)
+            .filter_map(|(rva, size, _)| {
+                let start = self.base as usize + rva + size;
+                let end = self.base as usize + (rva + size).next_multiple_of(alignment);
+                (start < end).then_some(start..end)
+            })
+            .collect()
+    }
+
+    /// Returns the characteristics of the section containing `address`.
+    pub(crate) unsafe fn section_characteristics(&self, address: usize) -> Option<u32> {
+        let rva = address.checked_sub(self.base as usize)?;
+        self.sections()
+            .into_iter()
+            .find(|&(start, size, _)| (start..start + size).contains(&rva))
+            .map(|(_, _, characteristics)| characteristics)
+    }
+
+    /// Returns the address ranges of the executable sections.
+    pub(crate) unsafe fn code_sections(&self) -> Vec<Range<usize>> {
+        self.sections()
+            .into_iter()
+            .filter(|&(_, _, characteristics)| characteristics & IMAGE_SCN_MEM_EXECUTE != 0)
+            .map(|(rva, size, _)| self.base as usize + rva..self.base as usize + rva + size)
+            .collect()
+    }
+
+    /// Returns the address ranges of the sections holding data, neither executable nor discardable.
+    pub(crate) unsafe fn data_sections(&self) -> Vec<Range<usize>> {
+        self.sections()
+            .into_iter()
+            .filter(|&(_, _, characteristics)| characteristics & (IMAGE_SCN_MEM_EXECUTE | IMAGE_SCN_MEM_DISCARDABLE) == 0)
+            .map(|(rva, size, _)| self.base as usize + rva..self.base as usize + rva + size)
+            .collect()
+    }
+
+    /// Returns the GUID, age and path of the PDB recorded in the CodeView entry of the debug directory.
+    pub(crate) unsafe fn codeview(&self) -> Option<([u8; 16], u32, &'static CStr)> {
+        let (rva, size) = self.data_directory(IMAGE_DIRECTORY_ENTRY_DEBUG)?;
+        (0..size / 28).map(|i| rva + i * 28).find_map(|entry| {
+            if read::<u32>(self.base, entry + 12) != IMAGE_DEBUG_TYPE_CODEVIEW {
+                return None;
+            }
+            let data = read::<u32>(self.base, entry + 20) as usize;
+            (data != 0 && read::<[u8; 4]>(self.base, data) == *b"RSDS").then(|| {
+                let path = CStr::from_ptr(self.base.add(data + 24).cast());
+                (read::<[u8; 16]>(self.base, data + 4), read::<u32>(self.base, data + 20), path)
+            })
+        })
+    }
+
+    /// Returns the names of all exports of the image.
+    pub(crate) unsafe fn export_names(&self) -> Vec<&'static CStr> {
+        let Some((rva, _)) = self.data_directory(IMAGE_DIRECTORY_ENTRY_EXPORT) else {
+            return Vec::new();
+        };
+        let count = read::<u32>(self.base, rva + 24) as usize;
+        let names = read::<u32>(self.base, rva + 32) as usize;
+        (0..count)
+            .map(|i| CStr::from_ptr(self.base.add(read::<u32>(self.base, names + i * 4) as usize).cast()))
+            .collect()
+    }
+}
+
+/// Lays out the PE file `file` as the loader would map it at `base`, relocations applied,
+/// without resolving imports.
+pub(crate) fn map_file(file: &[u8], base: usize) -> Option<Vec<u8>> {
+    // The headers are parsed from a page-sized copy, so reads past the end of a short file stay in bounds.
+    const HEADERS: usize = 0x1000;
+    let mut headers = vec![0u8; HEADERS];
+    headers[..file.len().min(HEADERS)].copy_from_slice(&file[..file.len().min(HEADERS)]);
+    if read_at::<u32>(&headers, 0x3C)? as usize + 0x200 > HEADERS {
+        return None;
+    }
+
+    unsafe {
+        let image = Image::new(headers.as_ptr())?;
+        let mut mapped = vec![0u8; image.size_of_image()];
+        let size_of_headers = read::<u32>(headers.as_ptr(), image.optional_header_offset() + 60) as usize;
+        let len = size_of_headers.min(file.len()).min(mapped.len());
+        mapped[..len].copy_from_slice(&file[..len]);
+
+        let (sections, count) = image.section_table();
+        for i in 0..count {
+            let section = headers.get(sections + i * 40..sections + i * 40 + 40)?;
+            let virtual_size = read_at::<u32>(section, 8)? as usize;
+            let rva = read_at::<u32>(section, 12)? as usize;
+            let raw_size = read_at::<u32>(section, 16)? as usize;
+            let raw_offset = read_at::<u32>(section, 20)? as usize;
+            let len = if virtual_size == 0 { raw_size } else { raw_size.min(virtual_size) }
+                .min(file.len().saturating_sub(raw_offset))
+                .min(mapped.len().saturating_sub(rva));
+            mapped[rva..rva + len].copy_from_slice(&file[raw_offset..raw_offset + len]);
+        }
+
+        let delta = base.wrapping_sub(image.image_base());
+        let Some((rva, size)) = image.data_directory(IMAGE_DIRECTORY_ENTRY_BASERELOC) else {
+            return Some(mapped);
+        };
+        let end = (rva + size).min(mapped.len());
+        let mut block = rva;
+        while block + 8 <= end {
+            let page = read_at::<u32>(&mapped, block)? as usize;
+            let block_size = read_at::<u32>(&mapped, block + 4)? as usize;
+            if block_size < 8 || block + block_size > end {
+                break;
+            }
+            for entry in (block + 8..block + block_size).step_by(2) {
+                let entry = read_at::<u16>(&mapped, entry)?;
+                let at = page + (entry & 0x0FFF) as usize;
+                match entry >> 12 {
+                    IMAGE_REL_BASED_HIGHLOW => {
+                        if let Some(value) = read_at::<u32>(&mapped, at) {
+                            mapped[at..at + 4].copy_from_slice(&value.wrapping_add(delta as u32).to_le_bytes());
+                        }
+                    }
+                    IMAGE_REL_BASED_DIR64 => {
+                        if let Some(value) = read_at::<u64>(&mapped, at) {
+                            mapped[at..at + 8].copy_from_slice(&value.wrapping_add(delta as u64).to_le_bytes());
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            block += block_size;
+        }
+        Some(mapped)
+    }
+}
+
+/// Reads a value from `data` at `offset`, or `None` past its end.
+fn read_at<V: Copy>(data: &[u8], offset: usize) -> Option<V> {
+    (offset.checked_add(std::mem::size_of::<V>())? <= data.len())
+        .then(|| unsafe { std::ptr::read_unaligned(data.as_ptr().add(offset).cast()) })
+}
+
+unsafe fn read<V: Copy>(base: *const u8, offset: usize) -> V {
+    std::ptr::read_unaligned(base.add(offset).cast())
+}
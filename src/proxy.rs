@@ -0,0 +1,165 @@
+//! Proxy objects forwarding every method to another object (x86_64 only).
+//!
+//! Some engines cache the vptr of their objects or compare it against the original table, so swapping
+//! it is detected or undone. An [`ObjectProxy`] leaves the object alone and is handed out in its place:
+//! a separate object with its own table, whose slots forward to the methods of the real object unless
+//! overridden.
+
+use std::cell::RefCell;
+use std::ffi::c_void;
+use std::io;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use crate::slot::{FnPtr, Slot};
+use crate::thunk::{Thunk, ARGUMENTS};
+use crate::{audit, observer, sys};
+
+/// Memory of a proxy object: its vptr, followed by the object it forwards to.
+#[repr(C)]
+struct Object {
+    vptr: *const usize,
+    target: *mut c_void,
+}
+
+/// A proxy for an object, whose table forwards every slot to the object unless overridden.
+///
+/// The forwarders pass the object as `this` and read its vptr on every call, so hooks installed on the
+/// object are honored. Code reading the fields of the object directly sees those of the proxy instead,
+/// which suits interfaces such as COM ones but not classes with public data. Methods returning large
+/// values, which take `this` as their second argument, must be overridden.
+pub struct ObjectProxy {
+    object: Box<Object>,
+    /// The word before the first method, copied for RTTI, then the methods.
+    table: Box<[AtomicUsize]>,
+    forwarders: Vec<usize>,
+    _thunk: Thunk,
+}
+
+unsafe impl Send for ObjectProxy {}
+unsafe impl Sync for ObjectProxy {}
+
+impl Drop for ObjectProxy {
+    fn drop(&mut self) {
+        observer::emit(self.as_ptr() as usize, audit::Operation::Uninstall);
+    }
+}
+
+impl ObjectProxy {
+    /// Creates a proxy for `target` with `count` methods.
+    ///
+    /// The proxy must outlive every use of its pointer.
+    pub unsafe fn new(target: *mut c_void, count: usize) -> io::Result<Self> {
+        let vtable = *(target as *const *const usize);
+        let before = vtable.wrapping_sub(1) as usize;
+        let rtti = match sys::is_readable(before, std::mem::size_of::<usize>()) {
+            true => *(before as *const usize),
+            false => 0,
+        };
+
+        let forwarders = RefCell::new(Vec::with_capacity(count));
+        let thunk = Thunk::new(|code| {
+            let mut forwarders = forwarders.borrow_mut();
+            forwarders.clear();
+            for id in 0..count {
+                forwarders.push(code.here());
+                code.mov_imm(ARGUMENTS[0], target as usize).mov_r11_imm(target as usize);
+                code.deref_r11(0).deref_r11((id * std::mem::size_of::<usize>()) as u32).jmp_r11();
+            }
+        })?;
+        let forwarders = forwarders.into_inner();
+
+        let table: Box<[AtomicUsize]> =
+            std::iter::once(rtti).chain(forwarders.iter().copied()).map(AtomicUsize::new).collect();
+        let object = Box::new(Object {
+            vptr: table[1..].as_ptr() as *const usize,
+            target,
+        });
+        let proxy = Self {
+            object,
+            table,
+            forwarders,
+            _thunk: thunk,
+        };
+        observer::emit(proxy.as_ptr() as usize, audit::Operation::Install { vtable: vtable as usize, count });
+        Ok(proxy)
+    }
+
+    /// Returns the proxy object to hand out in place of the target.
+    pub fn as_ptr(&self) -> *mut c_void {
+        &*self.object as *const Object as *mut c_void
+    }
+
+    /// Returns the object the proxy forwards to.
+    pub fn target(&self) -> *mut c_void {
+        self.object.target
+    }
+
+    /// Returns the object a proxy created by [`ObjectProxy`] forwards to, e.g. from an override given the
+    /// proxy as `this`.
+    pub unsafe fn target_of(proxy: *const c_void) -> *mut c_void {
+        (*(proxy as *const Object)).target
+    }
+
+    /// Returns the table of the proxy.
+    pub fn vtable(&self) -> *const usize {
+        self.object.vptr
+    }
+
+    /// Returns the number of methods of the proxy.
+    pub fn len(&self) -> usize {
+        self.forwarders.len()
+    }
+
+    /// Returns `true` if the proxy has no methods.
+    pub fn is_empty(&self) -> bool {
+        self.forwarders.is_empty()
+    }
+
+    /// Returns the function forwarding the method at `id` to the target, which overrides can call as
+    /// the original with the proxy or the target as `this`.
+    pub fn forwarder(&self, id: usize) -> usize {
+        self.forwarders[id]
+    }
+
+    /// Returns the method address currently stored at the specified index in the table of the proxy.
+    pub fn get_replaced_method(&self, id: usize) -> usize {
+        self.table[id + 1].load(Ordering::SeqCst)
+    }
+
+    /// Overrides the method at the specified index with a new function address, which receives the proxy
+    /// as `this`.
+    pub unsafe fn replace_method(&self, id: usize, func: usize) {
+        let old = self.table[id + 1].swap(func, Ordering::SeqCst);
+        observer::emit(self.as_ptr() as usize, audit::Operation::Replace { id, old, new: func });
+    }
+
+    /// Forwards the method at the specified index to the target again.
+    pub fn restore_method(&self, id: usize) {
+        let new = self.forwarders[id];
+        let old = self.table[id + 1].swap(new, Ordering::SeqCst);
+        observer::emit(self.as_ptr() as usize, audit::Operation::Restore { id, old, new });
+    }
+
+    /// Forwards every method to the target again.
+    pub fn restore_all_methods(&self) {
+        for (entry, &forwarder) in self.table[1..].iter().zip(&self.forwarders) {
+            entry.store(forwarder, Ordering::SeqCst);
+        }
+        observer::emit(self.as_ptr() as usize, audit::Operation::RestoreAll);
+    }
+
+    /// Returns the forwarder of the given slot.
+    pub unsafe fn get_original<F: FnPtr>(&self, slot: Slot<F>) -> F {
+        F::from_address(self.forwarder(slot.index()))
+    }
+
+    /// Overrides the method at the given slot.
+    pub unsafe fn replace<F: FnPtr>(&self, slot: Slot<F>, func: F) {
+        self.replace_method(slot.index(), func.to_address());
+    }
+
+    /// Forwards the method at the given slot to the target again.
+    pub fn restore<F>(&self, slot: Slot<F>) {
+        self.restore_method(slot.index());
+    }
+}
@@ -0,0 +1,193 @@
+//! Python bindings for driving table inspection from analysis scripts.
+//!
+//! Build a `cdylib` that depends on this crate with the `pyo3` feature to get an importable
+//! `vmt_hook` module.
+
+use pyo3::exceptions::PyValueError;
+use pyo3::prelude::*;
+
+use crate::dump::{self, TableDump};
+use crate::{detect, rtti, symbol, sys};
+
+/// Reads `count` entries of the VTable at `vtable` in the current process.
+#[pyfunction]
+fn read_table(vtable: usize, count: usize) -> PyResult<Vec<usize>> {
+    let size = count * std::mem::size_of::<usize>();
+    if !unsafe { sys::is_readable(vtable, size) } {
+        return Err(PyValueError::new_err(format!("{vtable:#x} is not readable")));
+    }
+    Ok(unsafe { std::slice::from_raw_parts(vtable as *const usize, count) }.to_vec())
+}
+
+/// Returns the VTable pointer of the object at `object` in the current process.
+#[pyfunction]
+fn vtable_of(object: usize) -> PyResult<usize> {
+    Ok(read_table(object, 1)?[0])
+}
+
+/// Counts the entries of the VTable at `vtable` up to the first null one, or returns `None` if the walk runs
+/// into unreadable memory first.
+#[pyfunction]
+fn count_methods(vtable: usize) -> Option<usize> {
+    unsafe { detect::probe_count(vtable as *const usize) }
+}
+
+/// Returns the address of an exported symbol of a loaded module.
+#[pyfunction]
+fn resolve_symbol(module: &str, name: &str) -> Option<usize> {
+    unsafe { sys::symbol(sys::module_handle(module)?, name).map(|address| address as usize) }
+}
+
+/// Returns where `address` lies, as `module!symbol+0x10`, `module+0x1234` or the bare address.
+#[pyfunction]
+fn locate(address: usize) -> String {
+    unsafe { dump::locate(address) }.to_string()
+}
+
+/// Resolves a method named as `module!Class::Method` to its `(vtable, index, address)`.
+#[pyfunction]
+fn resolve_method(path: &str) -> PyResult<(usize, usize, usize)> {
+    let method = unsafe { symbol::resolve(path) }.map_err(|error| PyValueError::new_err(error.to_string()))?;
+    Ok((method.vtable, method.index, method.address))
+}
+
+/// Finds the VTable of the class named `class` in the loaded module `module` through its RTTI.
+#[pyfunction]
+fn find_vtable(module: &str, class: &str) -> Option<(usize, usize)> {
+    unsafe { rtti::find_vtable(module, class) }.map(|found| (found.vtable, found.count))
+}
+
+/// Dumps the VTable at `vtable` in the current process, with `count` methods or up to the first null one.
+#[pyfunction]
+#[pyo3(signature = (vtable, count = None))]
+fn dump_table(vtable: usize, count: Option<usize>) -> PyResult<PyTableDump> {
+    let count = count
+        .or_else(|| count_methods(vtable))
+        .ok_or_else(|| PyValueError::new_err(format!("{vtable:#x} is not a null-terminated table")))?;
+    read_table(vtable, count)?;
+    Ok(PyTableDump(unsafe { TableDump::of_table(vtable as *const usize, count) }))
+}
+
+/// Returns `(id, disk, memory)` for each of the first `count` methods of `vtable` that differ from the
+/// file of its module.
+#[cfg(windows)]
+#[pyfunction]
+fn diff_with_disk(vtable: usize, count: usize) -> PyResult<Vec<(usize, usize, usize)>> {
+    read_table(vtable, count)?;
+    let diffs = unsafe { crate::disk::diff_with_disk(vtable as *const usize, count) }?;
+    Ok(diffs.into_iter().map(|diff| (diff.id, diff.disk, diff.memory)).collect())
+}
+
+/// The methods of a VTable, with the modules and symbols they lie in.
+#[pyclass(name = "TableDump")]
+struct PyTableDump(TableDump);
+
+#[pymethods]
+impl PyTableDump {
+    /// Name of the class from its RTTI, if any.
+    #[getter]
+    fn class(&self) -> Option<String> {
+        self.0.class.clone()
+    }
+
+    /// Address of the original VTable.
+    #[getter]
+    fn vtable(&self) -> usize {
+        self.0.vtable
+    }
+
+    /// `(id, original, current, method)` of every slot, `method` being the name of the original if known.
+    #[getter]
+    fn slots(&self) -> Vec<(usize, usize, usize, Option<String>)> {
+        let slots = self.0.slots.iter();
+        slots.map(|slot| (slot.id, slot.original.address, slot.current.address, slot.method.clone())).collect()
+    }
+
+    /// Returns the indices of the slots that no longer hold their original method.
+    fn replaced(&self) -> Vec<usize> {
+        self.0.replaced().map(|slot| slot.id).collect()
+    }
+
+    /// Returns an IDAPython script naming and commenting the table and its methods.
+    fn ida_script(&self) -> String {
+        self.0.ida_script()
+    }
+
+    /// Returns a Ghidra script naming and commenting the table and its methods.
+    fn ghidra_script(&self) -> String {
+        self.0.ghidra_script()
+    }
+
+    /// Returns the dump as JSON.
+    #[cfg(feature = "serde")]
+    fn export_json(&self) -> String {
+        self.0.export_json()
+    }
+
+    fn __str__(&self) -> String {
+        self.0.to_string()
+    }
+}
+
+/// A process opened for reading and writing its memory.
+#[cfg(windows)]
+#[pyclass(name = "Process")]
+struct PyProcess(crate::remote::Process);
+
+#[cfg(windows)]
+#[pymethods]
+impl PyProcess {
+    #[new]
+    fn new(id: u32) -> PyResult<Self> {
+        Ok(Self(crate::remote::Process::open(id)?))
+    }
+
+    /// Reads a pointer-sized value.
+    fn read_usize(&self, address: usize) -> PyResult<usize> {
+        Ok(self.0.read_usize(address)?)
+    }
+
+    /// Writes a pointer-sized value.
+    fn write_usize(&self, address: usize, value: usize) -> PyResult<()> {
+        Ok(self.0.write_usize(address, value)?)
+    }
+
+    /// Returns the VTable pointer of a remote object.
+    fn vtable_of(&self, object: usize) -> PyResult<usize> {
+        self.read_usize(object)
+    }
+
+    /// Reads `count` entries of a remote VTable.
+    fn read_table(&self, vtable: usize, count: usize) -> PyResult<Vec<usize>> {
+        let mut bytes = vec![0; count * std::mem::size_of::<usize>()];
+        self.0.read(vtable, &mut bytes)?;
+        Ok(bytes
+            .chunks_exact(std::mem::size_of::<usize>())
+            .map(|chunk| usize::from_ne_bytes(chunk.try_into().unwrap()))
+            .collect())
+    }
+
+    /// Dumps a remote VTable, with `count` methods or up to the first entry outside of executable memory.
+    #[pyo3(signature = (vtable, count = None))]
+    fn dump_table(&self, vtable: usize, count: Option<usize>) -> PyResult<PyTableDump> {
+        Ok(PyTableDump(TableDump::read_from(&self.0, vtable, count)?))
+    }
+}
+
+#[pymodule]
+fn vmt_hook(m: &Bound<'_, PyModule>) -> PyResult<()> {
+    m.add_function(wrap_pyfunction!(read_table, m)?)?;
+    m.add_function(wrap_pyfunction!(vtable_of, m)?)?;
+    m.add_function(wrap_pyfunction!(count_methods, m)?)?;
+    m.add_function(wrap_pyfunction!(resolve_symbol, m)?)?;
+    m.add_function(wrap_pyfunction!(locate, m)?)?;
+    m.add_function(wrap_pyfunction!(resolve_method, m)?)?;
+    m.add_function(wrap_pyfunction!(find_vtable, m)?)?;
+    m.add_function(wrap_pyfunction!(dump_table, m)?)?;
+    m.add_class::<PyTableDump>()?;
+    #[cfg(windows)]
+    m.add_function(wrap_pyfunction!(diff_with_disk, m)?)?;
+    #[cfg(windows)]
+    m.add_class::<PyProcess>()?;
+    Ok(())
+}
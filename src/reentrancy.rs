@@ -0,0 +1,86 @@
+//! Detecting hooks re-entered on the same thread.
+//!
+//! A hook that calls an API which ends up in the same virtual method, such as a `Present` hook whose
+//! logging presents again, recurses into itself. A [`ReentrancyGuard`] marks a key as entered on the
+//! current thread until it is dropped, so the nested call can go straight to the original.
+
+use std::cell::RefCell;
+
+thread_local! {
+    /// Keys entered on this thread, innermost last.
+    static ENTERED: RefCell<Vec<usize>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Marks a key as entered on the current thread while alive.
+#[must_use = "the key is only marked while the guard is alive"]
+pub struct ReentrancyGuard {
+    key: usize,
+}
+
+impl ReentrancyGuard {
+    /// Enters `key` on the current thread, or returns `None` if it is already entered.
+    ///
+    /// Keys are arbitrary, e.g. the index of the hooked slot.
+    pub fn enter(key: usize) -> Option<Self> {
+        let entered = ENTERED.try_with(|entered| {
+            let mut entered = entered.borrow_mut();
+            if entered.contains(&key) {
+                false
+            } else {
+                entered.push(key);
+                true
+            }
+        });
+        // While the thread is exiting nothing is tracked, and every call is let through.
+        entered.unwrap_or(true).then_some(Self { key })
+    }
+
+    /// Returns `true` if `key` is entered on the current thread.
+    pub fn is_entered(key: usize) -> bool {
+        ENTERED.try_with(|entered| entered.borrow().contains(&key)).unwrap_or(false)
+    }
+
+    /// Returns the key of the guard.
+    pub fn key(&self) -> usize {
+        self.key
+    }
+}
+
+impl Drop for ReentrancyGuard {
+    fn drop(&mut self) {
+        let _ = ENTERED.try_with(|entered| {
+            let mut entered = entered.borrow_mut();
+            if let Some(at) = entered.iter().rposition(|&key| key == self.key) {
+                entered.remove(at);
+            }
+        });
+    }
+}
+
+/// Runs the diverging `else` expression when the enclosing hook is re-entered on the same thread.
+///
+/// Without a key, the call site of the macro is the key. The guard lives until the end of the
+/// enclosing scope.
+///
+/// ```rust,ignore
+/// extern "system" fn hk_present(device: *mut c_void, /* ... */) -> HRESULT {
+///     vmt_hook::guard!(else return unsafe { ORIGINAL_PRESENT.unwrap()(device, /* ... */) });
+///     // Not re-entered from here on.
+/// }
+///
+/// // Keyed by slot, shared by every hook of index 17.
+/// vmt_hook::guard!(17, else return S_OK);
+/// ```
+#[macro_export]
+macro_rules! guard {
+    (else $fallback:expr) => {
+        let _guard = {
+            static KEY: u8 = 0;
+            $crate::reentrancy::ReentrancyGuard::enter(&KEY as *const u8 as usize)
+        };
+        let Some(_guard) = _guard else { $fallback };
+    };
+    ($key:expr, else $fallback:expr) => {
+        let Some(_guard) = $crate::reentrancy::ReentrancyGuard::enter($key) else { $fallback };
+    };
+}
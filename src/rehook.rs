@@ -0,0 +1,119 @@
+//! Keeping replacements alive across object recreation.
+//!
+//! Renderers recreate swapchains and devices on resolution changes and device loss, and other tools
+//! may swap the vptr back. [`Rehook`] remembers the registered replacements and re-installs them on
+//! the current object whenever [`Rehook::update`] notices a different object or a foreign vptr,
+//! typically called from a `ResizeBuffers` or `Present` hook.
+
+use crate::detect::VptrState;
+use crate::VTableHook;
+
+/// A set of replacements that follows an object through recreation.
+pub struct Rehook<T> {
+    hook: Option<VTableHook<T>>,
+    count: Option<usize>,
+    replacements: Vec<(usize, usize)>,
+}
+
+impl<T> Default for Rehook<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<T> Rehook<T> {
+    /// Creates an empty set whose VTable method counts are automatically determined.
+    pub fn new() -> Self {
+        Self {
+            hook: None,
+            count: None,
+            replacements: Vec::new(),
+        }
+    }
+
+    /// Creates an empty set for VTables with a specified method count.
+    pub fn with_count(count: usize) -> Self {
+        Self {
+            count: Some(count),
+            ..Self::new()
+        }
+    }
+
+    /// Registers a replacement and applies it to the current object.
+    pub unsafe fn replace_method(&mut self, id: usize, func: usize) {
+        self.replacements.retain(|&(replaced, _)| replaced != id);
+        self.replacements.push((id, func));
+        if let Some(hook) = &self.hook {
+            hook.replace_method(id, func);
+        }
+    }
+
+    /// Unregisters a replacement and restores the original method on the current object.
+    pub unsafe fn restore_method(&mut self, id: usize) {
+        self.replacements.retain(|&(replaced, _)| replaced != id);
+        if let Some(hook) = &self.hook {
+            hook.restore_method(id);
+        }
+    }
+
+    /// Returns the hook of the current object.
+    pub fn hook(&self) -> Option<&VTableHook<T>> {
+        self.hook.as_ref()
+    }
+
+    /// Returns the original method of the current object.
+    pub fn get_original_method(&self, id: usize) -> Option<usize> {
+        self.hook.as_ref().map(|hook| hook.get_original_method(id))
+    }
+
+    unsafe fn install(&mut self, object: T) {
+        let hook = match self.count {
+            Some(count) => VTableHook::with_count(object, count),
+            None => VTableHook::new(object),
+        };
+        for &(id, func) in &self.replacements {
+            hook.replace_method(id, func);
+        }
+        self.hook = Some(hook);
+    }
+
+    /// Installs the replacements on `object`, restoring the previous object.
+    pub unsafe fn attach(&mut self, object: T) {
+        self.hook = None;
+        self.install(object);
+    }
+
+    /// Releases the current object, restoring its original VTable.
+    pub fn release(&mut self) {
+        self.hook = None;
+    }
+}
+
+impl<T: PartialEq> Rehook<T> {
+    /// Re-installs the replacements if `object` was recreated or its vptr no longer points at our VTable.
+    ///
+    /// The previous object is assumed to be destroyed or reset, so its VTable is not restored.
+    /// An object whose destructor switched its vptr to a base class VTable is released instead of
+    /// being hooked again. Returns `true` if the replacements were installed.
+    pub unsafe fn update(&mut self, object: T) -> bool {
+        if let Some(hook) = &self.hook {
+            if *hook.object() == object {
+                match hook.vptr_state() {
+                    VptrState::Hooked => return false,
+                    VptrState::Destroying { .. } => {
+                        if let Some(hook) = self.hook.take() {
+                            hook.detach();
+                        }
+                        return false;
+                    }
+                    VptrState::Original | VptrState::Foreign { .. } => {}
+                }
+            }
+        }
+        if let Some(hook) = self.hook.take() {
+            hook.detach();
+        }
+        self.install(object);
+        true
+    }
+}
@@ -0,0 +1,292 @@
+//! VTable hooking in another process, without injecting code into it.
+//!
+//! The copy-and-swap is done through `ReadProcessMemory`, `WriteProcessMemory` and `VirtualAllocEx`:
+//! the shadow VTable lives in the target process and the remote object's VTable pointer is patched
+//! to point at it. Replacement addresses must be valid in the target process.
+
+use std::ffi::c_void;
+use std::io;
+
+use windows_sys::Win32::Foundation::{CloseHandle, HANDLE, INVALID_HANDLE_VALUE};
+use windows_sys::Win32::System::Diagnostics::Debug::{ReadProcessMemory, WriteProcessMemory};
+use windows_sys::Win32::System::Diagnostics::ToolHelp::{
+    CreateToolhelp32Snapshot, Module32FirstW, Module32NextW, MODULEENTRY32W, TH32CS_SNAPMODULE, TH32CS_SNAPMODULE32,
+};
+use windows_sys::Win32::System::Memory::{
+    VirtualAllocEx, VirtualFreeEx, VirtualQueryEx, MEMORY_BASIC_INFORMATION, MEM_COMMIT, MEM_RELEASE, MEM_RESERVE,
+    PAGE_EXECUTE, PAGE_EXECUTE_READ, PAGE_EXECUTE_READWRITE, PAGE_EXECUTE_WRITECOPY, PAGE_READWRITE,
+};
+use windows_sys::Win32::System::Threading::{
+    GetCurrentProcess, GetProcessId, IsWow64Process, OpenProcess, PROCESS_QUERY_LIMITED_INFORMATION,
+    PROCESS_VM_OPERATION, PROCESS_VM_READ, PROCESS_VM_WRITE,
+};
+
+use crate::detect::{self, MAX_PROBED_METHODS};
+use crate::{dump, sys};
+
+/// Handle to a process whose memory can be read, written and allocated.
+pub struct Process {
+    handle: HANDLE,
+}
+
+unsafe impl Send for Process {}
+unsafe impl Sync for Process {}
+
+impl Drop for Process {
+    fn drop(&mut self) {
+        unsafe {
+            CloseHandle(self.handle);
+        }
+    }
+}
+
+unsafe fn is_wow64(process: HANDLE) -> io::Result<bool> {
+    let mut wow64 = 0;
+    if IsWow64Process(process, &mut wow64) == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(wow64 != 0)
+}
+
+impl dump::Memory for Process {
+    fn read(&self, address: usize, buffer: &mut [u8]) -> io::Result<()> {
+        Process::read(self, address, buffer)
+    }
+
+    /// Lists the modules of the process through a toolhelp snapshot.
+    fn modules(&self) -> io::Result<Vec<dump::MappedModule>> {
+        unsafe {
+            let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPMODULE | TH32CS_SNAPMODULE32, GetProcessId(self.handle));
+            if snapshot == INVALID_HANDLE_VALUE {
+                return Err(io::Error::last_os_error());
+            }
+            let mut modules = Vec::new();
+            let mut entry = std::mem::zeroed::<MODULEENTRY32W>();
+            entry.dwSize = std::mem::size_of::<MODULEENTRY32W>() as u32;
+            let mut found = Module32FirstW(snapshot, &mut entry) != 0;
+            while found {
+                let len = entry.szModule.iter().position(|&c| c == 0).unwrap_or(entry.szModule.len());
+                let base = entry.modBaseAddr as usize;
+                let image = base..base + entry.modBaseSize as usize;
+                modules.push(dump::MappedModule {
+                    name: String::from_utf16_lossy(&entry.szModule[..len]),
+                    base,
+                    ranges: vec![image],
+                });
+                found = Module32NextW(snapshot, &mut entry) != 0;
+            }
+            CloseHandle(snapshot);
+            Ok(modules)
+        }
+    }
+
+    fn is_executable(&self, address: usize) -> Option<bool> {
+        let mut info = unsafe { std::mem::zeroed::<MEMORY_BASIC_INFORMATION>() };
+        let size = std::mem::size_of_val(&info);
+        if unsafe { VirtualQueryEx(self.handle, address as *const c_void, &mut info, size) } == 0 {
+            return None;
+        }
+        let executable = PAGE_EXECUTE | PAGE_EXECUTE_READ | PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY;
+        Some(info.State == MEM_COMMIT && info.Protect & executable != 0)
+    }
+}
+
+impl Process {
+    /// Opens the process with the given id.
+    ///
+    /// Fails if the process has a different pointer width than the current one.
+    pub fn open(id: u32) -> io::Result<Self> {
+        unsafe {
+            let access = PROCESS_VM_OPERATION | PROCESS_VM_READ | PROCESS_VM_WRITE | PROCESS_QUERY_LIMITED_INFORMATION;
+            let handle = OpenProcess(access, 0, id);
+            if handle.is_null() {
+                return Err(io::Error::last_os_error());
+            }
+            let process = Self { handle };
+            if is_wow64(process.handle)? != is_wow64(GetCurrentProcess())? {
+                return Err(io::Error::new(io::ErrorKind::Unsupported, "process has a different pointer width"));
+            }
+            Ok(process)
+        }
+    }
+
+    /// Returns the raw process handle.
+    pub fn handle(&self) -> HANDLE {
+        self.handle
+    }
+
+    /// Reads `buffer.len()` bytes at `address`.
+    pub fn read(&self, address: usize, buffer: &mut [u8]) -> io::Result<()> {
+        let mut read = 0;
+        let ok = unsafe {
+            ReadProcessMemory(self.handle, address as *const c_void, buffer.as_mut_ptr().cast(), buffer.len(), &mut read)
+        };
+        if ok == 0 || read != buffer.len() {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Writes `buffer` at `address`.
+    pub fn write(&self, address: usize, buffer: &[u8]) -> io::Result<()> {
+        let mut written = 0;
+        let ok = unsafe {
+            WriteProcessMemory(self.handle, address as *const c_void, buffer.as_ptr().cast(), buffer.len(), &mut written)
+        };
+        if ok == 0 || written != buffer.len() {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+
+    /// Reads a pointer-sized value at `address`.
+    pub fn read_usize(&self, address: usize) -> io::Result<usize> {
+        let mut bytes = [0; std::mem::size_of::<usize>()];
+        self.read(address, &mut bytes)?;
+        Ok(usize::from_ne_bytes(bytes))
+    }
+
+    /// Reads the words of the table at `address` a page at a time, up to the page holding the first null word
+    /// or [`MAX_PROBED_METHODS`] words.
+    fn read_table(&self, address: usize) -> io::Result<Vec<usize>> {
+        let size = std::mem::size_of::<usize>();
+        let mut words = Vec::new();
+        while words.len() < MAX_PROBED_METHODS && !words.contains(&0) {
+            let next = address + words.len() * size;
+            let page_end = (next | (sys::page_size() - 1)) + 1;
+            let mut bytes = vec![0; ((page_end - next) / size).min(MAX_PROBED_METHODS - words.len()) * size];
+            self.read(next, &mut bytes)?;
+            words.extend(bytes.chunks_exact(size).map(|chunk| usize::from_ne_bytes(chunk.try_into().unwrap())));
+        }
+        Ok(words)
+    }
+
+    /// Writes a pointer-sized value at `address`.
+    pub fn write_usize(&self, address: usize, value: usize) -> io::Result<()> {
+        self.write(address, &value.to_ne_bytes())
+    }
+
+    /// Allocates `size` bytes of read-write memory in the process.
+    pub fn alloc(&self, size: usize) -> io::Result<usize> {
+        let address = unsafe { VirtualAllocEx(self.handle, std::ptr::null(), size, MEM_COMMIT | MEM_RESERVE, PAGE_READWRITE) };
+        if address.is_null() {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(address as usize)
+    }
+
+    /// Frees memory returned by [`Process::alloc`].
+    pub fn free(&self, address: usize) {
+        unsafe {
+            VirtualFreeEx(self.handle, address as *mut c_void, 0, MEM_RELEASE);
+        }
+    }
+}
+
+/// Hooks the VTable of an object living in another process.
+///
+/// The shadow VTable is allocated in the target process and freed on drop,
+/// after the remote object's original VTable pointer is restored.
+pub struct RemoteVTableHook<'a> {
+    process: &'a Process,
+    /// Remote address of the object whose VTable is being hooked.
+    object: usize,
+    /// Methods of the original VTable.
+    original_vtbl: Box<[usize]>,
+    /// Remote address of the original VTable.
+    original_address: usize,
+    /// Local mirror of the shadow VTable.
+    new_vtbl: Box<[usize]>,
+    /// Remote address of the shadow VTable.
+    new_address: usize,
+}
+
+impl Drop for RemoteVTableHook<'_> {
+    /// Restoring the original VTable.
+    fn drop(&mut self) {
+        if self.process.write_usize(self.object, self.original_address).is_ok() {
+            self.process.free(self.new_address);
+        }
+    }
+}
+
+impl<'a> RemoteVTableHook<'a> {
+    /// Hooks the remote object at `object`. The count of methods is automatically determined.
+    pub fn new(process: &'a Process, object: usize) -> io::Result<Self> {
+        let vtable = process.read_usize(object)?;
+        let words = process.read_table(vtable)?;
+        Self::with_count(process, object, detect::detect_count_in(&words))
+    }
+
+    /// Hooks the remote object at `object` with a specified method count.
+    pub fn with_count(process: &'a Process, object: usize, count: usize) -> io::Result<Self> {
+        let original_address = process.read_usize(object)?;
+        let mut bytes = vec![0; count * std::mem::size_of::<usize>()];
+        process.read(original_address, &mut bytes)?;
+        let original_vtbl: Box<[usize]> = bytes
+            .chunks_exact(std::mem::size_of::<usize>())
+            .map(|chunk| usize::from_ne_bytes(chunk.try_into().unwrap()))
+            .collect();
+
+        let new_address = process.alloc(bytes.len().max(1))?;
+        let install = process.write(new_address, &bytes).and_then(|_| process.write_usize(object, new_address));
+        if let Err(error) = install {
+            process.free(new_address);
+            return Err(error);
+        }
+
+        Ok(Self {
+            process,
+            object,
+            new_vtbl: original_vtbl.clone(),
+            original_vtbl,
+            original_address,
+            new_address,
+        })
+    }
+
+    /// Returns the original method address at the specified index in the VTable.
+    pub fn get_original_method(&self, id: usize) -> usize {
+        self.original_vtbl[id]
+    }
+
+    /// Returns the replaced method address at the specified index in the VTable.
+    pub fn get_replaced_method(&self, id: usize) -> usize {
+        self.new_vtbl[id]
+    }
+
+    /// Hooks the method at the specified index in the VTable with a new function address.
+    pub fn replace_method(&mut self, id: usize, func: usize) -> io::Result<()> {
+        self.process.write_usize(self.new_address + id * std::mem::size_of::<usize>(), func)?;
+        self.new_vtbl[id] = func;
+        Ok(())
+    }
+
+    /// Restores the original method at the specified index in the VTable.
+    pub fn restore_method(&mut self, id: usize) -> io::Result<()> {
+        self.replace_method(id, self.original_vtbl[id])
+    }
+
+    /// Restores all methods in the VTable to their original address.
+    pub fn restore_all_methods(&mut self) -> io::Result<()> {
+        let bytes: Vec<u8> = self.original_vtbl.iter().flat_map(|method| method.to_ne_bytes()).collect();
+        self.process.write(self.new_address, &bytes)?;
+        self.new_vtbl.copy_from_slice(&self.original_vtbl);
+        Ok(())
+    }
+
+    /// Returns the remote address of the object.
+    pub fn object(&self) -> usize {
+        self.object
+    }
+
+    /// Returns the remote address of the shadow VTable.
+    pub fn shadow_vtable(&self) -> usize {
+        self.new_address
+    }
+
+    /// Returns `true` if the remote object still points at our hooked VTable.
+    pub fn is_installed(&self) -> io::Result<bool> {
+        Ok(self.process.read_usize(self.object)? == self.new_address)
+    }
+}
@@ -0,0 +1,466 @@
+//! Reading the class hierarchy recorded in C++ RTTI.
+//!
+//! The word before the first method of a VTable points at the RTTI of its class: an MSVC
+//! `CompleteObjectLocator` on Windows and an Itanium `type_info` elsewhere. Classes are identified by
+//! their mangled type names, which stay the same across modules.
+//!
+//! [`find_vtable`] follows these records backwards, from the type name to the VTable, to find the table
+//! of a class without an instance at hand.
+
+use std::collections::HashSet;
+use std::ffi::CStr;
+use std::fmt;
+use std::hash::Hash;
+use std::ops::Range;
+
+use crate::dump::{MappedModule, Memory};
+use crate::{detect, sys};
+
+/// Maximum depth of base classes followed, against cycles in corrupt data.
+const MAX_DEPTH: usize = 64;
+
+const WORD: usize = std::mem::size_of::<usize>();
+
+unsafe fn read<V: Copy>(address: usize) -> Option<V> {
+    sys::is_readable(address, std::mem::size_of::<V>()).then(|| std::ptr::read_unaligned(address as *const V))
+}
+
+unsafe fn read_name(address: usize) -> Option<String> {
+    // Type names are short; the readable check covers the usual length.
+    if !sys::is_readable(address, 1) {
+        return None;
+    }
+    let name = CStr::from_ptr(address as *const _).to_str().ok()?;
+    Some(name.trim_start_matches('*').to_owned())
+}
+
+/// Returns the mangled name of the class of `vtable`.
+pub unsafe fn class_name(vtable: *const usize) -> Option<String> {
+    class_hierarchy(vtable)?.into_iter().next()
+}
+
+/// Returns the mangled names of the class of `vtable` and of all its base classes, the class first.
+pub unsafe fn class_hierarchy(vtable: *const usize) -> Option<Vec<String>> {
+    let locator = read::<usize>(vtable.wrapping_sub(1) as usize)?;
+    if locator == 0 {
+        return None;
+    }
+    let mut names = Vec::new();
+    platform::hierarchy(locator, &mut names)?;
+    (!names.is_empty()).then_some(names)
+}
+
+/// Returns `true` if the class of `base` is the class of `derived` or one of its base classes.
+/// Returns `None` if either table has no readable RTTI.
+pub unsafe fn is_base_of(base: *const usize, derived: *const usize) -> Option<bool> {
+    let base = class_name(base)?;
+    Some(class_hierarchy(derived)?.contains(&base))
+}
+
+/// A VTable found by [`find_vtable`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FoundVTable {
+    /// Address of the first method.
+    pub vtable: usize,
+    /// Number of methods, up to the first entry that doesn't point at code.
+    pub count: usize,
+}
+
+/// Searches the data of the loaded module `module` for the VTable of the class named `class`, mangled or as
+/// accepted by [`demangle`], through the type name in its RTTI. Only the primary table is returned for classes
+/// with several bases.
+pub unsafe fn find_vtable(module: &str, class: &str) -> Option<FoundVTable> {
+    let base = sys::module_base(module)?;
+    let data = ModuleData::new(base);
+    let names = match demangle(class) {
+        Some(_) => vec![class.to_owned()],
+        None => platform::mangle(class),
+    };
+    let vtable = names.iter().find_map(|name| platform::find(base as usize, &data, name))?;
+    Some(FoundVTable { vtable, count: detect::probe_code_count(vtable as *const usize) })
+}
+
+/// A VTable found by [`inventory`].
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassVTable {
+    /// File name of the module holding the VTable.
+    pub module: String,
+    /// Name of the class, demangled where possible.
+    pub class: String,
+    /// Address of the first method.
+    pub vtable: usize,
+    /// Number of methods, up to the first entry that doesn't point at code.
+    pub count: usize,
+}
+
+impl fmt::Display for ClassVTable {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}!{} {:#x} ({} methods)", self.module, self.class, self.vtable, self.count)
+    }
+}
+
+/// Lists the primary VTables of the classes with RTTI in every loaded module, sorted by module and class.
+pub unsafe fn inventory() -> Vec<ClassVTable> {
+    let mut bases = Vec::new();
+    sys::modules(|base| bases.push(base));
+    bases.into_iter().flat_map(|base| classes_of(base)).collect()
+}
+
+/// Lists the primary VTables of the classes with RTTI in the loaded module `module`, sorted by class.
+pub unsafe fn module_inventory(module: &str) -> Option<Vec<ClassVTable>> {
+    Some(classes_of(sys::module_base(module)?))
+}
+
+unsafe fn classes_of(base: *mut std::ffi::c_void) -> Vec<ClassVTable> {
+    let module = sys::module_path(base)
+        .and_then(|path| Some(path.file_name()?.to_string_lossy().into_owned()))
+        .unwrap_or_else(|| format!("{:#x}", base as usize));
+    let mut classes: Vec<ClassVTable> = platform::classes(base as usize, &ModuleData::new(base))
+        .into_iter()
+        .map(|(class, vtable)| ClassVTable {
+            module: module.clone(),
+            class: demangle(&class).unwrap_or(class),
+            vtable,
+            count: detect::probe_code_count(vtable as *const usize),
+        })
+        .collect();
+    classes.sort_by(|a, b| (&a.class, a.vtable).cmp(&(&b.class, b.vtable)));
+    classes.dedup_by_key(|class| class.vtable);
+    classes
+}
+
+/// Searches the module `module` of the memory read by `memory`, such as another process or a minidump, for
+/// the VTable of the class named `class`; see [`find_vtable`]. The records are read with the ABI of the
+/// current target. Returns the address of the first method.
+pub fn find_vtable_in(memory: &dyn Memory, module: &MappedModule, class: &str) -> Option<usize> {
+    let data = ModuleData::read_from(memory, &module.ranges);
+    let names = match demangle(class) {
+        Some(_) => vec![class.to_owned()],
+        None => platform::mangle(class),
+    };
+    names.iter().find_map(|name| platform::find(module.base, &data, name))
+}
+
+/// Copies of the readable parts of a module's image, searched for RTTI records.
+struct ModuleData {
+    /// Start address and bytes of each part.
+    chunks: Vec<(usize, Vec<u8>)>,
+}
+
+impl ModuleData {
+    /// Copies the sections holding data of the module at `base` in the current process.
+    unsafe fn new(base: *mut std::ffi::c_void) -> Self {
+        let chunks = sys::module_data(base)
+            .into_iter()
+            .filter(|range| !range.is_empty() && sys::is_readable(range.start, range.len()))
+            .map(|range| (range.start, std::slice::from_raw_parts(range.start as *const u8, range.len()).to_vec()))
+            .collect();
+        Self { chunks }
+    }
+
+    /// Copies `ranges` from `memory`, leaving out the pages it can't read.
+    fn read_from(memory: &dyn Memory, ranges: &[Range<usize>]) -> Self {
+        const PAGE: usize = 0x1000;
+
+        let mut chunks: Vec<(usize, Vec<u8>)> = Vec::new();
+        for range in ranges {
+            let mut page = range.start;
+            while page < range.end {
+                let end = (page + 1).next_multiple_of(PAGE).min(range.end);
+                let mut bytes = vec![0; end - page];
+                if memory.read(page, &mut bytes).is_ok() {
+                    match chunks.last_mut() {
+                        Some((start, chunk)) if *start + chunk.len() == page => chunk.extend(bytes),
+                        _ => chunks.push((page, bytes)),
+                    }
+                }
+                page = end;
+            }
+        }
+        Self { chunks }
+    }
+
+    /// Reads a value if it lies entirely within the data.
+    fn read<V: Copy>(&self, address: usize) -> Option<V> {
+        let size = std::mem::size_of::<V>();
+        self.chunks.iter().find_map(|(start, bytes)| {
+            let offset = address.checked_sub(*start)?;
+            let bytes = bytes.get(offset..offset.checked_add(size)?)?;
+            Some(unsafe { std::ptr::read_unaligned(bytes.as_ptr().cast::<V>()) })
+        })
+    }
+
+    /// Reads the nul-terminated string at `address` if it lies entirely within the data.
+    fn read_str(&self, address: usize) -> Option<&str> {
+        self.chunks.iter().find_map(|(start, bytes)| {
+            let bytes = bytes.get(address.checked_sub(*start)?..)?;
+            std::str::from_utf8(&bytes[..bytes.iter().position(|&byte| byte == 0)?]).ok()
+        })
+    }
+
+    /// Returns the addresses where `needle` occurs.
+    fn find_bytes(&self, needle: &[u8]) -> Vec<usize> {
+        let mut found = Vec::new();
+        for (start, bytes) in &self.chunks {
+            let matches = bytes.windows(needle.len()).enumerate().filter(|(_, at)| *at == needle);
+            found.extend(matches.map(|(offset, _)| start + offset));
+        }
+        found
+    }
+
+    /// Returns the naturally aligned addresses holding `value`.
+    fn find<V: Copy + PartialEq>(&self, value: V) -> Vec<usize> {
+        let (size, align) = (std::mem::size_of::<V>(), std::mem::align_of::<V>());
+        let mut found = Vec::new();
+        for (start, bytes) in &self.chunks {
+            let first = start.next_multiple_of(align) - start;
+            let offsets = (first..bytes.len().saturating_sub(size - 1)).step_by(align);
+            let read = |offset: usize| unsafe { std::ptr::read_unaligned(bytes[offset..].as_ptr().cast::<V>()) };
+            found.extend(offsets.filter(|&offset| read(offset) == value).map(|offset| start + offset));
+        }
+        found
+    }
+
+    /// Returns the naturally aligned addresses holding one of `values`, with the value held.
+    fn find_any<V: Copy + Eq + Hash>(&self, values: &HashSet<V>) -> Vec<(usize, V)> {
+        let (size, align) = (std::mem::size_of::<V>(), std::mem::align_of::<V>());
+        let mut found = Vec::new();
+        if values.is_empty() {
+            return found;
+        }
+        for (start, bytes) in &self.chunks {
+            let first = start.next_multiple_of(align) - start;
+            for offset in (first..bytes.len().saturating_sub(size - 1)).step_by(align) {
+                let value = unsafe { std::ptr::read_unaligned(bytes[offset..].as_ptr().cast::<V>()) };
+                if values.contains(&value) {
+                    found.push((start + offset, value));
+                }
+            }
+        }
+        found
+    }
+}
+
+/// Turns a mangled class name as returned by [`class_name`] into its qualified source form, e.g.
+/// `.?AVPlayer@game@@` or `N4game6PlayerE` into `game::Player`. Templates aren't supported.
+pub fn demangle(mangled: &str) -> Option<String> {
+    if let Some(name) = mangled.strip_prefix(".?AV").or_else(|| mangled.strip_prefix(".?AU")) {
+        let parts: Vec<_> = name.strip_suffix("@@")?.split('@').rev().collect();
+        return (!parts.iter().any(|part| part.is_empty() || part.starts_with('?'))).then(|| parts.join("::"));
+    }
+    let (mut rest, nested) = match mangled.strip_prefix('N') {
+        Some(nested) => (nested.strip_suffix('E')?, true),
+        None => (mangled, false),
+    };
+    let mut parts = Vec::new();
+    while !rest.is_empty() {
+        let digits = rest.bytes().take_while(u8::is_ascii_digit).count();
+        let len: usize = rest[..digits].parse().ok()?;
+        parts.push(rest.get(digits..digits + len)?);
+        rest = &rest[digits + len..];
+    }
+    (!parts.is_empty() && (nested || parts.len() == 1)).then(|| parts.join("::"))
+}
+
+/// Returns `true` if the mangled class name `mangled` names the class `name`, given mangled or as
+/// accepted by [`demangle`].
+pub fn is_named(mangled: &str, name: &str) -> bool {
+    mangled == name || demangle(mangled).is_some_and(|demangled| demangled == name)
+}
+
+#[cfg(windows)]
+mod platform {
+    use std::collections::HashMap;
+
+    use super::{read, read_name, ModuleData, WORD};
+
+    /// Collects the names of a `CompleteObjectLocator`'s class and bases.
+    pub(super) unsafe fn hierarchy(locator: usize, names: &mut Vec<String>) -> Option<()> {
+        // On x86_64 the locator holds offsets from the image base, found through its own offset.
+        let image = if cfg!(target_pointer_width = "64") {
+            if read::<u32>(locator)? != 1 {
+                return None;
+            }
+            locator.checked_sub(read::<u32>(locator + 20)? as usize)?
+        } else {
+            0
+        };
+        let resolve = |offset: u32| image + offset as usize;
+
+        let descriptor = resolve(read::<u32>(locator + 16)?);
+        let count = read::<u32>(descriptor + 8)? as usize;
+        let bases = resolve(read::<u32>(descriptor + 12)?);
+        for i in 0..count.min(super::MAX_DEPTH) {
+            let base = resolve(read::<u32>(bases + i * 4)?);
+            let type_descriptor = resolve(read::<u32>(base)?);
+            names.push(read_name(type_descriptor + 2 * WORD)?);
+        }
+        Some(())
+    }
+
+    /// Returns the type names MSVC gives a class or a struct named `class`.
+    pub(super) fn mangle(class: &str) -> Vec<String> {
+        let mut parts: Vec<_> = class.split("::").collect();
+        parts.reverse();
+        let scope = parts.join("@");
+        vec![format!(".?AV{scope}@@"), format!(".?AU{scope}@@")]
+    }
+
+    /// Walks from the type name to its `TypeDescriptor`, the `CompleteObjectLocator` of the complete object
+    /// pointing at it, and the VTable preceded by a pointer to that locator.
+    pub(super) fn find(base: usize, data: &ModuleData, name: &str) -> Option<usize> {
+        let string = [name.as_bytes(), &[0]].concat();
+        data.find_bytes(&string).into_iter().find_map(|at| {
+            let reference = descriptor_reference(base, at.checked_sub(2 * WORD)?)?;
+            let mut locators = data.find(reference).into_iter().filter_map(|at| locator_of(base, data, at));
+            locators.find_map(|locator| Some(data.find(locator).first()? + WORD))
+        })
+    }
+
+    /// Returns the mangled names and primary VTables of every class with RTTI in the data.
+    pub(super) fn classes(base: usize, data: &ModuleData) -> Vec<(String, usize)> {
+        let mut names = HashMap::new();
+        for prefix in [b".?AV", b".?AU"] {
+            for at in data.find_bytes(prefix) {
+                let Some(name) = data.read_str(at).filter(|name| name.ends_with("@@")) else {
+                    continue;
+                };
+                let descriptor = at.checked_sub(2 * WORD);
+                if let Some(reference) = descriptor.and_then(|descriptor| descriptor_reference(base, descriptor)) {
+                    names.insert(reference, name.to_owned());
+                }
+            }
+        }
+        let mut locators = HashMap::new();
+        for (at, reference) in data.find_any(&names.keys().copied().collect()) {
+            if let Some(locator) = locator_of(base, data, at) {
+                locators.insert(locator, names[&reference].clone());
+            }
+        }
+        let vtables = data.find_any(&locators.keys().copied().collect());
+        vtables.into_iter().map(|(at, locator)| (locators[&locator].clone(), at + WORD)).collect()
+    }
+
+    /// Returns how a `CompleteObjectLocator` refers to the `TypeDescriptor` at `descriptor`.
+    fn descriptor_reference(base: usize, descriptor: usize) -> Option<u32> {
+        if cfg!(target_pointer_width = "64") {
+            u32::try_from(descriptor.checked_sub(base)?).ok()
+        } else {
+            u32::try_from(descriptor).ok()
+        }
+    }
+
+    /// Returns the locator of a complete object whose reference to its `TypeDescriptor` is at `reference`,
+    /// following its signature, offset and constructor displacement.
+    fn locator_of(base: usize, data: &ModuleData, reference: usize) -> Option<usize> {
+        let locator = reference.checked_sub(12)?;
+        let signature = if cfg!(target_pointer_width = "64") { 1 } else { 0 };
+        if data.read::<u32>(locator)? != signature || data.read::<u32>(locator + 4)? != 0 {
+            return None;
+        }
+        if cfg!(target_pointer_width = "64") && data.read::<u32>(locator + 20)? as usize != locator - base {
+            return None;
+        }
+        Some(locator)
+    }
+}
+
+#[cfg(not(windows))]
+mod platform {
+    use std::collections::{HashMap, HashSet};
+
+    use super::{read, read_name, ModuleData, WORD};
+    use crate::sys;
+
+    /// Returns the address `type_info` objects of the ABI class use as their vptr.
+    unsafe fn abi_vtable(name: &str) -> Option<usize> {
+        Some(sys::global_symbol(name)? as usize + 2 * WORD)
+    }
+
+    /// Collects the names of a `type_info`'s class and bases.
+    pub(super) unsafe fn hierarchy(type_info: usize, names: &mut Vec<String>) -> Option<()> {
+        let single = abi_vtable("_ZTVN10__cxxabiv120__si_class_type_infoE");
+        let multiple = abi_vtable("_ZTVN10__cxxabiv121__vmi_class_type_infoE");
+        walk(type_info, single, multiple, names, 0)
+    }
+
+    unsafe fn walk(
+        type_info: usize,
+        single: Option<usize>,
+        multiple: Option<usize>,
+        names: &mut Vec<String>,
+        depth: usize,
+    ) -> Option<()> {
+        if depth > super::MAX_DEPTH {
+            return None;
+        }
+        names.push(read_name(read::<usize>(type_info + WORD)?)?);
+
+        let kind = read::<usize>(type_info)?;
+        if Some(kind) == single {
+            // __si_class_type_info: the base follows the name.
+            walk(read::<usize>(type_info + 2 * WORD)?, single, multiple, names, depth + 1)?;
+        } else if Some(kind) == multiple {
+            // __vmi_class_type_info: flags, base count and an array of (base, offset flags) pairs.
+            let count = read::<u32>(type_info + 2 * WORD + 4)? as usize;
+            for i in 0..count {
+                let base = read::<usize>(type_info + 2 * WORD + 8 + i * 2 * WORD)?;
+                walk(base, single, multiple, names, depth + 1)?;
+            }
+        }
+        Some(())
+    }
+
+    /// Returns the type name the Itanium ABI gives a class named `class`.
+    pub(super) fn mangle(class: &str) -> Vec<String> {
+        let parts: Vec<_> = class.split("::").collect();
+        let encoded: String = parts.iter().map(|part| format!("{}{part}", part.len())).collect();
+        vec![if parts.len() > 1 { format!("N{encoded}E") } else { encoded }]
+    }
+
+    /// Walks from the type name to the `type_info` pointing at it, and the VTable whose first method follows
+    /// a zero offset-to-top and a pointer to that `type_info`.
+    pub(super) fn find(_base: usize, data: &ModuleData, name: &str) -> Option<usize> {
+        let string = [name.as_bytes(), &[0]].concat();
+        data.find_bytes(&string).into_iter().find_map(|at| {
+            // Names of classes local to a module may be referenced with a leading `*`.
+            let starred = (data.read::<u8>(at.wrapping_sub(1)) == Some(b'*')).then(|| at - 1);
+            std::iter::once(at).chain(starred).find_map(|name| {
+                data.find(name).into_iter().find_map(|at| {
+                    let type_info = at - WORD;
+                    let mut slots = data.find(type_info).into_iter();
+                    Some(slots.find(|&slot| is_primary(data, slot))? + WORD)
+                })
+            })
+        })
+    }
+
+    /// Returns the mangled names and primary VTables of every class with RTTI in the data, recognizing the
+    /// `type_info` objects by the ABI classes they are instances of.
+    pub(super) unsafe fn classes(_base: usize, data: &ModuleData) -> Vec<(String, usize)> {
+        let kinds = [
+            "_ZTVN10__cxxabiv117__class_type_infoE",
+            "_ZTVN10__cxxabiv120__si_class_type_infoE",
+            "_ZTVN10__cxxabiv121__vmi_class_type_infoE",
+        ];
+        let kinds: HashSet<usize> = kinds.into_iter().filter_map(|kind| abi_vtable(kind)).collect();
+        let mut names = HashMap::new();
+        for (type_info, _) in data.find_any(&kinds) {
+            if let Some(name) = data.read::<usize>(type_info + WORD).and_then(|name| data.read_str(name)) {
+                names.insert(type_info, name.trim_start_matches('*').to_owned());
+            }
+        }
+        let slots = data.find_any(&names.keys().copied().collect());
+        slots
+            .into_iter()
+            .filter(|&(slot, _)| is_primary(data, slot))
+            .map(|(slot, type_info)| (names[&type_info].clone(), slot + WORD))
+            .collect()
+    }
+
+    /// Returns `true` if the `type_info` pointer at `slot` belongs to a primary VTable, whose offset to the
+    /// complete object before it is zero.
+    fn is_primary(data: &ModuleData, slot: usize) -> bool {
+        data.read::<usize>(slot.wrapping_sub(WORD)) == Some(0)
+    }
+}
@@ -0,0 +1,628 @@
+//! Allocation of the shadow VTables used by [`CopySwap`](crate::CopySwap).
+//!
+//! Without `std` the copies are always on the heap, and the options and integrity checks of this module are gone.
+
+#[cfg(not(feature = "std"))]
+use alloc::boxed::Box;
+#[cfg(not(feature = "std"))]
+use core::sync::atomic::AtomicUsize;
+#[cfg(feature = "std")]
+use std::alloc::Layout;
+#[cfg(feature = "std")]
+use std::io;
+#[cfg(feature = "std")]
+use std::ops::Range;
+#[cfg(feature = "std")]
+use std::sync::atomic::AtomicBool;
+use core::sync::atomic::Ordering;
+#[cfg(feature = "std")]
+use std::sync::{Arc, Mutex, Weak};
+
+#[cfg(feature = "std")]
+use crate::allocations::{self, AllocationKind};
+#[cfg(feature = "std")]
+use crate::detect::{classify_vptr, VptrState};
+#[cfg(feature = "std")]
+use crate::{audit, observer, sys, Error, Result};
+
+/// Where the shadow VTable of a [`CopySwap`](crate::CopySwap) is allocated.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Allocation {
+    /// On the Rust heap.
+    #[default]
+    Heap,
+    /// In pages of its own, which can be made read-only with [`CopySwap::freeze`](crate::CopySwap::freeze).
+    Pages,
+    /// Like [`Allocation::Pages`], between two inaccessible guard pages. The copy ends right at the
+    /// trailing guard page, so reading past the last method faults instead of returning garbage.
+    GuardedPages,
+    /// Like [`Allocation::Pages`], with the copy starting at the beginning of its first page rather than
+    /// ending at the end of its last one, so the first copied word is page-aligned.
+    AlignedPages,
+    /// Like [`Allocation::Pages`], with the copy at the same offset within its page as the original,
+    /// so its address can't be told apart by alignment and it spans pages like the original does.
+    MatchedPages,
+    /// In unused space inside the image of the module containing the original VTable,
+    /// so the VTable pointer keeps pointing into that module.
+    ///
+    /// The space is taken from the slack at the end of non-executable sections, which is often only a few
+    /// hundred bytes; the hook fails with [`Error::Allocation`](crate::Error::Allocation) if no cave is
+    /// large enough. The cave keeps the protection of its section, like a frozen copy.
+    InModule,
+    /// In memory handed out by the callbacks of a [`ShadowAllocator`], such as a shared section,
+    /// executable-adjacent memory or a private arena.
+    Custom(ShadowAllocator),
+}
+
+/// Callbacks providing the memory of shadow VTables allocated with [`Allocation::Custom`].
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Copy)]
+pub struct ShadowAllocator {
+    /// Returns `size` bytes aligned to `align`, or null on failure.
+    pub alloc: unsafe fn(size: usize, align: usize) -> *mut u8,
+    /// Frees memory returned by `alloc`, called with the same size and alignment.
+    pub free: unsafe fn(address: *mut u8, size: usize, align: usize),
+}
+
+// Compares the callback addresses, which is all options need to tell allocators apart.
+#[cfg(feature = "std")]
+impl PartialEq for ShadowAllocator {
+    fn eq(&self, other: &Self) -> bool {
+        std::ptr::fn_addr_eq(self.alloc, other.alloc) && std::ptr::fn_addr_eq(self.free, other.free)
+    }
+}
+
+#[cfg(feature = "std")]
+impl Eq for ShadowAllocator {}
+
+/// Options controlling how [`CopySwap`](crate::CopySwap) copies the VTable.
+#[cfg(feature = "std")]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct VTableCopyOptions {
+    /// Where the copy is allocated.
+    pub allocation: Allocation,
+    /// Makes the copy read-only right after it is installed; needs [`Allocation::Pages`],
+    /// [`Allocation::GuardedPages`], [`Allocation::AlignedPages`] or [`Allocation::MatchedPages`].
+    pub freeze: bool,
+    /// Reproduces the surroundings of the original VTable in the copy, so it has the same shape:
+    /// the ABI words before the first method (the RTTI locator on Windows, the offset-to-top and
+    /// `type_info` pointer elsewhere) and the null terminator after the last one, if there is one.
+    pub mimic_layout: bool,
+    /// Number of words before the first method copied along with the methods, overriding the ABI
+    /// default of `mimic_layout`; see [`MSVC_PREFIX`] and [`ITANIUM_PREFIX`]. Engines keeping metadata
+    /// in front of their tables need more, as do Itanium classes with virtual bases, whose VTables start
+    /// with virtual base offsets. The hooked object points past them, at the copied first method.
+    pub prefix_words: Option<usize>,
+    /// Appends a null word after the last copied method if the original VTable has one, so tools
+    /// counting methods up to the terminator stop at the end of the copy; implied by `mimic_layout`.
+    pub null_terminate: bool,
+    /// Index of a frequently called method, e.g. `Present`, placed at the start of a [`CACHE_LINE`] so
+    /// that rewriting the methods before it doesn't slow down its callers through false sharing. The copy
+    /// is padded to whole cache lines on the heap. Copies in guarded pages or module caves can't be moved.
+    pub hot_slot: Option<usize>,
+}
+
+/// Size of the cache lines [`VTableCopyOptions::hot_slot`] aligns to.
+#[cfg(feature = "std")]
+pub const CACHE_LINE: usize = if cfg!(all(target_arch = "aarch64", target_vendor = "apple")) { 128 } else { 64 };
+
+/// Words MSVC stores before the first method: the `CompleteObjectLocator` pointer.
+#[cfg(feature = "std")]
+pub const MSVC_PREFIX: usize = 1;
+/// Words the Itanium ABI stores before the first method of a class without virtual bases:
+/// the offset-to-top and the `type_info` pointer.
+#[cfg(feature = "std")]
+pub const ITANIUM_PREFIX: usize = 2;
+
+/// Number of words the ABI of the platform stores before the first method of a VTable.
+#[cfg(feature = "std")]
+const ABI_PREFIX: usize = if cfg!(windows) { MSVC_PREFIX } else { ITANIUM_PREFIX };
+
+#[cfg(feature = "std")]
+impl VTableCopyOptions {
+    /// Returns options copying `words` words before the first method.
+    pub fn with_prefix_words(words: usize) -> Self {
+        Self {
+            prefix_words: Some(words),
+            ..Self::default()
+        }
+    }
+
+    /// Returns the number of words copied before the first method.
+    fn prefix(&self) -> usize {
+        match self.prefix_words {
+            Some(words) => words,
+            None if self.mimic_layout => ABI_PREFIX,
+            None => 0,
+        }
+    }
+
+    /// Returns `true` if a null terminator is copied after the last method.
+    fn terminator(&self) -> bool {
+        self.mimic_layout || self.null_terminate
+    }
+}
+
+/// Returns the distance from a cache-line-aligned address to a copy whose word at `offset` bytes starts a line.
+#[cfg(feature = "std")]
+fn line_shift(offset: usize) -> usize {
+    (CACHE_LINE - offset % CACHE_LINE) % CACHE_LINE
+}
+
+/// Caves of module images handed out to shadow VTables.
+#[cfg(feature = "std")]
+static CLAIMED_CAVES: Mutex<Vec<Range<usize>>> = Mutex::new(Vec::new());
+
+/// Claims `size` bytes of pointer-aligned space in the caves of the module containing `address`.
+#[cfg(feature = "std")]
+unsafe fn claim_cave(address: usize, size: usize) -> io::Result<Range<usize>> {
+    let module = sys::module_of(address)
+        .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "address isn't inside a module"))?;
+    let mut claimed = CLAIMED_CAVES.lock().unwrap_or_else(|e| e.into_inner());
+    for cave in sys::module_caves(module) {
+        let mut start = cave.start.next_multiple_of(std::mem::align_of::<usize>());
+        // Skips the parts of the cave already taken by other tables.
+        while start + size <= cave.end {
+            match claimed.iter().find(|other| other.start < start + size && start < other.end) {
+                Some(other) => start = other.end.next_multiple_of(std::mem::align_of::<usize>()),
+                None => {
+                    if !sys::is_readable(start, size) {
+                        break;
+                    }
+                    claimed.push(start..start + size);
+                    return Ok(start..start + size);
+                }
+            }
+        }
+    }
+    Err(io::Error::new(io::ErrorKind::OutOfMemory, "no module cave is large enough"))
+}
+
+#[cfg(feature = "std")]
+fn release_cave(cave: &Range<usize>) {
+    let mut claimed = CLAIMED_CAVES.lock().unwrap_or_else(|e| e.into_inner());
+    claimed.retain(|other| other != cave);
+}
+
+/// Returns the FNV-1a hash of `words`.
+#[cfg(feature = "std")]
+fn digest(words: &[usize]) -> u64 {
+    words.iter().flat_map(|word| word.to_le_bytes()).fold(0xCBF2_9CE4_8422_2325, |hash, byte| {
+        (hash ^ byte as u64).wrapping_mul(0x0100_0000_01B3)
+    })
+}
+
+/// What the methods of a shadow VTable should hold, shared with the [`IntegrityCheck`]s watching it.
+#[cfg(feature = "std")]
+struct Integrity {
+    /// Address of the first method of the copy.
+    table: usize,
+    /// Values written by the hook.
+    expected: Box<[usize]>,
+    /// Hash of `expected`.
+    digest: u64,
+    /// Set once the copy is freed.
+    released: bool,
+}
+
+#[cfg(feature = "std")]
+impl Integrity {
+    /// Returns the methods that no longer hold the values written by the hook.
+    unsafe fn tampered_slots(&self) -> Vec<usize> {
+        if self.released {
+            return Vec::new();
+        }
+        let live = std::slice::from_raw_parts(self.table as *const usize, self.expected.len());
+        if digest(live) == self.digest {
+            return Vec::new();
+        }
+        (0..live.len()).filter(|&id| live[id] != self.expected[id]).collect()
+    }
+}
+
+/// A handle that checks a shadow VTable for writes made behind the hook's back, usable from any thread.
+///
+/// The check holds no strong reference: once the hook is dropped it passes without touching the freed table.
+#[cfg(feature = "std")]
+#[derive(Clone)]
+pub struct IntegrityCheck {
+    integrity: Weak<Mutex<Integrity>>,
+    /// Address of the hooked object's vptr and its original VTable.
+    object: Option<(usize, usize)>,
+}
+
+#[cfg(feature = "std")]
+impl IntegrityCheck {
+    /// Checks that every method still holds the value written by the hook, reporting the others as
+    /// [`Error::Tampered`].
+    pub fn verify(&self) -> Result<()> {
+        let Some(integrity) = self.integrity.upgrade() else {
+            return Ok(());
+        };
+        let integrity = integrity.lock().unwrap_or_else(|e| e.into_inner());
+        let tampered = unsafe { integrity.tampered_slots() };
+        if tampered.is_empty() {
+            Ok(())
+        } else {
+            let object = self.object.map_or(integrity.table, |(vptr, _)| vptr);
+            drop(integrity);
+            observer::emit(object, audit::Operation::Tamper { slots: tampered.clone() });
+            Err(Error::Tampered(tampered))
+        }
+    }
+
+    /// Also watches the vptr at `vptr`, whose VTable was `original` before it was hooked.
+    pub(crate) fn with_object(mut self, vptr: usize, original: usize) -> Self {
+        self.object = Some((vptr, original));
+        self
+    }
+
+    /// Returns where the hooked object's vptr points, or `None` if the check doesn't watch an object,
+    /// the hook has been dropped or the vptr can't be read anymore.
+    pub fn vptr_state(&self) -> Option<VptrState> {
+        let (vptr, original) = self.object?;
+        let integrity = self.integrity.upgrade()?;
+        let integrity = integrity.lock().unwrap_or_else(|e| e.into_inner());
+        unsafe {
+            if integrity.released || !sys::is_readable(vptr, std::mem::size_of::<usize>()) {
+                return None;
+            }
+            let vtable = std::ptr::read(vptr as *const usize);
+            if vtable == integrity.table {
+                Some(VptrState::Hooked)
+            } else {
+                Some(classify_vptr(vtable, original))
+            }
+        }
+    }
+
+    /// Returns `true` once the hook owning the table has been dropped.
+    pub fn is_released(&self) -> bool {
+        self.integrity
+            .upgrade()
+            .is_none_or(|integrity| integrity.lock().unwrap_or_else(|e| e.into_inner()).released)
+    }
+}
+
+/// Error of [`ShadowTable::write`], failing only when the pages of a frozen copy can't be made writable.
+#[cfg(feature = "std")]
+pub(crate) type WriteError = io::Error;
+/// Writes can't fail without `std`, which copies can't be frozen without.
+#[cfg(not(feature = "std"))]
+pub(crate) type WriteError = core::convert::Infallible;
+
+/// Memory holding a shadow VTable.
+enum Storage {
+    /// Boxed slice turned into the raw `words` pointer.
+    Heap,
+    /// Pages returned by [`sys::alloc_pages`], guard pages included.
+    #[cfg(feature = "std")]
+    Pages { address: usize, size: usize },
+    /// Claimed module cave, whose original contents are put back on drop.
+    #[cfg(feature = "std")]
+    Cave { range: Range<usize>, saved: Box<[usize]> },
+    /// Heap memory aligned to a cache line, which the copy starts `base` bytes into.
+    #[cfg(feature = "std")]
+    Aligned { base: *mut u8, layout: Layout },
+    /// Memory returned by a custom allocator.
+    #[cfg(feature = "std")]
+    Custom { allocator: ShadowAllocator, base: *mut u8, layout: Layout },
+}
+
+/// A shadow VTable: a copy of the original methods that the hooked object points at,
+/// optionally surrounded by copies of the words around the original.
+pub(crate) struct ShadowTable {
+    /// First word of the copy.
+    words: *mut usize,
+    /// Number of words of the copy.
+    len: usize,
+    /// Index of the first method in the words.
+    prefix: usize,
+    /// Number of methods.
+    count: usize,
+    storage: Storage,
+    /// Whether the pages of the copy are read-only between writes.
+    #[cfg(feature = "std")]
+    frozen: AtomicBool,
+    /// Expected contents of the methods, for [`ShadowTable::verify`].
+    #[cfg(feature = "std")]
+    integrity: Arc<Mutex<Integrity>>,
+}
+
+unsafe impl Send for ShadowTable {}
+// Writes to the copy are serialized by the integrity lock, or word by word atomic without `std`.
+unsafe impl Sync for ShadowTable {}
+
+impl Drop for ShadowTable {
+    fn drop(&mut self) {
+        // Running checks finish before the memory goes away.
+        #[cfg(feature = "std")]
+        self.release();
+        #[cfg(feature = "std")]
+        allocations::unregister(self.as_ptr() as usize);
+        match &self.storage {
+            Storage::Heap => unsafe {
+                drop(Box::from_raw(core::ptr::slice_from_raw_parts_mut(self.words, self.len)));
+            },
+            #[cfg(feature = "std")]
+            Storage::Pages { address, size } => unsafe { sys::free_pages(*address, *size) },
+            #[cfg(feature = "std")]
+            Storage::Cave { range, saved } => unsafe {
+                let restored = sys::with_writable(range.start, range.len(), || {
+                    std::ptr::copy_nonoverlapping(saved.as_ptr(), range.start as *mut usize, saved.len());
+                });
+                // A cave that can't be cleaned up stays claimed rather than being reused.
+                if restored.is_ok() {
+                    release_cave(range);
+                }
+            },
+            #[cfg(feature = "std")]
+            Storage::Aligned { base, layout } => unsafe { std::alloc::dealloc(*base, *layout) },
+            #[cfg(feature = "std")]
+            Storage::Custom { allocator, base, layout } => unsafe {
+                (allocator.free)(*base, layout.size(), layout.align());
+            },
+        }
+    }
+}
+
+impl ShadowTable {
+    /// Copies the `count` methods of `vtable` to the heap, the only place for copies without `std`.
+    #[cfg(not(feature = "std"))]
+    pub(crate) unsafe fn on_heap(vtable: *const usize, count: usize) -> Self {
+        let copy: Box<[usize]> = core::slice::from_raw_parts(vtable, count).into();
+        Self { words: Box::into_raw(copy).cast(), len: count, prefix: 0, count, storage: Storage::Heap }
+    }
+
+    /// Allocates a copy of the `count` methods of `vtable` as described by `options`.
+    #[cfg(feature = "std")]
+    pub(crate) unsafe fn new(vtable: *const usize, count: usize, options: &VTableCopyOptions) -> io::Result<Self> {
+        let size = std::mem::size_of::<usize>();
+        let prefix = options.prefix();
+        let start = vtable.wrapping_sub(prefix);
+        if prefix != 0 && !sys::is_readable(start as usize, prefix * size) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "words before the vtable aren't readable"));
+        }
+        let end = vtable.wrapping_add(count);
+        let terminator = options.terminator() && sys::is_readable(end as usize, size) && *end == 0;
+
+        let mut words = std::slice::from_raw_parts(start, prefix + count).to_vec();
+        if terminator {
+            words.push(0);
+        }
+
+        let hot = match options.hot_slot {
+            Some(id) if id >= count => {
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, "hot slot is past the last method"));
+            }
+            Some(id) => Some((prefix + id) * size),
+            None => None,
+        };
+        let mut table = Self::allocate(&words, start as usize, options.allocation, hot)?;
+        table.prefix = prefix;
+        table.count = count;
+        let address = table.as_ptr() as usize;
+        allocations::register(AllocationKind::ShadowTable, address, table.size(), Some(vtable as usize));
+        *table.integrity() = Integrity {
+            table: table.as_ptr() as usize,
+            expected: table.methods().into(),
+            digest: digest(table.methods()),
+            released: false,
+        };
+        if options.freeze {
+            table.freeze()?;
+        }
+        Ok(table)
+    }
+
+    /// Allocates a copy of `words`, all of them methods, copied from `near`; module caves are taken from the
+    /// module containing it. With `hot`, the word at that byte offset starts a cache line.
+    #[cfg(feature = "std")]
+    unsafe fn allocate(words: &[usize], near: usize, allocation: Allocation, hot: Option<usize>) -> io::Result<Self> {
+        // Room for moving the copy to a cache line, and the whole lines it ends up spanning.
+        let padded = |offset| (line_shift(offset) + std::mem::size_of_val(words)).max(1).next_multiple_of(CACHE_LINE);
+        let (copy, storage, frozen) = match (allocation, hot) {
+            (Allocation::GuardedPages | Allocation::MatchedPages | Allocation::InModule, Some(_)) => {
+                let message = "copies in guarded or matched pages or module caves can't be aligned for a hot slot";
+                return Err(io::Error::new(io::ErrorKind::InvalidInput, message));
+            }
+            (Allocation::Heap, None) => {
+                let copy: Box<[usize]> = words.into();
+                (Box::into_raw(copy).cast(), Storage::Heap, false)
+            }
+            (Allocation::Heap, Some(offset)) => {
+                let layout = Layout::from_size_align(padded(offset), CACHE_LINE).map_err(io::Error::other)?;
+                let base = std::alloc::alloc(layout);
+                if base.is_null() {
+                    return Err(io::Error::from(io::ErrorKind::OutOfMemory));
+                }
+                let copy = base.add(line_shift(offset)).cast::<usize>();
+                std::ptr::copy_nonoverlapping(words.as_ptr(), copy, words.len());
+                (copy, Storage::Aligned { base, layout }, false)
+            }
+            (Allocation::Pages | Allocation::GuardedPages | Allocation::AlignedPages | Allocation::MatchedPages, _) => {
+                let page = sys::page_size();
+                let bytes = std::mem::size_of_val(words)
+                    + match (allocation, hot) {
+                        (Allocation::MatchedPages, _) => near % page,
+                        (_, Some(_)) => CACHE_LINE,
+                        (_, None) => 0,
+                    };
+                let data = bytes.max(1).next_multiple_of(page);
+                let guard = if allocation == Allocation::GuardedPages { page } else { 0 };
+                let size = data + 2 * guard;
+                let address = sys::alloc_pages(size, sys::Protection::ReadWrite)?;
+                if guard != 0 {
+                    let guarded = sys::protect(address, guard, sys::Protection::NoAccess)
+                        .and_then(|_| sys::protect(address + guard + data, guard, sys::Protection::NoAccess));
+                    if let Err(error) = guarded {
+                        sys::free_pages(address, size);
+                        return Err(error);
+                    }
+                }
+                let copy = match (allocation, hot) {
+                    (Allocation::AlignedPages, None) => address,
+                    (Allocation::AlignedPages, Some(offset)) => address + line_shift(offset),
+                    (Allocation::MatchedPages, _) => address + near % page,
+                    (_, None) => address + guard + data - std::mem::size_of_val(words),
+                    (_, Some(offset)) => {
+                        let end = address + data - std::mem::size_of_val(words);
+                        end - (end + offset) % CACHE_LINE
+                    }
+                } as *mut usize;
+                std::ptr::copy_nonoverlapping(words.as_ptr(), copy, words.len());
+                (copy, Storage::Pages { address, size }, false)
+            }
+            (Allocation::InModule, None) => {
+                let size = std::mem::size_of_val(words).max(std::mem::size_of::<usize>());
+                let range = claim_cave(near, size)?;
+                let cave = range.start as *mut usize;
+                let saved: Box<[usize]> = std::slice::from_raw_parts(cave, size / std::mem::size_of::<usize>()).into();
+                let copied = sys::with_writable(range.start, size, || {
+                    std::ptr::copy_nonoverlapping(words.as_ptr(), cave, words.len());
+                });
+                if let Err(error) = copied {
+                    release_cave(&range);
+                    return Err(error);
+                }
+                (cave, Storage::Cave { range, saved }, true)
+            }
+            (Allocation::Custom(allocator), _) => {
+                let layout = match hot {
+                    Some(offset) => Layout::from_size_align(padded(offset), CACHE_LINE),
+                    None => Layout::from_size_align(std::mem::size_of_val(words).max(1), std::mem::align_of::<usize>()),
+                }
+                .map_err(io::Error::other)?;
+                let base = (allocator.alloc)(layout.size(), layout.align());
+                if base.is_null() {
+                    return Err(io::Error::new(io::ErrorKind::OutOfMemory, "custom allocator returned null"));
+                }
+                if !(base as usize).is_multiple_of(layout.align()) {
+                    (allocator.free)(base, layout.size(), layout.align());
+                    let message = "custom allocator returned misaligned memory";
+                    return Err(io::Error::new(io::ErrorKind::InvalidData, message));
+                }
+                let copy = base.add(hot.map_or(0, line_shift)).cast::<usize>();
+                std::ptr::copy_nonoverlapping(words.as_ptr(), copy, words.len());
+                (copy, Storage::Custom { allocator, base, layout }, false)
+            }
+        };
+        Ok(Self {
+            words: copy,
+            len: words.len(),
+            prefix: 0,
+            count: words.len(),
+            storage,
+            frozen: AtomicBool::new(frozen),
+            // Filled in by `new` once the methods are known.
+            integrity: Arc::new(Mutex::new(Integrity {
+                table: copy as usize,
+                expected: Box::default(),
+                digest: digest(&[]),
+                released: false,
+            })),
+        })
+    }
+
+    #[cfg(feature = "std")]
+    fn integrity(&self) -> std::sync::MutexGuard<'_, Integrity> {
+        self.integrity.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Returns the address the object's VTable pointer is set to.
+    pub(crate) fn as_ptr(&self) -> *const usize {
+        self.words.wrapping_add(self.prefix)
+    }
+
+    /// Returns the size of the copy in bytes, at least one byte.
+    #[cfg(feature = "std")]
+    fn size(&self) -> usize {
+        (self.len * std::mem::size_of::<usize>()).max(1)
+    }
+
+    /// Returns the methods of the copy.
+    pub(crate) fn methods(&self) -> &[usize] {
+        unsafe { core::slice::from_raw_parts(self.as_ptr(), self.count) }
+    }
+
+    /// Overwrites the methods starting at index `id`.
+    pub(crate) unsafe fn write(&self, id: usize, methods: &[usize]) -> core::result::Result<(), WriteError> {
+        assert!(id + methods.len() <= self.count, "method index out of bounds");
+        let at = self.words.add(self.prefix + id);
+        #[cfg(feature = "std")]
+        {
+            // Held across the write so that checks never see it half done.
+            let mut integrity = self.integrity();
+            if self.frozen.load(Ordering::SeqCst) {
+                sys::with_writable(at as usize, std::mem::size_of_val(methods), || {
+                    std::ptr::copy_nonoverlapping(methods.as_ptr(), at, methods.len());
+                })?;
+            } else {
+                std::ptr::copy_nonoverlapping(methods.as_ptr(), at, methods.len());
+            }
+            integrity.expected[id..id + methods.len()].copy_from_slice(methods);
+            integrity.digest = digest(&integrity.expected);
+        }
+        #[cfg(not(feature = "std"))]
+        for (offset, &method) in methods.iter().enumerate() {
+            AtomicUsize::from_ptr(at.add(offset)).store(method, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+
+    /// Returns the methods that no longer hold the values last written through [`ShadowTable::write`].
+    #[cfg(feature = "std")]
+    pub(crate) fn tampered_slots(&self) -> Vec<usize> {
+        unsafe { self.integrity().tampered_slots() }
+    }
+
+    /// Marks the copy as released, so checks pass from now on without touching it or the object.
+    #[cfg(feature = "std")]
+    pub(crate) fn release(&self) {
+        self.integrity().released = true;
+    }
+
+    /// Returns a check of the copy usable from other threads.
+    #[cfg(feature = "std")]
+    pub(crate) fn integrity_check(&self) -> IntegrityCheck {
+        IntegrityCheck {
+            integrity: Arc::downgrade(&self.integrity),
+            object: None,
+        }
+    }
+
+    /// Returns `true` if the pages of the copy are read-only between writes.
+    #[cfg(feature = "std")]
+    pub(crate) fn is_frozen(&self) -> bool {
+        self.frozen.load(Ordering::SeqCst)
+    }
+
+    /// Makes the pages of the copy read-only. Later writes briefly make them writable again.
+    #[cfg(feature = "std")]
+    pub(crate) fn freeze(&self) -> io::Result<()> {
+        match self.storage {
+            Storage::Heap => Err(io::Error::new(io::ErrorKind::Unsupported, "heap vtables can't be frozen")),
+            Storage::Pages { .. } => {
+                unsafe { sys::protect(self.words as usize, self.size(), sys::Protection::ReadOnly)? };
+                self.frozen.store(true, Ordering::SeqCst);
+                Ok(())
+            }
+            Storage::Cave { .. } => Ok(()),
+            Storage::Aligned { .. } => Err(io::Error::new(io::ErrorKind::Unsupported, "heap vtables can't be frozen")),
+            Storage::Custom { .. } => {
+                Err(io::Error::new(io::ErrorKind::Unsupported, "custom allocations can't be frozen"))
+            }
+        }
+    }
+
+    /// Makes the pages of the copy writable again.
+    #[cfg(feature = "std")]
+    pub(crate) fn thaw(&self) -> io::Result<()> {
+        if let Storage::Pages { .. } = self.storage {
+            unsafe { sys::protect(self.words as usize, self.size(), sys::Protection::ReadWrite)? };
+            self.frozen.store(false, Ordering::SeqCst);
+        }
+        Ok(())
+    }
+}
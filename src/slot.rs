@@ -0,0 +1,137 @@
+//! Typed access to VTable slots.
+
+use core::marker::PhantomData;
+
+use crate::{HookBackend, VTableHook};
+
+/// Function pointer types that can be stored in a VTable slot.
+///
+/// Implemented for `extern` function pointers of the common calling conventions and Rust function pointers
+/// with up to 12 arguments.
+pub unsafe trait FnPtr: Copy {
+    /// Returns the address of the function.
+    fn to_address(self) -> usize;
+
+    /// Creates the function pointer from an address.
+    unsafe fn from_address(address: usize) -> Self;
+}
+
+macro_rules! impl_fn_ptr {
+    ($abi:literal: $($arg:ident),*) => {
+        unsafe impl<R, $($arg),*> FnPtr for extern $abi fn($($arg),*) -> R {
+            fn to_address(self) -> usize {
+                self as usize
+            }
+
+            unsafe fn from_address(address: usize) -> Self {
+                core::mem::transmute::<usize, Self>(address)
+            }
+        }
+
+        unsafe impl<R, $($arg),*> FnPtr for unsafe extern $abi fn($($arg),*) -> R {
+            fn to_address(self) -> usize {
+                self as usize
+            }
+
+            unsafe fn from_address(address: usize) -> Self {
+                core::mem::transmute::<usize, Self>(address)
+            }
+        }
+    };
+}
+
+macro_rules! impl_fn_ptr_all {
+    ($abi:literal) => {
+        impl_fn_ptr!($abi:);
+        impl_fn_ptr!($abi: A0);
+        impl_fn_ptr!($abi: A0, A1);
+        impl_fn_ptr!($abi: A0, A1, A2);
+        impl_fn_ptr!($abi: A0, A1, A2, A3);
+        impl_fn_ptr!($abi: A0, A1, A2, A3, A4);
+        impl_fn_ptr!($abi: A0, A1, A2, A3, A4, A5);
+        impl_fn_ptr!($abi: A0, A1, A2, A3, A4, A5, A6);
+        impl_fn_ptr!($abi: A0, A1, A2, A3, A4, A5, A6, A7);
+        impl_fn_ptr!($abi: A0, A1, A2, A3, A4, A5, A6, A7, A8);
+        impl_fn_ptr!($abi: A0, A1, A2, A3, A4, A5, A6, A7, A8, A9);
+        impl_fn_ptr!($abi: A0, A1, A2, A3, A4, A5, A6, A7, A8, A9, A10);
+        impl_fn_ptr!($abi: A0, A1, A2, A3, A4, A5, A6, A7, A8, A9, A10, A11);
+    };
+}
+
+impl_fn_ptr_all!("C");
+impl_fn_ptr_all!("system");
+impl_fn_ptr_all!("Rust");
+#[cfg(target_arch = "x86")]
+impl_fn_ptr_all!("stdcall");
+#[cfg(target_arch = "x86")]
+impl_fn_ptr_all!("fastcall");
+#[cfg(target_arch = "x86")]
+impl_fn_ptr_all!("thiscall");
+
+/// A VTable slot holding a function of type `F`.
+pub struct Slot<F> {
+    index: usize,
+    _func: PhantomData<F>,
+}
+
+impl<F> Clone for Slot<F> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<F> Copy for Slot<F> {}
+
+impl<F> core::fmt::Debug for Slot<F> {
+    fn fmt(&self, f: &mut core::fmt::Formatter<'_>) -> core::fmt::Result {
+        f.debug_tuple("Slot").field(&self.index).finish()
+    }
+}
+
+impl<F> Slot<F> {
+    /// Creates a slot at the specified index in the VTable.
+    pub const fn new(index: usize) -> Self {
+        Self {
+            index,
+            _func: PhantomData,
+        }
+    }
+
+    /// Returns the index of the slot in the VTable.
+    pub const fn index(&self) -> usize {
+        self.index
+    }
+}
+
+impl<T, B: HookBackend> VTableHook<T, B> {
+    /// Returns the original method of the slot.
+    pub unsafe fn get_original<F: FnPtr>(&self, slot: Slot<F>) -> F {
+        F::from_address(self.get_original_method(slot.index))
+    }
+
+    /// Returns the method of the slot stored in the original VTable right now; see
+    /// [`get_live_original_method`](VTableHook::get_live_original_method).
+    pub unsafe fn get_live_original<F: FnPtr>(&self, slot: Slot<F>) -> F {
+        F::from_address(self.get_live_original_method(slot.index))
+    }
+
+    /// Returns the replaced method of the slot.
+    pub unsafe fn get_replaced<F: FnPtr>(&self, slot: Slot<F>) -> F {
+        F::from_address(self.get_replaced_method(slot.index))
+    }
+
+    /// Hooks the method of the slot with a new function.
+    pub unsafe fn replace<F: FnPtr>(&self, slot: Slot<F>, func: F) {
+        self.replace_method(slot.index, func.to_address());
+    }
+
+    /// Points the slot `dst` at the original implementation of the slot `src` of the same type.
+    pub unsafe fn alias<F>(&self, dst: Slot<F>, src: Slot<F>) {
+        self.alias_method(dst.index, src.index);
+    }
+
+    /// Restores the original method of the slot.
+    pub unsafe fn restore<F>(&self, slot: Slot<F>) {
+        self.restore_method(slot.index);
+    }
+}
@@ -0,0 +1,37 @@
+//! Helpers for Source and GoldSrc engine interfaces.
+//!
+//! Every engine module exports `CreateInterface`, which returns the interface
+//! registered under the given version string (e.g. `"VClient018"`).
+
+use std::ffi::{c_char, c_int, c_void, CString};
+
+use crate::{sys, VTableHook};
+
+/// Signature of the `CreateInterface` export.
+pub type CreateInterfaceFn = unsafe extern "C" fn(name: *const c_char, return_code: *mut c_int) -> *mut c_void;
+
+/// Returns the `CreateInterface` export of an already loaded module.
+pub unsafe fn create_interface_fn(module: &str) -> Option<CreateInterfaceFn> {
+    let module = sys::module_handle(module)?;
+    let func = sys::symbol(module, "CreateInterface")?;
+    Some(std::mem::transmute::<*const c_void, CreateInterfaceFn>(func))
+}
+
+/// Resolves the interface registered under `version` in the module.
+pub unsafe fn create_interface(module: &str, version: &str) -> Option<*mut c_void> {
+    let create_interface = create_interface_fn(module)?;
+    let version = CString::new(version).ok()?;
+    let interface = create_interface(version.as_ptr(), std::ptr::null_mut());
+    (!interface.is_null()).then_some(interface)
+}
+
+/// Resolves the interface and hooks its VTable.
+/// The count of methods is automatically determined.
+pub unsafe fn hook_interface(module: &str, version: &str) -> Option<VTableHook<*mut c_void>> {
+    create_interface(module, version).map(|interface| VTableHook::new(interface))
+}
+
+/// Resolves the interface and hooks its VTable with a specified method count.
+pub unsafe fn hook_interface_with_count(module: &str, version: &str, count: usize) -> Option<VTableHook<*mut c_void>> {
+    create_interface(module, version).map(|interface| VTableHook::with_count(interface, count))
+}
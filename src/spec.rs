@@ -0,0 +1,231 @@
+//! Hook sets described in TOML or JSON files, so they can be tweaked without rebuilding the hooking module.
+//!
+//! A spec lists classes, identified by their RTTI name or by the code of one of their methods, and the
+//! replacements to install on their methods. Replacements are looked up by name among the functions
+//! registered in [`Detours`], then among the symbols exported by loaded modules. Methods can be named
+//! through [`symbol`](crate::symbol) paths instead of indices, which survive patches to the target.
+//!
+//! ```toml
+//! [[hooks]]
+//! class = "game::Player"
+//! slots = { Think = 12, TakeDamage = 31 }
+//! methods = [
+//!     { slot = "Think", hook = "player_think" },
+//!     { slot = 44, hook = "player_respawn" },
+//!     { slot = "game.dll!game::Player::Jump", hook = "player_jump" },
+//! ]
+//!
+//! [[hooks]]
+//! signature = { slot = 3, pattern = "48 89 5C 24 ?? 57 48 83 EC 20" }
+//! count = 20
+//! methods = [{ slot = 3, hook = "weapon_fire" }]
+//! ```
+
+use std::collections::{BTreeMap, HashMap};
+use std::ffi::c_void;
+use std::path::Path;
+
+use serde::Deserialize;
+
+use crate::error::{Error, Result};
+use crate::pattern::Pattern;
+use crate::slot::FnPtr;
+use crate::{detect, rtti, symbol, sys, VTableCopyOptions, VTableHook};
+
+/// A set of hooks loaded from a file.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HookSpec {
+    /// Classes to hook, tried in order against each object.
+    #[serde(default)]
+    pub hooks: Vec<ClassSpec>,
+}
+
+/// The hooks of one class.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ClassSpec {
+    /// RTTI name of the class, mangled or as returned by [`rtti::demangle`].
+    pub class: Option<String>,
+    /// Code one of the methods of the class starts with, for classes without RTTI.
+    pub signature: Option<SignatureSpec>,
+    /// Number of methods; detected up to the first null entry if absent.
+    pub count: Option<usize>,
+    /// Names given to method indices, for use in [`MethodSpec::slot`].
+    #[serde(default)]
+    pub slots: BTreeMap<String, usize>,
+    /// Replacements to install.
+    #[serde(default)]
+    pub methods: Vec<MethodSpec>,
+}
+
+/// Identifies a class by the code of one of its methods.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct SignatureSpec {
+    /// Index of the method.
+    pub slot: usize,
+    /// Signature the method starts with, in the syntax of [`Pattern::parse`].
+    pub pattern: String,
+}
+
+/// A replacement for one method.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MethodSpec {
+    /// The method to replace.
+    pub slot: SlotRef,
+    /// Name of the replacement.
+    pub hook: String,
+}
+
+/// A method index, or its name in [`ClassSpec::slots`].
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(untagged)]
+pub enum SlotRef {
+    /// The 0-based index of the method.
+    Index(usize),
+    /// A name given to the index, or a method named as `module!Class::Method`.
+    Name(String),
+}
+
+/// Replacements a [`HookSpec`] can install, by name.
+#[derive(Debug, Clone, Default)]
+pub struct Detours(HashMap<String, usize>);
+
+impl Detours {
+    /// Creates an empty set of replacements.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes `func` installable under `name`.
+    pub fn register<F: FnPtr>(&mut self, name: &str, func: F) -> &mut Self {
+        self.0.insert(name.to_owned(), func.to_address());
+        self
+    }
+
+    /// Returns the address of the replacement named `name`, or of an exported symbol with that name.
+    pub fn resolve(&self, name: &str) -> Option<usize> {
+        self.0.get(name).copied().or_else(|| unsafe { sys::global_symbol(name) }.map(|func| func as usize))
+    }
+}
+
+impl HookSpec {
+    /// Parses a spec written in TOML.
+    pub fn from_toml(text: &str) -> Result<Self> {
+        let spec: Self = toml::from_str(text).map_err(|error| Error::Invalid(format!("invalid hook spec: {error}")))?;
+        spec.validate()
+    }
+
+    /// Parses a spec written in JSON.
+    pub fn from_json(text: &str) -> Result<Self> {
+        let spec: Self =
+            serde_json::from_str(text).map_err(|error| Error::Invalid(format!("invalid hook spec: {error}")))?;
+        spec.validate()
+    }
+
+    /// Rejects signature patterns that don't parse, which would otherwise never match.
+    fn validate(self) -> Result<Self> {
+        let mut signatures = self.hooks.iter().filter_map(|class| class.signature.as_ref());
+        if let Some(signature) = signatures.find(|signature| Pattern::parse(&signature.pattern).is_none()) {
+            return Err(Error::Invalid(format!("invalid hook spec: bad signature pattern '{}'", signature.pattern)));
+        }
+        Ok(self)
+    }
+
+    /// Reads a spec from a `.json` file, or from a TOML file otherwise.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let text = std::fs::read_to_string(path)
+            .map_err(|error| Error::Invalid(format!("failed to read {}: {error}", path.display())))?;
+        match path.extension().and_then(|extension| extension.to_str()) {
+            Some(extension) if extension.eq_ignore_ascii_case("json") => Self::from_json(&text),
+            _ => Self::from_toml(&text),
+        }
+    }
+
+    /// Returns the first class of the spec `object` is an instance of.
+    pub unsafe fn class_of(&self, object: *mut c_void) -> Option<&ClassSpec> {
+        sys::probe(object.cast())?;
+        let vtable = *object.cast::<*const usize>();
+        self.hooks.iter().find(|class| class.matches(vtable))
+    }
+
+    /// Hooks `object` with the replacements of the first class it is an instance of. Returns `None` if the
+    /// spec doesn't cover the class.
+    ///
+    /// Fails with [`Error::Invalid`] if a slot or a replacement can't be resolved, before touching the object,
+    /// and with the error of [`VTableHook::with_count_and_options`] if the hook can't be installed.
+    pub unsafe fn install(&self, object: *mut c_void, detours: &Detours) -> Result<Option<VTableHook<*mut c_void>>> {
+        let Some(class) = self.class_of(object) else {
+            return Ok(None);
+        };
+        let vtable = *object.cast::<*const usize>();
+        let count = match class.count {
+            Some(count) => count,
+            None => detect::probe_count(vtable)
+                .ok_or_else(|| Error::Invalid(format!("vtable {vtable:p} isn't readable")))?,
+        };
+        let methods = class
+            .methods
+            .iter()
+            .map(|method| {
+                let id = class.resolve_slot(&method.slot, vtable, count)?;
+                let func = detours
+                    .resolve(&method.hook)
+                    .ok_or_else(|| Error::Invalid(format!("no replacement named '{}'", method.hook)))?;
+                Ok((id, func))
+            })
+            .collect::<Result<Vec<_>>>()?;
+
+        let hook = VTableHook::with_count_and_options(object, count, &VTableCopyOptions::default())?;
+        for (id, func) in methods {
+            hook.replace_method(id, func);
+        }
+        Ok(Some(hook))
+    }
+}
+
+impl ClassSpec {
+    /// Returns `true` if the class of `vtable` is the one described.
+    pub unsafe fn matches(&self, vtable: *const usize) -> bool {
+        if let Some(name) = &self.class {
+            if !rtti::class_name(vtable).is_some_and(|class| rtti::is_named(&class, name)) {
+                return false;
+            }
+        }
+        if let Some(signature) = &self.signature {
+            let Some(pattern) = Pattern::parse(&signature.pattern) else {
+                return false;
+            };
+            let Some(method) = sys::probe(vtable.wrapping_add(signature.slot)) else {
+                return false;
+            };
+            if !sys::is_readable(method, pattern.len()) || !pattern.matches_at(method) {
+                return false;
+            }
+        }
+        self.class.is_some() || self.signature.is_some()
+    }
+
+    /// Returns the method index `slot` refers to, checked against the `count` methods of `vtable`.
+    ///
+    /// Names not in [`slots`](Self::slots) of the form `module!Class::Method` are resolved through
+    /// [`symbol::resolve_in`], so a method only found in another VTable of the class, such as the one of a
+    /// second base, is rejected.
+    pub unsafe fn resolve_slot(&self, slot: &SlotRef, vtable: *const usize, count: usize) -> Result<usize> {
+        let id = match slot {
+            SlotRef::Index(id) => *id,
+            SlotRef::Name(name) => match self.slots.get(name) {
+                Some(&id) => id,
+                None if name.contains('!') => symbol::resolve_in(name, vtable)?.index,
+                None => return Err(Error::Invalid(format!("no slot named '{name}'"))),
+            },
+        };
+        if id >= count {
+            return Err(Error::Invalid(format!("slot {id} is out of bounds of {count} methods")));
+        }
+        Ok(id)
+    }
+}
@@ -0,0 +1,151 @@
+//! Helpers for Steamworks interfaces.
+//!
+//! User interfaces are obtained through `SteamInternal_FindOrCreateUserInterface` exported by
+//! the `steam_api` module, using version strings such as `"SteamFriends017"`.
+
+use std::ffi::{c_char, c_void, CString};
+
+use crate::{sys, VTableHook};
+
+/// Handle of a Steam user.
+pub type HSteamUser = i32;
+
+type CreateInterfaceFn = unsafe extern "C" fn(version: *const c_char) -> *mut c_void;
+type FindOrCreateUserInterfaceFn = unsafe extern "C" fn(user: HSteamUser, version: *const c_char) -> *mut c_void;
+type GetHSteamUserFn = unsafe extern "C" fn() -> HSteamUser;
+
+/// Name of the `steam_api` module for the current platform.
+#[cfg(all(windows, target_pointer_width = "64"))]
+pub const STEAM_API_MODULE: &str = "steam_api64.dll";
+/// Name of the `steam_api` module for the current platform.
+#[cfg(all(windows, target_pointer_width = "32"))]
+pub const STEAM_API_MODULE: &str = "steam_api.dll";
+/// Name of the `steam_api` module for the current platform.
+#[cfg(target_os = "macos")]
+pub const STEAM_API_MODULE: &str = "libsteam_api.dylib";
+/// Name of the `steam_api` module for the current platform.
+#[cfg(all(unix, not(target_os = "macos")))]
+pub const STEAM_API_MODULE: &str = "libsteam_api.so";
+
+/// Describes a Steamworks interface.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SteamInterface {
+    /// Version string without the trailing number, e.g. `"SteamFriends"`.
+    pub prefix: &'static str,
+    /// Name of the C++ class, e.g. `"ISteamFriends"`.
+    pub class: &'static str,
+}
+
+macro_rules! steam_interfaces {
+    ($($name:ident => $prefix:literal, $class:literal;)*) => {
+        $(
+            #[doc = concat!("`", $class, "`.")]
+            pub const $name: SteamInterface = SteamInterface { prefix: $prefix, class: $class };
+        )*
+    };
+}
+
+steam_interfaces! {
+    STEAM_CLIENT => "SteamClient", "ISteamClient";
+    STEAM_USER => "SteamUser", "ISteamUser";
+    STEAM_FRIENDS => "SteamFriends", "ISteamFriends";
+    STEAM_UTILS => "SteamUtils", "ISteamUtils";
+    STEAM_MATCHMAKING => "SteamMatchMaking", "ISteamMatchmaking";
+    STEAM_USER_STATS => "STEAMUSERSTATS_INTERFACE_VERSION", "ISteamUserStats";
+    STEAM_APPS => "STEAMAPPS_INTERFACE_VERSION", "ISteamApps";
+    STEAM_NETWORKING => "SteamNetworking", "ISteamNetworking";
+    STEAM_REMOTE_STORAGE => "STEAMREMOTESTORAGE_INTERFACE_VERSION", "ISteamRemoteStorage";
+    STEAM_SCREENSHOTS => "STEAMSCREENSHOTS_INTERFACE_VERSION", "ISteamScreenshots";
+    STEAM_HTTP => "STEAMHTTP_INTERFACE_VERSION", "ISteamHTTP";
+    STEAM_UGC => "STEAMUGC_INTERFACE_VERSION", "ISteamUGC";
+    STEAM_INPUT => "SteamInput", "ISteamInput";
+}
+
+impl SteamInterface {
+    /// Returns the version string for the given interface version, e.g. `"SteamFriends017"`.
+    pub fn version(&self, number: u32) -> String {
+        format!("{}{:03}", self.prefix, number)
+    }
+
+    /// Returns the version number if `version` is a version string of this interface.
+    pub fn parse_version(&self, version: &str) -> Option<u32> {
+        let number = version.strip_prefix(self.prefix)?;
+        if number.is_empty() || !number.bytes().all(|c| c.is_ascii_digit()) {
+            return None;
+        }
+        number.parse().ok()
+    }
+}
+
+unsafe fn steam_api_symbol(name: &str) -> Option<*const c_void> {
+    sys::symbol(sys::module_handle(STEAM_API_MODULE)?, name)
+}
+
+/// Returns the interface for the given version string, e.g. `"SteamFriends017"`.
+///
+/// `ISteamClient` is created through `SteamInternal_CreateInterface`,
+/// all other interfaces through `SteamInternal_FindOrCreateUserInterface` for the current user.
+pub unsafe fn find_interface(version: &str) -> Option<*mut c_void> {
+    let version_c = CString::new(version).ok()?;
+
+    let interface = if STEAM_CLIENT.parse_version(version).is_some() {
+        let create_interface: CreateInterfaceFn =
+            std::mem::transmute(steam_api_symbol("SteamInternal_CreateInterface")?);
+        create_interface(version_c.as_ptr())
+    } else {
+        let get_user: GetHSteamUserFn = std::mem::transmute(steam_api_symbol("SteamAPI_GetHSteamUser")?);
+        let find_or_create: FindOrCreateUserInterfaceFn =
+            std::mem::transmute(steam_api_symbol("SteamInternal_FindOrCreateUserInterface")?);
+        find_or_create(get_user(), version_c.as_ptr())
+    };
+
+    (!interface.is_null()).then_some(interface)
+}
+
+/// Returns the newest available version of the interface, probing down from `newest`.
+pub unsafe fn find_latest_interface(interface: &SteamInterface, newest: u32) -> Option<(u32, *mut c_void)> {
+    (1..=newest)
+        .rev()
+        .find_map(|number| find_interface(&interface.version(number)).map(|ptr| (number, ptr)))
+}
+
+/// Returns a hint for the number of methods of the interface.
+///
+/// The hint is the number of flat API wrappers (`SteamAPI_ISteamFriends_*`) exported by the loaded
+/// `steam_api` module, so it matches the SDK the game was built with. Deprecated methods have no
+/// wrappers, so the hint can be lower than the real count.
+#[cfg(windows)]
+pub unsafe fn count_hint(interface: &SteamInterface) -> Option<usize> {
+    let module = sys::module_handle(STEAM_API_MODULE)?;
+    let image = crate::pe::Image::new(module.cast())?;
+    let prefix = format!("SteamAPI_{}_", interface.class);
+    let count = image
+        .export_names()
+        .iter()
+        .filter(|name| name.to_bytes().starts_with(prefix.as_bytes()))
+        .count();
+    (count != 0).then_some(count)
+}
+
+/// Returns a hint for the number of methods of the interface.
+///
+/// Export enumeration is only implemented for PE images, so no hint is available on this platform.
+#[cfg(not(windows))]
+pub unsafe fn count_hint(_interface: &SteamInterface) -> Option<usize> {
+    None
+}
+
+/// Resolves the interface and hooks its VTable.
+/// The count of methods is taken from [`count_hint`], falling back to automatic detection.
+pub unsafe fn hook_interface(interface: &SteamInterface, version: u32) -> Option<VTableHook<*mut c_void>> {
+    let ptr = find_interface(&interface.version(version))?;
+    Some(match count_hint(interface) {
+        Some(count) => VTableHook::with_count(ptr, count),
+        None => VTableHook::new(ptr),
+    })
+}
+
+/// Resolves the interface and hooks its VTable with a specified method count.
+pub unsafe fn hook_interface_with_count(version: &str, count: usize) -> Option<VTableHook<*mut c_void>> {
+    find_interface(version).map(|ptr| VTableHook::with_count(ptr, count))
+}
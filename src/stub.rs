@@ -0,0 +1,183 @@
+//! Generated stubs returning a constant or nothing, for neutering methods without writing a function
+//! per slot (x86/x86_64 only).
+//!
+//! Stubs are shared between every slot returning the same value with the same stack cleanup, and are
+//! never freed, so tables may keep pointing at them after their hooks are gone.
+
+use std::io;
+use std::sync::Mutex;
+
+use crate::allocations::{self, AllocationKind};
+use crate::slot::{FnPtr, Slot};
+use crate::{sys, HookBackend, VTableHook};
+
+/// Generated stubs by returned value, `None` leaving the return register alone, and bytes of arguments popped.
+static STUBS: Mutex<Vec<(Option<usize>, u16, usize)>> = Mutex::new(Vec::new());
+
+/// Who pops the stack arguments, as far as 32-bit x86 tells conventions apart.
+#[allow(dead_code)]
+enum Convention {
+    Caller,
+    Callee,
+    This,
+    Fast,
+}
+
+/// Returns the bytes of stack arguments a method with arguments of `sizes` pops under `convention`.
+const fn cleanup(convention: Convention, sizes: &[usize]) -> u16 {
+    let mut bytes = 0;
+    let mut registers = match convention {
+        Convention::Caller => return 0,
+        Convention::Callee => 0,
+        Convention::This => 1,
+        Convention::Fast => 2,
+    };
+    let mut i = 0;
+    while i < sizes.len() {
+        if registers > 0 && sizes[i] <= 4 {
+            registers -= 1;
+        } else {
+            bytes += sizes[i].next_multiple_of(4);
+        }
+        i += 1;
+    }
+    bytes as u16
+}
+
+/// Function pointer types a stub can stand in for, which tells the stack arguments to pop.
+///
+/// Implemented for the [`FnPtr`] types. Every convention is caller-cleanup on x86_64.
+pub unsafe trait StubFn: FnPtr {
+    /// Bytes of stack arguments the method pops on return.
+    const CLEANUP: u16;
+}
+
+macro_rules! impl_stub_fn {
+    ($abi:literal, $convention:ident: $($arg:ident),*) => {
+        unsafe impl<R, $($arg),*> StubFn for extern $abi fn($($arg),*) -> R {
+            const CLEANUP: u16 = stub_cleanup(Convention::$convention, &[$(std::mem::size_of::<$arg>()),*]);
+        }
+
+        unsafe impl<R, $($arg),*> StubFn for unsafe extern $abi fn($($arg),*) -> R {
+            const CLEANUP: u16 = stub_cleanup(Convention::$convention, &[$(std::mem::size_of::<$arg>()),*]);
+        }
+    };
+}
+
+macro_rules! impl_stub_fn_all {
+    ($abi:literal, $convention:ident) => {
+        impl_stub_fn!($abi, $convention:);
+        impl_stub_fn!($abi, $convention: A0);
+        impl_stub_fn!($abi, $convention: A0, A1);
+        impl_stub_fn!($abi, $convention: A0, A1, A2);
+        impl_stub_fn!($abi, $convention: A0, A1, A2, A3);
+        impl_stub_fn!($abi, $convention: A0, A1, A2, A3, A4);
+        impl_stub_fn!($abi, $convention: A0, A1, A2, A3, A4, A5);
+        impl_stub_fn!($abi, $convention: A0, A1, A2, A3, A4, A5, A6);
+        impl_stub_fn!($abi, $convention: A0, A1, A2, A3, A4, A5, A6, A7);
+        impl_stub_fn!($abi, $convention: A0, A1, A2, A3, A4, A5, A6, A7, A8);
+        impl_stub_fn!($abi, $convention: A0, A1, A2, A3, A4, A5, A6, A7, A8, A9);
+        impl_stub_fn!($abi, $convention: A0, A1, A2, A3, A4, A5, A6, A7, A8, A9, A10);
+        impl_stub_fn!($abi, $convention: A0, A1, A2, A3, A4, A5, A6, A7, A8, A9, A10, A11);
+    };
+}
+
+const fn stub_cleanup(convention: Convention, sizes: &[usize]) -> u16 {
+    if cfg!(target_arch = "x86") {
+        cleanup(convention, sizes)
+    } else {
+        0
+    }
+}
+
+impl_stub_fn_all!("C", Caller);
+#[cfg(all(target_arch = "x86", windows))]
+impl_stub_fn_all!("system", Callee);
+#[cfg(not(all(target_arch = "x86", windows)))]
+impl_stub_fn_all!("system", Caller);
+#[cfg(target_arch = "x86")]
+impl_stub_fn_all!("stdcall", Callee);
+#[cfg(target_arch = "x86")]
+impl_stub_fn_all!("fastcall", Fast);
+#[cfg(target_arch = "x86")]
+impl_stub_fn_all!("thiscall", This);
+
+/// Returns a stub returning `value` and popping `cleanup` bytes of arguments, generating it if needed.
+pub unsafe fn stub(value: usize, cleanup: u16) -> io::Result<usize> {
+    generate(Some(value), cleanup)
+}
+
+/// Returns a stub returning immediately and popping `cleanup` bytes of arguments, generating it if needed.
+pub unsafe fn nop(cleanup: u16) -> io::Result<usize> {
+    generate(None, cleanup)
+}
+
+unsafe fn generate(value: Option<usize>, cleanup: u16) -> io::Result<usize> {
+    let mut stubs = STUBS.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some(&(_, _, address)) = stubs.iter().find(|&&(v, c, _)| v == value && c == cleanup) {
+        return Ok(address);
+    }
+
+    let mut code = Vec::new();
+    if let Some(value) = value {
+        // mov eax/rax, value
+        code.extend_from_slice(if cfg!(target_arch = "x86_64") { &[0x48, 0xB8] } else { &[0xB8] });
+        code.extend_from_slice(&value.to_le_bytes());
+    }
+    match cleanup {
+        0 => code.push(0xC3),
+        bytes => {
+            code.push(0xC2);
+            code.extend_from_slice(&bytes.to_le_bytes());
+        }
+    }
+
+    let size = sys::page_size();
+    let address = sys::alloc_pages(size, sys::Protection::ReadWrite)?;
+    std::ptr::copy_nonoverlapping(code.as_ptr(), address as *mut u8, code.len());
+    if let Err(error) = sys::protect(address, size, sys::Protection::ReadExecute) {
+        sys::free_pages(address, size);
+        return Err(error);
+    }
+    allocations::register(AllocationKind::Stub, address, size, None);
+    stubs.push((value, cleanup, address));
+    Ok(address)
+}
+
+impl<T, B: HookBackend> VTableHook<T, B> {
+    /// Replaces the method at the specified index with a stub returning `value`, such as an `HRESULT`.
+    ///
+    /// Only available on x86_64, where callers pop the stack arguments. On x86 the stub has to pop those
+    /// of callee-cleanup methods such as COM's, so use [`stub`](Self::stub) with a typed slot, or
+    /// [`stub`](fn@stub) with the size of the arguments.
+    #[cfg(target_arch = "x86_64")]
+    pub unsafe fn stub_method(&self, id: usize, value: usize) -> io::Result<()> {
+        self.replace_method(id, stub(value, 0)?);
+        Ok(())
+    }
+
+    /// Replaces the method of the slot with a stub returning `value` and popping the stack arguments its
+    /// type takes.
+    pub unsafe fn stub<F: StubFn>(&self, slot: Slot<F>, value: usize) -> io::Result<()> {
+        self.replace_method(slot.index(), stub(value, F::CLEANUP)?);
+        Ok(())
+    }
+
+    /// Replaces the method at the specified index with a stub returning immediately, for methods
+    /// returning nothing.
+    ///
+    /// Only available on x86_64, like [`stub_method`](Self::stub_method); use [`nop`](Self::nop) with a
+    /// typed slot, or [`nop`](fn@nop) with the size of the arguments, on x86.
+    #[cfg(target_arch = "x86_64")]
+    pub unsafe fn nop_method(&self, id: usize) -> io::Result<()> {
+        self.replace_method(id, nop(0)?);
+        Ok(())
+    }
+
+    /// Replaces the method of the slot with a stub returning immediately and popping the stack arguments
+    /// its type takes.
+    pub unsafe fn nop<F: StubFn>(&self, slot: Slot<F>) -> io::Result<()> {
+        self.replace_method(slot.index(), nop(F::CLEANUP)?);
+        Ok(())
+    }
+}
@@ -0,0 +1,269 @@
+//! Hook targets named as `module!Class::Method`, resolved at runtime to a VTable and a method index.
+//!
+//! Names are looked up among the symbols the module exports and, with the `pdb` feature, the public
+//! symbols of its PDB, so a hook definition keeps working when a patch of the target moves methods around
+//! in the table. MSVC and Itanium mangled names are understood; overloads aren't told apart.
+
+use std::ffi::c_void;
+use std::fmt;
+
+use crate::detect::probe_code_count;
+use crate::error::{Error, Result};
+use crate::sys;
+
+/// A method named as `module!Class::Method`, where the class may be qualified by namespaces.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct MethodPath {
+    /// File name of the module defining the class.
+    pub module: String,
+    /// Qualified name of the class, e.g. `game::Player`.
+    pub class: String,
+    /// Name of the method.
+    pub method: String,
+}
+
+/// A method found in the VTable of its class.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResolvedMethod {
+    /// Address of the first method of the VTable.
+    pub vtable: usize,
+    /// Index of the method in the VTable.
+    pub index: usize,
+    /// Address of the method.
+    pub address: usize,
+}
+
+impl MethodPath {
+    /// Parses a path of the form `module!Class::Method`.
+    pub fn parse(path: &str) -> Option<Self> {
+        let (module, qualified) = path.split_once('!')?;
+        let (class, method) = qualified.rsplit_once("::")?;
+        if module.is_empty() || class.is_empty() || method.is_empty() {
+            return None;
+        }
+        Some(Self {
+            module: module.to_owned(),
+            class: class.to_owned(),
+            method: method.to_owned(),
+        })
+    }
+
+    /// Finds the method among the symbols of its module and its index in a VTable of the class.
+    ///
+    /// Fails with [`Error::Invalid`] if the module isn't loaded, has no symbol for the method or the class's
+    /// VTable, or if the method isn't in one of the class's VTables.
+    pub unsafe fn resolve(&self) -> Result<ResolvedMethod> {
+        self.find(|_| true)?
+            .ok_or_else(|| Error::Invalid(format!("{self} isn't in a vtable of {}", self.class)))
+    }
+
+    /// Like [`resolve`](Self::resolve), but only looks for the method in the VTable whose first method is at
+    /// `vtable`, such as the primary VTable of an object, and not in the other VTables of the class.
+    pub unsafe fn resolve_in(&self, vtable: *const usize) -> Result<ResolvedMethod> {
+        self.find(|start| start == vtable as usize)?
+            .ok_or_else(|| Error::Invalid(format!("{self} isn't in vtable {vtable:p}")))
+    }
+
+    unsafe fn find(&self, mut accept: impl FnMut(usize) -> bool) -> Result<Option<ResolvedMethod>> {
+        let base = sys::module_base(&self.module)
+            .ok_or_else(|| Error::Invalid(format!("module {} isn't loaded", self.module)))?;
+        let symbols = module_symbols(base);
+
+        let qualified = format!("{}::{}", self.class, self.method);
+        let methods: Vec<usize> = symbols
+            .iter()
+            .filter(|(name, _)| demangle_function(name).is_some_and(|name| name == qualified))
+            .map(|&(_, address)| address)
+            .collect();
+        if methods.is_empty() {
+            return Err(Error::Invalid(format!("no symbol for {self}")));
+        }
+
+        Ok(symbols
+            .iter()
+            .filter(|(name, _)| vtable_class(name).is_some_and(|class| class == self.class))
+            .find_map(|(name, address)| {
+                let vtable = vtable_start(&symbols, name, *address);
+                if !accept(vtable) {
+                    return None;
+                }
+                let (index, address) = find_method(vtable, &methods)?;
+                Some(ResolvedMethod { vtable, index, address })
+            }))
+    }
+}
+
+impl fmt::Display for MethodPath {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}!{}::{}", self.module, self.class, self.method)
+    }
+}
+
+/// Resolves a path of the form `module!Class::Method`; see [`MethodPath::resolve`].
+pub unsafe fn resolve(path: &str) -> Result<ResolvedMethod> {
+    parse(path)?.resolve()
+}
+
+/// Resolves a path of the form `module!Class::Method` in the VTable at `vtable`; see [`MethodPath::resolve_in`].
+pub unsafe fn resolve_in(path: &str, vtable: *const usize) -> Result<ResolvedMethod> {
+    parse(path)?.resolve_in(vtable)
+}
+
+fn parse(path: &str) -> Result<MethodPath> {
+    MethodPath::parse(path).ok_or_else(|| Error::Invalid(format!("'{path}' isn't of the form module!Class::Method")))
+}
+
+/// Returns the qualified name of the method a mangled symbol names, e.g. `game::Player::Think` for
+/// `?Think@Player@game@@UEAAXXZ` or `_ZN4game6Player5ThinkEv`. Special members and templates aren't supported.
+pub fn demangle_function(mangled: &str) -> Option<String> {
+    if let Some(name) = mangled.strip_prefix('?') {
+        return msvc_scope(name.split_once("@@")?.0);
+    }
+    let (parts, rest) = itanium_nested(mangled.strip_prefix("_ZN")?)?;
+    (parts.len() > 1 && !rest.is_empty()).then(|| parts.join("::"))
+}
+
+/// Returns the qualified name of the class whose VTable a mangled symbol names, such as `??_7Player@game@@6B@`
+/// or `_ZTVN4game6PlayerE`.
+fn vtable_class(mangled: &str) -> Option<String> {
+    if let Some(name) = mangled.strip_prefix("??_7") {
+        return msvc_scope(name.split_once("@@6B")?.0);
+    }
+    match mangled.strip_prefix("_ZTV")? {
+        nested if nested.starts_with('N') => {
+            let (parts, rest) = itanium_nested(&nested[1..])?;
+            rest.is_empty().then(|| parts.join("::"))
+        }
+        name => {
+            let (part, rest) = itanium_source_name(name)?;
+            rest.is_empty().then(|| part.to_owned())
+        }
+    }
+}
+
+/// Joins the `@`-separated scopes of an MSVC name, innermost first, outermost last.
+fn msvc_scope(name: &str) -> Option<String> {
+    let parts: Vec<_> = name.split('@').rev().collect();
+    let plain = |part: &&str| !part.is_empty() && part.bytes().all(|byte| byte.is_ascii_alphanumeric() || byte == b'_');
+    let valid = parts.iter().all(plain) && !parts.iter().any(|part| part.bytes().all(|byte| byte.is_ascii_digit()));
+    valid.then(|| parts.join("::"))
+}
+
+/// Parses an Itanium `<length><identifier>`.
+fn itanium_source_name(name: &str) -> Option<(&str, &str)> {
+    let digits = name.bytes().take_while(u8::is_ascii_digit).count();
+    let len: usize = name[..digits].parse().ok()?;
+    let end = digits.checked_add(len)?;
+    Some((name.get(digits..end)?, &name[end..]))
+}
+
+/// Parses the parts of an Itanium nested name following its `N`, returning them and what follows the `E`.
+fn itanium_nested(mut name: &str) -> Option<(Vec<&str>, &str)> {
+    name = name.trim_start_matches(['r', 'V', 'K']);
+    let mut parts = Vec::new();
+    loop {
+        if let Some(rest) = name.strip_prefix('E') {
+            return (!parts.is_empty()).then_some((parts, rest));
+        }
+        let (part, rest) = itanium_source_name(name)?;
+        parts.push(part);
+        name = rest;
+    }
+}
+
+/// Returns the address of the first method of the VTable the symbol `name` at `address` names.
+///
+/// MSVC symbols point at the first method. Itanium ones point at the offsets preceding it, of which
+/// there are more than two with virtual bases; the methods follow the pointer to the `type_info`.
+unsafe fn vtable_start(symbols: &[(String, usize)], name: &str, address: usize) -> usize {
+    const MAX_PREFIX: usize = 16;
+
+    let Some(class) = name.strip_prefix("_ZTV") else {
+        return address;
+    };
+    let word = std::mem::size_of::<usize>();
+    let type_info = symbols
+        .iter()
+        .find(|(symbol, _)| symbol.strip_prefix("_ZTI") == Some(class))
+        .map(|&(_, type_info)| type_info);
+    type_info
+        .and_then(|type_info| {
+            (0..MAX_PREFIX).find(|&id| sys::probe((address + id * word) as *const usize) == Some(type_info))
+        })
+        .map_or(address + 2 * word, |id| address + (id + 1) * word)
+}
+
+/// Returns the index and the address of the first entry of `vtable` that is one of `methods`, looking
+/// at the entries counted by [`probe_code_count`].
+unsafe fn find_method(vtable: usize, methods: &[usize]) -> Option<(usize, usize)> {
+    let vtable = vtable as *const usize;
+    (0..probe_code_count(vtable)).find_map(|id| {
+        let entry = *vtable.add(id);
+        methods.iter().find(|&&method| same_function(entry, method)).map(|&method| (id, method))
+    })
+}
+
+/// Returns `true` if `a` and `b` are the same function, seeing through incremental-linking and import stubs.
+unsafe fn same_function(a: usize, b: usize) -> bool {
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    {
+        a == b || crate::detect::resolve_thunk(a) == crate::detect::resolve_thunk(b)
+    }
+    #[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+    {
+        a == b
+    }
+}
+
+/// Returns the names and addresses of the symbols known for the module at `base`: its exports, and with the
+/// `pdb` feature the public symbols of its PDB.
+pub(crate) unsafe fn module_symbols(base: *mut c_void) -> Vec<(String, usize)> {
+    #[allow(unused_mut)]
+    let mut symbols = sys::exports(base);
+    #[cfg(all(feature = "pdb", windows))]
+    symbols.extend(pdb_symbols(base));
+    symbols
+}
+
+/// Returns the public symbols of the PDB of the module at `base`, found at the path recorded in the module
+/// or next to it, if its GUID matches.
+#[cfg(all(feature = "pdb", windows))]
+unsafe fn pdb_symbols(base: *mut c_void) -> Vec<(String, usize)> {
+    let Some((guid, _, recorded)) = crate::pe::Image::new(base.cast()).and_then(|image| image.codeview()) else {
+        return Vec::new();
+    };
+    let recorded = std::path::PathBuf::from(recorded.to_string_lossy().into_owned());
+    let beside = sys::module_path(base).map(|path| path.with_extension("pdb"));
+    std::iter::once(recorded)
+        .chain(beside)
+        .find_map(|path| read_pdb(&path, guid, base as usize))
+        .unwrap_or_default()
+}
+
+#[cfg(all(feature = "pdb", windows))]
+fn read_pdb(path: &std::path::Path, guid: [u8; 16], base: usize) -> Option<Vec<(String, usize)>> {
+    use pdb::FallibleIterator;
+
+    let mut pdb = pdb::PDB::open(std::fs::File::open(path).ok()?).ok()?;
+    // The CodeView record stores the first three fields of the GUID little-endian.
+    let mut expected = guid;
+    expected[..4].reverse();
+    expected[4..6].reverse();
+    expected[6..8].reverse();
+    if pdb.pdb_information().ok()?.guid.as_bytes() != &expected {
+        return None;
+    }
+
+    let address_map = pdb.address_map().ok()?;
+    let globals = pdb.global_symbols().ok()?;
+    let mut symbols = Vec::new();
+    let mut iter = globals.iter();
+    while let Ok(Some(symbol)) = iter.next() {
+        if let Ok(pdb::SymbolData::Public(public)) = symbol.parse() {
+            if let Some(rva) = public.offset.to_rva(&address_map) {
+                symbols.push((public.name.to_string().into_owned(), base + rva.0 as usize));
+            }
+        }
+    }
+    Some(symbols)
+}
@@ -0,0 +1,63 @@
+//! Stand-ins for the memory APIs when running under Miri, which can't query or protect pages.
+//!
+//! Allocations come from the Rust allocator and every address is treated as readable and
+//! writable, so that Miri itself reports accesses outside of the synthetic tables under test.
+
+use std::alloc::{self, Layout};
+use std::ffi::c_void;
+use std::io;
+
+use super::{Protection, RawProtection};
+
+/// Returns the size of a memory page.
+pub(crate) fn page_size() -> usize {
+    4096
+}
+
+fn page_layout(size: usize) -> Layout {
+    Layout::from_size_align(size.max(1), page_size()).expect("invalid page allocation size")
+}
+
+/// Returns `true`; Miri reports writes to memory that isn't writable.
+pub(crate) unsafe fn is_writable(_address: usize) -> bool {
+    true
+}
+
+/// Returns `true`; Miri reports reads outside of allocations.
+pub(crate) unsafe fn is_readable(_address: usize, _size: usize) -> bool {
+    true
+}
+
+/// Accepts the protection change without doing anything.
+pub(crate) unsafe fn protect(_address: usize, _size: usize, _protection: Protection) -> io::Result<RawProtection> {
+    Ok(RawProtection::default())
+}
+
+/// Accepts the protection change without doing anything.
+pub(crate) unsafe fn protect_raw(_address: usize, _size: usize, raw: RawProtection) -> io::Result<RawProtection> {
+    Ok(raw)
+}
+
+/// Allocates `size` bytes of zeroed, page-aligned memory.
+pub(crate) unsafe fn alloc_pages(size: usize, _protection: Protection) -> io::Result<usize> {
+    let address = alloc::alloc_zeroed(page_layout(size));
+    if address.is_null() {
+        return Err(io::Error::from(io::ErrorKind::OutOfMemory));
+    }
+    Ok(address.expose_provenance())
+}
+
+/// Frees memory returned by [`alloc_pages`].
+pub(crate) unsafe fn free_pages(address: usize, size: usize) {
+    alloc::dealloc(std::ptr::with_exposed_provenance_mut(address), page_layout(size));
+}
+
+/// Returns `None`; there are no loaded modules to look up under Miri.
+pub(crate) unsafe fn module_of(_address: usize) -> Option<*mut c_void> {
+    None
+}
+
+/// Reads the word at `address`; Miri reports reads outside of allocations.
+pub(crate) unsafe fn probe(address: *const usize) -> Option<usize> {
+    Some(address.read_unaligned())
+}
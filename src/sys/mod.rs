@@ -0,0 +1,45 @@
+//! Thin wrappers over the platform APIs.
+
+// Not every helper is used by every combination of features.
+#![allow(dead_code)]
+
+#[cfg(windows)]
+mod windows;
+#[cfg(windows)]
+pub(crate) use self::windows::*;
+
+#[cfg(unix)]
+mod unix;
+#[cfg(unix)]
+pub(crate) use self::unix::*;
+
+// Named imports shadow the platform's glob import.
+#[cfg(all(feature = "region", not(miri)))]
+mod region;
+#[cfg(all(feature = "region", not(miri)))]
+pub(crate) use self::region::{alloc_pages, free_pages, is_readable, is_writable, page_size, protect};
+
+#[cfg(miri)]
+mod miri;
+#[cfg(miri)]
+pub(crate) use self::miri::{
+    alloc_pages, free_pages, is_readable, is_writable, module_of, page_size, probe, protect, protect_raw,
+};
+
+/// Page protection requested from [`protect`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Protection {
+    NoAccess,
+    ReadOnly,
+    ReadWrite,
+    ReadExecute,
+    ReadWriteExecute,
+}
+
+/// Makes the pages covering `address..address + size` writable while `f` runs.
+pub(crate) unsafe fn with_writable<R>(address: usize, size: usize, f: impl FnOnce() -> R) -> std::io::Result<R> {
+    let previous = protect(address, size, Protection::ReadWrite)?;
+    let result = f();
+    protect_raw(address, size, previous)?;
+    Ok(result)
+}
@@ -0,0 +1,101 @@
+//! Page queries, protection changes and page allocations through the `region` crate, for
+//! consistency with other tools in the same process already managing protections through it.
+//!
+//! Saving and restoring a protection still goes through the platform, as `region` drops flags such as
+//! `PAGE_GUARD` and `PAGE_WRITECOPY` that have to survive a round trip.
+
+use std::io;
+
+use super::{Protection, RawProtection};
+use crate::memory_map;
+
+fn region_protection(protection: Protection) -> region::Protection {
+    match protection {
+        Protection::NoAccess => region::Protection::NONE,
+        Protection::ReadOnly => region::Protection::READ,
+        Protection::ReadWrite => region::Protection::READ_WRITE,
+        Protection::ReadExecute => region::Protection::READ_EXECUTE,
+        Protection::ReadWriteExecute => region::Protection::READ_WRITE_EXECUTE,
+    }
+}
+
+/// Converts a protection reported by `region` to the platform's flags.
+#[cfg(unix)]
+fn raw_protection(protection: region::Protection) -> RawProtection {
+    let mut raw = libc::PROT_NONE;
+    if protection.contains(region::Protection::READ) {
+        raw |= libc::PROT_READ;
+    }
+    if protection.contains(region::Protection::WRITE) {
+        raw |= libc::PROT_WRITE;
+    }
+    if protection.contains(region::Protection::EXECUTE) {
+        raw |= libc::PROT_EXEC;
+    }
+    raw
+}
+
+/// Returns the size of a memory page.
+pub(crate) fn page_size() -> usize {
+    region::page::size()
+}
+
+/// Returns `true` if the region containing `address` is writable.
+pub(crate) unsafe fn is_writable(address: usize) -> bool {
+    if let Some(region) = memory_map::region_of(address) {
+        return region.writable;
+    }
+    region::query(address as *const u8).is_ok_and(|region| region.is_writable())
+}
+
+/// Returns `true` if every page of `address..address + size` is mapped readable.
+pub(crate) unsafe fn is_readable(address: usize, size: usize) -> bool {
+    if let Some(readable) = memory_map::is_readable(address, size) {
+        return readable;
+    }
+    let end = address.saturating_add(size.max(1));
+    let Ok(regions) = region::query_range(address as *const u8, end - address) else {
+        return false;
+    };
+    let mut at = address;
+    for region in regions {
+        match region {
+            Ok(region) if region.as_range().start <= at && region.is_readable() => at = region.as_range().end,
+            _ => return false,
+        }
+        if at >= end {
+            return true;
+        }
+    }
+    false
+}
+
+/// Changes the protection of the pages covering `address..address + size`,
+/// returning the previous protection of the first page.
+pub(crate) unsafe fn protect(address: usize, size: usize, protection: Protection) -> io::Result<RawProtection> {
+    let previous = region::query(address as *const u8).map_err(region::Error::into_io_error)?.protection();
+    let mut requested = region_protection(protection);
+    // Keeps code pages executable when only write access is requested.
+    if protection == Protection::ReadWrite {
+        requested |= previous & region::Protection::EXECUTE;
+    }
+    #[cfg(windows)]
+    let raw = super::query_protection(address).ok_or_else(io::Error::last_os_error)?;
+    #[cfg(unix)]
+    let raw = raw_protection(previous);
+    memory_map::invalidate_range(address, size);
+    region::protect(address as *const u8, size, requested).map_err(region::Error::into_io_error)?;
+    Ok(raw)
+}
+
+/// Allocates `size` bytes of fresh pages with the given protection.
+pub(crate) unsafe fn alloc_pages(size: usize, protection: Protection) -> io::Result<usize> {
+    let allocation = region::alloc(size, region_protection(protection)).map_err(region::Error::into_io_error)?;
+    Ok(allocation.into_raw_parts::<u8>().0 as usize)
+}
+
+/// Frees pages returned by [`alloc_pages`].
+pub(crate) unsafe fn free_pages(address: usize, size: usize) {
+    memory_map::invalidate_range(address, size);
+    drop(region::Allocation::from_raw_parts(address as *mut u8, size.next_multiple_of(page_size())));
+}
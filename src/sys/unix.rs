@@ -0,0 +1,412 @@
+use std::ffi::{c_void, CString};
+use std::io;
+use std::ops::Range;
+use std::path::PathBuf;
+
+use super::Protection;
+use crate::memory_map::{self, Region};
+
+/// Platform page protection flags.
+pub(crate) type RawProtection = libc::c_int;
+
+/// Returns the handle of an already loaded module.
+pub(crate) unsafe fn module_handle(name: &str) -> Option<*mut c_void> {
+    let name = CString::new(name).ok()?;
+    let module = libc::dlopen(name.as_ptr(), libc::RTLD_NOW | libc::RTLD_NOLOAD);
+    (!module.is_null()).then_some(module)
+}
+
+/// Returns the base address of an already loaded module, found by the file name of its mappings.
+pub(crate) unsafe fn module_base(name: &str) -> Option<*mut c_void> {
+    let mapping = mappings().into_iter().find(|mapping| {
+        mapping.path == name || std::path::Path::new(&mapping.path).file_name().is_some_and(|file| file == name)
+    })?;
+    Some(mapping.start as *mut c_void)
+}
+
+/// Returns the address of an exported symbol of the module.
+pub(crate) unsafe fn symbol(module: *mut c_void, name: &str) -> Option<*const c_void> {
+    let name = CString::new(name).ok()?;
+    let func = libc::dlsym(module, name.as_ptr());
+    (!func.is_null()).then_some(func as *const c_void)
+}
+
+/// Returns the address of a symbol exported by any loaded module.
+pub(crate) unsafe fn global_symbol(name: &str) -> Option<*const c_void> {
+    symbol(libc::RTLD_DEFAULT, name)
+}
+
+/// Returns the base address of the module containing `address`.
+pub(crate) unsafe fn module_of(address: usize) -> Option<*mut c_void> {
+    let mut info = std::mem::zeroed::<libc::Dl_info>();
+    (libc::dladdr(address as *const c_void, &mut info) != 0 && !info.dli_fbase.is_null()).then_some(info.dli_fbase)
+}
+
+/// Calls `f` with the base address of every module loaded in the current process.
+pub(crate) unsafe fn modules(mut f: impl FnMut(*mut c_void)) {
+    let mut bases = Vec::new();
+    for mapping in mappings().iter().filter(|mapping| mapping.path.starts_with('/')) {
+        // Files mapped other than by the loader aren't known to `dladdr`.
+        if let Some(base) = module_of(mapping.start).filter(|base| !bases.contains(base)) {
+            bases.push(base);
+            f(base);
+        }
+    }
+}
+
+/// Returns the path of the module at `base`.
+pub(crate) unsafe fn module_path(base: *mut c_void) -> Option<PathBuf> {
+    let mut info = std::mem::zeroed::<libc::Dl_info>();
+    if libc::dladdr(base, &mut info) == 0 || info.dli_fname.is_null() {
+        return None;
+    }
+    let name = std::ffi::CStr::from_ptr(info.dli_fname).to_string_lossy().into_owned();
+    (!name.is_empty()).then(|| name.into())
+}
+
+/// Returns the name of the exported symbol containing `address` and the offset of the address into it.
+#[cfg(feature = "symbols")]
+pub(crate) unsafe fn symbolize(address: usize) -> Option<(String, usize)> {
+    let mut info = std::mem::zeroed::<libc::Dl_info>();
+    if libc::dladdr(address as *const c_void, &mut info) == 0 || info.dli_sname.is_null() {
+        return None;
+    }
+    let name = std::ffi::CStr::from_ptr(info.dli_sname).to_string_lossy().into_owned();
+    Some((name, address.wrapping_sub(info.dli_saddr as usize)))
+}
+
+unsafe fn read<V: Copy>(base: *const u8, offset: usize) -> V {
+    std::ptr::read_unaligned(base.add(offset).cast())
+}
+
+const PT_LOAD: u32 = 1;
+const PT_DYNAMIC: u32 = 2;
+
+/// A program header as `(type, flags, vaddr, memsz)`.
+type ProgramHeader = (u32, u32, usize, usize);
+
+/// Returns the program headers of the ELF module at `base` and the bias added to their addresses.
+unsafe fn program_headers(base: *mut c_void) -> Option<(Vec<ProgramHeader>, usize)> {
+    let base = base as *const u8;
+    if read::<[u8; 4]>(base, 0) != *b"\x7fELF" {
+        return None;
+    }
+    let segments: Vec<ProgramHeader> = match read::<u8>(base, 4) {
+        // ELFCLASS64: (type, flags, vaddr, memsz) from 56-byte program headers.
+        2 => {
+            let (offset, size, count) = (read::<u64>(base, 0x20) as usize, read::<u16>(base, 0x36), read::<u16>(base, 0x38));
+            (0..count as usize)
+                .map(|i| base.add(offset + i * size as usize))
+                .map(|h| (read(h, 0), read(h, 4), read::<u64>(h, 16) as usize, read::<u64>(h, 40) as usize))
+                .collect()
+        }
+        // ELFCLASS32: 32-byte program headers.
+        1 => {
+            let (offset, size, count) = (read::<u32>(base, 0x1C) as usize, read::<u16>(base, 0x2A), read::<u16>(base, 0x2C));
+            (0..count as usize)
+                .map(|i| base.add(offset + i * size as usize))
+                .map(|h| (read(h, 0), read(h, 24), read::<u32>(h, 8) as usize, read::<u32>(h, 20) as usize))
+                .collect()
+        }
+        _ => return None,
+    };
+
+    let first = segments.iter().filter(|segment| segment.0 == PT_LOAD).map(|segment| segment.2).min()?;
+    let bias = base as usize - (first & !(page_size() - 1));
+    Some((segments, bias))
+}
+
+/// Returns the `PT_LOAD` segments of the ELF module at `base` as `(executable, range)` pairs.
+unsafe fn load_segments(base: *mut c_void) -> Vec<(bool, Range<usize>)> {
+    const PF_X: u32 = 1;
+
+    let Some((segments, bias)) = program_headers(base) else {
+        return Vec::new();
+    };
+    segments
+        .iter()
+        .filter(|segment| segment.0 == PT_LOAD)
+        .map(|&(_, flags, vaddr, memsz)| (flags & PF_X != 0, bias + vaddr..bias + vaddr + memsz))
+        .collect()
+}
+
+/// Returns the names and addresses of the symbols the ELF module at `base` defines in its dynamic symbol table.
+pub(crate) unsafe fn exports(base: *mut c_void) -> Vec<(String, usize)> {
+    const DT_HASH: usize = 4;
+    const DT_STRTAB: usize = 5;
+    const DT_SYMTAB: usize = 6;
+
+    let Some((segments, bias)) = program_headers(base) else {
+        return Vec::new();
+    };
+    let Some(dynamic) = segments.iter().find(|segment| segment.0 == PT_DYNAMIC) else {
+        return Vec::new();
+    };
+    // The loader relocates the entries of most modules in place, but not of all.
+    let relocated = |address: usize| if address < bias { bias + address } else { address };
+
+    let word = std::mem::size_of::<usize>();
+    let (mut hash, mut strtab, mut symtab) = (0, 0, 0);
+    let mut entry = (bias + dynamic.2) as *const u8;
+    loop {
+        match (read::<usize>(entry, 0), read::<usize>(entry, word)) {
+            (0, _) => break,
+            (DT_HASH, value) => hash = relocated(value),
+            (DT_STRTAB, value) => strtab = relocated(value),
+            (DT_SYMTAB, value) => symtab = relocated(value),
+            _ => {}
+        }
+        entry = entry.add(2 * word);
+    }
+    if strtab == 0 || symtab == 0 {
+        return Vec::new();
+    }
+
+    // Size of `Elf64_Sym` or `Elf32_Sym`, and the offsets of `st_value` and `st_shndx` in it.
+    let (size, value, section) = if cfg!(target_pointer_width = "64") { (24, 8, 6) } else { (16, 4, 14) };
+    // Without a `DT_HASH` the count isn't recorded; linkers put the string table right after the symbols.
+    let count = if hash != 0 {
+        read::<u32>(hash as *const u8, 4) as usize
+    } else {
+        strtab.saturating_sub(symtab) / size
+    };
+    (0..count)
+        .map(|i| (symtab + i * size) as *const u8)
+        .filter(|&symbol| read::<u16>(symbol, section) != 0 && read::<usize>(symbol, value) != 0)
+        .filter_map(|symbol| {
+            let name = std::ffi::CStr::from_ptr((strtab + read::<u32>(symbol, 0) as usize) as *const _);
+            Some((name.to_str().ok()?.to_owned(), bias + read::<usize>(symbol, value)))
+        })
+        .collect()
+}
+
+/// Returns the unused space at the end of the last page of each non-executable segment
+/// of the ELF module at `base`.
+pub(crate) unsafe fn module_caves(base: *mut c_void) -> Vec<Range<usize>> {
+    let page = page_size();
+    load_segments(base)
+        .into_iter()
+        .filter(|(executable, _)| !executable)
+        .filter_map(|(_, segment)| {
+            let end = segment.end.next_multiple_of(page);
+            (segment.end < end).then_some(segment.end..end)
+        })
+        .collect()
+}
+
+/// Returns the executable segments of the ELF module at `base`.
+pub(crate) unsafe fn module_code(base: *mut c_void) -> Vec<Range<usize>> {
+    load_segments(base)
+        .into_iter()
+        .filter_map(|(executable, segment)| executable.then_some(segment))
+        .collect()
+}
+
+/// Returns the segments of the ELF module at `base` that aren't executable.
+pub(crate) unsafe fn module_data(base: *mut c_void) -> Vec<Range<usize>> {
+    load_segments(base)
+        .into_iter()
+        .filter_map(|(executable, segment)| (!executable).then_some(segment))
+        .collect()
+}
+
+/// A mapping listed in `/proc/self/maps`.
+pub(crate) struct Mapping {
+    pub(crate) start: usize,
+    pub(crate) end: usize,
+    pub(crate) readable: bool,
+    pub(crate) writable: bool,
+    pub(crate) executable: bool,
+    pub(crate) path: String,
+}
+
+/// Returns the mappings of the current process.
+pub(crate) fn mappings() -> Vec<Mapping> {
+    let Ok(maps) = std::fs::read_to_string("/proc/self/maps") else {
+        return Vec::new();
+    };
+    maps.lines()
+        .filter_map(|line| {
+            let mut fields = line.split_whitespace();
+            let (start, end) = fields.next()?.split_once('-')?;
+            let perms = fields.next()?.as_bytes();
+            let path = fields.nth(3).unwrap_or_default().to_owned();
+            Some(Mapping {
+                start: usize::from_str_radix(start, 16).ok()?,
+                end: usize::from_str_radix(end, 16).ok()?,
+                readable: perms.first() == Some(&b'r'),
+                writable: perms.get(1) == Some(&b'w'),
+                executable: perms.get(2) == Some(&b'x'),
+                path,
+            })
+        })
+        .collect()
+}
+
+/// Returns the mappings of the current process for the [`memory_map`] cache.
+pub(crate) unsafe fn regions() -> Vec<Region> {
+    mappings()
+        .into_iter()
+        .map(|mapping| Region {
+            start: mapping.start,
+            end: mapping.end,
+            readable: mapping.readable,
+            writable: mapping.writable,
+            executable: mapping.executable,
+            module: None,
+        })
+        .collect()
+}
+
+/// Calls `f` with the address and size of every writable anonymous mapping that may hold heap
+/// allocations. Block boundaries are unknown, so every pointer-aligned address is a candidate.
+/// The mapping of the current stack is skipped.
+pub(crate) unsafe fn heap_regions(mut f: impl FnMut(usize, usize)) {
+    let stack = &f as *const _ as usize;
+    for mapping in mappings() {
+        let heap = mapping.path.is_empty() || mapping.path == "[heap]";
+        let stack = (mapping.start..mapping.end).contains(&stack);
+        if mapping.readable && mapping.writable && heap && !stack {
+            f(mapping.start, mapping.end - mapping.start);
+        }
+    }
+}
+
+/// Returns the size of a memory page.
+pub(crate) fn page_size() -> usize {
+    unsafe { libc::sysconf(libc::_SC_PAGESIZE) as usize }
+}
+
+/// Returns the protection of the mapping containing `address`.
+pub(crate) unsafe fn query_protection(address: usize) -> Option<RawProtection> {
+    let mapping = mappings().into_iter().find(|m| (m.start..m.end).contains(&address))?;
+    let mut raw = libc::PROT_NONE;
+    if mapping.readable {
+        raw |= libc::PROT_READ;
+    }
+    if mapping.writable {
+        raw |= libc::PROT_WRITE;
+    }
+    if mapping.executable {
+        raw |= libc::PROT_EXEC;
+    }
+    Some(raw)
+}
+
+/// Returns `true` if the mapping containing `address` is executable.
+pub(crate) unsafe fn is_executable(address: usize) -> bool {
+    if let Some(region) = memory_map::region_of(address) {
+        return region.executable;
+    }
+    query_protection(address).is_some_and(|raw| raw & libc::PROT_EXEC != 0)
+}
+
+/// Returns `true` if the mapping containing `address` is writable.
+pub(crate) unsafe fn is_writable(address: usize) -> bool {
+    if let Some(region) = memory_map::region_of(address) {
+        return region.writable;
+    }
+    query_protection(address).is_some_and(|raw| raw & libc::PROT_WRITE != 0)
+}
+
+fn raw_protection(protection: Protection) -> RawProtection {
+    match protection {
+        Protection::NoAccess => libc::PROT_NONE,
+        Protection::ReadOnly => libc::PROT_READ,
+        Protection::ReadWrite => libc::PROT_READ | libc::PROT_WRITE,
+        Protection::ReadExecute => libc::PROT_READ | libc::PROT_EXEC,
+        Protection::ReadWriteExecute => libc::PROT_READ | libc::PROT_WRITE | libc::PROT_EXEC,
+    }
+}
+
+/// Changes the protection of the pages covering `address..address + size`,
+/// returning the previous protection of the first page.
+pub(crate) unsafe fn protect(address: usize, size: usize, protection: Protection) -> io::Result<RawProtection> {
+    let mut raw = raw_protection(protection);
+    let previous = query_protection(address).ok_or_else(|| io::Error::from_raw_os_error(libc::ENOMEM))?;
+    // Keeps code pages executable when only write access is requested.
+    if protection == Protection::ReadWrite {
+        raw |= previous & libc::PROT_EXEC;
+    }
+    protect_raw(address, size, raw)?;
+    Ok(previous)
+}
+
+/// Restores the protection returned by [`protect`].
+pub(crate) unsafe fn protect_raw(address: usize, size: usize, raw: RawProtection) -> io::Result<RawProtection> {
+    let page = page_size();
+    let start = address & !(page - 1);
+    let end = (address + size).next_multiple_of(page);
+    memory_map::invalidate_range(start, end - start);
+    if libc::mprotect(start as *mut c_void, end - start, raw) != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(raw)
+}
+
+/// Allocates `size` bytes of fresh pages with the given protection.
+pub(crate) unsafe fn alloc_pages(size: usize, protection: Protection) -> io::Result<usize> {
+    let flags = libc::MAP_PRIVATE | libc::MAP_ANONYMOUS;
+    let address = libc::mmap(std::ptr::null_mut(), size, raw_protection(protection), flags, -1, 0);
+    if address == libc::MAP_FAILED {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(address as usize)
+}
+
+/// Frees pages returned by [`alloc_pages`].
+pub(crate) unsafe fn free_pages(address: usize, size: usize) {
+    memory_map::invalidate_range(address, size);
+    libc::munmap(address as *mut c_void, size);
+}
+
+/// Returns the id of the calling thread, as shown by debuggers.
+pub(crate) fn current_thread_id() -> u64 {
+    #[cfg(any(target_os = "linux", target_os = "android"))]
+    unsafe {
+        libc::gettid() as u64
+    }
+    #[cfg(target_vendor = "apple")]
+    unsafe {
+        let mut id = 0;
+        libc::pthread_threadid_np(0, &mut id);
+        id
+    }
+    #[cfg(not(any(target_os = "linux", target_os = "android", target_vendor = "apple")))]
+    unsafe {
+        libc::pthread_self() as u64
+    }
+}
+
+/// Returns `true` if every page of `address..address + size` is mapped readable.
+pub(crate) unsafe fn is_readable(address: usize, size: usize) -> bool {
+    if let Some(readable) = memory_map::is_readable(address, size) {
+        return readable;
+    }
+    let end = address.saturating_add(size.max(1));
+    let mappings = mappings();
+    let mut at = address;
+    while at < end {
+        match mappings.iter().find(|m| (m.start..m.end).contains(&at)) {
+            Some(mapping) if mapping.readable => at = mapping.end,
+            _ => return false,
+        }
+    }
+    true
+}
+
+/// Reads the word at `address` through the kernel, which reports a fault as an error instead of raising it.
+#[cfg(any(target_os = "linux", target_os = "android"))]
+pub(crate) unsafe fn probe(address: *const usize) -> Option<usize> {
+    let mut value = 0usize;
+    let size = std::mem::size_of::<usize>();
+    let local = libc::iovec { iov_base: (&mut value as *mut usize).cast(), iov_len: size };
+    let remote = libc::iovec { iov_base: address.cast_mut().cast(), iov_len: size };
+    (libc::process_vm_readv(libc::getpid(), &local, 1, &remote, 1, 0) == size as isize).then_some(value)
+}
+
+/// Reads the word at `address` if it is mapped readable.
+#[cfg(not(any(target_os = "linux", target_os = "android")))]
+pub(crate) unsafe fn probe(address: *const usize) -> Option<usize> {
+    is_readable(address as usize, std::mem::size_of::<usize>()).then(|| address.read_unaligned())
+}
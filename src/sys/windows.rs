@@ -0,0 +1,423 @@
+use std::ffi::{c_char, c_void, CStr, CString, OsString};
+use std::io;
+use std::ops::Range;
+use std::os::windows::ffi::OsStringExt;
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+use windows_sys::Win32::Foundation::{CloseHandle, INVALID_HANDLE_VALUE};
+use windows_sys::Win32::System::Diagnostics::Debug::ReadProcessMemory;
+use windows_sys::Win32::System::Diagnostics::ToolHelp::{
+    CreateToolhelp32Snapshot, Module32FirstW, Module32NextW, Thread32First, Thread32Next, MODULEENTRY32W,
+    TH32CS_SNAPMODULE, TH32CS_SNAPTHREAD, THREADENTRY32,
+};
+use windows_sys::Win32::System::LibraryLoader::{
+    GetModuleFileNameW, GetModuleHandleA, GetModuleHandleExW, GetProcAddress, GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS,
+    GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT,
+};
+use windows_sys::Win32::System::Memory::{
+    GetProcessHeaps, HeapLock, HeapUnlock, HeapWalk, VirtualAlloc, VirtualFree, VirtualProtect, VirtualQuery,
+    MEMORY_BASIC_INFORMATION, MEM_COMMIT, MEM_IMAGE, MEM_RELEASE, MEM_RESERVE, PAGE_EXECUTE, PAGE_EXECUTE_READ,
+    PAGE_EXECUTE_READWRITE, PAGE_EXECUTE_WRITECOPY, PAGE_GUARD, PAGE_NOACCESS, PAGE_PROTECTION_FLAGS, PAGE_READONLY,
+    PAGE_READWRITE, PAGE_WRITECOPY, PROCESS_HEAP_ENTRY,
+};
+use windows_sys::Win32::System::SystemInformation::{GetSystemInfo, SYSTEM_INFO};
+use windows_sys::Win32::System::SystemServices::PROCESS_HEAP_ENTRY_BUSY;
+use windows_sys::Win32::System::Threading::{GetCurrentProcess, GetCurrentProcessId, GetCurrentThreadId};
+
+use super::Protection;
+use crate::detect;
+use crate::memory_map::{self, Region};
+use crate::pe;
+
+/// Platform page protection flags.
+pub(crate) type RawProtection = PAGE_PROTECTION_FLAGS;
+
+/// Returns the handle of an already loaded module.
+pub(crate) unsafe fn module_handle(name: &str) -> Option<*mut c_void> {
+    let name = CString::new(name).ok()?;
+    let module = GetModuleHandleA(name.as_ptr().cast());
+    (!module.is_null()).then_some(module)
+}
+
+/// Returns the base address of an already loaded module, which is its handle.
+pub(crate) unsafe fn module_base(name: &str) -> Option<*mut c_void> {
+    module_handle(name)
+}
+
+/// Returns the address of an exported symbol of the module.
+pub(crate) unsafe fn symbol(module: *mut c_void, name: &str) -> Option<*const c_void> {
+    let name = CString::new(name).ok()?;
+    GetProcAddress(module, name.as_ptr().cast()).map(|func| func as *const c_void)
+}
+
+/// Returns the names and addresses of the exports of the module at `base`.
+pub(crate) unsafe fn exports(base: *mut c_void) -> Vec<(String, usize)> {
+    let Some(image) = pe::Image::new(base.cast()) else {
+        return Vec::new();
+    };
+    image
+        .export_names()
+        .into_iter()
+        .filter_map(|name| {
+            let name = name.to_str().ok()?;
+            Some((name.to_owned(), symbol(base, name)? as usize))
+        })
+        .collect()
+}
+
+/// Returns the address of a symbol exported by any loaded module.
+pub(crate) unsafe fn global_symbol(name: &str) -> Option<*const c_void> {
+    let mut found = None;
+    modules(|module| {
+        if found.is_none() {
+            found = symbol(module, name);
+        }
+    });
+    found
+}
+
+/// Calls `f` with the handle of every module loaded in the current process.
+pub(crate) unsafe fn modules(mut f: impl FnMut(*mut c_void)) {
+    let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPMODULE, 0);
+    if snapshot == INVALID_HANDLE_VALUE {
+        return;
+    }
+
+    let mut entry = std::mem::zeroed::<MODULEENTRY32W>();
+    entry.dwSize = std::mem::size_of::<MODULEENTRY32W>() as u32;
+    let mut found = Module32FirstW(snapshot, &mut entry) != 0;
+    while found {
+        f(entry.hModule);
+        found = Module32NextW(snapshot, &mut entry) != 0;
+    }
+
+    CloseHandle(snapshot);
+}
+
+/// Calls `f` with the address and size of every allocated block of every process heap.
+///
+/// `f` runs while the heap is locked and walked, so it must not allocate or free memory.
+pub(crate) unsafe fn heap_blocks(mut f: impl FnMut(usize, usize)) {
+    let count = GetProcessHeaps(0, std::ptr::null_mut());
+    let mut heaps = vec![std::ptr::null_mut(); count as usize];
+    let count = GetProcessHeaps(heaps.len() as u32, heaps.as_mut_ptr()).min(count);
+
+    for &heap in &heaps[..count as usize] {
+        if HeapLock(heap) == 0 {
+            continue;
+        }
+        let mut entry = std::mem::zeroed::<PROCESS_HEAP_ENTRY>();
+        while HeapWalk(heap, &mut entry) != 0 {
+            if entry.wFlags & PROCESS_HEAP_ENTRY_BUSY as u16 != 0 {
+                f(entry.lpData as usize, entry.cbData as usize);
+            }
+        }
+        HeapUnlock(heap);
+    }
+}
+
+/// Changes the protection of the pages covering `address..address + size`,
+/// returning the previous protection of the first page.
+pub(crate) unsafe fn protect(address: usize, size: usize, protection: Protection) -> io::Result<RawProtection> {
+    let raw = raw_protection(protection);
+    protect_raw(address, size, if is_executable(address) { executable(raw) } else { raw })
+}
+
+fn raw_protection(protection: Protection) -> RawProtection {
+    match protection {
+        Protection::NoAccess => PAGE_NOACCESS,
+        Protection::ReadOnly => PAGE_READONLY,
+        Protection::ReadWrite => PAGE_READWRITE,
+        Protection::ReadExecute => PAGE_EXECUTE_READ,
+        Protection::ReadWriteExecute => PAGE_EXECUTE_READWRITE,
+    }
+}
+
+/// Returns the unused space inside the image of the module at `base` that isn't executable.
+pub(crate) unsafe fn module_caves(base: *mut c_void) -> Vec<Range<usize>> {
+    pe::Image::new(base.cast()).map_or_else(Vec::new, |image| image.section_slack())
+}
+
+/// Returns the executable sections of the module at `base`.
+pub(crate) unsafe fn module_code(base: *mut c_void) -> Vec<Range<usize>> {
+    pe::Image::new(base.cast()).map_or_else(Vec::new, |image| image.code_sections())
+}
+
+/// Returns the sections of the module at `base` holding data.
+pub(crate) unsafe fn module_data(base: *mut c_void) -> Vec<Range<usize>> {
+    pe::Image::new(base.cast()).map_or_else(Vec::new, |image| image.data_sections())
+}
+
+/// Returns `true` if the page containing `address` is executable.
+pub(crate) unsafe fn is_executable(address: usize) -> bool {
+    if let Some(characteristics) = wine_section(address) {
+        return characteristics & pe::IMAGE_SCN_MEM_EXECUTE != 0;
+    }
+    if let Some(region) = memory_map::region_of(address) {
+        return region.executable;
+    }
+    query_protection(address).is_some_and(executable_protection)
+}
+
+fn executable_protection(raw: RawProtection) -> bool {
+    raw & (PAGE_EXECUTE | PAGE_EXECUTE_READ | PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY) != 0
+}
+
+/// Returns `true` if the page containing `address` is writable, copy-on-write included.
+pub(crate) unsafe fn is_writable(address: usize) -> bool {
+    if let Some(characteristics) = wine_section(address) {
+        return characteristics & pe::IMAGE_SCN_MEM_WRITE != 0;
+    }
+    if let Some(region) = memory_map::region_of(address) {
+        return region.writable;
+    }
+    query_protection(address).is_some_and(writable_protection)
+}
+
+fn writable_protection(raw: RawProtection) -> bool {
+    raw & (PAGE_READWRITE | PAGE_WRITECOPY | PAGE_EXECUTE_READWRITE | PAGE_EXECUTE_WRITECOPY) != 0
+}
+
+fn executable(raw: RawProtection) -> RawProtection {
+    match raw {
+        PAGE_READONLY => PAGE_EXECUTE_READ,
+        PAGE_READWRITE => PAGE_EXECUTE_READWRITE,
+        _ => raw,
+    }
+}
+
+/// Restores the protection returned by [`protect`].
+pub(crate) unsafe fn protect_raw(address: usize, size: usize, raw: RawProtection) -> io::Result<RawProtection> {
+    let mut previous = 0;
+    memory_map::invalidate_range(address, size);
+    if VirtualProtect(address as *const c_void, size, raw, &mut previous) == 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(previous)
+}
+
+/// Returns the size of a memory page.
+pub(crate) fn page_size() -> usize {
+    unsafe {
+        let mut info = std::mem::zeroed::<SYSTEM_INFO>();
+        GetSystemInfo(&mut info);
+        info.dwPageSize as usize
+    }
+}
+
+/// Returns the protection of the page containing `address`.
+pub(crate) unsafe fn query_protection(address: usize) -> Option<RawProtection> {
+    let mut info = std::mem::zeroed::<MEMORY_BASIC_INFORMATION>();
+    (VirtualQuery(address as *const c_void, &mut info, std::mem::size_of_val(&info)) != 0).then_some(info.Protect)
+}
+
+/// Returns the version reported by `wine_get_version` if the process runs under Wine or Proton.
+pub(crate) fn wine_version() -> Option<&'static str> {
+    static VERSION: OnceLock<Option<String>> = OnceLock::new();
+    VERSION
+        .get_or_init(|| unsafe {
+            let export = symbol(module_handle("ntdll.dll")?, "wine_get_version")?;
+            let version = std::mem::transmute::<*const c_void, extern "C" fn() -> *const c_char>(export)();
+            (!version.is_null()).then(|| CStr::from_ptr(version).to_string_lossy().into_owned())
+        })
+        .as_deref()
+}
+
+/// Returns the characteristics of the section containing `address` when working around Wine and the address
+/// lies in a module.
+///
+/// Wine maps images whose sections aren't page-aligned in one piece, read-write-execute, so their read-only
+/// data looks writable and executable to `VirtualQuery`. The section headers tell what the image asked for.
+unsafe fn wine_section(address: usize) -> Option<u32> {
+    if !detect::wine_compat() {
+        return None;
+    }
+    pe::Image::new(module_of(address)?.cast())?.section_characteristics(address)
+}
+
+/// Returns the handle of the module whose image contains `address`.
+///
+/// Wine doesn't report every module mapping as `MEM_IMAGE`, builtin modules in particular, so the
+/// loader is asked instead when running under it.
+pub(crate) unsafe fn module_of(address: usize) -> Option<*mut c_void> {
+    if detect::wine_compat() {
+        let flags = GET_MODULE_HANDLE_EX_FLAG_FROM_ADDRESS | GET_MODULE_HANDLE_EX_FLAG_UNCHANGED_REFCOUNT;
+        let mut module = std::ptr::null_mut();
+        let found = GetModuleHandleExW(flags, address as *const u16, &mut module) != 0;
+        return (found && !module.is_null()).then_some(module);
+    }
+    if let Some(region) = memory_map::region_of(address) {
+        return region.module.map(|base| base as *mut c_void);
+    }
+    let mut info = std::mem::zeroed::<MEMORY_BASIC_INFORMATION>();
+    if VirtualQuery(address as *const c_void, &mut info, std::mem::size_of_val(&info)) == 0 || info.Type != MEM_IMAGE {
+        return None;
+    }
+    Some(info.AllocationBase)
+}
+
+/// Returns the path of the module at `base`.
+pub(crate) unsafe fn module_path(base: *mut c_void) -> Option<PathBuf> {
+    let mut buffer = vec![0u16; 32768];
+    let len = GetModuleFileNameW(base, buffer.as_mut_ptr(), buffer.len() as u32) as usize;
+    (len != 0).then(|| OsString::from_wide(&buffer[..len]).into())
+}
+
+/// Process-wide dbghelp session; dbghelp isn't thread-safe, so every call goes through the lock.
+#[cfg(feature = "symbols")]
+static DBGHELP: std::sync::Mutex<bool> = std::sync::Mutex::new(false);
+
+#[cfg(feature = "symbols")]
+unsafe fn with_dbghelp<R>(f: impl FnOnce() -> R) -> R {
+    use windows_sys::Win32::System::Diagnostics::Debug::{
+        SymInitializeW, SymSetOptions, SYMOPT_DEFERRED_LOADS, SYMOPT_UNDNAME,
+    };
+
+    let mut initialized = DBGHELP.lock().unwrap_or_else(|e| e.into_inner());
+    if !*initialized {
+        SymSetOptions(SYMOPT_UNDNAME | SYMOPT_DEFERRED_LOADS);
+        *initialized = SymInitializeW(GetCurrentProcess(), std::ptr::null(), 1) != 0;
+    }
+    f()
+}
+
+/// Returns the name of the symbol containing `address` and the offset of the address into it, from the
+/// exports or the PDB of its module as found by dbghelp.
+#[cfg(feature = "symbols")]
+pub(crate) unsafe fn symbolize(address: usize) -> Option<(String, usize)> {
+    use windows_sys::Win32::System::Diagnostics::Debug::{SymFromAddrW, SymRefreshModuleList, SYMBOL_INFOW};
+
+    const MAX_NAME: usize = 1024;
+
+    with_dbghelp(|| {
+        // The name continues past the end of the structure.
+        let mut buffer = vec![0u64; (std::mem::size_of::<SYMBOL_INFOW>() + MAX_NAME * 2).div_ceil(8)];
+        let info = buffer.as_mut_ptr().cast::<SYMBOL_INFOW>();
+        (*info).SizeOfStruct = std::mem::size_of::<SYMBOL_INFOW>() as u32;
+        (*info).MaxNameLen = MAX_NAME as u32;
+        let mut displacement = 0u64;
+        let process = GetCurrentProcess();
+        let lookup = |displacement: &mut u64| SymFromAddrW(process, address as u64, displacement, info) != 0;
+        // Modules loaded since the session started are only known after a refresh.
+        if !lookup(&mut displacement) && (SymRefreshModuleList(process) == 0 || !lookup(&mut displacement)) {
+            return None;
+        }
+        let name = std::slice::from_raw_parts((*info).Name.as_ptr(), ((*info).NameLen as usize).min(MAX_NAME));
+        Some((String::from_utf16_lossy(name), displacement as usize))
+    })
+}
+
+/// Sets the paths dbghelp searches for PDBs.
+#[cfg(feature = "symbols")]
+pub(crate) unsafe fn set_symbol_path(path: &str) -> io::Result<()> {
+    use windows_sys::Win32::System::Diagnostics::Debug::SymSetSearchPathW;
+
+    let path: Vec<u16> = path.encode_utf16().chain([0]).collect();
+    with_dbghelp(|| {
+        if SymSetSearchPathW(GetCurrentProcess(), path.as_ptr()) == 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    })
+}
+
+/// Allocates `size` bytes of fresh pages with the given protection.
+pub(crate) unsafe fn alloc_pages(size: usize, protection: Protection) -> io::Result<usize> {
+    let address = VirtualAlloc(std::ptr::null(), size, MEM_COMMIT | MEM_RESERVE, raw_protection(protection));
+    if address.is_null() {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(address as usize)
+}
+
+/// Frees pages returned by [`alloc_pages`].
+pub(crate) unsafe fn free_pages(address: usize, size: usize) {
+    memory_map::invalidate_range(address, size);
+    VirtualFree(address as *mut c_void, 0, MEM_RELEASE);
+}
+
+/// Returns the id of the calling thread.
+pub(crate) fn current_thread_id() -> u64 {
+    unsafe { GetCurrentThreadId() as u64 }
+}
+
+/// Calls `f` with the id of every thread of the current process.
+pub(crate) unsafe fn threads(mut f: impl FnMut(u32)) {
+    let snapshot = CreateToolhelp32Snapshot(TH32CS_SNAPTHREAD, 0);
+    if snapshot == INVALID_HANDLE_VALUE {
+        return;
+    }
+
+    let process = GetCurrentProcessId();
+    let mut entry = std::mem::zeroed::<THREADENTRY32>();
+    entry.dwSize = std::mem::size_of::<THREADENTRY32>() as u32;
+    let mut found = Thread32First(snapshot, &mut entry) != 0;
+    while found {
+        if entry.th32OwnerProcessID == process {
+            f(entry.th32ThreadID);
+        }
+        found = Thread32Next(snapshot, &mut entry) != 0;
+    }
+
+    CloseHandle(snapshot);
+}
+
+/// Returns the committed regions of the address space for the [`memory_map`] cache.
+pub(crate) unsafe fn regions() -> Vec<Region> {
+    let mut regions = Vec::new();
+    let mut at = 0usize;
+    loop {
+        let mut info = std::mem::zeroed::<MEMORY_BASIC_INFORMATION>();
+        if VirtualQuery(at as *const c_void, &mut info, std::mem::size_of_val(&info)) == 0 {
+            break regions;
+        }
+        let start = info.BaseAddress as usize;
+        let end = start + info.RegionSize;
+        if info.State == MEM_COMMIT {
+            regions.push(Region {
+                start,
+                end,
+                readable: info.Protect & (PAGE_NOACCESS | PAGE_GUARD) == 0 && info.Protect != 0,
+                writable: writable_protection(info.Protect),
+                executable: executable_protection(info.Protect),
+                module: (info.Type == MEM_IMAGE).then_some(info.AllocationBase as usize),
+            });
+        }
+        if end <= at {
+            break regions;
+        }
+        at = end;
+    }
+}
+
+/// Returns `true` if every page of `address..address + size` is committed and readable.
+pub(crate) unsafe fn is_readable(address: usize, size: usize) -> bool {
+    if let Some(readable) = memory_map::is_readable(address, size) {
+        return readable;
+    }
+    let end = address.saturating_add(size.max(1));
+    let mut at = address;
+    while at < end {
+        let mut info = std::mem::zeroed::<MEMORY_BASIC_INFORMATION>();
+        if VirtualQuery(at as *const c_void, &mut info, std::mem::size_of_val(&info)) == 0
+            || info.State != MEM_COMMIT
+            || info.Protect & (PAGE_NOACCESS | PAGE_GUARD) != 0
+            || info.Protect == 0
+        {
+            return false;
+        }
+        at = info.BaseAddress as usize + info.RegionSize;
+    }
+    true
+}
+
+/// Reads the word at `address` through the kernel, which reports a fault as an error instead of raising it.
+pub(crate) unsafe fn probe(address: *const usize) -> Option<usize> {
+    let mut value = 0usize;
+    let mut read = 0;
+    let size = std::mem::size_of::<usize>();
+    let buffer = (&mut value as *mut usize).cast();
+    let ok = ReadProcessMemory(GetCurrentProcess(), address.cast(), buffer, size, &mut read) != 0;
+    (ok && read == size).then_some(value)
+}
@@ -0,0 +1,52 @@
+//! Reporting hook operations to `tracing`.
+
+use std::collections::BTreeMap;
+use std::fmt;
+use std::sync::Mutex;
+
+use crate::audit::Operation;
+use crate::rtti;
+
+/// Class names of the installed hooks by object, resolved from RTTI when they are installed.
+static CLASSES: Mutex<BTreeMap<usize, Option<String>>> = Mutex::new(BTreeMap::new());
+
+/// Formats addresses in hexadecimal.
+struct Hex(usize);
+
+impl fmt::Debug for Hex {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{:#x}", self.0)
+    }
+}
+
+/// Emits an event for `operation` inside a span identifying the hooked object and its class.
+pub(crate) fn trace(object: usize, operation: &Operation) {
+    let mut classes = CLASSES.lock().unwrap_or_else(|e| e.into_inner());
+    let class = match *operation {
+        Operation::Install { vtable, .. } => {
+            let class = unsafe { rtti::class_name(vtable as *const usize) };
+            classes.insert(object, class.clone());
+            class
+        }
+        Operation::Uninstall | Operation::Detach => classes.remove(&object).flatten(),
+        _ => classes.get(&object).cloned().flatten(),
+    };
+    drop(classes);
+
+    let span = tracing::info_span!("vmt_hook", object = ?Hex(object), class = class.as_deref());
+    let _entered = span.enter();
+    match operation {
+        &Operation::Install { vtable, count } => tracing::info!(vtable = ?Hex(vtable), count, "hook installed"),
+        &Operation::Replace { id, old, new } => {
+            tracing::debug!(id, old = ?Hex(old), new = ?Hex(new), "method replaced")
+        }
+        &Operation::Restore { id, old, new } => {
+            tracing::debug!(id, old = ?Hex(old), new = ?Hex(new), "method restored")
+        }
+        Operation::RestoreAll => tracing::debug!("all methods restored"),
+        Operation::Uninstall => tracing::info!("hook uninstalled"),
+        Operation::Detach => tracing::info!("hook detached from a destroyed object"),
+        Operation::Tamper { slots } => tracing::warn!(?slots, "tampering detected"),
+        Operation::Panic { id, message } => tracing::error!(id, panic = %message, "hook panicked"),
+    }
+}
@@ -0,0 +1,90 @@
+//! Thunks skipping a hook while the current thread bypasses hooks.
+
+use std::cell::Cell;
+use std::io;
+use std::marker::PhantomData;
+
+use super::{Thunk, ARGUMENTS};
+use crate::backend::HookBackend;
+use crate::VTableHook;
+
+thread_local! {
+    /// Number of live [`HookBypass`] guards of this thread.
+    static BYPASSES: Cell<usize> = const { Cell::new(0) };
+}
+
+/// Makes the current thread call the original methods behind every [`BypassThunk`] while alive.
+///
+/// Lets code of the tool call hooked interfaces without re-entering its own hooks. Guards nest.
+#[must_use = "hooks are only bypassed while the guard is alive"]
+pub struct HookBypass {
+    _thread: PhantomData<*const ()>,
+}
+
+impl HookBypass {
+    /// Starts bypassing hooks on the current thread.
+    pub fn for_current_thread() -> Self {
+        BYPASSES.set(BYPASSES.get() + 1);
+        Self { _thread: PhantomData }
+    }
+
+    /// Returns `true` if the current thread bypasses hooks.
+    pub fn is_active() -> bool {
+        BYPASSES.get() != 0
+    }
+}
+
+impl Drop for HookBypass {
+    fn drop(&mut self) {
+        BYPASSES.set(BYPASSES.get() - 1);
+    }
+}
+
+/// Returns the function a [`BypassThunk`] continues to.
+extern "C" fn select(hook: usize, original: usize) -> usize {
+    if HookBypass::is_active() {
+        original
+    } else {
+        hook
+    }
+}
+
+/// A thunk jumping to `hook`, or to `original` on threads holding a [`HookBypass`].
+pub struct BypassThunk {
+    thunk: Thunk,
+    hook: usize,
+    original: usize,
+}
+
+impl BypassThunk {
+    /// Creates a thunk choosing between `hook` and `original` on every call.
+    pub unsafe fn new(hook: usize, original: usize) -> io::Result<Self> {
+        let thunk = Thunk::new(|code| {
+            code.call_preserving(select as *const () as usize, |code| {
+                code.mov_imm(ARGUMENTS[0], hook).mov_imm(ARGUMENTS[1], original);
+            });
+            code.jmp_r11();
+        })?;
+        Ok(Self { thunk, hook, original })
+    }
+
+    /// Replaces the method at `id` of `hook` with `func`, bypassed in favor of what the slot currently reaches.
+    pub unsafe fn install<T, B: HookBackend>(hook: &VTableHook<T, B>, id: usize, func: usize) -> io::Result<Self> {
+        super::install(hook, id, |current| Self::new(func, current), Self::address)
+    }
+
+    /// Returns the address of the thunk.
+    pub fn address(&self) -> usize {
+        self.thunk.address()
+    }
+
+    /// Returns the function called unless bypassed.
+    pub fn hook(&self) -> usize {
+        self.hook
+    }
+
+    /// Returns the function called while bypassed.
+    pub fn original(&self) -> usize {
+        self.original
+    }
+}
@@ -0,0 +1,128 @@
+//! Thunks diverting calls to a hook only while a condition holds.
+
+use std::io;
+use std::sync::atomic::AtomicBool;
+use std::sync::Arc;
+
+use super::{Thunk, ARGUMENTS};
+use crate::backend::HookBackend;
+use crate::VTableHook;
+
+/// A predicate with the targets it chooses between, passed to [`select`].
+struct Predicate {
+    predicate: Box<dyn Fn() -> bool + Send + Sync>,
+    hook: usize,
+    original: usize,
+}
+
+/// Returns the function a predicate-gated thunk continues to.
+extern "C" fn select(predicate: &Predicate) -> usize {
+    if (predicate.predicate)() {
+        predicate.hook
+    } else {
+        predicate.original
+    }
+}
+
+/// A thunk jumping to `hook` while a flag is set or a predicate holds, and to `original` otherwise.
+///
+/// Features can then be toggled at runtime, e.g. from a menu, without touching the table. A flag is
+/// read by the thunk itself; a predicate is called on every call.
+pub struct ConditionalThunk {
+    thunk: Thunk,
+    hook: usize,
+    original: usize,
+    flag: Option<Arc<AtomicBool>>,
+    _predicate: Option<Box<Predicate>>,
+}
+
+impl ConditionalThunk {
+    /// Creates a thunk diverting calls to `hook` while `flag` is set.
+    pub unsafe fn with_flag(hook: usize, original: usize, flag: Arc<AtomicBool>) -> io::Result<Self> {
+        let address = flag.as_ptr() as usize;
+        let thunk = Thunk::new(|code| {
+            // cmp byte [r11], 0; je original
+            code.mov_r11_imm(address).bytes(&[0x41, 0x80, 0x3B, 0x00, 0x74, 13]);
+            code.mov_r11_imm(hook).jmp_r11();
+            code.mov_r11_imm(original).jmp_r11();
+        })?;
+        Ok(Self {
+            thunk,
+            hook,
+            original,
+            flag: Some(flag),
+            _predicate: None,
+        })
+    }
+
+    /// Creates a thunk diverting calls to `hook` while `predicate` returns `true`.
+    ///
+    /// The predicate runs on the calling thread before every call and must not panic.
+    pub unsafe fn with_predicate(
+        hook: usize,
+        original: usize,
+        predicate: impl Fn() -> bool + Send + Sync + 'static,
+    ) -> io::Result<Self> {
+        let predicate = Box::new(Predicate {
+            predicate: Box::new(predicate),
+            hook,
+            original,
+        });
+        let context = &*predicate as *const Predicate as usize;
+        let thunk = Thunk::new(|code| {
+            code.call_preserving(select as *const () as usize, |code| {
+                code.mov_imm(ARGUMENTS[0], context);
+            });
+            code.jmp_r11();
+        })?;
+        Ok(Self {
+            thunk,
+            hook,
+            original,
+            flag: None,
+            _predicate: Some(predicate),
+        })
+    }
+
+    /// Replaces the method at `id` of `hook` with `func` while `flag` is set, and what the slot currently
+    /// reaches otherwise.
+    pub unsafe fn install_with_flag<T, B: HookBackend>(
+        hook: &VTableHook<T, B>,
+        id: usize,
+        func: usize,
+        flag: Arc<AtomicBool>,
+    ) -> io::Result<Self> {
+        super::install(hook, id, |current| Self::with_flag(func, current, flag), Self::address)
+    }
+
+    /// Replaces the method at `id` of `hook` with `func` while `predicate` holds, and what the slot
+    /// currently reaches otherwise.
+    pub unsafe fn install_with_predicate<T, B: HookBackend>(
+        hook: &VTableHook<T, B>,
+        id: usize,
+        func: usize,
+        predicate: impl Fn() -> bool + Send + Sync + 'static,
+    ) -> io::Result<Self> {
+        super::install(hook, id, |current| Self::with_predicate(func, current, predicate), Self::address)
+    }
+
+    /// Returns the address of the thunk.
+    pub fn address(&self) -> usize {
+        self.thunk.address()
+    }
+
+    /// Returns the function called while the condition holds.
+    pub fn hook(&self) -> usize {
+        self.hook
+    }
+
+    /// Returns the function called otherwise.
+    pub fn original(&self) -> usize {
+        self.original
+    }
+
+    /// Returns the flag checked by the thunk, unless it checks a predicate.
+    pub fn flag(&self) -> Option<&Arc<AtomicBool>> {
+        self.flag.as_ref()
+    }
+}
@@ -0,0 +1,67 @@
+//! Thunks passing a context pointer to a shared hook function.
+
+use std::ffi::c_void;
+use std::io;
+
+use super::{Thunk, ARGUMENTS};
+use crate::backend::HookBackend;
+use crate::VTableHook;
+
+/// A thunk calling `hook` with the arguments of the call followed by an embedded context pointer.
+///
+/// One hook function can then serve many slots or objects, finding its state through the extra
+/// parameter instead of a global lookup table. The context is passed in the integer argument register
+/// following the method's own, so the method may take at most [`MAX_ARGUMENTS`](Self::MAX_ARGUMENTS)
+/// arguments passed in integer registers: all arguments on Windows, which assigns registers by position,
+/// and the integer and pointer arguments elsewhere.
+pub struct ContextThunk {
+    thunk: Thunk,
+    hook: usize,
+    context: *mut c_void,
+}
+
+unsafe impl Send for ContextThunk {}
+unsafe impl Sync for ContextThunk {}
+
+impl ContextThunk {
+    /// Maximum number of integer register arguments of a method before the context.
+    pub const MAX_ARGUMENTS: usize = ARGUMENTS.len() - 1;
+
+    /// Creates a thunk calling `hook` with `context` after the `arguments` integer register arguments.
+    pub unsafe fn new(hook: usize, context: *mut c_void, arguments: usize) -> io::Result<Self> {
+        let Some(&register) = ARGUMENTS.get(arguments) else {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "no argument register left for the context"));
+        };
+        let thunk = Thunk::new(|code| {
+            code.mov_imm(register, context as usize).mov_r11_imm(hook).jmp_r11();
+        })?;
+        Ok(Self { thunk, hook, context })
+    }
+
+    /// Replaces the method at `id` of `hook`, which takes `arguments` integer register arguments, with a
+    /// thunk calling `func` with `context` appended.
+    pub unsafe fn install<T, B: HookBackend>(
+        hook: &VTableHook<T, B>,
+        id: usize,
+        func: usize,
+        context: *mut c_void,
+        arguments: usize,
+    ) -> io::Result<Self> {
+        super::install(hook, id, |_| Self::new(func, context, arguments), Self::address)
+    }
+
+    /// Returns the address of the thunk.
+    pub fn address(&self) -> usize {
+        self.thunk.address()
+    }
+
+    /// Returns the function called by the thunk.
+    pub fn hook(&self) -> usize {
+        self.hook
+    }
+
+    /// Returns the context passed to the hook.
+    pub fn context(&self) -> *mut c_void {
+        self.context
+    }
+}
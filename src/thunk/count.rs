@@ -0,0 +1,101 @@
+//! Thunks counting the calls they forward.
+
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+
+use super::Thunk;
+use crate::backend::HookBackend;
+use crate::metrics::SlotCalls;
+use crate::VTableHook;
+
+/// Counters of every live [`CallCounter`], by object, for [`metrics::stats`](crate::metrics::stats).
+static COUNTERS: Mutex<Vec<(usize, Weak<[AtomicU64]>)>> = Mutex::new(Vec::new());
+
+/// Counts the calls to the methods of a hook through thunks incrementing a counter per slot.
+///
+/// The thunks only bump the counter before jumping to the method the slot reached before, so they are cheap
+/// enough to put on every method to find out which are called and how often. Counts of called slots are
+/// also reported by [`metrics::stats`](crate::metrics::stats).
+pub struct CallCounter {
+    object: usize,
+    counts: Arc<[AtomicU64]>,
+    thunks: Mutex<Vec<Thunk>>,
+}
+
+impl CallCounter {
+    /// Creates a counter for the methods of `hook`, without instrumenting any.
+    pub fn new<T, B: HookBackend>(hook: &VTableHook<T, B>) -> Self {
+        let counts: Arc<[AtomicU64]> = (0..hook.backend().count()).map(|_| AtomicU64::new(0)).collect();
+        let object = hook.vptr() as usize;
+        let mut counters = COUNTERS.lock().unwrap_or_else(|e| e.into_inner());
+        counters.retain(|(_, counts)| counts.strong_count() != 0);
+        counters.push((object, Arc::downgrade(&counts)));
+        Self {
+            object,
+            counts,
+            thunks: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Creates a counter and instruments every method of `hook`.
+    pub unsafe fn instrument_all<T, B: HookBackend>(hook: &VTableHook<T, B>) -> io::Result<Self> {
+        let counter = Self::new(hook);
+        for id in 0..counter.counts.len() {
+            counter.instrument(hook, id)?;
+        }
+        Ok(counter)
+    }
+
+    /// Starts counting the calls to the method at `id` of `hook`, in front of whatever it currently reaches.
+    pub unsafe fn instrument<T, B: HookBackend>(&self, hook: &VTableHook<T, B>, id: usize) -> io::Result<()> {
+        let counter = &self.counts[id] as *const AtomicU64 as usize;
+        let target = hook.get_replaced_method(id);
+        let thunk = Thunk::new(|code| {
+            // lock inc qword [r11]
+            code.mov_r11_imm(counter).bytes(&[0xF0, 0x49, 0xFF, 0x03]).mov_r11_imm(target).jmp_r11();
+        })?;
+        hook.replace_method(id, thunk.address());
+        self.thunks.lock().unwrap_or_else(|e| e.into_inner()).push(thunk);
+        Ok(())
+    }
+
+    /// Returns the address of the object's VTable pointer the counter was created for.
+    pub fn object(&self) -> usize {
+        self.object
+    }
+
+    /// Returns the number of calls to the method at `id` since it was instrumented or last reset.
+    pub fn count(&self, id: usize) -> u64 {
+        self.counts[id].load(Ordering::Relaxed)
+    }
+
+    /// Returns the call counts of every method, by index.
+    pub fn counts(&self) -> Vec<u64> {
+        self.counts.iter().map(|count| count.load(Ordering::Relaxed)).collect()
+    }
+
+    /// Resets every count to zero.
+    pub fn reset(&self) {
+        for count in self.counts.iter() {
+            count.store(0, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Returns the counts of every called slot of the live counters.
+pub(crate) fn slot_calls() -> Vec<SlotCalls> {
+    let counters = COUNTERS.lock().unwrap_or_else(|e| e.into_inner());
+    counters
+        .iter()
+        .filter_map(|(object, counts)| Some((*object, counts.upgrade()?)))
+        .flat_map(|(object, counts)| {
+            let calls: Vec<_> = counts.iter().map(|count| count.load(Ordering::Relaxed)).collect();
+            calls.into_iter().enumerate().filter(|&(_, calls)| calls != 0).map(move |(slot, calls)| SlotCalls {
+                object,
+                slot,
+                calls,
+            })
+        })
+        .collect()
+}
@@ -0,0 +1,80 @@
+//! Thunks diverting calls to a hook only for chosen callers.
+
+use std::io;
+use std::ops::Range;
+
+use super::Thunk;
+use crate::backend::HookBackend;
+use crate::{sys, VTableHook};
+
+/// A thunk jumping to `hook` when the return address of the call lies in one of the caller ranges,
+/// and to `original` otherwise.
+///
+/// Lets a hook see only the calls made by the game, not the ones coming from another overlay hooking
+/// the same method. The thunk only reads the return address, so it fits any signature.
+pub struct CallerFilter {
+    thunk: Thunk,
+    hook: usize,
+    original: usize,
+    callers: Vec<Range<usize>>,
+}
+
+impl CallerFilter {
+    /// Creates a thunk diverting the calls returning into `callers` to `hook`.
+    pub unsafe fn new(hook: usize, original: usize, callers: &[Range<usize>]) -> io::Result<Self> {
+        let thunk = Thunk::new(|code| {
+            for range in callers {
+                // mov r11, start; cmp [rsp], r11; jb next
+                code.mov_r11_imm(range.start).bytes(&[0x4C, 0x39, 0x1C, 0x24, 0x72, 29]);
+                // mov r11, end; cmp [rsp], r11; jae next
+                code.mov_r11_imm(range.end).bytes(&[0x4C, 0x39, 0x1C, 0x24, 0x73, 13]);
+                code.mov_r11_imm(hook).jmp_r11();
+            }
+            code.mov_r11_imm(original).jmp_r11();
+        })?;
+        Ok(Self {
+            thunk,
+            hook,
+            original,
+            callers: callers.to_vec(),
+        })
+    }
+
+    /// Creates a thunk diverting the calls made from the code of the loaded module `module` to `hook`.
+    pub unsafe fn for_module(hook: usize, original: usize, module: &str) -> io::Result<Self> {
+        let base = sys::module_base(module)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "module isn't loaded"))?;
+        Self::new(hook, original, &sys::module_code(base))
+    }
+
+    /// Replaces the method at `id` of `hook` with a filter diverting the calls returning into `callers`
+    /// to `func`, and the others to what the slot currently reaches.
+    pub unsafe fn install<T, B: HookBackend>(
+        hook: &VTableHook<T, B>,
+        id: usize,
+        func: usize,
+        callers: &[Range<usize>],
+    ) -> io::Result<Self> {
+        super::install(hook, id, |current| Self::new(func, current, callers), Self::address)
+    }
+
+    /// Returns the address of the thunk.
+    pub fn address(&self) -> usize {
+        self.thunk.address()
+    }
+
+    /// Returns the function receiving the filtered calls.
+    pub fn hook(&self) -> usize {
+        self.hook
+    }
+
+    /// Returns the function receiving every other call.
+    pub fn original(&self) -> usize {
+        self.original
+    }
+
+    /// Returns the address ranges of the callers diverted to the hook.
+    pub fn callers(&self) -> &[Range<usize>] {
+        &self.callers
+    }
+}
@@ -0,0 +1,50 @@
+//! Thunks calling whatever a table entry holds at the time of the call.
+
+use std::io;
+
+use super::Thunk;
+use crate::backend::HookBackend;
+use crate::slot::{FnPtr, Slot};
+use crate::VTableHook;
+
+/// A function jumping to the method stored in a table entry when it is called, rather than when it was
+/// created.
+///
+/// Calling the original through it instead of through a pointer saved at install keeps hooks that others
+/// patch into the real table afterwards in the chain.
+pub struct LiveOriginal {
+    thunk: Thunk,
+}
+
+impl LiveOriginal {
+    /// Creates a thunk jumping to the function stored at `entry`.
+    ///
+    /// The entry must stay readable as long as the thunk can be called.
+    pub unsafe fn new(entry: *const usize) -> io::Result<Self> {
+        let thunk = Thunk::new(|code| {
+            code.mov_r11_imm(entry as usize).deref_r11(0).jmp_r11();
+        })?;
+        Ok(Self { thunk })
+    }
+
+    /// Creates a thunk calling the method at `id` of the original VTable of `hook`; see
+    /// [`get_live_original_method`](VTableHook::get_live_original_method).
+    pub unsafe fn of<T, B: HookBackend>(hook: &VTableHook<T, B>, id: usize) -> io::Result<Self> {
+        Self::new((hook.original_vtable() as *const usize).add(id))
+    }
+
+    /// Creates a thunk calling the method of `slot` in the original VTable of `hook`.
+    pub unsafe fn of_slot<T, B: HookBackend, F: FnPtr>(hook: &VTableHook<T, B>, slot: Slot<F>) -> io::Result<Self> {
+        Self::of(hook, slot.index())
+    }
+
+    /// Returns the address of the thunk.
+    pub fn address(&self) -> usize {
+        self.thunk.address()
+    }
+
+    /// Returns the thunk as a function pointer.
+    pub unsafe fn get<F: FnPtr>(&self) -> F {
+        F::from_address(self.address())
+    }
+}
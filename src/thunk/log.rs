@@ -0,0 +1,398 @@
+//! Thunks pretty-printing the arguments and results of the calls they forward.
+
+use std::io;
+use std::sync::{Arc, Mutex};
+
+use super::{CallArgs, CallReturn, Middleware, RecordedCall, ARGUMENTS, VECTORS};
+use crate::backend::HookBackend;
+use crate::slot::Slot;
+use crate::{sys, VTableHook};
+
+/// Longest string argument shown, in characters.
+const MAX_STRING: usize = 256;
+
+/// How an argument or a return value is decoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum ArgKind {
+    /// No value, for methods returning nothing.
+    Void,
+    /// A `bool`, from the low byte.
+    Bool,
+    /// A signed 32-bit integer.
+    I32,
+    /// An unsigned 32-bit integer.
+    U32,
+    /// A signed 64-bit integer.
+    I64,
+    /// An unsigned 64-bit integer.
+    U64,
+    /// A pointer-sized signed integer.
+    Isize,
+    /// A pointer-sized unsigned integer.
+    Usize,
+    /// A single-precision float.
+    F32,
+    /// A double-precision float.
+    F64,
+    /// A pointer, shown in hex.
+    Pointer,
+    /// An `HRESULT`, shown by name for the common ones.
+    HResult,
+    /// A pointer to a NUL-terminated string.
+    CStr,
+    /// A pointer to a NUL-terminated UTF-16 string.
+    WideStr,
+}
+
+impl ArgKind {
+    fn is_float(self) -> bool {
+        matches!(self, Self::F32 | Self::F64)
+    }
+
+    /// Formats the value of this kind in the low bits of `bits`.
+    fn format(self, bits: u64) -> String {
+        match self {
+            Self::Void => "()".to_owned(),
+            Self::Bool => (bits as u8 != 0).to_string(),
+            Self::I32 => (bits as u32 as i32).to_string(),
+            Self::U32 => (bits as u32).to_string(),
+            Self::I64 => (bits as i64).to_string(),
+            Self::U64 => bits.to_string(),
+            Self::Isize => (bits as usize as isize).to_string(),
+            Self::Usize => (bits as usize).to_string(),
+            Self::F32 => f32::from_bits(bits as u32).to_string(),
+            Self::F64 => f64::from_bits(bits).to_string(),
+            Self::Pointer => format_pointer(bits as usize),
+            Self::HResult => format_hresult(bits as u32),
+            Self::CStr => format_string::<u8>(bits as usize),
+            Self::WideStr => format_string::<u16>(bits as usize),
+        }
+    }
+}
+
+fn format_pointer(address: usize) -> String {
+    if address == 0 {
+        "null".to_owned()
+    } else {
+        format!("{address:#x}")
+    }
+}
+
+fn format_hresult(hresult: u32) -> String {
+    let name = match hresult {
+        0x0000_0000 => "S_OK",
+        0x0000_0001 => "S_FALSE",
+        0x8000_4001 => "E_NOTIMPL",
+        0x8000_4002 => "E_NOINTERFACE",
+        0x8000_4003 => "E_POINTER",
+        0x8000_4005 => "E_FAIL",
+        0x8007_000E => "E_OUTOFMEMORY",
+        0x8007_0057 => "E_INVALIDARG",
+        0x887A_0001 => "DXGI_ERROR_INVALID_CALL",
+        0x887A_0005 => "DXGI_ERROR_DEVICE_REMOVED",
+        0x887A_0006 => "DXGI_ERROR_DEVICE_HUNG",
+        0x887A_0007 => "DXGI_ERROR_DEVICE_RESET",
+        0x087A_0001 => "DXGI_STATUS_OCCLUDED",
+        _ => return format!("{hresult:#010x}"),
+    };
+    name.to_owned()
+}
+
+/// Formats the string at `address`, stopping at unreadable memory.
+fn format_string<C: Copy + Into<u32>>(address: usize) -> String {
+    if address == 0 {
+        return "null".to_owned();
+    }
+    let size = std::mem::size_of::<C>();
+    let page = sys::page_size();
+    let mut units = Vec::new();
+    let mut at = address;
+    while units.len() < MAX_STRING {
+        if (at == address || at % page < size) && !unsafe { sys::is_readable(at, size) } {
+            break;
+        }
+        let unit: u32 = unsafe { std::ptr::read_unaligned(at as *const C) }.into();
+        if unit == 0 {
+            break;
+        }
+        units.push(unit);
+        at += size;
+    }
+    let text: String = if size == 1 {
+        String::from_utf8_lossy(&units.iter().map(|&unit| unit as u8).collect::<Vec<_>>()).into_owned()
+    } else {
+        char::decode_utf16(units.iter().map(|&unit| unit as u16)).map(|c| c.unwrap_or('\u{FFFD}')).collect()
+    };
+    format!("{text:?}")
+}
+
+/// Rust types of arguments and return values with a matching [`ArgKind`].
+pub trait ArgType {
+    /// The kind the type is decoded as.
+    const KIND: ArgKind;
+}
+
+macro_rules! impl_arg_type {
+    ($($ty:ty => $kind:ident),*) => {
+        $(impl ArgType for $ty {
+            const KIND: ArgKind = ArgKind::$kind;
+        })*
+    };
+}
+
+impl_arg_type!(
+    () => Void, bool => Bool, i8 => I32, i16 => I32, i32 => I32, u8 => U32, u16 => U32, u32 => U32,
+    i64 => I64, u64 => U64, isize => Isize, usize => Usize, f32 => F32, f64 => F64
+);
+
+impl<T: ?Sized> ArgType for *const T {
+    const KIND: ArgKind = ArgKind::Pointer;
+}
+
+impl<T: ?Sized> ArgType for *mut T {
+    const KIND: ArgKind = ArgKind::Pointer;
+}
+
+/// Function pointer types whose arguments and return type have an [`ArgType`].
+pub trait TypedFn {
+    /// Returns the kinds of the arguments, in parameter order.
+    fn args() -> Vec<ArgKind>;
+
+    /// Returns the kind of the return value.
+    fn returns() -> ArgKind;
+}
+
+macro_rules! impl_typed_fn {
+    ($abi:literal: $($arg:ident),*) => {
+        impl<R: ArgType, $($arg: ArgType),*> TypedFn for extern $abi fn($($arg),*) -> R {
+            fn args() -> Vec<ArgKind> {
+                vec![$($arg::KIND),*]
+            }
+
+            fn returns() -> ArgKind {
+                R::KIND
+            }
+        }
+
+        impl<R: ArgType, $($arg: ArgType),*> TypedFn for unsafe extern $abi fn($($arg),*) -> R {
+            fn args() -> Vec<ArgKind> {
+                vec![$($arg::KIND),*]
+            }
+
+            fn returns() -> ArgKind {
+                R::KIND
+            }
+        }
+    };
+}
+
+macro_rules! impl_typed_fn_all {
+    ($abi:literal) => {
+        impl_typed_fn!($abi:);
+        impl_typed_fn!($abi: A0);
+        impl_typed_fn!($abi: A0, A1);
+        impl_typed_fn!($abi: A0, A1, A2);
+        impl_typed_fn!($abi: A0, A1, A2, A3);
+        impl_typed_fn!($abi: A0, A1, A2, A3, A4);
+        impl_typed_fn!($abi: A0, A1, A2, A3, A4, A5);
+        impl_typed_fn!($abi: A0, A1, A2, A3, A4, A5, A6);
+        impl_typed_fn!($abi: A0, A1, A2, A3, A4, A5, A6, A7);
+        impl_typed_fn!($abi: A0, A1, A2, A3, A4, A5, A6, A7, A8);
+        impl_typed_fn!($abi: A0, A1, A2, A3, A4, A5, A6, A7, A8, A9);
+        impl_typed_fn!($abi: A0, A1, A2, A3, A4, A5, A6, A7, A8, A9, A10);
+        impl_typed_fn!($abi: A0, A1, A2, A3, A4, A5, A6, A7, A8, A9, A10, A11);
+    };
+}
+
+impl_typed_fn_all!("C");
+impl_typed_fn_all!("system");
+
+/// Where an argument is passed.
+enum Location {
+    Register(usize),
+    Vector(usize),
+    Stack(usize),
+}
+
+/// The name, argument kinds and return kind of a method, describing how to print its calls.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    name: String,
+    args: Vec<ArgKind>,
+    returns: ArgKind,
+}
+
+impl Signature {
+    /// Creates a signature without arguments returning nothing; `this` is an argument like any other.
+    pub fn new(name: &str) -> Self {
+        Self {
+            name: name.to_owned(),
+            args: Vec::new(),
+            returns: ArgKind::Void,
+        }
+    }
+
+    /// Creates the signature of the function type of `slot`.
+    ///
+    /// `HRESULT`s are plain `i32`s to the type system; use [`returns`](Self::returns) to print them by name.
+    pub fn of<F: TypedFn>(name: &str, _slot: Slot<F>) -> Self {
+        Self {
+            name: name.to_owned(),
+            args: F::args(),
+            returns: F::returns(),
+        }
+    }
+
+    /// Appends an argument.
+    pub fn arg(mut self, kind: ArgKind) -> Self {
+        self.args.push(kind);
+        self
+    }
+
+    /// Sets the kind of the return value.
+    pub fn returns(mut self, kind: ArgKind) -> Self {
+        self.returns = kind;
+        self
+    }
+
+    /// Returns the name of the method.
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn locations(&self) -> Vec<Location> {
+        let (mut registers, mut vectors, mut stack) = (0, 0, 0);
+        self.args
+            .iter()
+            .enumerate()
+            .map(|(position, kind)| {
+                // Windows assigns registers by position, other platforms count each class separately.
+                let (index, limit) = match (cfg!(windows), kind.is_float()) {
+                    (true, _) => (position, ARGUMENTS.len()),
+                    (false, false) => (registers, ARGUMENTS.len()),
+                    (false, true) => (vectors, VECTORS as usize),
+                };
+                match (index < limit, kind.is_float()) {
+                    (true, false) => {
+                        registers += 1;
+                        Location::Register(index)
+                    }
+                    (true, true) => {
+                        vectors += 1;
+                        Location::Vector(index)
+                    }
+                    (false, _) => {
+                        stack += 1;
+                        Location::Stack(stack - 1)
+                    }
+                }
+            })
+            .collect()
+    }
+
+    fn format_call(&self, arg: impl Fn(&Location) -> Option<u64>) -> String {
+        let mut line = format!("{}(", self.name);
+        for (i, (kind, location)) in self.args.iter().zip(self.locations()).enumerate() {
+            if i != 0 {
+                line.push_str(", ");
+            }
+            match arg(&location) {
+                Some(bits) => line.push_str(&kind.format(bits)),
+                None => line.push('?'),
+            }
+        }
+        line.push(')');
+        line
+    }
+
+    /// Formats the arguments of a call seen by a [`Middleware`], e.g. `Present(0x1f2e3d4c, 1, 0)`.
+    pub fn format_args(&self, args: &CallArgs) -> String {
+        self.format_call(|location| {
+            Some(match *location {
+                Location::Register(i) => args.arg(i) as u64,
+                Location::Vector(i) => args.float(i).to_bits(),
+                Location::Stack(i) => args.arg(ARGUMENTS.len() + i) as u64,
+            })
+        })
+    }
+
+    /// Formats the result of a call seen by a [`Middleware`], e.g. `Present -> S_OK`.
+    pub fn format_return(&self, result: &CallReturn) -> String {
+        let bits = match self.returns.is_float() {
+            true => result.float().to_bits(),
+            false => result.value() as u64,
+        };
+        format!("{} -> {}", self.name, self.returns.format(bits))
+    }
+
+    /// Formats the arguments of a call recorded by a [`CallRecorder`](super::CallRecorder).
+    ///
+    /// Floating-point and unrecorded arguments are shown as `?`, as only integer argument words are recorded.
+    pub fn format_recorded(&self, call: &RecordedCall) -> String {
+        self.format_call(|location| match *location {
+            Location::Register(i) => call.args.get(i).map(|&word| word as u64),
+            Location::Vector(_) => None,
+            Location::Stack(i) => call.args.get(ARGUMENTS.len() + i).map(|&word| word as u64),
+        })
+    }
+}
+
+/// Receives the lines printed by an [`ArgLogger`].
+pub type Sink = dyn Fn(&str) + Send + Sync;
+
+/// Prints the arguments and results of the calls to hooked methods, an API monitor for any interface.
+///
+/// Every logged call produces a line with its decoded arguments when it is made, and one with its result
+/// once it returns; see [`Middleware`] for the methods that can't have their result observed.
+pub struct ArgLogger {
+    sink: Arc<Sink>,
+    thunks: Mutex<Vec<Middleware>>,
+}
+
+impl ArgLogger {
+    /// Creates a logger passing every line to `sink`.
+    pub fn new(sink: impl Fn(&str) + Send + Sync + 'static) -> Self {
+        Self {
+            sink: Arc::new(sink),
+            thunks: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Creates a logger emitting every line as a `tracing` event with the target `vmt_hook::calls`.
+    #[cfg(feature = "tracing")]
+    pub fn tracing() -> Self {
+        Self::new(|line| tracing::info!(target: "vmt_hook::calls", "{line}"))
+    }
+
+    /// Starts logging the calls to the method at `id` of `hook` as described by `signature`, in front of
+    /// whatever it currently reaches.
+    pub unsafe fn log<T, B: HookBackend>(
+        &self,
+        hook: &VTableHook<T, B>,
+        id: usize,
+        signature: Signature,
+    ) -> io::Result<()> {
+        let signature = Arc::new(signature);
+        let (before, after) = (signature.clone(), signature);
+        let (before_sink, after_sink) = (self.sink.clone(), self.sink.clone());
+        let thunk = Middleware::install(
+            hook,
+            id,
+            move |args| before_sink(&before.format_args(args)),
+            move |result| after_sink(&after.format_return(result)),
+        )?;
+        self.thunks.lock().unwrap_or_else(|e| e.into_inner()).push(thunk);
+        Ok(())
+    }
+
+    /// Starts logging the calls to the method of `slot` in `hook`, named `name`, decoded from its type.
+    pub unsafe fn log_slot<T, B: HookBackend, F: TypedFn>(
+        &self,
+        hook: &VTableHook<T, B>,
+        name: &str,
+        slot: Slot<F>,
+    ) -> io::Result<()> {
+        self.log(hook, slot.index(), Signature::of(name, slot))
+    }
+}
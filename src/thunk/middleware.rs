@@ -0,0 +1,351 @@
+//! Thunks calling observation callbacks before and after the methods they forward to.
+
+use std::cell::{Cell, RefCell};
+use std::io;
+use std::marker::PhantomData;
+
+use super::{reg, Thunk, ARGUMENTS, SAVED_VECTORS, SHADOW};
+use crate::backend::HookBackend;
+use crate::VTableHook;
+
+thread_local! {
+    /// Return addresses and `this` of the calls in progress on this thread with an `after` callback,
+    /// innermost last.
+    static CALLS: RefCell<Vec<(usize, usize)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A callback seeing the arguments of a call.
+pub type Before = dyn Fn(&CallArgs) + Send + Sync;
+
+/// A callback seeing the result of a call.
+pub type After = dyn Fn(&CallReturn) + Send + Sync;
+
+/// A callback changing the arguments of a call before they reach the method.
+pub type ArgTransform = dyn Fn(&mut CallArgs) + Send + Sync;
+
+/// A callback changing the result of a call before it reaches the caller.
+pub type ReturnTransform = dyn Fn(&mut CallReturn) + Send + Sync;
+
+/// The arguments of a call seen by a `before` callback.
+pub struct CallArgs<'a> {
+    slot: usize,
+    registers: *mut usize,
+    stack: *mut usize,
+    _frame: PhantomData<&'a mut usize>,
+}
+
+impl CallArgs<'_> {
+    /// Returns the index of the called method in the VTable.
+    pub fn slot(&self) -> usize {
+        self.slot
+    }
+
+    /// Returns the first integer argument, which is `this` for methods not returning large values.
+    pub fn this(&self) -> usize {
+        self.arg(0)
+    }
+
+    /// Returns the integer argument word `i`; stack arguments follow the register ones.
+    ///
+    /// Reading past the arguments of the method returns whatever the caller's frame holds.
+    pub fn arg(&self, i: usize) -> usize {
+        unsafe {
+            match i.checked_sub(ARGUMENTS.len()) {
+                None => *self.registers.add(i),
+                Some(i) => *self.stack.add(i),
+            }
+        }
+    }
+
+    /// Replaces the integer argument word `i` passed to the method.
+    pub fn set_arg(&mut self, i: usize, value: usize) {
+        unsafe {
+            match i.checked_sub(ARGUMENTS.len()) {
+                None => *self.registers.add(i) = value,
+                Some(i) => *self.stack.add(i) = value,
+            }
+        }
+    }
+
+    fn vector(&self, i: usize) -> *mut [u64; 2] {
+        assert!(i < super::VECTORS as usize, "xmm{i} doesn't hold arguments");
+        let vectors = (self.registers as usize + (SAVED_VECTORS - SHADOW) as usize) as *mut [u64; 2];
+        unsafe { vectors.add(i) }
+    }
+
+    /// Returns the low 64 bits of the vector register `xmm{i}` as a double.
+    ///
+    /// On Windows the register matches the position of the argument, on other platforms it counts the
+    /// floating-point arguments only.
+    pub fn float(&self, i: usize) -> f64 {
+        unsafe { f64::from_bits((*self.vector(i))[0]) }
+    }
+
+    /// Returns the low 32 bits of the vector register `xmm{i}` as a single-precision float.
+    pub fn float32(&self, i: usize) -> f32 {
+        unsafe { f32::from_bits((*self.vector(i))[0] as u32) }
+    }
+
+    /// Replaces the double in the vector register `xmm{i}` passed to the method.
+    pub fn set_float(&mut self, i: usize, value: f64) {
+        unsafe { (*self.vector(i))[0] = value.to_bits() }
+    }
+
+    /// Replaces the single-precision float in the vector register `xmm{i}` passed to the method.
+    pub fn set_float32(&mut self, i: usize, value: f32) {
+        unsafe {
+            let low = &mut (*self.vector(i))[0];
+            *low = *low & !0xFFFF_FFFF | value.to_bits() as u64;
+        }
+    }
+}
+
+/// The result of a call seen by an `after` callback.
+pub struct CallReturn {
+    slot: usize,
+    this: usize,
+    value: usize,
+    float: u64,
+}
+
+impl CallReturn {
+    /// Returns the index of the called method in the VTable.
+    pub fn slot(&self) -> usize {
+        self.slot
+    }
+
+    /// Returns the first integer argument of the call.
+    pub fn this(&self) -> usize {
+        self.this
+    }
+
+    /// Returns the integer return value, such as an `HRESULT` in its low 32 bits.
+    pub fn value(&self) -> usize {
+        self.value
+    }
+
+    /// Returns the floating-point return value.
+    pub fn float(&self) -> f64 {
+        f64::from_bits(self.float)
+    }
+
+    /// Returns the single-precision floating-point return value.
+    pub fn float32(&self) -> f32 {
+        f32::from_bits(self.float as u32)
+    }
+
+    /// Replaces the integer return value, e.g. with `S_OK`.
+    pub fn set_value(&mut self, value: usize) {
+        self.value = value;
+    }
+
+    /// Replaces the floating-point return value.
+    pub fn set_float(&mut self, value: f64) {
+        self.float = value.to_bits();
+    }
+
+    /// Replaces the single-precision floating-point return value.
+    pub fn set_float32(&mut self, value: f32) {
+        self.float = self.float & !0xFFFF_FFFF | value.to_bits() as u64;
+    }
+}
+
+#[derive(Default)]
+struct Callbacks {
+    slot: usize,
+    resume: usize,
+    transform: Option<Box<ArgTransform>>,
+    before: Option<Box<Before>>,
+    transform_return: Option<Box<ReturnTransform>>,
+    after: Option<Box<After>>,
+}
+
+impl Callbacks {
+    /// Returns `true` if the result of the calls is needed.
+    fn returns(&self) -> bool {
+        self.transform_return.is_some() || self.after.is_some()
+    }
+}
+
+/// Called on entry with the saved argument registers and the slot holding the caller's return address,
+/// which is redirected to `resume` if the result is needed. Changed arguments are reloaded from
+/// the saved registers.
+unsafe extern "C" fn enter(callbacks: &Callbacks, registers: *mut usize, return_slot: *mut usize) {
+    let mut args = CallArgs {
+        slot: callbacks.slot,
+        registers,
+        stack: return_slot.add(1 + SHADOW as usize / 8),
+        _frame: PhantomData,
+    };
+    if let Some(transform) = &callbacks.transform {
+        transform(&mut args);
+    }
+    if let Some(before) = &callbacks.before {
+        before(&args);
+    }
+    if callbacks.returns() {
+        let pushed = CALLS.try_with(|calls| calls.borrow_mut().push((*return_slot, *registers)));
+        // The results of calls made while the thread is exiting aren't observed.
+        if pushed.is_ok() {
+            *return_slot = callbacks.resume;
+        }
+    }
+}
+
+/// Called once the method returned with its saved `rax`, `rdx` and `xmm0`, which are reloaded from there;
+/// returns the caller's return address.
+unsafe extern "C" fn leave(callbacks: &Callbacks, returned: *mut usize) -> usize {
+    let (address, this) = CALLS.with(|calls| calls.borrow_mut().pop()).expect("unbalanced observed call");
+    let mut result = CallReturn {
+        slot: callbacks.slot,
+        this,
+        value: *returned,
+        float: *returned.add(2) as u64,
+    };
+    if let Some(transform) = &callbacks.transform_return {
+        transform(&mut result);
+        *returned = result.value;
+        *returned.add(2) = result.float as usize;
+    }
+    if let Some(after) = &callbacks.after {
+        after(&result);
+    }
+    address
+}
+
+/// A thunk calling `before` with the arguments of every call and `after` with its result, around the
+/// method it forwards to.
+///
+/// Covers observation-only hooks without writing an extern function or keeping the original pointer, and
+/// hooks that only change an argument or the result, through [`transform_args`](Self::transform_args) and
+/// [`transform_return`](Self::transform_return). To see the result, the thunk swaps the caller's return
+/// address for its own, so methods that throw C++ exceptions or long-jump out must not have an `after`
+/// callback nor a result transform. A panic in a callback aborts the process.
+pub struct Middleware {
+    thunk: Thunk,
+    entry: usize,
+    original: usize,
+    _callbacks: Box<Callbacks>,
+}
+
+impl Middleware {
+    /// Creates a thunk observing calls as made to `slot` before jumping to `original`.
+    pub unsafe fn new(
+        slot: usize,
+        original: usize,
+        before: Option<Box<Before>>,
+        after: Option<Box<After>>,
+    ) -> io::Result<Self> {
+        Self::with_callbacks(slot, original, Callbacks { before, after, ..Callbacks::default() })
+    }
+
+    unsafe fn with_callbacks(slot: usize, original: usize, callbacks: Callbacks) -> io::Result<Self> {
+        let mut callbacks = Box::new(Callbacks { slot, ..callbacks });
+        let context = &*callbacks as *const Callbacks as usize;
+        // Return values: rax and rdx, then xmm0 and xmm1.
+        let frame = SHADOW + 48;
+        let resume = Cell::new(0);
+        let entry = Cell::new(0);
+
+        let thunk = Thunk::new(|code| {
+            // The method returned here with the stack aligned.
+            resume.set(code.here());
+            code.sub_rsp(frame).store(reg::RAX, SHADOW).store(reg::RDX, SHADOW + 8);
+            code.store_xmm(0, SHADOW + 16).store_xmm(1, SHADOW + 32);
+            code.mov_imm(ARGUMENTS[0], context).lea_rsp(ARGUMENTS[1], SHADOW);
+            code.mov_r11_imm(leave as *const () as usize).call_r11().mov_r11_rax();
+            code.load(reg::RAX, SHADOW).load(reg::RDX, SHADOW + 8);
+            code.load_xmm(0, SHADOW + 16).load_xmm(1, SHADOW + 32);
+            code.add_rsp(frame).jmp_r11();
+
+            entry.set(code.here() - resume.get());
+            code.call_preserving(enter as *const () as usize, |code| {
+                code.mov_imm(ARGUMENTS[0], context).lea_rsp(ARGUMENTS[1], SHADOW).lea_rbp(ARGUMENTS[2], 8);
+            });
+            code.mov_r11_imm(original).jmp_r11();
+        })?;
+        callbacks.resume = resume.get();
+        Ok(Self {
+            thunk,
+            entry: entry.get(),
+            original,
+            _callbacks: callbacks,
+        })
+    }
+
+    /// Observes the calls to the method at `id` of `hook` with both callbacks, in front of whatever it
+    /// currently reaches.
+    pub unsafe fn install<T, B: HookBackend>(
+        hook: &VTableHook<T, B>,
+        id: usize,
+        before: impl Fn(&CallArgs) + Send + Sync + 'static,
+        after: impl Fn(&CallReturn) + Send + Sync + 'static,
+    ) -> io::Result<Self> {
+        let callbacks = Callbacks {
+            before: Some(Box::new(before)),
+            after: Some(Box::new(after)),
+            ..Callbacks::default()
+        };
+        Self::install_callbacks(hook, id, callbacks)
+    }
+
+    /// Calls `before` with the arguments of the calls to the method at `id` of `hook`.
+    pub unsafe fn before<T, B: HookBackend>(
+        hook: &VTableHook<T, B>,
+        id: usize,
+        before: impl Fn(&CallArgs) + Send + Sync + 'static,
+    ) -> io::Result<Self> {
+        Self::install_callbacks(hook, id, Callbacks { before: Some(Box::new(before)), ..Callbacks::default() })
+    }
+
+    /// Calls `after` with the results of the calls to the method at `id` of `hook`.
+    pub unsafe fn after<T, B: HookBackend>(
+        hook: &VTableHook<T, B>,
+        id: usize,
+        after: impl Fn(&CallReturn) + Send + Sync + 'static,
+    ) -> io::Result<Self> {
+        Self::install_callbacks(hook, id, Callbacks { after: Some(Box::new(after)), ..Callbacks::default() })
+    }
+
+    /// Passes the arguments of the calls to the method at `id` of `hook` through `transform`, which may
+    /// change them before they reach whatever the slot currently reaches, e.g. to clamp one parameter.
+    pub unsafe fn transform_args<T, B: HookBackend>(
+        hook: &VTableHook<T, B>,
+        id: usize,
+        transform: impl Fn(&mut CallArgs) + Send + Sync + 'static,
+    ) -> io::Result<Self> {
+        Self::install_callbacks(hook, id, Callbacks { transform: Some(Box::new(transform)), ..Callbacks::default() })
+    }
+
+    /// Passes the results of the calls to the method at `id` of `hook` through `transform`, which may
+    /// change them before they reach the caller, e.g. to force `S_OK`.
+    pub unsafe fn transform_return<T, B: HookBackend>(
+        hook: &VTableHook<T, B>,
+        id: usize,
+        transform: impl Fn(&mut CallReturn) + Send + Sync + 'static,
+    ) -> io::Result<Self> {
+        let callbacks = Callbacks {
+            transform_return: Some(Box::new(transform)),
+            ..Callbacks::default()
+        };
+        Self::install_callbacks(hook, id, callbacks)
+    }
+
+    unsafe fn install_callbacks<T, B: HookBackend>(
+        hook: &VTableHook<T, B>,
+        id: usize,
+        callbacks: Callbacks,
+    ) -> io::Result<Self> {
+        super::install(hook, id, |current| Self::with_callbacks(id, current, callbacks), Self::address)
+    }
+
+    /// Returns the address of the thunk.
+    pub fn address(&self) -> usize {
+        self.thunk.address() + self.entry
+    }
+
+    /// Returns the function the thunk forwards to.
+    pub fn original(&self) -> usize {
+        self.original
+    }
+}
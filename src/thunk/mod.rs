@@ -0,0 +1,280 @@
+//! Small machine-code thunks generated at runtime (x86_64 only).
+//!
+//! # Lifetime
+//!
+//! A thunk is executable memory freed when its owner is dropped, while the tables it was installed into keep
+//! pointing at it. It must outlive every table pointing at it, so restore its slots or drop their hooks first.
+//! Thunks that regain control once the method returns, those of [`Middleware`], [`CallTimer`] and [`ArgLogger`],
+//! must also outlive the calls in progress.
+
+use std::io;
+
+use crate::allocations::{self, AllocationKind};
+use crate::{sys, HookBackend, VTableHook};
+
+mod bypass;
+mod conditional;
+mod context;
+mod count;
+mod filter;
+mod live;
+mod log;
+mod middleware;
+mod record;
+mod sampling;
+mod spoof;
+mod timing;
+mod trace;
+
+pub use bypass::{BypassThunk, HookBypass};
+pub use conditional::ConditionalThunk;
+pub use context::ContextThunk;
+pub use count::CallCounter;
+pub(crate) use count::slot_calls;
+pub use filter::CallerFilter;
+pub use live::LiveOriginal;
+pub use log::{ArgKind, ArgLogger, ArgType, Signature, Sink, TypedFn};
+pub use middleware::{After, ArgTransform, Before, CallArgs, CallReturn, Middleware, ReturnTransform};
+pub use record::{CallRecorder, RecordedCall};
+pub use sampling::SamplingThunk;
+pub use spoof::{find_gadget, SpoofedCall};
+pub(crate) use timing::slot_timings;
+pub use timing::CallTimer;
+pub use trace::ChromeTrace;
+
+/// Registers holding the integer arguments, in parameter order.
+#[cfg(windows)]
+pub(crate) const ARGUMENTS: &[u8] = &[reg::RCX, reg::RDX, reg::R8, reg::R9];
+#[cfg(not(windows))]
+pub(crate) const ARGUMENTS: &[u8] = &[reg::RDI, reg::RSI, reg::RDX, reg::RCX, reg::R8, reg::R9];
+
+/// Vector registers that may hold arguments.
+#[cfg(windows)]
+pub(crate) const VECTORS: u8 = 6;
+#[cfg(not(windows))]
+pub(crate) const VECTORS: u8 = 8;
+
+/// Space the callee may use below its parameters.
+#[cfg(windows)]
+pub(crate) const SHADOW: u32 = 32;
+#[cfg(not(windows))]
+pub(crate) const SHADOW: u32 = 0;
+
+/// Offset from `rsp` of the vector registers saved by [`Assembler::call_preserving`].
+pub(crate) const SAVED_VECTORS: u32 = (SHADOW + (ARGUMENTS.len() as u32 + 2) * 8).next_multiple_of(16);
+
+/// Builds a thunk with `build` in front of what the slot `id` of `hook` currently reaches, and points the slot
+/// at the thunk's `address`. The thunk is then bound by the [lifetime](self#lifetime) rule.
+pub(crate) unsafe fn install<T, B: HookBackend, H>(
+    hook: &VTableHook<T, B>,
+    id: usize,
+    build: impl FnOnce(usize) -> io::Result<H>,
+    address: impl Fn(&H) -> usize,
+) -> io::Result<H> {
+    let thunk = build(hook.get_replaced_method(id))?;
+    hook.replace_method(id, address(&thunk));
+    Ok(thunk)
+}
+
+/// Executable memory holding one generated thunk.
+pub(crate) struct Thunk {
+    address: usize,
+    size: usize,
+}
+
+unsafe impl Send for Thunk {}
+unsafe impl Sync for Thunk {}
+
+impl Drop for Thunk {
+    fn drop(&mut self) {
+        allocations::unregister(self.address);
+        unsafe { sys::free_pages(self.address, self.size) }
+    }
+}
+
+impl Thunk {
+    /// Assembles a thunk with `assemble`, which is called once to measure the code and once more to emit it
+    /// at its final address.
+    pub(crate) unsafe fn new(assemble: impl Fn(&mut Assembler)) -> io::Result<Self> {
+        let mut measure = Assembler { code: Vec::new(), base: 0 };
+        assemble(&mut measure);
+
+        let size = measure.code.len().max(1).next_multiple_of(sys::page_size());
+        let address = sys::alloc_pages(size, sys::Protection::ReadWrite)?;
+        let thunk = Self { address, size };
+        allocations::register(AllocationKind::Thunk, address, size, None);
+
+        let mut assembler = Assembler { code: Vec::new(), base: address };
+        assemble(&mut assembler);
+        std::ptr::copy_nonoverlapping(assembler.code.as_ptr(), address as *mut u8, assembler.code.len());
+        sys::protect(address, size, sys::Protection::ReadExecute)?;
+        Ok(thunk)
+    }
+
+    /// Returns the address of the first instruction.
+    pub(crate) fn address(&self) -> usize {
+        self.address
+    }
+}
+
+/// Emits machine code, keeping track of the address it will run at.
+pub(crate) struct Assembler {
+    code: Vec<u8>,
+    base: usize,
+}
+
+impl Assembler {
+    /// Returns the address of the next emitted byte.
+    pub(crate) fn here(&self) -> usize {
+        self.base + self.code.len()
+    }
+
+    /// Emits raw bytes.
+    pub(crate) fn bytes(&mut self, bytes: &[u8]) -> &mut Self {
+        self.code.extend_from_slice(bytes);
+        self
+    }
+
+    /// Emits a 32-bit little-endian immediate.
+    pub(crate) fn imm32(&mut self, value: u32) -> &mut Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    /// Emits a 64-bit little-endian immediate.
+    pub(crate) fn imm64(&mut self, value: u64) -> &mut Self {
+        self.bytes(&value.to_le_bytes())
+    }
+
+    /// `mov r11, imm64`
+    pub(crate) fn mov_r11_imm(&mut self, value: usize) -> &mut Self {
+        self.bytes(&[0x49, 0xBB]).imm64(value as u64)
+    }
+
+    /// `mov r11, rax`
+    pub(crate) fn mov_r11_rax(&mut self) -> &mut Self {
+        self.bytes(&[0x49, 0x89, 0xC3])
+    }
+
+    /// `mov r11, [rsp + disp32]`
+    pub(crate) fn load_r11(&mut self, disp: u32) -> &mut Self {
+        self.bytes(&[0x4C, 0x8B, 0x9C, 0x24]).imm32(disp)
+    }
+
+    /// `mov r11, [r11 + disp32]`
+    pub(crate) fn deref_r11(&mut self, disp: u32) -> &mut Self {
+        self.bytes(&[0x4D, 0x8B, 0x9B]).imm32(disp)
+    }
+
+    /// `mov [rsp + disp32], r11`
+    pub(crate) fn store_r11(&mut self, disp: u32) -> &mut Self {
+        self.bytes(&[0x4C, 0x89, 0x9C, 0x24]).imm32(disp)
+    }
+
+    /// `sub rsp, imm32`
+    pub(crate) fn sub_rsp(&mut self, value: u32) -> &mut Self {
+        self.bytes(&[0x48, 0x81, 0xEC]).imm32(value)
+    }
+
+    /// `add rsp, imm32`
+    pub(crate) fn add_rsp(&mut self, value: u32) -> &mut Self {
+        self.bytes(&[0x48, 0x81, 0xC4]).imm32(value)
+    }
+
+    /// `jmp r11`
+    pub(crate) fn jmp_r11(&mut self) -> &mut Self {
+        self.bytes(&[0x41, 0xFF, 0xE3])
+    }
+
+    /// `mov reg, imm64`
+    pub(crate) fn mov_imm(&mut self, reg: u8, value: usize) -> &mut Self {
+        self.bytes(&[0x48 | reg >> 3, 0xB8 | reg & 7]).imm64(value as u64)
+    }
+
+    /// `mov [rsp + disp32], reg`
+    pub(crate) fn store(&mut self, reg: u8, disp: u32) -> &mut Self {
+        self.bytes(&[0x48 | (reg >> 3) << 2, 0x89, 0x84 | (reg & 7) << 3, 0x24]).imm32(disp)
+    }
+
+    /// `mov reg, [rsp + disp32]`
+    pub(crate) fn load(&mut self, reg: u8, disp: u32) -> &mut Self {
+        self.bytes(&[0x48 | (reg >> 3) << 2, 0x8B, 0x84 | (reg & 7) << 3, 0x24]).imm32(disp)
+    }
+
+    /// `movdqu [rsp + disp32], xmm` for `xmm0`-`xmm7`
+    pub(crate) fn store_xmm(&mut self, xmm: u8, disp: u32) -> &mut Self {
+        self.bytes(&[0xF3, 0x0F, 0x7F, 0x84 | xmm << 3, 0x24]).imm32(disp)
+    }
+
+    /// `movdqu xmm, [rsp + disp32]` for `xmm0`-`xmm7`
+    pub(crate) fn load_xmm(&mut self, xmm: u8, disp: u32) -> &mut Self {
+        self.bytes(&[0xF3, 0x0F, 0x6F, 0x84 | xmm << 3, 0x24]).imm32(disp)
+    }
+
+    /// `lea reg, [rsp + disp32]`
+    pub(crate) fn lea_rsp(&mut self, reg: u8, disp: u32) -> &mut Self {
+        self.bytes(&[0x48 | (reg >> 3) << 2, 0x8D, 0x84 | (reg & 7) << 3, 0x24]).imm32(disp)
+    }
+
+    /// `lea reg, [rbp + disp32]`
+    pub(crate) fn lea_rbp(&mut self, reg: u8, disp: u32) -> &mut Self {
+        self.bytes(&[0x48 | (reg >> 3) << 2, 0x8D, 0x85 | (reg & 7) << 3]).imm32(disp)
+    }
+
+    /// `push rbp; mov rbp, rsp`
+    pub(crate) fn enter(&mut self) -> &mut Self {
+        self.bytes(&[0x55, 0x48, 0x89, 0xE5])
+    }
+
+    /// `leave`
+    pub(crate) fn leave(&mut self) -> &mut Self {
+        self.bytes(&[0xC9])
+    }
+
+    /// `call r11`
+    pub(crate) fn call_r11(&mut self) -> &mut Self {
+        self.bytes(&[0x41, 0xFF, 0xD3])
+    }
+
+    /// Calls `func` from a frame preserving the argument registers, `rax` and `r10`, with `setup` loading
+    /// the arguments of the call. The value returned by `func` is left in `r11`.
+    ///
+    /// Must be emitted at the entry of a thunk. In `setup`, the saved integer argument registers are at
+    /// `[rsp + SHADOW]` in parameter order, the vector registers at `[rsp + SAVED_VECTORS]` and the caller's return
+    /// address is at `[rbp + 8]`.
+    pub(crate) fn call_preserving(&mut self, func: usize, setup: impl FnOnce(&mut Self)) -> &mut Self {
+        let saved = [ARGUMENTS, &[reg::RAX, reg::R10]].concat();
+        let vectors = SAVED_VECTORS;
+        let frame = vectors + VECTORS as u32 * 16;
+
+        // The pushed rbp realigns the stack, and the frame is a multiple of 16.
+        self.enter().sub_rsp(frame);
+        for (i, &reg) in saved.iter().enumerate() {
+            self.store(reg, SHADOW + i as u32 * 8);
+        }
+        for xmm in 0..VECTORS {
+            self.store_xmm(xmm, vectors + xmm as u32 * 16);
+        }
+        setup(self);
+        self.mov_r11_imm(func).call_r11().mov_r11_rax();
+        for (i, &reg) in saved.iter().enumerate() {
+            self.load(reg, SHADOW + i as u32 * 8);
+        }
+        for xmm in 0..VECTORS {
+            self.load_xmm(xmm, vectors + xmm as u32 * 16);
+        }
+        self.leave()
+    }
+}
+
+/// General-purpose register numbers, as encoded in instructions.
+#[allow(dead_code)]
+pub(crate) mod reg {
+    pub(crate) const RAX: u8 = 0;
+    pub(crate) const RCX: u8 = 1;
+    pub(crate) const RDX: u8 = 2;
+    pub(crate) const RSI: u8 = 6;
+    pub(crate) const RDI: u8 = 7;
+    pub(crate) const R8: u8 = 8;
+    pub(crate) const R9: u8 = 9;
+    pub(crate) const R10: u8 = 10;
+}
@@ -0,0 +1,181 @@
+//! Thunks appending every call they forward to a ring buffer.
+
+use std::io;
+use std::sync::atomic::{fence, AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use super::{Thunk, ARGUMENTS, SHADOW};
+use crate::backend::HookBackend;
+use crate::{sys, VTableHook};
+
+/// Words of every entry before the arguments: sequence, slot, `this`, time and thread.
+const HEADER: usize = 5;
+
+/// A call seen by a recording thunk.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RecordedCall {
+    /// Index of the method in the VTable.
+    pub slot: usize,
+    /// First integer argument, which is `this` for methods not returning large values.
+    pub this: usize,
+    /// Time of the call since the recorder was created.
+    pub time: Duration,
+    /// Id of the calling thread, as shown by debuggers.
+    pub thread: u64,
+    /// First integer argument words; stack arguments follow the register ones.
+    pub args: Vec<usize>,
+}
+
+struct Buffer {
+    start: Instant,
+    arguments: usize,
+    mask: usize,
+    head: AtomicUsize,
+    entries: Box<[AtomicUsize]>,
+}
+
+impl Buffer {
+    fn entry(&self, ticket: usize) -> &[AtomicUsize] {
+        let words = HEADER + self.arguments;
+        &self.entries[(ticket & self.mask) * words..][..words]
+    }
+
+    /// Reads the entry of `ticket`: `Err(true)` if it was overwritten, `Err(false)` if it isn't written yet.
+    fn read(&self, ticket: usize) -> Result<RecordedCall, bool> {
+        let entry = self.entry(ticket);
+        let sequence = entry[0].load(Ordering::Acquire);
+        if sequence != 2 * ticket + 2 {
+            return Err(sequence > 2 * ticket + 2);
+        }
+        let call = RecordedCall {
+            slot: entry[1].load(Ordering::Relaxed),
+            this: entry[2].load(Ordering::Relaxed),
+            time: Duration::from_nanos(entry[3].load(Ordering::Relaxed) as u64),
+            thread: entry[4].load(Ordering::Relaxed) as u64,
+            args: entry[HEADER..].iter().map(|word| word.load(Ordering::Relaxed)).collect(),
+        };
+        fence(Ordering::Acquire);
+        if entry[0].load(Ordering::Relaxed) != sequence {
+            return Err(true);
+        }
+        Ok(call)
+    }
+}
+
+/// Called by the thunks with the saved argument registers and the stack arguments of the call.
+unsafe extern "C" fn record(buffer: &Buffer, slot: usize, registers: *const usize, stack: *const usize) {
+    let ticket = buffer.head.fetch_add(1, Ordering::Relaxed);
+    let entry = buffer.entry(ticket);
+    entry[0].store(2 * ticket + 1, Ordering::Relaxed);
+    fence(Ordering::Release);
+    entry[1].store(slot, Ordering::Relaxed);
+    entry[2].store(*registers, Ordering::Relaxed);
+    entry[3].store(buffer.start.elapsed().as_nanos() as usize, Ordering::Relaxed);
+    entry[4].store(sys::current_thread_id() as usize, Ordering::Relaxed);
+    for (i, word) in entry[HEADER..].iter().enumerate() {
+        let value = match i.checked_sub(ARGUMENTS.len()) {
+            None => *registers.add(i),
+            Some(i) => *stack.add(i),
+        };
+        word.store(value, Ordering::Relaxed);
+    }
+    entry[0].store(2 * ticket + 2, Ordering::Release);
+}
+
+/// Records calls into a lock-free ring buffer through thunks placed in front of hooked methods.
+///
+/// Every call reaching a thunk stores the slot index, the `this` pointer, a timestamp, the thread id and
+/// the first argument words, then continues to the method the slot reached before. When more calls
+/// are recorded than the buffer holds before it is drained, the oldest are lost.
+pub struct CallRecorder {
+    buffer: Box<Buffer>,
+    /// Ticket of the next entry to drain, with the number of entries lost so far.
+    cursor: Mutex<(usize, usize)>,
+    thunks: Mutex<Vec<Thunk>>,
+}
+
+impl CallRecorder {
+    /// Creates a recorder keeping at least `capacity` calls with `arguments` argument words each.
+    pub fn new(capacity: usize, arguments: usize) -> Self {
+        let capacity = capacity.max(1).next_power_of_two();
+        Self {
+            buffer: Box::new(Buffer {
+                start: Instant::now(),
+                arguments,
+                mask: capacity - 1,
+                head: AtomicUsize::new(0),
+                entries: (0..capacity * (HEADER + arguments)).map(|_| AtomicUsize::new(0)).collect(),
+            }),
+            cursor: Mutex::new((0, 0)),
+            thunks: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Returns the time the recorded calls are measured from.
+    pub fn start(&self) -> Instant {
+        self.buffer.start
+    }
+
+    /// Generates a thunk recording calls as made to `slot` before jumping to `target`.
+    pub unsafe fn thunk(&self, slot: usize, target: usize) -> io::Result<usize> {
+        let buffer = &*self.buffer as *const Buffer as usize;
+        let thunk = Thunk::new(|code| {
+            code.call_preserving(record as *const () as usize, |code| {
+                code.mov_imm(ARGUMENTS[0], buffer)
+                    .mov_imm(ARGUMENTS[1], slot)
+                    .lea_rsp(ARGUMENTS[2], SHADOW)
+                    .lea_rbp(ARGUMENTS[3], 16 + SHADOW);
+            });
+            code.mov_r11_imm(target).jmp_r11();
+        })?;
+
+        let address = thunk.address();
+        self.thunks.lock().unwrap_or_else(|e| e.into_inner()).push(thunk);
+        Ok(address)
+    }
+
+    /// Starts recording the calls to the method at `id` of `hook`, in front of whatever it currently reaches.
+    pub unsafe fn record<T, B: HookBackend>(&self, hook: &VTableHook<T, B>, id: usize) -> io::Result<()> {
+        let thunk = self.thunk(id, hook.get_replaced_method(id))?;
+        hook.replace_method(id, thunk);
+        Ok(())
+    }
+
+    /// Starts recording the calls to every method of `hook`.
+    pub unsafe fn record_all<T, B: HookBackend>(&self, hook: &VTableHook<T, B>) -> io::Result<()> {
+        for id in 0..hook.backend().count() {
+            self.record(hook, id)?;
+        }
+        Ok(())
+    }
+
+    /// Removes and returns the calls recorded since the last drain, oldest first.
+    pub fn drain(&self) -> Vec<RecordedCall> {
+        let mut cursor = self.cursor.lock().unwrap_or_else(|e| e.into_inner());
+        let (tail, lost) = &mut *cursor;
+        let head = self.buffer.head.load(Ordering::Acquire);
+        let capacity = self.buffer.mask + 1;
+        if head - *tail > capacity {
+            *lost += head - capacity - *tail;
+            *tail = head - capacity;
+        }
+
+        let mut calls = Vec::with_capacity(head - *tail);
+        while *tail < head {
+            match self.buffer.read(*tail) {
+                Ok(call) => calls.push(call),
+                Err(true) => *lost += 1,
+                // Still being written; drained next time.
+                Err(false) => break,
+            }
+            *tail += 1;
+        }
+        calls
+    }
+
+    /// Returns the number of calls overwritten before they could be drained.
+    pub fn lost(&self) -> usize {
+        self.cursor.lock().unwrap_or_else(|e| e.into_inner()).1
+    }
+}
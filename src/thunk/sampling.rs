@@ -0,0 +1,141 @@
+//! Thunks diverting only a sample of the calls to a hook.
+
+use std::cell::Cell;
+use std::hash::{BuildHasher, RandomState};
+use std::io;
+use std::sync::atomic::AtomicI64;
+
+use super::{Thunk, ARGUMENTS};
+use crate::backend::HookBackend;
+use crate::VTableHook;
+
+thread_local! {
+    /// State of the xorshift generator of this thread, seeded on first use.
+    static RANDOM: Cell<u64> = const { Cell::new(0) };
+}
+
+fn random() -> u64 {
+    let mut state = RANDOM.get();
+    if state == 0 {
+        state = RandomState::new().hash_one(0u8) | 1;
+    }
+    state ^= state << 13;
+    state ^= state >> 7;
+    state ^= state << 17;
+    RANDOM.set(state);
+    state
+}
+
+/// A threshold with the targets it chooses between, passed to [`select`].
+struct Chance {
+    threshold: u64,
+    hook: usize,
+    original: usize,
+}
+
+/// Returns the function a probability-sampling thunk continues to.
+extern "C" fn select(chance: &Chance) -> usize {
+    if random() < chance.threshold {
+        chance.hook
+    } else {
+        chance.original
+    }
+}
+
+/// A thunk jumping to `hook` for a sample of the calls, and straight to `original` for the rest.
+///
+/// Keeps the overhead of hooking extremely hot methods low, e.g. for statistical profiling of
+/// `DrawIndexed`. Sampling every Nth call costs one atomic decrement per call.
+pub struct SamplingThunk {
+    thunk: Thunk,
+    hook: usize,
+    original: usize,
+    _countdown: Option<Box<AtomicI64>>,
+    _chance: Option<Box<Chance>>,
+}
+
+impl SamplingThunk {
+    /// Creates a thunk diverting every `n`th call to `hook`.
+    ///
+    /// Concurrent calls may shift which call is diverted, but not how often.
+    pub unsafe fn every(hook: usize, original: usize, n: u32) -> io::Result<Self> {
+        let n = n.clamp(1, i32::MAX as u32);
+        let countdown = Box::new(AtomicI64::new(n as i64));
+        let address = countdown.as_ptr() as usize;
+        let thunk = Thunk::new(|code| {
+            // lock dec qword [r11]; jnz original
+            code.mov_r11_imm(address).bytes(&[0xF0, 0x49, 0xFF, 0x0B, 0x75, 20]);
+            // mov qword [r11], n
+            code.bytes(&[0x49, 0xC7, 0x03]).imm32(n);
+            code.mov_r11_imm(hook).jmp_r11();
+            code.mov_r11_imm(original).jmp_r11();
+        })?;
+        Ok(Self {
+            thunk,
+            hook,
+            original,
+            _countdown: Some(countdown),
+            _chance: None,
+        })
+    }
+
+    /// Creates a thunk diverting each call to `hook` with the probability `p`.
+    pub unsafe fn with_probability(hook: usize, original: usize, p: f64) -> io::Result<Self> {
+        let chance = Box::new(Chance {
+            threshold: (p.clamp(0.0, 1.0) * u64::MAX as f64) as u64,
+            hook,
+            original,
+        });
+        let context = &*chance as *const Chance as usize;
+        let thunk = Thunk::new(|code| {
+            code.call_preserving(select as *const () as usize, |code| {
+                code.mov_imm(ARGUMENTS[0], context);
+            });
+            code.jmp_r11();
+        })?;
+        Ok(Self {
+            thunk,
+            hook,
+            original,
+            _countdown: None,
+            _chance: Some(chance),
+        })
+    }
+
+    /// Replaces the method at `id` of `hook` with `func` for every `n`th call, and what the slot currently
+    /// reaches for the others.
+    pub unsafe fn install_every<T, B: HookBackend>(
+        hook: &VTableHook<T, B>,
+        id: usize,
+        func: usize,
+        n: u32,
+    ) -> io::Result<Self> {
+        super::install(hook, id, |current| Self::every(func, current, n), Self::address)
+    }
+
+    /// Replaces the method at `id` of `hook` with `func` for calls sampled with the probability `p`, and
+    /// what the slot currently reaches for the others.
+    pub unsafe fn install_with_probability<T, B: HookBackend>(
+        hook: &VTableHook<T, B>,
+        id: usize,
+        func: usize,
+        p: f64,
+    ) -> io::Result<Self> {
+        super::install(hook, id, |current| Self::with_probability(func, current, p), Self::address)
+    }
+
+    /// Returns the address of the thunk.
+    pub fn address(&self) -> usize {
+        self.thunk.address()
+    }
+
+    /// Returns the function receiving the sampled calls.
+    pub fn hook(&self) -> usize {
+        self.hook
+    }
+
+    /// Returns the function receiving the other calls.
+    pub fn original(&self) -> usize {
+        self.original
+    }
+}
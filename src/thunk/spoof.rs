@@ -0,0 +1,124 @@
+//! Calls that reach their target with a return address inside a chosen module.
+//!
+//! The thunk copies the stack arguments into a new frame and sets its return address to a
+//! `jmp rbx` or `jmp qword ptr [rbx]` gadget found in the module, with `rbx` leading back into the
+//! thunk. When the target returns into the gadget, the thunk restores `rbx` and returns to the caller.
+//! `rbx` is callee-saved, so the target preserves it.
+
+use std::cell::Cell;
+use std::io;
+
+use crate::slot::FnPtr;
+use crate::sys;
+
+use super::Thunk;
+
+/// Encoding of `jmp qword ptr [rbx]`.
+const JMP_RBX_INDIRECT: [u8; 2] = [0xFF, 0x23];
+/// Encoding of `jmp rbx`.
+const JMP_RBX: [u8; 2] = [0xFF, 0xE3];
+
+/// Returns the address of a `jmp rbx` or `jmp qword ptr [rbx]` gadget in the code of the module
+/// containing `address`.
+pub unsafe fn find_gadget(address: usize) -> Option<usize> {
+    let module = sys::module_of(address)?;
+    sys::module_code(module).into_iter().find_map(|code| {
+        if !sys::is_readable(code.start, code.len()) {
+            return None;
+        }
+        let bytes = std::slice::from_raw_parts(code.start as *const u8, code.len());
+        bytes
+            .windows(2)
+            .position(|window| window == JMP_RBX_INDIRECT || window == JMP_RBX)
+            .map(|at| code.start + at)
+    })
+}
+
+/// A thunk calling `target` so that its return address points at a gadget, usually in the target's module.
+///
+/// The thunk has the signature of the target. `stack_words` is the number of pointer-sized words
+/// above the return address that the target may read: the 4 words of shadow space plus the stack
+/// arguments on Windows, only the stack arguments elsewhere.
+pub struct SpoofedCall {
+    thunk: Thunk,
+    entry: usize,
+    target: usize,
+    gadget: usize,
+}
+
+impl SpoofedCall {
+    /// Creates a thunk calling `target` through a gadget found in the target's own module.
+    pub unsafe fn new(target: usize, stack_words: usize) -> io::Result<Self> {
+        let gadget = find_gadget(target)
+            .ok_or_else(|| io::Error::new(io::ErrorKind::NotFound, "no gadget in the target's module"))?;
+        Self::with_gadget(target, gadget, stack_words)
+    }
+
+    /// Creates a thunk calling `target` through the `jmp rbx` or `jmp qword ptr [rbx]` gadget at `gadget`.
+    pub unsafe fn with_gadget(target: usize, gadget: usize, stack_words: usize) -> io::Result<Self> {
+        if !sys::is_readable(gadget, 2) {
+            return Err(io::Error::new(io::ErrorKind::InvalidInput, "gadget isn't readable"));
+        }
+        let indirect = match std::ptr::read(gadget as *const [u8; 2]) {
+            JMP_RBX_INDIRECT => true,
+            JMP_RBX => false,
+            _ => return Err(io::Error::new(io::ErrorKind::InvalidInput, "not a jmp rbx gadget")),
+        };
+
+        // [rsp] return address for the target, then the copied words, then the record:
+        // the resume address for `jmp [rbx]`, the saved rbx and the caller's return address.
+        let record = 8 + 8 * stack_words as u32;
+        let frame = (record + 24).next_multiple_of(16);
+        let entry = Cell::new(0);
+
+        let thunk = Thunk::new(|asm| {
+            // The target returned into the gadget, which jumped here with rsp just above the return slot.
+            let resume = asm.here();
+            asm.load_r11(record + 8);
+            // mov rbx, [rsp + record]
+            asm.bytes(&[0x48, 0x8B, 0x9C, 0x24]).imm32(record);
+            asm.add_rsp(frame).jmp_r11();
+
+            entry.set(asm.here() - resume);
+            asm.sub_rsp(frame);
+            for word in 0..stack_words as u32 {
+                asm.load_r11(frame + 8 + 8 * word).store_r11(8 + 8 * word);
+            }
+            asm.load_r11(frame).store_r11(record + 16);
+            // mov [rsp + record + 8], rbx
+            asm.bytes(&[0x48, 0x89, 0x9C, 0x24]).imm32(record + 8);
+            if indirect {
+                asm.mov_r11_imm(resume).store_r11(record);
+                // lea rbx, [rsp + record]
+                asm.bytes(&[0x48, 0x8D, 0x9C, 0x24]).imm32(record);
+            } else {
+                // mov rbx, imm64
+                asm.bytes(&[0x48, 0xBB]).imm64(resume as u64);
+            }
+            asm.mov_r11_imm(gadget).store_r11(0);
+            asm.mov_r11_imm(target).jmp_r11();
+        })?;
+
+        Ok(Self { thunk, entry: entry.get(), target, gadget })
+    }
+
+    /// Returns the address of the thunk.
+    pub fn address(&self) -> usize {
+        self.thunk.address() + self.entry
+    }
+
+    /// Returns the thunk as a typed function, which must match the target's signature.
+    pub unsafe fn get<F: FnPtr>(&self) -> F {
+        F::from_address(self.address())
+    }
+
+    /// Returns the function called by the thunk.
+    pub fn target(&self) -> usize {
+        self.target
+    }
+
+    /// Returns the gadget used as the target's return address.
+    pub fn gadget(&self) -> usize {
+        self.gadget
+    }
+}
@@ -0,0 +1,181 @@
+//! Thunks measuring the duration of the calls they forward.
+
+use std::cell::{Cell, RefCell};
+use std::io;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant};
+
+use super::{reg, Thunk, ARGUMENTS, SHADOW};
+use crate::backend::HookBackend;
+use crate::metrics::SlotTiming;
+use crate::VTableHook;
+
+thread_local! {
+    /// Return addresses and start times of the timed calls in progress on this thread, innermost last.
+    static CALLS: RefCell<Vec<(usize, Instant)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Timings of every live [`CallTimer`], by object, for [`metrics::stats`](crate::metrics::stats).
+static TIMERS: Mutex<Vec<(usize, Weak<[Durations]>)>> = Mutex::new(Vec::new());
+
+/// Accumulated durations of the calls to one method, in nanoseconds.
+struct Durations {
+    calls: AtomicU64,
+    total: AtomicU64,
+    min: AtomicU64,
+    max: AtomicU64,
+}
+
+impl Durations {
+    fn new() -> Self {
+        Self {
+            calls: AtomicU64::new(0),
+            total: AtomicU64::new(0),
+            min: AtomicU64::new(u64::MAX),
+            max: AtomicU64::new(0),
+        }
+    }
+
+    fn snapshot(&self, object: usize, slot: usize) -> SlotTiming {
+        let calls = self.calls.load(Ordering::Relaxed);
+        let min = if calls == 0 { 0 } else { self.min.load(Ordering::Relaxed) };
+        SlotTiming {
+            object,
+            slot,
+            calls,
+            total: Duration::from_nanos(self.total.load(Ordering::Relaxed)),
+            min: Duration::from_nanos(min),
+            max: Duration::from_nanos(self.max.load(Ordering::Relaxed)),
+        }
+    }
+
+    fn reset(&self) {
+        self.calls.store(0, Ordering::Relaxed);
+        self.total.store(0, Ordering::Relaxed);
+        self.min.store(u64::MAX, Ordering::Relaxed);
+        self.max.store(0, Ordering::Relaxed);
+    }
+}
+
+/// Called on entry with the slot holding the caller's return address, which is redirected to `resume`.
+unsafe extern "C" fn enter(return_slot: *mut usize, resume: usize) {
+    let pushed = CALLS.try_with(|calls| calls.borrow_mut().push((*return_slot, Instant::now())));
+    // Calls made while the thread is exiting aren't timed.
+    if pushed.is_ok() {
+        *return_slot = resume;
+    }
+}
+
+/// Called once the method returned; accounts its duration and returns the caller's return address.
+extern "C" fn leave(durations: &Durations) -> usize {
+    let (address, start) = CALLS.with(|calls| calls.borrow_mut().pop()).expect("unbalanced timed call");
+    let elapsed = start.elapsed().as_nanos() as u64;
+    durations.calls.fetch_add(1, Ordering::Relaxed);
+    durations.total.fetch_add(elapsed, Ordering::Relaxed);
+    durations.min.fetch_min(elapsed, Ordering::Relaxed);
+    durations.max.fetch_max(elapsed, Ordering::Relaxed);
+    address
+}
+
+/// Measures the calls to the methods of a hook through thunks timing each call to the original.
+///
+/// The thunks swap the caller's return address for their own to regain control once the method returns,
+/// so methods that throw C++ exceptions or long-jump out must not be timed. Durations are measured with
+/// [`Instant`], which uses `QueryPerformanceCounter` on Windows. Timings of called slots are also reported by
+/// [`metrics::stats`](crate::metrics::stats).
+pub struct CallTimer {
+    object: usize,
+    durations: Arc<[Durations]>,
+    thunks: Mutex<Vec<Thunk>>,
+}
+
+impl CallTimer {
+    /// Creates a timer for the methods of `hook`, without instrumenting any.
+    pub fn new<T, B: HookBackend>(hook: &VTableHook<T, B>) -> Self {
+        let durations: Arc<[Durations]> = (0..hook.backend().count()).map(|_| Durations::new()).collect();
+        let object = hook.vptr() as usize;
+        let mut timers = TIMERS.lock().unwrap_or_else(|e| e.into_inner());
+        timers.retain(|(_, durations)| durations.strong_count() != 0);
+        timers.push((object, Arc::downgrade(&durations)));
+        Self {
+            object,
+            durations,
+            thunks: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Creates a timer and instruments every method of `hook`.
+    pub unsafe fn instrument_all<T, B: HookBackend>(hook: &VTableHook<T, B>) -> io::Result<Self> {
+        let timer = Self::new(hook);
+        for id in 0..timer.durations.len() {
+            timer.instrument(hook, id)?;
+        }
+        Ok(timer)
+    }
+
+    /// Starts timing the calls to the method at `id` of `hook`, around whatever it currently reaches.
+    pub unsafe fn instrument<T, B: HookBackend>(&self, hook: &VTableHook<T, B>, id: usize) -> io::Result<()> {
+        let durations = &self.durations[id] as *const Durations as usize;
+        let target = hook.get_replaced_method(id);
+        // Return values: rax and rdx, then xmm0 and xmm1.
+        let frame = SHADOW + 48;
+        let entry = Cell::new(0);
+
+        let thunk = Thunk::new(|code| {
+            // The method returned here with the stack aligned.
+            let resume = code.here();
+            code.sub_rsp(frame).store(reg::RAX, SHADOW).store(reg::RDX, SHADOW + 8);
+            code.store_xmm(0, SHADOW + 16).store_xmm(1, SHADOW + 32);
+            code.mov_imm(ARGUMENTS[0], durations).mov_r11_imm(leave as *const () as usize).call_r11();
+            code.mov_r11_rax();
+            code.load(reg::RAX, SHADOW).load(reg::RDX, SHADOW + 8);
+            code.load_xmm(0, SHADOW + 16).load_xmm(1, SHADOW + 32);
+            code.add_rsp(frame).jmp_r11();
+
+            entry.set(code.here() - resume);
+            code.call_preserving(enter as *const () as usize, |code| {
+                code.lea_rbp(ARGUMENTS[0], 8).mov_imm(ARGUMENTS[1], resume);
+            });
+            code.mov_r11_imm(target).jmp_r11();
+        })?;
+        hook.replace_method(id, thunk.address() + entry.get());
+        self.thunks.lock().unwrap_or_else(|e| e.into_inner()).push(thunk);
+        Ok(())
+    }
+
+    /// Returns the address of the object's VTable pointer the timer was created for.
+    pub fn object(&self) -> usize {
+        self.object
+    }
+
+    /// Returns the timings of the method at `id` since it was instrumented or last reset.
+    pub fn timing(&self, id: usize) -> SlotTiming {
+        self.durations[id].snapshot(self.object, id)
+    }
+
+    /// Returns the timings of every method, by index.
+    pub fn timings(&self) -> Vec<SlotTiming> {
+        (0..self.durations.len()).map(|id| self.timing(id)).collect()
+    }
+
+    /// Resets every timing.
+    pub fn reset(&self) {
+        for durations in self.durations.iter() {
+            durations.reset();
+        }
+    }
+}
+
+/// Returns the timings of every called slot of the live timers.
+pub(crate) fn slot_timings() -> Vec<SlotTiming> {
+    let timers = TIMERS.lock().unwrap_or_else(|e| e.into_inner());
+    timers
+        .iter()
+        .filter_map(|(object, durations)| Some((*object, durations.upgrade()?)))
+        .flat_map(|(object, durations)| {
+            let timings: Vec<_> = durations.iter().enumerate().map(|(slot, d)| d.snapshot(object, slot)).collect();
+            timings.into_iter().filter(|timing| timing.calls != 0)
+        })
+        .collect()
+}
@@ -0,0 +1,121 @@
+//! Exporting recorded calls in the Chrome tracing format.
+
+use std::collections::BTreeMap;
+use std::fmt::Write as _;
+use std::io;
+use std::time::{Duration, Instant};
+
+use super::{CallRecorder, RecordedCall, Signature};
+
+/// Builds a Chrome tracing JSON file from recorded calls, viewable in `chrome://tracing`, Perfetto or speedscope.
+///
+/// Every call becomes an instant event on the timeline of its thread, named after its slot. Markers such as
+/// frame boundaries are shown across all threads.
+pub struct ChromeTrace {
+    start: Instant,
+    names: BTreeMap<usize, String>,
+    signatures: BTreeMap<usize, Signature>,
+    events: Vec<String>,
+}
+
+impl ChromeTrace {
+    /// Creates an empty trace for calls recorded by `recorder`.
+    pub fn new(recorder: &CallRecorder) -> Self {
+        Self {
+            start: recorder.start(),
+            names: BTreeMap::new(),
+            signatures: BTreeMap::new(),
+            events: Vec::new(),
+        }
+    }
+
+    /// Names the calls to `slot`, which are otherwise shown as `slot N`.
+    pub fn name_slot(&mut self, slot: usize, name: &str) -> &mut Self {
+        self.names.insert(slot, name.to_owned());
+        self
+    }
+
+    /// Names the calls to `slot` after `signature` and shows their decoded arguments.
+    pub fn describe_slot(&mut self, slot: usize, signature: Signature) -> &mut Self {
+        self.name_slot(slot, signature.name());
+        self.signatures.insert(slot, signature);
+        self
+    }
+
+    /// Adds recorded calls, e.g. the result of [`CallRecorder::drain`].
+    pub fn add_calls(&mut self, calls: &[RecordedCall]) -> &mut Self {
+        for call in calls {
+            let name = match self.names.get(&call.slot) {
+                Some(name) => escape(name),
+                None => format!("slot {}", call.slot),
+            };
+            let args = call.args.iter().map(|arg| format!("\"{arg:#x}\"")).collect::<Vec<_>>().join(",");
+            let decoded = match self.signatures.get(&call.slot) {
+                Some(signature) => format!(r#","call":"{}""#, escape(&signature.format_recorded(call))),
+                None => String::new(),
+            };
+            self.events.push(format!(
+                concat!(
+                    r#"{{"name":"{name}","cat":"vcall","ph":"i","s":"t","ts":{},"pid":{},"tid":{},"#,
+                    r#""args":{{"slot":{},"this":"{:#x}","args":[{args}]{decoded}}}}}"#,
+                ),
+                micros(call.time),
+                std::process::id(),
+                call.thread,
+                call.slot,
+                call.this,
+                name = name,
+                args = args,
+                decoded = decoded,
+            ));
+        }
+        self
+    }
+
+    /// Adds a marker at `time` shown across all threads.
+    pub fn mark(&mut self, name: &str, time: Instant) -> &mut Self {
+        self.events.push(format!(
+            r#"{{"name":"{}","cat":"marker","ph":"i","s":"g","ts":{},"pid":{},"tid":0}}"#,
+            escape(name),
+            micros(time.saturating_duration_since(self.start)),
+            std::process::id(),
+        ));
+        self
+    }
+
+    /// Adds a frame boundary at `time`.
+    pub fn mark_frame(&mut self, time: Instant) -> &mut Self {
+        self.mark("frame", time)
+    }
+
+    /// Returns the trace as JSON.
+    pub fn to_json(&self) -> String {
+        format!("{{\"traceEvents\":[\n{}\n]}}\n", self.events.join(",\n"))
+    }
+
+    /// Writes the trace as JSON to `out`.
+    pub fn write_to(&self, mut out: impl io::Write) -> io::Result<()> {
+        out.write_all(self.to_json().as_bytes())
+    }
+}
+
+/// Formats a duration in microseconds, the unit of trace timestamps.
+fn micros(time: Duration) -> String {
+    format!("{}.{:03}", time.as_micros(), time.subsec_nanos() % 1000)
+}
+
+/// Escapes `text` for a JSON string.
+fn escape(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '"' => escaped.push_str("\\\""),
+            '\\' => escaped.push_str("\\\\"),
+            c if (c as u32) < 0x20 => {
+                let _ = write!(escaped, "\\u{:04x}", c as u32);
+            }
+            c => escaped.push(c),
+        }
+    }
+    escaped
+}
@@ -0,0 +1,212 @@
+//! Installing hooks on several objects as a single operation.
+//!
+//! An overlay hooking a swapchain, a device and an input device is unusable with only some of them
+//! hooked. A [`Transaction`] describes every hook first and installs them when committed: either all
+//! of them succeed, or the ones already installed are rolled back and none remain.
+
+use std::ffi::c_void;
+use std::fmt;
+
+use crate::error::{Error, Result};
+use crate::{sys, InPlaceVmtHook, VTableCopyOptions, VTableHook};
+
+enum Target {
+    Object { object: usize, options: VTableCopyOptions },
+    Class { vtable: usize },
+}
+
+struct Entry {
+    target: Target,
+    count: usize,
+    replacements: Vec<(usize, usize)>,
+}
+
+/// A hook installed by a committed [`Transaction`].
+enum Installed {
+    Object(VTableHook<usize>),
+    Class(InPlaceVmtHook),
+}
+
+/// Error returned when committing a [`Transaction`] failed; nothing it described remains installed.
+#[derive(Debug)]
+pub struct TransactionError {
+    /// Index of the hook that failed, in the order it was added.
+    pub index: usize,
+    /// Why it failed.
+    pub error: Error,
+}
+
+impl fmt::Display for TransactionError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "hook {} of the transaction failed: {}", self.index, self.error)
+    }
+}
+
+impl std::error::Error for TransactionError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        Some(&self.error)
+    }
+}
+
+/// Hooks of several objects and classes installed all at once, or not at all.
+#[derive(Default)]
+pub struct Transaction {
+    entries: Vec<Entry>,
+}
+
+impl Transaction {
+    /// Creates an empty transaction.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a hook of `object` with `count` methods and the replacements, given as method index and
+    /// function address pairs. Returns its index in the committed hooks.
+    pub fn hook(&mut self, object: *mut c_void, count: usize, replacements: &[(usize, usize)]) -> usize {
+        self.hook_with_options(object, count, replacements, &VTableCopyOptions::default())
+    }
+
+    /// Adds a hook of `object` whose VTable copy is allocated as described by `options`.
+    pub fn hook_with_options(
+        &mut self,
+        object: *mut c_void,
+        count: usize,
+        replacements: &[(usize, usize)],
+        options: &VTableCopyOptions,
+    ) -> usize {
+        let target = Target::Object {
+            object: object as usize,
+            options: options.clone(),
+        };
+        self.push(target, count, replacements)
+    }
+
+    /// Adds a class-wide hook patching the `count` methods of `vtable` in place; see [`InPlaceVmtHook`].
+    pub fn patch(&mut self, vtable: *mut usize, count: usize, replacements: &[(usize, usize)]) -> usize {
+        self.push(Target::Class { vtable: vtable as usize }, count, replacements)
+    }
+
+    fn push(&mut self, target: Target, count: usize, replacements: &[(usize, usize)]) -> usize {
+        self.entries.push(Entry {
+            target,
+            count,
+            replacements: replacements.to_vec(),
+        });
+        self.entries.len() - 1
+    }
+
+    /// Returns the number of hooks in the transaction.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns `true` if the transaction holds no hooks.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Checks every hook, then installs them in order.
+    ///
+    /// Objects and tables that aren't readable and replacements outside the tables fail before anything
+    /// is touched. If installing a hook fails, the hooks installed before it are dropped, which restores
+    /// their objects and tables.
+    pub unsafe fn commit(self) -> std::result::Result<Committed, TransactionError> {
+        for (index, entry) in self.entries.iter().enumerate() {
+            entry.check().map_err(|error| TransactionError { index, error })?;
+        }
+
+        let mut installed = Vec::with_capacity(self.entries.len());
+        for (index, entry) in self.entries.iter().enumerate() {
+            match entry.install() {
+                Ok(hook) => installed.push(hook),
+                // Dropping the installed hooks rolls them back, the latest first.
+                Err(error) => {
+                    while installed.pop().is_some() {}
+                    return Err(TransactionError { index, error });
+                }
+            }
+        }
+        Ok(Committed { installed })
+    }
+}
+
+impl Entry {
+    unsafe fn check(&self) -> Result<()> {
+        let word = std::mem::size_of::<usize>();
+        let vtable = match self.target {
+            Target::Object { object, .. } => {
+                if !sys::is_readable(object, word) {
+                    return Err(Error::Invalid(format!("object {object:#x} isn't readable")));
+                }
+                *(object as *const usize)
+            }
+            Target::Class { vtable } => vtable,
+        };
+        if !sys::is_readable(vtable, self.count * word) {
+            return Err(Error::Invalid(format!("vtable {vtable:#x} with {} methods isn't readable", self.count)));
+        }
+        match self.replacements.iter().find(|&&(id, _)| id >= self.count) {
+            Some(&(id, _)) => Err(Error::Invalid(format!("slot {id} is out of bounds of {} methods", self.count))),
+            None => Ok(()),
+        }
+    }
+
+    unsafe fn install(&self) -> Result<Installed> {
+        match &self.target {
+            Target::Object { object, options } => {
+                let hook = VTableHook::with_count_and_options(*object, self.count, options)?;
+                for &(id, func) in &self.replacements {
+                    hook.replace_method(id, func);
+                }
+                Ok(Installed::Object(hook))
+            }
+            Target::Class { vtable } => {
+                let hook = InPlaceVmtHook::new(*vtable as *mut usize, self.count);
+                for &(id, func) in &self.replacements {
+                    hook.replace_method(id, func)?;
+                }
+                Ok(Installed::Class(hook))
+            }
+        }
+    }
+}
+
+/// The hooks of a committed [`Transaction`], restored together when dropped.
+pub struct Committed {
+    installed: Vec<Installed>,
+}
+
+impl Committed {
+    /// Returns the hook of an object added with [`Transaction::hook`], by its index.
+    pub fn hook(&self, index: usize) -> Option<&VTableHook<usize>> {
+        match self.installed.get(index)? {
+            Installed::Object(hook) => Some(hook),
+            Installed::Class(_) => None,
+        }
+    }
+
+    /// Returns the class-wide hook added with [`Transaction::patch`], by its index.
+    pub fn patch(&self, index: usize) -> Option<&InPlaceVmtHook> {
+        match self.installed.get(index)? {
+            Installed::Class(hook) => Some(hook),
+            Installed::Object(_) => None,
+        }
+    }
+
+    /// Returns the number of installed hooks.
+    pub fn len(&self) -> usize {
+        self.installed.len()
+    }
+
+    /// Returns `true` if no hooks were installed.
+    pub fn is_empty(&self) -> bool {
+        self.installed.is_empty()
+    }
+}
+
+impl Drop for Committed {
+    /// Restoring the hooks in the reverse order of their installation.
+    fn drop(&mut self) {
+        while self.installed.pop().is_some() {}
+    }
+}
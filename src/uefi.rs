@@ -0,0 +1,212 @@
+//! Hooking UEFI protocol interfaces, which are tables of function pointers like C++ VTables.
+//!
+//! Available without `std`. Memory comes from the `AllocatePool` boot service once [`init`] has been called
+//! with the boot services table, so [`PoolAllocator`] can serve as the global allocator of a driver or
+//! application and the copy-and-swap [`VTableHook`](crate::VTableHook) works as well. Boot services are gone
+//! after `ExitBootServices`, and with them allocation; hooks installed before keep working.
+
+use alloc::boxed::Box;
+use core::alloc::{GlobalAlloc, Layout};
+use core::ffi::c_void;
+use core::ptr;
+use core::sync::atomic::{AtomicPtr, AtomicUsize, Ordering};
+
+use crate::slot::{FnPtr, Slot};
+
+/// `EfiLoaderData`, the memory type of pool allocations made by loaded images.
+const LOADER_DATA: u32 = 2;
+
+/// Alignment the UEFI specification guarantees for pool allocations.
+const POOL_ALIGN: usize = 8;
+
+/// The start of `EFI_BOOT_SERVICES`, up to `FreePool`.
+#[repr(C)]
+struct BootServices {
+    header: [u64; 3],
+    raise_tpl: usize,
+    restore_tpl: usize,
+    allocate_pages: usize,
+    free_pages: usize,
+    get_memory_map: usize,
+    allocate_pool: unsafe extern "efiapi" fn(pool_type: u32, size: usize, buffer: *mut *mut u8) -> usize,
+    free_pool: unsafe extern "efiapi" fn(buffer: *mut u8) -> usize,
+}
+
+static BOOT_SERVICES: AtomicPtr<BootServices> = AtomicPtr::new(ptr::null_mut());
+
+/// Records the `EFI_BOOT_SERVICES` table, as found in the system table passed to the image entry point.
+pub unsafe fn init(boot_services: *mut c_void) {
+    BOOT_SERVICES.store(boot_services.cast(), Ordering::Release);
+}
+
+/// Allocates `size` bytes from the pool, returning null before [`init`] or on failure.
+unsafe fn allocate_pool(size: usize) -> *mut u8 {
+    let services = BOOT_SERVICES.load(Ordering::Acquire);
+    if services.is_null() {
+        return ptr::null_mut();
+    }
+    let mut buffer = ptr::null_mut();
+    match ((*services).allocate_pool)(LOADER_DATA, size, &mut buffer) {
+        0 => buffer,
+        _ => ptr::null_mut(),
+    }
+}
+
+/// Frees memory returned by [`allocate_pool`].
+unsafe fn free_pool(buffer: *mut u8) {
+    let services = BOOT_SERVICES.load(Ordering::Acquire);
+    if !services.is_null() {
+        ((*services).free_pool)(buffer);
+    }
+}
+
+/// A global allocator serving allocations from the UEFI pool.
+///
+/// Allocations aligned past the 8 bytes the pool guarantees are padded, with the pool address
+/// kept in the word before the returned one.
+pub struct PoolAllocator;
+
+unsafe impl GlobalAlloc for PoolAllocator {
+    unsafe fn alloc(&self, layout: Layout) -> *mut u8 {
+        if layout.align() <= POOL_ALIGN {
+            return allocate_pool(layout.size());
+        }
+        let base = allocate_pool(layout.size() + layout.align() + core::mem::size_of::<usize>());
+        if base.is_null() {
+            return base;
+        }
+        let offset = (base as usize + core::mem::size_of::<usize>()).next_multiple_of(layout.align()) - base as usize;
+        let aligned = base.add(offset);
+        aligned.cast::<*mut u8>().sub(1).write(base);
+        aligned
+    }
+
+    unsafe fn dealloc(&self, address: *mut u8, layout: Layout) {
+        if layout.align() <= POOL_ALIGN {
+            free_pool(address);
+        } else {
+            free_pool(address.cast::<*mut u8>().sub(1).read());
+        }
+    }
+}
+
+/// Runs `f` with the write-protect bit of `CR0` cleared, so that read-only pages of the identity map can be
+/// written, as firmware enforcing `EFI_MEMORY_RO` on code and protocol data requires.
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+pub unsafe fn without_write_protect<R>(f: impl FnOnce() -> R) -> R {
+    const WP: usize = 1 << 16;
+    let cr0: usize;
+    core::arch::asm!("mov {}, cr0", out(reg) cr0, options(nomem, nostack, preserves_flags));
+    core::arch::asm!("mov cr0, {}", in(reg) cr0 & !WP, options(nostack, preserves_flags));
+    let result = f();
+    core::arch::asm!("mov cr0, {}", in(reg) cr0, options(nostack, preserves_flags));
+    result
+}
+
+/// Hooks a protocol interface by patching its function pointers in place, so every caller that looked
+/// the protocol up is affected.
+///
+/// Dropping the hook restores every function pointer.
+pub struct ProtocolHook {
+    /// First patched function pointer of the interface.
+    functions: *mut usize,
+    /// Function pointers at the time the hook was created.
+    original: Box<[usize]>,
+    /// Whether writes clear the write-protect bit of `CR0`.
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    clear_write_protect: bool,
+}
+
+impl Drop for ProtocolHook {
+    /// Restoring the original function pointers.
+    fn drop(&mut self) {
+        unsafe { self.restore_all_methods() }
+    }
+}
+
+impl ProtocolHook {
+    /// Creates a hook for the `count` function pointers of the interface at `interface`, starting `offset`
+    /// bytes in to skip leading fields such as a `Revision`.
+    pub unsafe fn new(interface: *mut c_void, offset: usize, count: usize) -> Self {
+        let functions = interface.cast::<u8>().add(offset).cast::<usize>();
+        Self {
+            functions,
+            original: core::slice::from_raw_parts(functions, count).into(),
+            #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+            clear_write_protect: false,
+        }
+    }
+
+    /// Makes writes clear the write-protect bit of `CR0`, for interfaces in pages the firmware maps read-only.
+    #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+    pub fn clear_write_protect(mut self) -> Self {
+        self.clear_write_protect = true;
+        self
+    }
+
+    unsafe fn entry(&self, id: usize) -> &AtomicUsize {
+        assert!(id < self.len(), "method index out of bounds");
+        AtomicUsize::from_ptr(self.functions.add(id))
+    }
+
+    unsafe fn write(&self, id: usize, func: usize) {
+        let entry = self.entry(id);
+        #[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+        if self.clear_write_protect {
+            return without_write_protect(|| entry.store(func, Ordering::SeqCst));
+        }
+        entry.store(func, Ordering::SeqCst);
+    }
+
+    /// Returns the number of function pointers covered by the hook.
+    pub fn len(&self) -> usize {
+        self.original.len()
+    }
+
+    /// Returns `true` if the hook covers no function pointers.
+    pub fn is_empty(&self) -> bool {
+        self.original.is_empty()
+    }
+
+    /// Returns the original method address at the specified index in the interface.
+    pub fn get_original_method(&self, id: usize) -> usize {
+        self.original[id]
+    }
+
+    /// Returns the method address currently stored at the specified index in the interface.
+    pub unsafe fn get_replaced_method(&self, id: usize) -> usize {
+        self.entry(id).load(Ordering::SeqCst)
+    }
+
+    /// Hooks the method at the specified index in the interface with a new function address.
+    pub unsafe fn replace_method(&self, id: usize, func: usize) {
+        self.write(id, func);
+    }
+
+    /// Restores the original method at the specified index in the interface.
+    pub unsafe fn restore_method(&self, id: usize) {
+        self.write(id, self.original[id]);
+    }
+
+    /// Restores all methods in the interface to their original address.
+    pub unsafe fn restore_all_methods(&self) {
+        for id in 0..self.len() {
+            self.restore_method(id);
+        }
+    }
+
+    /// Returns the original method of the slot.
+    pub unsafe fn get_original<F: FnPtr>(&self, slot: Slot<F>) -> F {
+        F::from_address(self.get_original_method(slot.index()))
+    }
+
+    /// Hooks the method of the slot with a new function.
+    pub unsafe fn replace<F: FnPtr>(&self, slot: Slot<F>, func: F) {
+        self.replace_method(slot.index(), func.to_address());
+    }
+
+    /// Restores the original method of the slot.
+    pub unsafe fn restore<F>(&self, slot: Slot<F>) {
+        self.restore_method(slot.index());
+    }
+}
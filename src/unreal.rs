@@ -0,0 +1,246 @@
+//! Helpers for Unreal Engine `UObject`-derived classes.
+//!
+//! Offsets default to the UE4/UE5 layout of the current pointer width
+//! and can be overridden for customized engine builds.
+
+use std::collections::HashMap;
+use std::ffi::c_void;
+
+use crate::pattern::Pattern;
+use crate::VTableHook;
+
+/// Offsets of the `UObjectBase` fields used by the helpers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectLayout {
+    /// Offset of `ClassPrivate`.
+    pub class: usize,
+    /// Offset of `UStruct::SuperStruct` inside a `UClass`, used for inheritance checks.
+    pub super_struct: Option<usize>,
+}
+
+impl Default for ObjectLayout {
+    fn default() -> Self {
+        let ptr = std::mem::size_of::<usize>();
+        Self {
+            class: 8 + ptr,
+            super_struct: None,
+        }
+    }
+}
+
+/// Layout of `FUObjectItem` entries in the global object array.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ObjectItemLayout {
+    /// Size of one `FUObjectItem`.
+    pub size: usize,
+    /// Offset of `SerialNumber`.
+    pub serial_number: usize,
+    /// Number of items per chunk, `None` for the flat array of older engine versions.
+    pub chunk_size: Option<usize>,
+}
+
+impl Default for ObjectItemLayout {
+    fn default() -> Self {
+        let ptr = std::mem::size_of::<usize>();
+        Self {
+            size: (ptr + 12).next_multiple_of(ptr),
+            serial_number: ptr + 8,
+            chunk_size: Some(64 * 1024),
+        }
+    }
+}
+
+/// Reference to an object in the global object array that survives garbage collection.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct WeakObject {
+    /// Index of the object in the array.
+    pub index: usize,
+    /// Serial number of the item when the reference was taken.
+    pub serial_number: i32,
+}
+
+/// View over `TUObjectArray` (commonly called `GObjects`).
+#[derive(Debug, Clone, Copy)]
+pub struct ObjectArray {
+    /// Address of the `TUObjectArray`.
+    address: usize,
+    item_layout: ObjectItemLayout,
+}
+
+impl ObjectArray {
+    /// Creates a view over the `TUObjectArray` at `address`.
+    pub unsafe fn new(address: usize, item_layout: ObjectItemLayout) -> Self {
+        Self { address, item_layout }
+    }
+
+    /// Returns the number of used items.
+    pub unsafe fn len(&self) -> usize {
+        let ptr = std::mem::size_of::<usize>();
+        let offset = match self.item_layout.chunk_size {
+            // Objects, PreAllocatedObjects, MaxElements, NumElements.
+            Some(_) => ptr * 2 + 4,
+            // Objects, MaxElements, NumElements.
+            None => ptr + 4,
+        };
+        read::<i32>(self.address + offset).max(0) as usize
+    }
+
+    /// Returns `true` if there are no used items.
+    pub unsafe fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Returns the address of the `FUObjectItem` at `index`.
+    unsafe fn item(&self, index: usize) -> Option<usize> {
+        if index >= self.len() {
+            return None;
+        }
+        let objects = read::<usize>(self.address);
+        let item = match self.item_layout.chunk_size {
+            Some(chunk_size) => {
+                let chunk = read::<usize>(objects + index / chunk_size * std::mem::size_of::<usize>());
+                (chunk != 0).then_some(chunk + index % chunk_size * self.item_layout.size)?
+            }
+            None => objects + index * self.item_layout.size,
+        };
+        Some(item)
+    }
+
+    /// Returns the object at `index`.
+    pub unsafe fn get(&self, index: usize) -> Option<*mut c_void> {
+        let object = read::<*mut c_void>(self.item(index)?);
+        (!object.is_null()).then_some(object)
+    }
+
+    /// Returns a weak reference to the object at `index`.
+    pub unsafe fn weak(&self, index: usize) -> Option<WeakObject> {
+        self.get(index)?;
+        let serial_number = read::<i32>(self.item(index)? + self.item_layout.serial_number);
+        Some(WeakObject { index, serial_number })
+    }
+
+    /// Resolves a weak reference, returning `None` if the object was garbage collected
+    /// and its slot was reused by another object.
+    pub unsafe fn resolve(&self, weak: &WeakObject) -> Option<*mut c_void> {
+        let current = self.weak(weak.index)?;
+        (current.serial_number == weak.serial_number).then(|| self.get(weak.index)).flatten()
+    }
+
+    /// Iterates over all live objects together with their index.
+    pub unsafe fn iter(&self) -> impl Iterator<Item = (usize, *mut c_void)> + '_ {
+        (0..self.len()).filter_map(move |index| unsafe { self.get(index).map(|object| (index, object)) })
+    }
+}
+
+/// Returns the `UClass` of the object.
+pub unsafe fn class_of(object: *const c_void, layout: &ObjectLayout) -> usize {
+    read::<usize>(object as usize + layout.class)
+}
+
+/// Returns `true` if the object is an instance of `class` or, when `SuperStruct` is known, of a subclass.
+pub unsafe fn is_a(object: *const c_void, class: usize, layout: &ObjectLayout) -> bool {
+    let mut current = class_of(object, layout);
+    while current != 0 {
+        if current == class {
+            return true;
+        }
+        match layout.super_struct {
+            Some(offset) => current = read::<usize>(current + offset),
+            None => break,
+        }
+    }
+    false
+}
+
+/// Returns the index of the first of `count` methods of the object whose code starts with `signature`.
+///
+/// Commonly used to locate `ProcessEvent`, whose index changes between engine versions.
+pub unsafe fn find_method_by_signature(object: *const c_void, count: usize, signature: &Pattern) -> Option<usize> {
+    let vtable = read::<*const usize>(object as usize);
+    (0..count).find(|&id| {
+        let method = *vtable.add(id);
+        method != 0 && signature.matches_at(method)
+    })
+}
+
+/// Installs per-instance hooks on every object of a class found in the global object array.
+///
+/// Call [`ClassHooks::update`] periodically (e.g. from a hooked `ProcessEvent` or `Present`)
+/// to pick up newly constructed objects and to drop hooks of garbage collected ones.
+pub struct ClassHooks {
+    class: usize,
+    layout: ObjectLayout,
+    count: usize,
+    replacements: Vec<(usize, usize)>,
+    hooks: HashMap<usize, (WeakObject, VTableHook<*mut c_void>)>,
+}
+
+impl ClassHooks {
+    /// Creates an empty set of hooks for instances of `class` whose VTables have `count` methods.
+    pub fn new(class: usize, count: usize, layout: ObjectLayout) -> Self {
+        Self {
+            class,
+            layout,
+            count,
+            replacements: Vec::new(),
+            hooks: HashMap::new(),
+        }
+    }
+
+    /// Adds a replacement installed on every instance.
+    pub unsafe fn replace_method(&mut self, id: usize, func: usize) {
+        self.replacements.push((id, func));
+        for (_, hook) in self.hooks.values() {
+            hook.replace_method(id, func);
+        }
+    }
+
+    /// Synchronizes the hooks with the global object array.
+    pub unsafe fn update(&mut self, objects: &ObjectArray) {
+        let collected = self
+            .hooks
+            .iter()
+            .filter(|(_, (weak, hook))| objects.resolve(weak) != Some(*hook.object()))
+            .map(|(&index, _)| index)
+            .collect::<Vec<_>>();
+        for index in collected {
+            // The object memory is gone, so the original VTable must not be written back.
+            if let Some((_, hook)) = self.hooks.remove(&index) {
+                hook.detach();
+            }
+        }
+
+        for (index, object) in objects.iter() {
+            if self.hooks.contains_key(&index) || !is_a(object, self.class, &self.layout) {
+                continue;
+            }
+            let Some(weak) = objects.weak(index) else {
+                continue;
+            };
+            let hook = VTableHook::with_count(object, self.count);
+            for &(id, func) in &self.replacements {
+                hook.replace_method(id, func);
+            }
+            self.hooks.insert(index, (weak, hook));
+        }
+    }
+
+    /// Returns the number of hooked instances.
+    pub fn len(&self) -> usize {
+        self.hooks.len()
+    }
+
+    /// Returns `true` if no instances are hooked.
+    pub fn is_empty(&self) -> bool {
+        self.hooks.is_empty()
+    }
+
+    /// Iterates over the hooked instances.
+    pub fn iter(&self) -> impl Iterator<Item = &VTableHook<*mut c_void>> {
+        self.hooks.values().map(|(_, hook)| hook)
+    }
+}
+
+unsafe fn read<V: Copy>(address: usize) -> V {
+    std::ptr::read_unaligned(address as *const V)
+}
@@ -0,0 +1,167 @@
+//! Vectored exception handler shared by the exception-based backends.
+//!
+//! Backends register redirects from an original method to its replacement. When a thread
+//! stops at a redirected method, [`enter`] swaps the return address for a no-access landing
+//! page and resumes in the replacement. Returning faults on the landing page, which resumes
+//! at the real return address. Until then, calls to the same method made by the replacement
+//! on that thread reach the original.
+
+use std::cell::RefCell;
+use std::collections::HashMap;
+use std::sync::{Mutex, MutexGuard, OnceLock};
+
+use windows_sys::Win32::Foundation::{STATUS_ACCESS_VIOLATION, STATUS_GUARD_PAGE_VIOLATION, STATUS_SINGLE_STEP};
+use windows_sys::Win32::System::Diagnostics::Debug::{
+    AddVectoredExceptionHandler, CONTEXT, EXCEPTION_CONTINUE_EXECUTION, EXCEPTION_CONTINUE_SEARCH,
+    EXCEPTION_POINTERS, EXCEPTION_RECORD,
+};
+
+use crate::backend::{hardware_breakpoint, page_guard};
+use crate::sys::{self, Protection};
+
+/// Handles an exception raised for a backend, returning `true` if execution can continue.
+type Handler = unsafe fn(&EXCEPTION_RECORD, &mut CONTEXT) -> bool;
+
+/// Backend handlers, tried in order.
+const HANDLERS: &[Handler] = &[page_guard::handle, hardware_breakpoint::handle];
+
+/// Trap flag of EFLAGS, raising a single-step exception after the next instruction.
+const TRAP_FLAG: u32 = 0x100;
+
+#[derive(Default)]
+struct State {
+    /// Original method addresses mapped to their replacements.
+    redirects: HashMap<usize, usize>,
+}
+
+unsafe impl Send for State {}
+
+static STATE: Mutex<Option<State>> = Mutex::new(None);
+
+/// Address of the landing page, allocated once the handler is installed.
+static LANDING: OnceLock<usize> = OnceLock::new();
+
+thread_local! {
+    /// Redirected methods running on this thread with their real return addresses.
+    static ACTIVE: RefCell<Vec<(usize, usize)>> = const { RefCell::new(Vec::new()) };
+}
+
+fn with_state<R>(f: impl FnOnce(&mut State) -> R) -> R {
+    let mut state: MutexGuard<'static, Option<State>> = STATE.lock().unwrap_or_else(|e| e.into_inner());
+    f(state.get_or_insert_with(State::default))
+}
+
+/// Installs the exception handler and allocates the landing page.
+///
+/// Both stay for the rest of the process: a thread may still be inside a replacement after
+/// its backend is dropped, and returning from it faults on the landing page.
+pub(crate) fn acquire() {
+    LANDING.get_or_init(|| unsafe {
+        let landing = sys::alloc_pages(sys::page_size(), Protection::NoAccess).expect("failed to allocate landing page");
+        AddVectoredExceptionHandler(1, Some(handler));
+        landing
+    });
+}
+
+/// Redirects calls reaching `target` to `replacement`.
+pub(crate) fn redirect(target: usize, replacement: usize) {
+    with_state(|state| state.redirects.insert(target, replacement));
+}
+
+/// Removes the redirect of `target`.
+pub(crate) fn remove(target: usize) {
+    with_state(|state| state.redirects.remove(&target));
+}
+
+/// Enters the replacement if the thread stopped at the first instruction of a redirected method
+/// that isn't already running on this thread.
+pub(crate) unsafe fn enter(context: &mut CONTEXT) -> bool {
+    let target = *ip(context);
+    let Some(replacement) = with_state(|state| state.redirects.get(&target).copied()) else {
+        return false;
+    };
+    let Some(&landing) = LANDING.get() else {
+        return false;
+    };
+
+    let active = ACTIVE.with_borrow(|active| active.iter().any(|&(method, _)| method == target));
+    if active {
+        return false;
+    }
+
+    let return_address = *sp(context) as *mut usize;
+    ACTIVE.with_borrow_mut(|active| active.push((target, *return_address)));
+    *return_address = landing;
+    *ip(context) = replacement;
+    true
+}
+
+/// Resumes at the real return address when a replacement returns to the landing page.
+unsafe fn leave(record: &EXCEPTION_RECORD, context: &mut CONTEXT) -> bool {
+    if LANDING.get() != Some(&(record.ExceptionAddress as usize)) {
+        return false;
+    }
+    match ACTIVE.with_borrow_mut(|active| active.pop()) {
+        Some((_, return_address)) => {
+            *ip(context) = return_address;
+            true
+        }
+        None => false,
+    }
+}
+
+/// Returns `true` if the exception is one the backends raise.
+fn is_backend_exception(code: i32) -> bool {
+    matches!(code, STATUS_ACCESS_VIOLATION | STATUS_GUARD_PAGE_VIOLATION | STATUS_SINGLE_STEP)
+}
+
+unsafe extern "system" fn handler(info: *mut EXCEPTION_POINTERS) -> i32 {
+    let record = &*(*info).ExceptionRecord;
+    let context = &mut *(*info).ContextRecord;
+    if !is_backend_exception(record.ExceptionCode) {
+        return EXCEPTION_CONTINUE_SEARCH;
+    }
+
+    if record.ExceptionCode == STATUS_ACCESS_VIOLATION && leave(record, context) {
+        return EXCEPTION_CONTINUE_EXECUTION;
+    }
+    for handle in HANDLERS {
+        if handle(record, context) {
+            return EXCEPTION_CONTINUE_EXECUTION;
+        }
+    }
+    EXCEPTION_CONTINUE_SEARCH
+}
+
+/// Sets or clears the trap flag of the thread.
+pub(crate) fn set_trap_flag(context: &mut CONTEXT, enabled: bool) {
+    if enabled {
+        context.EFlags |= TRAP_FLAG;
+    } else {
+        context.EFlags &= !TRAP_FLAG;
+    }
+}
+
+/// Returns the instruction pointer of the thread.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn ip(context: &mut CONTEXT) -> &mut usize {
+    unsafe { &mut *(std::ptr::addr_of_mut!(context.Rip) as *mut usize) }
+}
+
+/// Returns the stack pointer of the thread.
+#[cfg(target_arch = "x86_64")]
+fn sp(context: &mut CONTEXT) -> &mut usize {
+    unsafe { &mut *(std::ptr::addr_of_mut!(context.Rsp) as *mut usize) }
+}
+
+/// Returns the instruction pointer of the thread.
+#[cfg(target_arch = "x86")]
+pub(crate) fn ip(context: &mut CONTEXT) -> &mut usize {
+    unsafe { &mut *(std::ptr::addr_of_mut!(context.Eip) as *mut usize) }
+}
+
+/// Returns the stack pointer of the thread.
+#[cfg(target_arch = "x86")]
+fn sp(context: &mut CONTEXT) -> &mut usize {
+    unsafe { &mut *(std::ptr::addr_of_mut!(context.Esp) as *mut usize) }
+}
@@ -0,0 +1,327 @@
+//! Hooking of Vulkan dispatch tables.
+//!
+//! Every dispatchable handle (`VkInstance`, `VkPhysicalDevice`, `VkDevice`, `VkQueue`, `VkCommandBuffer`)
+//! starts with a pointer to the loader's dispatch table, so it can be hooked exactly like a VTable. Physical
+//! devices point at the table of their instance, queues and command buffers at the table of their device,
+//! but hooking one handle only affects calls made with that handle.
+//!
+//! Both tables begin with the Vulkan 1.0 commands of their level in `vk.xml` order, which is stable across
+//! loader versions; the instance table puts the loader's `vk_layerGetPhysicalDeviceProcAddr` in front of
+//! them. Extension commands follow at loader-specific positions; those of devices can be located with
+//! [`DispatchTableHook::find_command`].
+
+use std::ffi::{c_char, c_void, CStr};
+
+use crate::slot::{FnPtr, Slot};
+use crate::{HookBackend, VTableHook};
+
+pub type VkInstance = *mut c_void;
+pub type VkPhysicalDevice = *mut c_void;
+pub type VkDevice = *mut c_void;
+pub type VkQueue = *mut c_void;
+pub type VkCommandBuffer = *mut c_void;
+
+pub type VkBuffer = u64;
+pub type VkBufferView = u64;
+pub type VkCommandPool = u64;
+pub type VkDescriptorPool = u64;
+pub type VkDescriptorSet = u64;
+pub type VkDescriptorSetLayout = u64;
+pub type VkDeviceMemory = u64;
+pub type VkEvent = u64;
+pub type VkFence = u64;
+pub type VkFramebuffer = u64;
+pub type VkImage = u64;
+pub type VkImageView = u64;
+pub type VkPipeline = u64;
+pub type VkPipelineCache = u64;
+pub type VkPipelineLayout = u64;
+pub type VkQueryPool = u64;
+pub type VkRenderPass = u64;
+pub type VkSampler = u64;
+pub type VkSemaphore = u64;
+pub type VkShaderModule = u64;
+
+pub type VkBool32 = u32;
+pub type VkDeviceSize = u64;
+pub type VkFlags = u32;
+pub type VkResult = i32;
+
+/// Generic function pointer returned by `vkGetDeviceProcAddr`.
+#[allow(non_camel_case_types)]
+pub type PFN_vkVoidFunction = Option<unsafe extern "system" fn()>;
+
+/// Value of the first 8 bytes of device dispatch tables created by Vulkan-Loader 1.3 and newer.
+pub const DEVICE_DISPATCH_MAGIC: u64 = 0x10AD_ED04_0410_ADED;
+
+/// Declares typed slots for consecutive dispatch table entries.
+///
+/// Structure arguments are passed as untyped pointers, cast them to the bindings you use.
+///
+/// ```rust,ignore
+/// vmt_hook::dispatch_layout! {
+///     pub mod my_layout {
+///         QUEUE_PRESENT_KHR = "vkQueuePresentKHR": fn(VkQueue, *const c_void) -> VkResult;
+///     }
+/// }
+/// ```
+#[macro_export]
+macro_rules! dispatch_layout {
+    (
+        $(#[$attr:meta])*
+        $vis:vis mod $module:ident {
+            $($name:ident = $command:literal: fn($($arg:ty),*) $(-> $ret:ty)?;)*
+        }
+    ) => {
+        $(#[$attr])*
+        #[allow(non_camel_case_types)]
+        $vis mod $module {
+            #[allow(unused_imports)]
+            use super::*;
+
+            #[allow(clippy::upper_case_acronyms)]
+            enum Index {
+                $($name,)*
+            }
+
+            $(
+                #[doc = concat!("`", $command, "`.")]
+                pub const $name: $crate::slot::Slot<unsafe extern "system" fn($($arg),*) $(-> $ret)?> =
+                    $crate::slot::Slot::new(Index::$name as usize);
+            )*
+
+            /// Names of the commands in table order.
+            pub const COMMANDS: &[&str] = &[$($command),*];
+        }
+    };
+}
+
+dispatch_layout! {
+    /// Vulkan 1.0 instance-level commands at the start of the instance dispatch table, shared by the instance's
+    /// physical devices.
+    pub mod instance {
+        GET_PHYSICAL_DEVICE_PROC_ADDR = "vk_layerGetPhysicalDeviceProcAddr": fn(VkInstance, *const c_char) -> PFN_vkVoidFunction;
+        CREATE_INSTANCE = "vkCreateInstance": fn(*const c_void, *const c_void, *mut VkInstance) -> VkResult;
+        DESTROY_INSTANCE = "vkDestroyInstance": fn(VkInstance, *const c_void);
+        ENUMERATE_PHYSICAL_DEVICES = "vkEnumeratePhysicalDevices": fn(VkInstance, *mut u32, *mut VkPhysicalDevice) -> VkResult;
+        GET_PHYSICAL_DEVICE_FEATURES = "vkGetPhysicalDeviceFeatures": fn(VkPhysicalDevice, *mut c_void);
+        GET_PHYSICAL_DEVICE_FORMAT_PROPERTIES = "vkGetPhysicalDeviceFormatProperties": fn(VkPhysicalDevice, i32, *mut c_void);
+        GET_PHYSICAL_DEVICE_IMAGE_FORMAT_PROPERTIES = "vkGetPhysicalDeviceImageFormatProperties": fn(VkPhysicalDevice, i32, i32, i32, VkFlags, VkFlags, *mut c_void) -> VkResult;
+        GET_PHYSICAL_DEVICE_PROPERTIES = "vkGetPhysicalDeviceProperties": fn(VkPhysicalDevice, *mut c_void);
+        GET_PHYSICAL_DEVICE_QUEUE_FAMILY_PROPERTIES = "vkGetPhysicalDeviceQueueFamilyProperties": fn(VkPhysicalDevice, *mut u32, *mut c_void);
+        GET_PHYSICAL_DEVICE_MEMORY_PROPERTIES = "vkGetPhysicalDeviceMemoryProperties": fn(VkPhysicalDevice, *mut c_void);
+        GET_INSTANCE_PROC_ADDR = "vkGetInstanceProcAddr": fn(VkInstance, *const c_char) -> PFN_vkVoidFunction;
+        CREATE_DEVICE = "vkCreateDevice": fn(VkPhysicalDevice, *const c_void, *const c_void, *mut VkDevice) -> VkResult;
+        ENUMERATE_INSTANCE_EXTENSION_PROPERTIES = "vkEnumerateInstanceExtensionProperties": fn(*const c_char, *mut u32, *mut c_void) -> VkResult;
+        ENUMERATE_DEVICE_EXTENSION_PROPERTIES = "vkEnumerateDeviceExtensionProperties": fn(VkPhysicalDevice, *const c_char, *mut u32, *mut c_void) -> VkResult;
+        ENUMERATE_INSTANCE_LAYER_PROPERTIES = "vkEnumerateInstanceLayerProperties": fn(*mut u32, *mut c_void) -> VkResult;
+        ENUMERATE_DEVICE_LAYER_PROPERTIES = "vkEnumerateDeviceLayerProperties": fn(VkPhysicalDevice, *mut u32, *mut c_void) -> VkResult;
+        GET_PHYSICAL_DEVICE_SPARSE_IMAGE_FORMAT_PROPERTIES = "vkGetPhysicalDeviceSparseImageFormatProperties": fn(VkPhysicalDevice, i32, i32, VkFlags, VkFlags, i32, *mut u32, *mut c_void);
+    }
+}
+
+dispatch_layout! {
+    /// Vulkan 1.0 device-level commands at the start of the device dispatch table.
+    pub mod device {
+        GET_DEVICE_PROC_ADDR = "vkGetDeviceProcAddr": fn(VkDevice, *const c_char) -> PFN_vkVoidFunction;
+        DESTROY_DEVICE = "vkDestroyDevice": fn(VkDevice, *const c_void);
+        GET_DEVICE_QUEUE = "vkGetDeviceQueue": fn(VkDevice, u32, u32, *mut VkQueue);
+        QUEUE_SUBMIT = "vkQueueSubmit": fn(VkQueue, u32, *const c_void, VkFence) -> VkResult;
+        QUEUE_WAIT_IDLE = "vkQueueWaitIdle": fn(VkQueue) -> VkResult;
+        DEVICE_WAIT_IDLE = "vkDeviceWaitIdle": fn(VkDevice) -> VkResult;
+        ALLOCATE_MEMORY = "vkAllocateMemory": fn(VkDevice, *const c_void, *const c_void, *mut VkDeviceMemory) -> VkResult;
+        FREE_MEMORY = "vkFreeMemory": fn(VkDevice, VkDeviceMemory, *const c_void);
+        MAP_MEMORY = "vkMapMemory": fn(VkDevice, VkDeviceMemory, VkDeviceSize, VkDeviceSize, VkFlags, *mut *mut c_void) -> VkResult;
+        UNMAP_MEMORY = "vkUnmapMemory": fn(VkDevice, VkDeviceMemory);
+        FLUSH_MAPPED_MEMORY_RANGES = "vkFlushMappedMemoryRanges": fn(VkDevice, u32, *const c_void) -> VkResult;
+        INVALIDATE_MAPPED_MEMORY_RANGES = "vkInvalidateMappedMemoryRanges": fn(VkDevice, u32, *const c_void) -> VkResult;
+        GET_DEVICE_MEMORY_COMMITMENT = "vkGetDeviceMemoryCommitment": fn(VkDevice, VkDeviceMemory, *mut VkDeviceSize);
+        BIND_BUFFER_MEMORY = "vkBindBufferMemory": fn(VkDevice, VkBuffer, VkDeviceMemory, VkDeviceSize) -> VkResult;
+        BIND_IMAGE_MEMORY = "vkBindImageMemory": fn(VkDevice, VkImage, VkDeviceMemory, VkDeviceSize) -> VkResult;
+        GET_BUFFER_MEMORY_REQUIREMENTS = "vkGetBufferMemoryRequirements": fn(VkDevice, VkBuffer, *mut c_void);
+        GET_IMAGE_MEMORY_REQUIREMENTS = "vkGetImageMemoryRequirements": fn(VkDevice, VkImage, *mut c_void);
+        GET_IMAGE_SPARSE_MEMORY_REQUIREMENTS = "vkGetImageSparseMemoryRequirements": fn(VkDevice, VkImage, *mut u32, *mut c_void);
+        QUEUE_BIND_SPARSE = "vkQueueBindSparse": fn(VkQueue, u32, *const c_void, VkFence) -> VkResult;
+        CREATE_FENCE = "vkCreateFence": fn(VkDevice, *const c_void, *const c_void, *mut VkFence) -> VkResult;
+        DESTROY_FENCE = "vkDestroyFence": fn(VkDevice, VkFence, *const c_void);
+        RESET_FENCES = "vkResetFences": fn(VkDevice, u32, *const VkFence) -> VkResult;
+        GET_FENCE_STATUS = "vkGetFenceStatus": fn(VkDevice, VkFence) -> VkResult;
+        WAIT_FOR_FENCES = "vkWaitForFences": fn(VkDevice, u32, *const VkFence, VkBool32, u64) -> VkResult;
+        CREATE_SEMAPHORE = "vkCreateSemaphore": fn(VkDevice, *const c_void, *const c_void, *mut VkSemaphore) -> VkResult;
+        DESTROY_SEMAPHORE = "vkDestroySemaphore": fn(VkDevice, VkSemaphore, *const c_void);
+        CREATE_EVENT = "vkCreateEvent": fn(VkDevice, *const c_void, *const c_void, *mut VkEvent) -> VkResult;
+        DESTROY_EVENT = "vkDestroyEvent": fn(VkDevice, VkEvent, *const c_void);
+        GET_EVENT_STATUS = "vkGetEventStatus": fn(VkDevice, VkEvent) -> VkResult;
+        SET_EVENT = "vkSetEvent": fn(VkDevice, VkEvent) -> VkResult;
+        RESET_EVENT = "vkResetEvent": fn(VkDevice, VkEvent) -> VkResult;
+        CREATE_QUERY_POOL = "vkCreateQueryPool": fn(VkDevice, *const c_void, *const c_void, *mut VkQueryPool) -> VkResult;
+        DESTROY_QUERY_POOL = "vkDestroyQueryPool": fn(VkDevice, VkQueryPool, *const c_void);
+        GET_QUERY_POOL_RESULTS = "vkGetQueryPoolResults": fn(VkDevice, VkQueryPool, u32, u32, usize, *mut c_void, VkDeviceSize, VkFlags) -> VkResult;
+        CREATE_BUFFER = "vkCreateBuffer": fn(VkDevice, *const c_void, *const c_void, *mut VkBuffer) -> VkResult;
+        DESTROY_BUFFER = "vkDestroyBuffer": fn(VkDevice, VkBuffer, *const c_void);
+        CREATE_BUFFER_VIEW = "vkCreateBufferView": fn(VkDevice, *const c_void, *const c_void, *mut VkBufferView) -> VkResult;
+        DESTROY_BUFFER_VIEW = "vkDestroyBufferView": fn(VkDevice, VkBufferView, *const c_void);
+        CREATE_IMAGE = "vkCreateImage": fn(VkDevice, *const c_void, *const c_void, *mut VkImage) -> VkResult;
+        DESTROY_IMAGE = "vkDestroyImage": fn(VkDevice, VkImage, *const c_void);
+        GET_IMAGE_SUBRESOURCE_LAYOUT = "vkGetImageSubresourceLayout": fn(VkDevice, VkImage, *const c_void, *mut c_void);
+        CREATE_IMAGE_VIEW = "vkCreateImageView": fn(VkDevice, *const c_void, *const c_void, *mut VkImageView) -> VkResult;
+        DESTROY_IMAGE_VIEW = "vkDestroyImageView": fn(VkDevice, VkImageView, *const c_void);
+        CREATE_SHADER_MODULE = "vkCreateShaderModule": fn(VkDevice, *const c_void, *const c_void, *mut VkShaderModule) -> VkResult;
+        DESTROY_SHADER_MODULE = "vkDestroyShaderModule": fn(VkDevice, VkShaderModule, *const c_void);
+        CREATE_PIPELINE_CACHE = "vkCreatePipelineCache": fn(VkDevice, *const c_void, *const c_void, *mut VkPipelineCache) -> VkResult;
+        DESTROY_PIPELINE_CACHE = "vkDestroyPipelineCache": fn(VkDevice, VkPipelineCache, *const c_void);
+        GET_PIPELINE_CACHE_DATA = "vkGetPipelineCacheData": fn(VkDevice, VkPipelineCache, *mut usize, *mut c_void) -> VkResult;
+        MERGE_PIPELINE_CACHES = "vkMergePipelineCaches": fn(VkDevice, VkPipelineCache, u32, *const VkPipelineCache) -> VkResult;
+        CREATE_GRAPHICS_PIPELINES = "vkCreateGraphicsPipelines": fn(VkDevice, VkPipelineCache, u32, *const c_void, *const c_void, *mut VkPipeline) -> VkResult;
+        CREATE_COMPUTE_PIPELINES = "vkCreateComputePipelines": fn(VkDevice, VkPipelineCache, u32, *const c_void, *const c_void, *mut VkPipeline) -> VkResult;
+        DESTROY_PIPELINE = "vkDestroyPipeline": fn(VkDevice, VkPipeline, *const c_void);
+        CREATE_PIPELINE_LAYOUT = "vkCreatePipelineLayout": fn(VkDevice, *const c_void, *const c_void, *mut VkPipelineLayout) -> VkResult;
+        DESTROY_PIPELINE_LAYOUT = "vkDestroyPipelineLayout": fn(VkDevice, VkPipelineLayout, *const c_void);
+        CREATE_SAMPLER = "vkCreateSampler": fn(VkDevice, *const c_void, *const c_void, *mut VkSampler) -> VkResult;
+        DESTROY_SAMPLER = "vkDestroySampler": fn(VkDevice, VkSampler, *const c_void);
+        CREATE_DESCRIPTOR_SET_LAYOUT = "vkCreateDescriptorSetLayout": fn(VkDevice, *const c_void, *const c_void, *mut VkDescriptorSetLayout) -> VkResult;
+        DESTROY_DESCRIPTOR_SET_LAYOUT = "vkDestroyDescriptorSetLayout": fn(VkDevice, VkDescriptorSetLayout, *const c_void);
+        CREATE_DESCRIPTOR_POOL = "vkCreateDescriptorPool": fn(VkDevice, *const c_void, *const c_void, *mut VkDescriptorPool) -> VkResult;
+        DESTROY_DESCRIPTOR_POOL = "vkDestroyDescriptorPool": fn(VkDevice, VkDescriptorPool, *const c_void);
+        RESET_DESCRIPTOR_POOL = "vkResetDescriptorPool": fn(VkDevice, VkDescriptorPool, VkFlags) -> VkResult;
+        ALLOCATE_DESCRIPTOR_SETS = "vkAllocateDescriptorSets": fn(VkDevice, *const c_void, *mut VkDescriptorSet) -> VkResult;
+        FREE_DESCRIPTOR_SETS = "vkFreeDescriptorSets": fn(VkDevice, VkDescriptorPool, u32, *const VkDescriptorSet) -> VkResult;
+        UPDATE_DESCRIPTOR_SETS = "vkUpdateDescriptorSets": fn(VkDevice, u32, *const c_void, u32, *const c_void);
+        CREATE_FRAMEBUFFER = "vkCreateFramebuffer": fn(VkDevice, *const c_void, *const c_void, *mut VkFramebuffer) -> VkResult;
+        DESTROY_FRAMEBUFFER = "vkDestroyFramebuffer": fn(VkDevice, VkFramebuffer, *const c_void);
+        CREATE_RENDER_PASS = "vkCreateRenderPass": fn(VkDevice, *const c_void, *const c_void, *mut VkRenderPass) -> VkResult;
+        DESTROY_RENDER_PASS = "vkDestroyRenderPass": fn(VkDevice, VkRenderPass, *const c_void);
+        GET_RENDER_AREA_GRANULARITY = "vkGetRenderAreaGranularity": fn(VkDevice, VkRenderPass, *mut c_void);
+        CREATE_COMMAND_POOL = "vkCreateCommandPool": fn(VkDevice, *const c_void, *const c_void, *mut VkCommandPool) -> VkResult;
+        DESTROY_COMMAND_POOL = "vkDestroyCommandPool": fn(VkDevice, VkCommandPool, *const c_void);
+        RESET_COMMAND_POOL = "vkResetCommandPool": fn(VkDevice, VkCommandPool, VkFlags) -> VkResult;
+        ALLOCATE_COMMAND_BUFFERS = "vkAllocateCommandBuffers": fn(VkDevice, *const c_void, *mut VkCommandBuffer) -> VkResult;
+        FREE_COMMAND_BUFFERS = "vkFreeCommandBuffers": fn(VkDevice, VkCommandPool, u32, *const VkCommandBuffer);
+        BEGIN_COMMAND_BUFFER = "vkBeginCommandBuffer": fn(VkCommandBuffer, *const c_void) -> VkResult;
+        END_COMMAND_BUFFER = "vkEndCommandBuffer": fn(VkCommandBuffer) -> VkResult;
+        RESET_COMMAND_BUFFER = "vkResetCommandBuffer": fn(VkCommandBuffer, VkFlags) -> VkResult;
+        CMD_BIND_PIPELINE = "vkCmdBindPipeline": fn(VkCommandBuffer, i32, VkPipeline);
+        CMD_SET_VIEWPORT = "vkCmdSetViewport": fn(VkCommandBuffer, u32, u32, *const c_void);
+        CMD_SET_SCISSOR = "vkCmdSetScissor": fn(VkCommandBuffer, u32, u32, *const c_void);
+        CMD_SET_LINE_WIDTH = "vkCmdSetLineWidth": fn(VkCommandBuffer, f32);
+        CMD_SET_DEPTH_BIAS = "vkCmdSetDepthBias": fn(VkCommandBuffer, f32, f32, f32);
+        CMD_SET_BLEND_CONSTANTS = "vkCmdSetBlendConstants": fn(VkCommandBuffer, *const [f32; 4]);
+        CMD_SET_DEPTH_BOUNDS = "vkCmdSetDepthBounds": fn(VkCommandBuffer, f32, f32);
+        CMD_SET_STENCIL_COMPARE_MASK = "vkCmdSetStencilCompareMask": fn(VkCommandBuffer, VkFlags, u32);
+        CMD_SET_STENCIL_WRITE_MASK = "vkCmdSetStencilWriteMask": fn(VkCommandBuffer, VkFlags, u32);
+        CMD_SET_STENCIL_REFERENCE = "vkCmdSetStencilReference": fn(VkCommandBuffer, VkFlags, u32);
+        CMD_BIND_DESCRIPTOR_SETS = "vkCmdBindDescriptorSets": fn(VkCommandBuffer, i32, VkPipelineLayout, u32, u32, *const VkDescriptorSet, u32, *const u32);
+        CMD_BIND_INDEX_BUFFER = "vkCmdBindIndexBuffer": fn(VkCommandBuffer, VkBuffer, VkDeviceSize, i32);
+        CMD_BIND_VERTEX_BUFFERS = "vkCmdBindVertexBuffers": fn(VkCommandBuffer, u32, u32, *const VkBuffer, *const VkDeviceSize);
+        CMD_DRAW = "vkCmdDraw": fn(VkCommandBuffer, u32, u32, u32, u32);
+        CMD_DRAW_INDEXED = "vkCmdDrawIndexed": fn(VkCommandBuffer, u32, u32, u32, i32, u32);
+        CMD_DRAW_INDIRECT = "vkCmdDrawIndirect": fn(VkCommandBuffer, VkBuffer, VkDeviceSize, u32, u32);
+        CMD_DRAW_INDEXED_INDIRECT = "vkCmdDrawIndexedIndirect": fn(VkCommandBuffer, VkBuffer, VkDeviceSize, u32, u32);
+        CMD_DISPATCH = "vkCmdDispatch": fn(VkCommandBuffer, u32, u32, u32);
+        CMD_DISPATCH_INDIRECT = "vkCmdDispatchIndirect": fn(VkCommandBuffer, VkBuffer, VkDeviceSize);
+        CMD_COPY_BUFFER = "vkCmdCopyBuffer": fn(VkCommandBuffer, VkBuffer, VkBuffer, u32, *const c_void);
+        CMD_COPY_IMAGE = "vkCmdCopyImage": fn(VkCommandBuffer, VkImage, i32, VkImage, i32, u32, *const c_void);
+        CMD_BLIT_IMAGE = "vkCmdBlitImage": fn(VkCommandBuffer, VkImage, i32, VkImage, i32, u32, *const c_void, i32);
+        CMD_COPY_BUFFER_TO_IMAGE = "vkCmdCopyBufferToImage": fn(VkCommandBuffer, VkBuffer, VkImage, i32, u32, *const c_void);
+        CMD_COPY_IMAGE_TO_BUFFER = "vkCmdCopyImageToBuffer": fn(VkCommandBuffer, VkImage, i32, VkBuffer, u32, *const c_void);
+        CMD_UPDATE_BUFFER = "vkCmdUpdateBuffer": fn(VkCommandBuffer, VkBuffer, VkDeviceSize, VkDeviceSize, *const c_void);
+        CMD_FILL_BUFFER = "vkCmdFillBuffer": fn(VkCommandBuffer, VkBuffer, VkDeviceSize, VkDeviceSize, u32);
+        CMD_CLEAR_COLOR_IMAGE = "vkCmdClearColorImage": fn(VkCommandBuffer, VkImage, i32, *const c_void, u32, *const c_void);
+        CMD_CLEAR_DEPTH_STENCIL_IMAGE = "vkCmdClearDepthStencilImage": fn(VkCommandBuffer, VkImage, i32, *const c_void, u32, *const c_void);
+        CMD_CLEAR_ATTACHMENTS = "vkCmdClearAttachments": fn(VkCommandBuffer, u32, *const c_void, u32, *const c_void);
+        CMD_RESOLVE_IMAGE = "vkCmdResolveImage": fn(VkCommandBuffer, VkImage, i32, VkImage, i32, u32, *const c_void);
+        CMD_SET_EVENT = "vkCmdSetEvent": fn(VkCommandBuffer, VkEvent, VkFlags);
+        CMD_RESET_EVENT = "vkCmdResetEvent": fn(VkCommandBuffer, VkEvent, VkFlags);
+        CMD_WAIT_EVENTS = "vkCmdWaitEvents": fn(VkCommandBuffer, u32, *const VkEvent, VkFlags, VkFlags, u32, *const c_void, u32, *const c_void, u32, *const c_void);
+        CMD_PIPELINE_BARRIER = "vkCmdPipelineBarrier": fn(VkCommandBuffer, VkFlags, VkFlags, VkFlags, u32, *const c_void, u32, *const c_void, u32, *const c_void);
+        CMD_BEGIN_QUERY = "vkCmdBeginQuery": fn(VkCommandBuffer, VkQueryPool, u32, VkFlags);
+        CMD_END_QUERY = "vkCmdEndQuery": fn(VkCommandBuffer, VkQueryPool, u32);
+        CMD_RESET_QUERY_POOL = "vkCmdResetQueryPool": fn(VkCommandBuffer, VkQueryPool, u32, u32);
+        CMD_WRITE_TIMESTAMP = "vkCmdWriteTimestamp": fn(VkCommandBuffer, VkFlags, VkQueryPool, u32);
+        CMD_COPY_QUERY_POOL_RESULTS = "vkCmdCopyQueryPoolResults": fn(VkCommandBuffer, VkQueryPool, u32, u32, VkBuffer, VkDeviceSize, VkDeviceSize, VkFlags);
+        CMD_PUSH_CONSTANTS = "vkCmdPushConstants": fn(VkCommandBuffer, VkPipelineLayout, VkFlags, u32, u32, *const c_void);
+        CMD_BEGIN_RENDER_PASS = "vkCmdBeginRenderPass": fn(VkCommandBuffer, *const c_void, i32);
+        CMD_NEXT_SUBPASS = "vkCmdNextSubpass": fn(VkCommandBuffer, i32);
+        CMD_END_RENDER_PASS = "vkCmdEndRenderPass": fn(VkCommandBuffer);
+        CMD_EXECUTE_COMMANDS = "vkCmdExecuteCommands": fn(VkCommandBuffer, u32, *const VkCommandBuffer);
+    }
+}
+
+/// Hook of the dispatch table of a dispatchable handle.
+///
+/// Slot indices are relative to the first command, skipping the magic header of newer loaders.
+pub struct DispatchTableHook<H> {
+    hook: VTableHook<H>,
+    base: usize,
+}
+
+impl<H> DispatchTableHook<H> {
+    /// Hooks the dispatch table of the handle, copying `count` commands.
+    ///
+    /// `count` should cover every command you intend to hook, at least [`device::COMMANDS`] for device tables
+    /// and [`instance::COMMANDS`] for instance tables.
+    pub unsafe fn new(handle: H, count: usize) -> Self {
+        let table = *std::mem::transmute_copy::<H, *const *const u64>(&handle);
+        let base = if std::ptr::read_unaligned(table) == DEVICE_DISPATCH_MAGIC {
+            std::mem::size_of::<u64>() / std::mem::size_of::<usize>()
+        } else {
+            0
+        };
+
+        Self {
+            hook: VTableHook::with_count(handle, base + count),
+            base,
+        }
+    }
+
+    /// Returns the number of pointer-sized header words before the first command.
+    pub fn base(&self) -> usize {
+        self.base
+    }
+
+    /// Returns the underlying VTable hook, which is indexed including the header.
+    pub fn hook(&self) -> &VTableHook<H> {
+        &self.hook
+    }
+
+    fn slot<F>(&self, slot: Slot<F>) -> Slot<F> {
+        Slot::new(self.base + slot.index())
+    }
+
+    /// Returns the original command of the slot.
+    pub unsafe fn get_original<F: FnPtr>(&self, slot: Slot<F>) -> F {
+        self.hook.get_original(self.slot(slot))
+    }
+
+    /// Returns the replaced command of the slot.
+    pub unsafe fn get_replaced<F: FnPtr>(&self, slot: Slot<F>) -> F {
+        self.hook.get_replaced(self.slot(slot))
+    }
+
+    /// Hooks the command of the slot with a new function.
+    pub unsafe fn replace<F: FnPtr>(&self, slot: Slot<F>, func: F) {
+        self.hook.replace(self.slot(slot), func);
+    }
+
+    /// Restores the original command of the slot.
+    pub unsafe fn restore<F>(&self, slot: Slot<F>) {
+        self.hook.restore(self.slot(slot));
+    }
+
+    /// Restores all commands to their original address.
+    pub unsafe fn restore_all(&self) {
+        self.hook.restore_all_methods();
+    }
+
+    /// Returns the slot index of a device command, such as an extension command like `vkQueuePresentKHR`.
+    ///
+    /// The table of `device` is filled from its own `vkGetDeviceProcAddr`, so the index
+    /// is found by looking up the address it returns for `name` in this table.
+    pub unsafe fn find_command(&self, device: VkDevice, name: &CStr) -> Option<usize> {
+        let get_device_proc_addr = self.get_original(device::GET_DEVICE_PROC_ADDR);
+        let address = get_device_proc_addr(device, name.as_ptr())? as usize;
+        (self.base..self.hook.backend().count())
+            .find(|&id| self.hook.get_original_method(id) == address)
+            .map(|id| id - self.base)
+    }
+}
@@ -0,0 +1,125 @@
+//! Periodic integrity verification of hooked VTables from a background thread.
+//!
+//! Besides writes into the hooked VTable, checks created by [`VTableHook::integrity_check`](crate::VTableHook::integrity_check)
+//! watch the object's vptr. A destructor pointing it at base class VTables is reported as
+//! [`Violation::Destruction`] rather than tampering, so callbacks don't re-apply the hook onto a dying object.
+
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+use std::time::Duration;
+
+use crate::detect::VptrState;
+use crate::shadow::IntegrityCheck;
+use crate::Error;
+
+/// A change to a watched VTable that wasn't made by its hook.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum Violation {
+    /// The listed methods of the hooked VTable were overwritten.
+    Slots(Vec<usize>),
+    /// The object's vptr was pointed back at the original VTable.
+    Restored,
+    /// The object's vptr was pointed at another VTable.
+    Vptr {
+        /// The VTable the object points at.
+        vtable: usize,
+    },
+    /// The object's vptr was pointed at a base class VTable by its destructor. The object is dying,
+    /// so the hook must not be re-applied; the watchdog stops watching it.
+    Destruction {
+        /// The base class VTable.
+        vtable: usize,
+    },
+}
+
+struct Watched {
+    check: IntegrityCheck,
+    on_violation: Box<dyn FnMut(&Violation) + Send>,
+}
+
+/// A background thread running [`IntegrityCheck`]s at a fixed interval.
+///
+/// Checks of hooks that have been dropped are removed automatically. The thread is stopped on drop.
+pub struct Watchdog {
+    watched: Arc<Mutex<Vec<Watched>>>,
+    stop: Option<mpsc::Sender<()>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        drop(self.stop.take());
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Watchdog {
+    /// Starts a thread checking every watched VTable each `interval`.
+    pub fn spawn(interval: Duration) -> Self {
+        let watched = Arc::new(Mutex::new(Vec::new()));
+        let (stop, stopped) = mpsc::channel::<()>();
+        let thread = {
+            let watched = Arc::clone(&watched);
+            std::thread::spawn(move || {
+                while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(interval) {
+                    run(&watched);
+                }
+            })
+        };
+        Self {
+            watched,
+            stop: Some(stop),
+            thread: Some(thread),
+        }
+    }
+
+    /// Adds a VTable to watch; `on_violation` is called from the watchdog thread for every failed check.
+    pub fn watch(&self, check: IntegrityCheck, on_violation: impl FnMut(&Violation) + Send + 'static) {
+        self.watched.lock().unwrap_or_else(|e| e.into_inner()).push(Watched {
+            check,
+            on_violation: Box::new(on_violation),
+        });
+    }
+
+    /// Runs every check right away on the calling thread.
+    pub fn check_now(&self) {
+        run(&self.watched);
+    }
+
+    /// Returns the number of VTables still watched.
+    pub fn len(&self) -> usize {
+        self.watched.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    /// Returns `true` if no VTable is watched.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+fn run(watched: &Mutex<Vec<Watched>>) {
+    // Taken out of the lock so that callbacks can add more checks.
+    let mut current = std::mem::take(&mut *watched.lock().unwrap_or_else(|e| e.into_inner()));
+    current.retain_mut(|watched| {
+        if let Err(Error::Tampered(slots)) = watched.check.verify() {
+            (watched.on_violation)(&Violation::Slots(slots));
+        }
+        match watched.check.vptr_state() {
+            Some(VptrState::Destroying { vtable }) => {
+                (watched.on_violation)(&Violation::Destruction { vtable });
+                return false;
+            }
+            Some(VptrState::Foreign { vtable }) => (watched.on_violation)(&Violation::Vptr { vtable }),
+            Some(VptrState::Original) => (watched.on_violation)(&Violation::Restored),
+            Some(VptrState::Hooked) | None => {}
+        }
+        !watched.check.is_released()
+    });
+    let mut watched = watched.lock().unwrap_or_else(|e| e.into_inner());
+    current.append(&mut watched);
+    *watched = current;
+}
@@ -0,0 +1,278 @@
+//! Minimal x86 and x86_64 instruction length decoder.
+//!
+//! Only what is needed to relocate function prologues: instruction boundaries and whether an
+//! instruction depends on its own address, plus recognition of the jumps hooking libraries write.
+
+// Not every helper is used by every combination of features.
+#![allow(dead_code)]
+
+/// A decoded instruction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct Instruction {
+    /// Length in bytes.
+    pub len: usize,
+    /// `true` for relative branches and RIP-relative memory operands.
+    pub relative: bool,
+}
+
+/// Length of the jump written by [`write_jump`].
+#[cfg(target_arch = "x86_64")]
+pub(crate) const JUMP_LEN: usize = 14;
+/// Length of the jump written by [`write_jump`].
+#[cfg(target_arch = "x86")]
+pub(crate) const JUMP_LEN: usize = 5;
+
+const LONG_MODE: bool = cfg!(target_arch = "x86_64");
+
+/// An unconditional jump decoded by [`decode_jump`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Jump {
+    /// `jmp rel8` or `jmp rel32` to the address.
+    Relative(usize),
+    /// `jmp [mem]` through the pointer at the address.
+    Indirect(usize),
+    /// `mov reg, imm` followed by `jmp reg` to the address.
+    Register(usize),
+    /// `push imm` followed by `ret`, with the upper half written by `mov [rsp + 4], imm32` on x86_64.
+    PushReturn(usize),
+}
+
+/// Decodes the unconditional jump at the start of `code`, assuming it executes at address `at`.
+///
+/// A leading `endbr32`/`endbr64` and a `bnd` prefix, as found in CET-enabled PLT stubs, are skipped.
+pub(crate) fn decode_jump(mut code: &[u8], mut at: usize) -> Option<Jump> {
+    if code.starts_with(&[0xF3, 0x0F, 0x1E]) && matches!(code.get(3), Some(0xFA | 0xFB)) {
+        code = &code[4..];
+        at += 4;
+    }
+    if code.starts_with(&[0xF2]) && matches!(code.get(1), Some(0xE9 | 0xFF)) {
+        code = &code[1..];
+        at += 1;
+    }
+    let imm32 = |offset: usize| code.get(offset..offset + 4).map(|b| u32::from_le_bytes(b.try_into().unwrap()));
+    match *code.first()? {
+        // jmp rel8
+        0xEB => Some(Jump::Relative((at + 2).wrapping_add(*code.get(1)? as i8 as usize))),
+        // jmp rel32
+        0xE9 => Some(Jump::Relative((at + 5).wrapping_add(imm32(1)? as i32 as usize))),
+        // jmp [rip + disp32] or jmp [disp32]
+        0xFF if *code.get(1)? == 0x25 => Some(Jump::Indirect(if LONG_MODE {
+            (at + 6).wrapping_add(imm32(2)? as i32 as usize)
+        } else {
+            imm32(2)? as usize
+        })),
+        // push imm32; ret
+        0x68 if !LONG_MODE && *code.get(5)? == 0xC3 => Some(Jump::PushReturn(imm32(1)? as usize)),
+        // push imm32; mov dword ptr [rsp + 4], imm32; ret
+        0x68 if LONG_MODE && code.get(5..9)? == [0xC7, 0x44, 0x24, 0x04] && *code.get(13)? == 0xC3 => {
+            Some(Jump::PushReturn((imm32(1)? as u64 | (imm32(9)? as u64) << 32) as usize))
+        }
+        // mov r32, imm32; jmp r32
+        0xB8..=0xBF if !LONG_MODE && code.get(5..7)? == [0xFF, 0xE0 + (code[0] - 0xB8)] => {
+            Some(Jump::Register(imm32(1)? as usize))
+        }
+        // mov r64, imm64; jmp r64
+        rex @ (0x48 | 0x49) if LONG_MODE && (0xB8..=0xBF).contains(code.get(1)?) => {
+            let register = code[1] - 0xB8;
+            let value = u64::from_le_bytes(code.get(2..10)?.try_into().unwrap()) as usize;
+            let jump = if rex == 0x49 {
+                code.get(10..13)? == [0x41, 0xFF, 0xE0 + register]
+            } else {
+                code.get(10..12)? == [0xFF, 0xE0 + register]
+            };
+            jump.then_some(Jump::Register(value))
+        }
+        _ => None,
+    }
+}
+
+/// Writes a jump to `to` into `code`, assuming it will execute at address `at`.
+#[cfg(target_arch = "x86_64")]
+pub(crate) fn write_jump(code: &mut [u8], _at: usize, to: usize) {
+    // jmp qword ptr [rip + 0]
+    code[..6].copy_from_slice(&[0xFF, 0x25, 0, 0, 0, 0]);
+    code[6..14].copy_from_slice(&(to as u64).to_le_bytes());
+}
+
+/// Writes a jump to `to` into `code`, assuming it will execute at address `at`.
+#[cfg(target_arch = "x86")]
+pub(crate) fn write_jump(code: &mut [u8], at: usize, to: usize) {
+    // jmp rel32
+    code[0] = 0xE9;
+    code[1..5].copy_from_slice(&(to.wrapping_sub(at + 5) as u32).to_le_bytes());
+}
+
+/// Decodes the instruction at the start of `code`.
+///
+/// Returns `None` for truncated input, VEX/EVEX encodings and other unsupported opcodes.
+pub(crate) fn decode(code: &[u8]) -> Option<Instruction> {
+    let mut at = 0;
+    let mut operand16 = false;
+    let mut address16 = false;
+    let mut rex_w = false;
+
+    loop {
+        match *code.get(at)? {
+            0x66 => operand16 = true,
+            0x67 => address16 = true,
+            0x26 | 0x2E | 0x36 | 0x3E | 0x64 | 0x65 | 0xF0 | 0xF2 | 0xF3 => {}
+            _ => break,
+        }
+        at += 1;
+    }
+    if LONG_MODE && (0x40..=0x4F).contains(code.get(at)?) {
+        rex_w = code[at] & 0x08 != 0;
+        at += 1;
+    }
+
+    let imm_z = if operand16 { 2 } else { 4 };
+    let mut relative = false;
+    let opcode = *code.get(at)?;
+    at += 1;
+
+    // (has ModRM, immediate size)
+    let (modrm, mut imm) = match opcode {
+        0x0F => return decode_two_byte(code, at, address16, relative),
+        0x00..=0x3F => match opcode & 0x07 {
+            0..=3 => (true, 0),
+            4 => (false, 1),
+            5 => (false, imm_z),
+            _ if LONG_MODE && matches!(opcode, 0x06 | 0x07 | 0x0E | 0x16 | 0x17 | 0x1E | 0x1F | 0x27 | 0x2F | 0x37 | 0x3F) => return None,
+            _ => (false, 0),
+        },
+        0x40..=0x5F | 0x90..=0x99 | 0x9B..=0x9F | 0xA4..=0xA7 | 0xAA..=0xAF | 0xC3 | 0xC9 | 0xCB | 0xCC | 0xCE
+        | 0xCF | 0xD6 | 0xD7 | 0xEC..=0xEF | 0xF1 | 0xF4 | 0xF5 | 0xF8..=0xFD | 0x6C..=0x6F => (false, 0),
+        0x60 | 0x61 if LONG_MODE => return None,
+        0x60 | 0x61 => (false, 0),
+        0x62 if LONG_MODE => return None,
+        0x62 | 0x63 => (true, 0),
+        0x68 => (false, imm_z),
+        0x69 => (true, imm_z),
+        0x6A => (false, 1),
+        0x6B => (true, 1),
+        0x70..=0x7F | 0xE0..=0xE3 | 0xEB => {
+            relative = true;
+            (false, 1)
+        }
+        0x80 | 0x82 | 0x83 | 0xC0 | 0xC1 | 0xC6 => (true, 1),
+        0x81 | 0xC7 => (true, imm_z),
+        0x84..=0x8F | 0xD0..=0xD3 | 0xD8..=0xDF | 0xFE | 0xFF => (true, 0),
+        0x9A | 0xEA if LONG_MODE => return None,
+        0x9A | 0xEA => (false, imm_z + 2),
+        0xA0..=0xA3 => (false, if LONG_MODE { if address16 { 4 } else { 8 } } else if address16 { 2 } else { 4 }),
+        0xA8 | 0xB0..=0xB7 | 0xCD | 0xD4 | 0xD5 | 0xE4..=0xE7 => (false, 1),
+        0xA9 => (false, imm_z),
+        0xB8..=0xBF => (false, if rex_w { 8 } else { imm_z }),
+        0xC2 | 0xCA => (false, 2),
+        0xC4 | 0xC5 if LONG_MODE => return None,
+        0xC4 | 0xC5 => (true, 0),
+        0xC8 => (false, 3),
+        0xE8 | 0xE9 => {
+            relative = true;
+            (false, imm_z)
+        }
+        0xF6 | 0xF7 => (true, 0),
+        _ => return None,
+    };
+
+    if modrm {
+        let byte = *code.get(at)?;
+        if matches!(opcode, 0xF6 | 0xF7) && (byte >> 3) & 0x07 < 2 {
+            // test r/m, imm
+            imm = if opcode == 0xF6 { 1 } else { imm_z };
+        }
+        let (len, rip_relative) = modrm_len(code, at, address16)?;
+        at += len;
+        relative |= rip_relative;
+    }
+
+    at += imm;
+    (at <= code.len()).then_some(Instruction { len: at, relative })
+}
+
+fn decode_two_byte(code: &[u8], mut at: usize, address16: bool, mut relative: bool) -> Option<Instruction> {
+    let opcode = *code.get(at)?;
+    at += 1;
+
+    let (modrm, imm) = match opcode {
+        0x38 => {
+            at += 1;
+            (true, 0)
+        }
+        0x3A => {
+            at += 1;
+            (true, 1)
+        }
+        0x05..=0x09 | 0x0B | 0x0E | 0x30..=0x35 | 0x37 | 0x77 | 0xA0 | 0xA1 | 0xA2 | 0xA8 | 0xA9 | 0xAA
+        | 0xC8..=0xCF => (false, 0),
+        0x80..=0x8F => {
+            relative = true;
+            (false, 4)
+        }
+        0x70..=0x73 | 0xA4 | 0xAC | 0xBA | 0xC2 | 0xC4..=0xC6 => (true, 1),
+        0x0F | 0x24 | 0x25 | 0x26 | 0x27 | 0x36 | 0x39 | 0x3B..=0x3F | 0x04 | 0x0A | 0x0C | 0xFF => return None,
+        _ => (true, 0),
+    };
+
+    if modrm {
+        let (len, rip_relative) = modrm_len(code, at, address16)?;
+        at += len;
+        relative |= rip_relative;
+    }
+
+    at += imm;
+    (at <= code.len()).then_some(Instruction { len: at, relative })
+}
+
+/// Returns the length of the ModRM byte with its SIB and displacement, and whether it is RIP-relative.
+fn modrm_len(code: &[u8], at: usize, address16: bool) -> Option<(usize, bool)> {
+    let modrm = *code.get(at)?;
+    let mode = modrm >> 6;
+    let rm = modrm & 0x07;
+
+    if mode == 3 {
+        return Some((1, false));
+    }
+
+    if address16 && !LONG_MODE {
+        let disp = match mode {
+            0 if rm == 6 => 2,
+            0 => 0,
+            1 => 1,
+            _ => 2,
+        };
+        return Some((1 + disp, false));
+    }
+
+    let mut len = 1;
+    let mut disp = match mode {
+        1 => 1,
+        2 => 4,
+        _ => 0,
+    };
+    let mut rip_relative = false;
+    if rm == 4 {
+        let sib = *code.get(at + 1)?;
+        len += 1;
+        if mode == 0 && sib & 0x07 == 5 {
+            disp = 4;
+        }
+    } else if mode == 0 && rm == 5 {
+        disp = 4;
+        rip_relative = LONG_MODE;
+    }
+    Some((len + disp, rip_relative))
+}
+
+/// Returns the number of bytes of whole position-independent instructions covering at least `min` bytes.
+pub(crate) fn relocatable_len(code: &[u8], min: usize) -> Option<usize> {
+    let mut len = 0;
+    while len < min {
+        let instruction = decode(&code[len..])?;
+        if instruction.relative {
+            return None;
+        }
+        len += instruction.len;
+    }
+    Some(len)
+}
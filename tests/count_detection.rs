@@ -0,0 +1,93 @@
+//! Property tests of method count detection over generated table layouts.
+
+use proptest::prelude::*;
+use vmt_hook::detect::{code_count_in, detect_count_in, probe_code_count, MAX_PROBED_METHODS};
+use vmt_hook::{HookBackend, VTableHook};
+
+/// Words that look like method addresses: never null, sometimes tiny or with the high bits set.
+fn method() -> impl Strategy<Value = usize> {
+    prop_oneof![1..0x1000usize, 0x1000..usize::MAX, Just(usize::MAX), Just(1)]
+}
+
+/// Arbitrary words, null a fair share of the time as in padding, RTTI gaps and adjacent tables.
+fn word() -> impl Strategy<Value = usize> {
+    prop_oneof![1 => Just(0usize), 3 => any::<usize>()]
+}
+
+/// Methods, a terminator and whatever follows the table: the next table, data or more nulls.
+fn terminated_table() -> impl Strategy<Value = (Vec<usize>, Vec<usize>)> {
+    (prop::collection::vec(method(), 0..64), prop::collection::vec(word(), 0..16))
+}
+
+/// Start and size of the pretend code section of the buffer-based code counts.
+const CODE: usize = 0x1_0000;
+const CODE_SIZE: usize = 0x1_0000;
+
+fn is_code(address: usize) -> bool {
+    (CODE..CODE + CODE_SIZE).contains(&address)
+}
+
+/// An MSVC-style table in the pretend code section: methods followed by the RTTI pointer of the next table,
+/// which lies in data, and whatever comes after it.
+fn msvc_table() -> impl Strategy<Value = (Vec<usize>, usize, Vec<usize>)> {
+    let data = prop_oneof![1..CODE, CODE + CODE_SIZE..usize::MAX, Just(0usize)];
+    (prop::collection::vec(CODE..CODE + CODE_SIZE, 0..64), data, prop::collection::vec(any::<usize>(), 0..16))
+}
+
+extern "C" fn first() {}
+extern "C" fn second() {}
+extern "C" fn third() {}
+
+static RTTI: [usize; 2] = [0, 0];
+
+proptest! {
+    #[test]
+    fn unterminated_buffer_counts_every_word(words in prop::collection::vec(method(), 0..256)) {
+        prop_assert_eq!(detect_count_in(&words), words.len());
+    }
+
+    #[test]
+    fn trailing_words_are_ignored((methods, trailing) in terminated_table()) {
+        let words = [&methods[..], &[0], &trailing[..]].concat();
+        prop_assert_eq!(detect_count_in(&words), methods.len());
+        prop_assert_eq!(detect_count_in(&words[..methods.len()]), methods.len());
+    }
+
+    #[test]
+    fn matches_in_place_detection((methods, trailing) in terminated_table()) {
+        let mut words = [&methods[..], &[0], &trailing[..]].concat();
+        let mut object = words.as_mut_ptr().cast_const();
+        let hook = unsafe { VTableHook::new(&mut object as *mut *const usize) };
+        prop_assert_eq!(hook.backend().count(), detect_count_in(&words));
+        drop(hook);
+        prop_assert_eq!(object, words.as_ptr());
+    }
+
+    #[test]
+    fn code_count_ends_at_next_table((methods, rtti, trailing) in msvc_table()) {
+        let words = [&methods[..], &[rtti], &trailing[..]].concat();
+        prop_assert_eq!(code_count_in(&words, is_code), methods.len());
+    }
+
+    #[test]
+    fn code_count_stops_at_null_code(methods in prop::collection::vec(method(), 0..64), trailing in word()) {
+        let words = [&methods[..], &[0, trailing]].concat();
+        prop_assert_eq!(code_count_in(&words, |_| true), methods.len());
+    }
+
+    #[test]
+    fn probed_code_count_matches_buffer(picks in prop::collection::vec(0..3usize, 0..32)) {
+        let functions = [first as *const () as usize, second as *const () as usize, third as *const () as usize];
+        let methods: Vec<usize> = picks.iter().map(|&pick| functions[pick]).collect();
+        let words = [&methods[..], &[RTTI.as_ptr() as usize, functions[0]]].concat();
+        let count = unsafe { probe_code_count(words.as_ptr()) };
+        prop_assert_eq!(count, methods.len());
+        prop_assert_eq!(code_count_in(&words, |address| functions.contains(&address)), count);
+    }
+}
+
+#[test]
+fn code_count_is_bounded() {
+    let words = vec![CODE; MAX_PROBED_METHODS + 1];
+    assert_eq!(code_count_in(&words, is_code), MAX_PROBED_METHODS);
+}
@@ -0,0 +1,78 @@
+// Class hierarchies exercising the VTable layouts of the platform ABI.
+//
+// Destructors are declared last so the method indices are the same under MSVC, which emits one
+// destructor slot, and Itanium, which emits two.
+
+#ifdef _WIN32
+#define EXPORT extern "C" __declspec(dllexport)
+#else
+#define EXPORT extern "C" __attribute__((visibility("default")))
+#endif
+
+// Single inheritance.
+
+struct Base {
+    virtual int id() { return 1; }
+    virtual int value(int x) { return x; }
+    virtual ~Base() {}
+};
+
+struct Derived : Base {
+    int id() override { return 2; }
+    int value(int x) override { return x * 2; }
+};
+
+EXPORT Base* make_derived() { return new Derived; }
+EXPORT int call_id(Base* object) { return object->id(); }
+EXPORT int call_value(Base* object, int x) { return object->value(x); }
+EXPORT void destroy_base(Base* object) { delete object; }
+
+// Hooked class-wide by name, so no other test sees its table patched.
+
+struct Spawned : Base {
+    int id() override { return 3; }
+};
+
+EXPORT Base* make_spawned() { return new Spawned; }
+
+// Multiple inheritance: the second base is a subobject with a VTable of its own.
+
+struct Left {
+    int left_field = 10;
+    virtual int left() { return 10; }
+    virtual ~Left() {}
+};
+
+struct Right {
+    int right_field = 20;
+    virtual int right() { return right_field; }
+    virtual ~Right() {}
+};
+
+struct Both : Left, Right {
+    int left() override { return 11; }
+    int right() override { return right_field + 1; }
+};
+
+EXPORT Both* make_both() { return new Both; }
+EXPORT Right* both_as_right(Both* object) { return object; }
+EXPORT int call_left(Left* object) { return object->left(); }
+EXPORT int call_right(Right* object) { return object->right(); }
+EXPORT void destroy_both(Both* object) { delete object; }
+
+// Virtual inheritance: reaching the virtual base goes through an offset stored with the VTable.
+
+struct Shared {
+    int shared_field = 30;
+    virtual int shared() { return shared_field; }
+    virtual ~Shared() {}
+};
+
+struct Middle : virtual Shared {
+    virtual int middle() { return 40; }
+};
+
+EXPORT Middle* make_middle() { return new Middle; }
+EXPORT int call_middle(Middle* object) { return object->middle(); }
+EXPORT int read_shared_field(Middle* object) { return object->shared_field; }
+EXPORT void destroy_middle(Middle* object) { delete object; }
@@ -0,0 +1,257 @@
+//! Hooks real C++ objects compiled at test time, catching ABI assumptions synthetic tables can't.
+//!
+//! Needs a C++ compiler found by `cc`; run with `cargo test --features cpp-fixture`.
+
+use std::ffi::{c_int, c_void};
+use std::path::PathBuf;
+use std::process::Command;
+use std::sync::OnceLock;
+
+use libloading::Library;
+use vmt_hook::{rtti, symbol, InPlaceVmtHook, VTableCopyOptions, VTableHook};
+
+/// Returns the target the tests were built for, as `cc` needs it outside of build scripts.
+fn target() -> &'static str {
+    match (std::env::consts::ARCH, std::env::consts::OS, cfg!(target_env = "msvc")) {
+        ("x86_64", "windows", true) => "x86_64-pc-windows-msvc",
+        ("x86", "windows", true) => "i686-pc-windows-msvc",
+        ("x86_64", "windows", false) => "x86_64-pc-windows-gnu",
+        ("x86", "windows", false) => "i686-pc-windows-gnu",
+        ("x86_64", "linux", _) => "x86_64-unknown-linux-gnu",
+        ("x86", "linux", _) => "i686-unknown-linux-gnu",
+        ("aarch64", "linux", _) => "aarch64-unknown-linux-gnu",
+        ("x86_64", "macos", _) => "x86_64-apple-darwin",
+        ("aarch64", "macos", _) => "aarch64-apple-darwin",
+        (arch, os, _) => panic!("no C++ fixture target for {arch}-{os}"),
+    }
+}
+
+/// Compiles the fixture into a shared library once and loads it.
+fn fixture() -> &'static Library {
+    static FIXTURE: OnceLock<Library> = OnceLock::new();
+    FIXTURE.get_or_init(|| {
+        let source = PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/cpp/fixture.cpp");
+        let out = PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("cpp-fixture");
+        std::fs::create_dir_all(&out).unwrap();
+        let library = out.join(libloading::library_filename("fixture"));
+
+        let compiler = cc::Build::new()
+            .cpp(true)
+            .target(target())
+            .host(target())
+            .opt_level(0)
+            .cargo_metadata(false)
+            .out_dir(&out)
+            .get_compiler();
+        let mut command: Command = compiler.to_command();
+        if compiler.is_like_msvc() {
+            command.arg("/LD").arg("/GR").arg(&source).arg(format!("/Fe{}", library.display()));
+            command.arg(format!("/Fo{}\\", out.display()));
+        } else {
+            command.args(["-shared", "-fPIC", "-frtti", "-o"]).arg(&library).arg(&source);
+        }
+        let status = command.status().expect("failed to run the C++ compiler");
+        assert!(status.success(), "failed to compile the C++ fixture");
+        load(&library)
+    })
+}
+
+/// Loads the fixture with its C++ runtime in the global scope, where [`rtti`] finds the ABI's `type_info` classes.
+#[cfg(unix)]
+fn load(path: &std::path::Path) -> Library {
+    use libloading::os::unix::{Library, RTLD_GLOBAL, RTLD_NOW};
+    unsafe { Library::open(Some(path), RTLD_NOW | RTLD_GLOBAL).unwrap().into() }
+}
+#[cfg(windows)]
+fn load(path: &std::path::Path) -> Library {
+    unsafe { Library::new(path).unwrap() }
+}
+
+/// Looks up an exported function of the fixture.
+unsafe fn export<F: Copy>(name: &str) -> F {
+    *fixture().get::<F>(name.as_bytes()).unwrap()
+}
+
+type Make = unsafe extern "C" fn() -> *mut c_void;
+type Call = unsafe extern "C" fn(*mut c_void) -> c_int;
+type CallWith = unsafe extern "C" fn(*mut c_void, c_int) -> c_int;
+type Cast = unsafe extern "C" fn(*mut c_void) -> *mut c_void;
+type Destroy = unsafe extern "C" fn(*mut c_void);
+
+#[cfg(all(windows, target_arch = "x86"))]
+type Method = unsafe extern "thiscall" fn(*mut c_void) -> c_int;
+#[cfg(not(all(windows, target_arch = "x86")))]
+type Method = unsafe extern "C" fn(*mut c_void) -> c_int;
+
+#[cfg(all(windows, target_arch = "x86"))]
+unsafe extern "thiscall" fn returns_100(_this: *mut c_void) -> c_int {
+    100
+}
+#[cfg(not(all(windows, target_arch = "x86")))]
+unsafe extern "C" fn returns_100(_this: *mut c_void) -> c_int {
+    100
+}
+
+#[test]
+fn single_inheritance() {
+    unsafe {
+        let object = export::<Make>("make_derived")();
+        let (call_id, call_value) = (export::<Call>("call_id"), export::<CallWith>("call_value"));
+        let vtable = *(object as *const *const usize);
+
+        let hook = VTableHook::with_count(object, 3);
+        hook.replace_method(0, returns_100 as Method as usize);
+        assert_eq!(call_id(object), 100);
+        assert_eq!(call_value(object, 21), 42);
+
+        let original: Method = std::mem::transmute(hook.get_original_method(0));
+        assert_eq!(original(object), 2);
+        drop(hook);
+        assert_eq!(call_id(object), 2);
+        assert_eq!(*(object as *const *const usize), vtable);
+        export::<Destroy>("destroy_base")(object);
+    }
+}
+
+#[test]
+fn rtti_names() {
+    unsafe {
+        let object = export::<Make>("make_derived")();
+        let vtable = *(object as *const *const usize);
+        let hierarchy = rtti::class_hierarchy(vtable).expect("no RTTI");
+        assert!(hierarchy[0].contains("Derived"), "{hierarchy:?}");
+        assert!(hierarchy.iter().any(|name| name.contains("Base")), "{hierarchy:?}");
+        export::<Destroy>("destroy_base")(object);
+    }
+}
+
+#[test]
+#[cfg_attr(windows, ignore = "MSVC doesn't export the methods; needs the pdb feature and the fixture's PDB")]
+fn symbol_paths() {
+    unsafe {
+        let object = export::<Make>("make_derived")();
+        let vtable = *(object as *const *const usize);
+        let module = libloading::library_filename("fixture").into_string().unwrap();
+
+        let value = symbol::resolve(&format!("{module}!Derived::value")).unwrap();
+        assert_eq!((value.vtable, value.index), (vtable as usize, 1));
+        assert_eq!(value.address, *vtable.add(1));
+        let id = symbol::resolve(&format!("{module}!Derived::id")).unwrap();
+        assert_eq!(id.index, 0);
+        assert!(symbol::resolve(&format!("{module}!Derived::missing")).is_err());
+        assert!(symbol::resolve_in(&format!("{module}!Derived::id"), vtable.add(1)).is_err());
+        export::<Destroy>("destroy_base")(object);
+    }
+}
+
+#[test]
+fn dump_names_modules() {
+    unsafe {
+        let object = export::<Make>("make_derived")();
+        let module = libloading::library_filename("fixture").into_string().unwrap();
+        let hook = VTableHook::with_count(object, 2);
+        hook.replace_method(0, returns_100 as Method as usize);
+
+        let dump = hook.dump();
+        assert_eq!(dump.class.as_deref(), Some("Derived"));
+        assert_eq!(dump.slots[1].original.module.as_deref(), Some(module.as_str()));
+        assert!(dump.slots[0].is_replaced() && !dump.slots[1].is_replaced());
+        #[cfg(not(windows))]
+        assert_eq!(dump.slots[1].method.as_deref(), Some("Derived::value"));
+        #[cfg(all(feature = "symbols", not(windows)))]
+        assert_eq!(dump.slots[1].original.symbol.as_ref().unwrap().name, "Derived::value");
+        drop(hook);
+        export::<Destroy>("destroy_base")(object);
+    }
+}
+
+#[test]
+fn find_vtable() {
+    unsafe {
+        let object = export::<Make>("make_derived")();
+        let vtable = *(object as *const *const usize);
+        let module = libloading::library_filename("fixture").into_string().unwrap();
+
+        let found = rtti::find_vtable(&module, "Derived").unwrap();
+        assert_eq!(found.vtable, vtable as usize);
+        assert!(found.count >= 3);
+        let mangled = rtti::class_name(vtable).unwrap();
+        assert_eq!(rtti::find_vtable(&module, &mangled), Some(found));
+        assert_eq!(rtti::find_vtable(&module, "Missing"), None);
+        export::<Destroy>("destroy_base")(object);
+    }
+}
+
+#[test]
+fn class_inventory() {
+    unsafe {
+        let object = export::<Make>("make_derived")();
+        let vtable = *(object as *const *const usize) as usize;
+        let module = libloading::library_filename("fixture").into_string().unwrap();
+
+        let classes = rtti::module_inventory(&module).unwrap();
+        let derived = classes.iter().find(|class| class.class == "Derived").unwrap();
+        assert_eq!((derived.module.as_str(), derived.vtable), (module.as_str(), vtable));
+        assert!(derived.count >= 3);
+        for class in ["Base", "Left", "Right", "Both", "Shared", "Middle"] {
+            assert!(classes.iter().any(|found| found.class == class), "{class} missing from {classes:?}");
+        }
+        assert!(rtti::inventory().iter().any(|class| class.vtable == vtable));
+        export::<Destroy>("destroy_base")(object);
+    }
+}
+
+#[test]
+fn in_place_for_class() {
+    unsafe {
+        let module = libloading::library_filename("fixture").into_string().unwrap();
+        let hook = InPlaceVmtHook::for_class("Spawned", &module).unwrap();
+        hook.replace_method(0, returns_100 as Method as usize).unwrap();
+        let object = export::<Make>("make_spawned")();
+        assert_eq!(export::<Call>("call_id")(object), 100);
+        drop(hook);
+        assert_eq!(export::<Call>("call_id")(object), 3);
+        export::<Destroy>("destroy_base")(object);
+        assert!(InPlaceVmtHook::for_class("Missing", &module).is_err());
+    }
+}
+
+#[test]
+fn multiple_inheritance() {
+    unsafe {
+        let object = export::<Make>("make_both")();
+        let right = export::<Cast>("both_as_right")(object);
+        assert_ne!(right, object);
+        let (call_left, call_right) = (export::<Call>("call_left"), export::<Call>("call_right"));
+
+        let hook = VTableHook::with_count(right, 1);
+        hook.replace_method(0, returns_100 as Method as usize);
+        assert_eq!(call_right(right), 100);
+        assert_eq!(call_left(object), 11);
+
+        let original: Method = std::mem::transmute(hook.get_original_method(0));
+        assert_eq!(original(right), 21);
+        drop(hook);
+        assert_eq!(call_right(right), 21);
+        export::<Destroy>("destroy_both")(object);
+    }
+}
+
+#[test]
+fn virtual_inheritance() {
+    unsafe {
+        let object = export::<Make>("make_middle")();
+        let (call_middle, read_shared) = (export::<Call>("call_middle"), export::<Call>("read_shared_field"));
+
+        // Itanium keeps the offset of the virtual base before the first method, MSVC in a separate table.
+        let prefix = if cfg!(target_env = "msvc") { 1 } else { 3 };
+        let options = VTableCopyOptions::with_prefix_words(prefix);
+        let hook = VTableHook::with_count_and_options(object, 1, &options).unwrap();
+        hook.replace_method(0, returns_100 as Method as usize);
+        assert_eq!(call_middle(object), 100);
+        assert_eq!(read_shared(object), 30);
+        drop(hook);
+        assert_eq!(call_middle(object), 40);
+        export::<Destroy>("destroy_middle")(object);
+    }
+}
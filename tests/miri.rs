@@ -0,0 +1,490 @@
+//! Copy, swap and restore logic over synthetic tables, with nothing the OS has to answer.
+//!
+//! Run with `MIRIFLAGS=-Zmiri-permissive-provenance cargo +nightly miri test --test miri` to check the
+//! unsafe code for undefined behavior; the flag accepts the addresses the crate passes around as `usize`.
+//! The tests also run natively as part of `cargo test`.
+
+use vmt_hook::backend::{LazyCopySwap, SharedCopySwap};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use vmt_hook::shadow::{Allocation, ShadowAllocator, CACHE_LINE};
+use vmt_hook::{Error, InPlaceVmtHook, VTableCopyOptions, VTableHook};
+
+/// Words of a synthetic VTable: two ABI prefix words, three methods and a null terminator.
+const TABLE: [usize; 6] = [0x50, 0x51, 0x1000, 0x2000, 0x3000, 0];
+
+/// A synthetic VTable and an object pointing at it.
+struct Fixture {
+    table: *mut usize,
+    object: *mut *const usize,
+}
+
+impl Fixture {
+    fn new() -> Self {
+        let table = Box::into_raw(Box::new(TABLE)).cast::<usize>();
+        let object = Box::into_raw(Box::new(table.wrapping_add(2).cast_const()));
+        Self { table, object }
+    }
+
+    /// Returns the address of the first method.
+    fn vtable(&self) -> *const usize {
+        self.table.wrapping_add(2)
+    }
+
+    /// Returns the method the object currently reaches at `id`.
+    unsafe fn method(&self, id: usize) -> usize {
+        *(*self.object).add(id)
+    }
+}
+
+impl Drop for Fixture {
+    fn drop(&mut self) {
+        unsafe {
+            drop(Box::from_raw(self.object));
+            drop(Box::from_raw(self.table.cast::<[usize; 6]>()));
+        }
+    }
+}
+
+#[test]
+fn copy_swap_replace_and_restore() {
+    let fixture = Fixture::new();
+    unsafe {
+        let hook = VTableHook::with_count(fixture.object, 3);
+        assert!(hook.is_installed());
+        assert_ne!(*fixture.object, fixture.vtable());
+
+        hook.replace_method(1, 0x20);
+        assert_eq!(fixture.method(1), 0x20);
+        assert_eq!(hook.get_original_method(1), 0x2000);
+        assert_eq!(*fixture.vtable().add(1), 0x2000);
+
+        hook.restore_method(1);
+        assert_eq!(fixture.method(1), 0x2000);
+        hook.replace_method(0, 0x10);
+        hook.replace_method(2, 0x30);
+        hook.restore_all_methods();
+        assert_eq!((fixture.method(0), fixture.method(2)), (0x1000, 0x3000));
+        drop(hook);
+    }
+    assert_eq!(unsafe { *fixture.object }, fixture.vtable());
+}
+
+#[test]
+fn detects_count_up_to_terminator() {
+    let fixture = Fixture::new();
+    unsafe {
+        let hook = VTableHook::new(fixture.object);
+        assert_eq!(vmt_hook::HookBackend::count(hook.backend()), 3);
+    }
+}
+
+#[test]
+fn detach_leaves_vptr() {
+    let fixture = Fixture::new();
+    unsafe {
+        let hook = VTableHook::with_count(fixture.object, 3);
+        let copy = *fixture.object;
+        hook.detach();
+        assert_eq!(*fixture.object, copy);
+        *fixture.object = fixture.vtable();
+    }
+}
+
+#[test]
+fn page_copies_freeze_and_mimic_layout() {
+    let fixture = Fixture::new();
+    let pages = [Allocation::Pages, Allocation::GuardedPages, Allocation::AlignedPages, Allocation::MatchedPages];
+    for allocation in pages {
+        let options = VTableCopyOptions {
+            allocation,
+            freeze: true,
+            mimic_layout: true,
+            ..VTableCopyOptions::default()
+        };
+        unsafe {
+            let hook = VTableHook::with_count_and_options(fixture.object, 3, &options).unwrap();
+            let copy = *fixture.object;
+            assert_eq!(*copy.sub(2), 0x50);
+            assert_eq!(*copy.sub(1), 0x51);
+            assert_eq!(*copy.add(3), 0);
+            match allocation {
+                Allocation::AlignedPages => assert_eq!(copy.sub(2) as usize % 4096, 0),
+                Allocation::MatchedPages => assert_eq!(copy.sub(2) as usize % 4096, fixture.table as usize % 4096),
+                _ => {}
+            }
+
+            hook.replace_method(2, 0x30);
+            assert_eq!(fixture.method(2), 0x30);
+            hook.thaw().unwrap();
+            hook.replace_method(0, 0x10);
+            hook.freeze().unwrap();
+            assert!(hook.verify().is_ok());
+        }
+    }
+    assert_eq!(unsafe { *fixture.object }, fixture.vtable());
+}
+
+#[test]
+fn custom_allocator_owns_copy() {
+    static LIVE: AtomicUsize = AtomicUsize::new(0);
+    unsafe fn alloc(size: usize, align: usize) -> *mut u8 {
+        LIVE.fetch_add(1, Ordering::SeqCst);
+        std::alloc::alloc(std::alloc::Layout::from_size_align(size, align).unwrap())
+    }
+    unsafe fn free(address: *mut u8, size: usize, align: usize) {
+        LIVE.fetch_sub(1, Ordering::SeqCst);
+        std::alloc::dealloc(address, std::alloc::Layout::from_size_align(size, align).unwrap());
+    }
+
+    let fixture = Fixture::new();
+    let options = VTableCopyOptions {
+        allocation: Allocation::Custom(ShadowAllocator { alloc, free }),
+        ..VTableCopyOptions::default()
+    };
+    unsafe {
+        let hook = VTableHook::with_count_and_options(fixture.object, 3, &options).unwrap();
+        assert_eq!(LIVE.load(Ordering::SeqCst), 1);
+        hook.replace_method(0, 0x10);
+        assert_eq!(fixture.method(0), 0x10);
+        assert!(hook.freeze().is_err());
+        drop(hook);
+    }
+    assert_eq!(LIVE.load(Ordering::SeqCst), 0);
+}
+
+#[test]
+fn hot_slot_starts_cache_line() {
+    let fixture = Fixture::new();
+    for allocation in [Allocation::Heap, Allocation::Pages, Allocation::AlignedPages] {
+        let options = VTableCopyOptions {
+            allocation,
+            mimic_layout: true,
+            hot_slot: Some(1),
+            ..VTableCopyOptions::default()
+        };
+        unsafe {
+            let hook = VTableHook::with_count_and_options(fixture.object, 3, &options).unwrap();
+            assert_eq!((*fixture.object).add(1) as usize % CACHE_LINE, 0);
+            assert_eq!((fixture.method(1), *(*fixture.object).sub(2)), (0x2000, 0x50));
+            hook.replace_method(1, 0x20);
+            assert_eq!(fixture.method(1), 0x20);
+        }
+    }
+    let guarded = VTableCopyOptions {
+        allocation: Allocation::GuardedPages,
+        hot_slot: Some(1),
+        ..VTableCopyOptions::default()
+    };
+    assert!(unsafe { VTableHook::with_count_and_options(fixture.object, 3, &guarded) }.is_err());
+    let past = VTableCopyOptions { hot_slot: Some(3), ..VTableCopyOptions::default() };
+    assert!(unsafe { VTableHook::with_count_and_options(fixture.object, 3, &past) }.is_err());
+}
+
+#[test]
+fn heap_copies_refuse_to_freeze() {
+    let fixture = Fixture::new();
+    unsafe {
+        let hook = VTableHook::with_count(fixture.object, 3);
+        assert!(hook.freeze().is_err());
+    }
+}
+
+#[test]
+fn verify_reports_foreign_writes() {
+    let fixture = Fixture::new();
+    unsafe {
+        let hook = VTableHook::with_count(fixture.object, 3);
+        let check = hook.integrity_check();
+        hook.replace_method(0, 0x10);
+        assert!(check.verify().is_ok());
+
+        (*fixture.object).cast_mut().add(2).write(0xBAD);
+        match hook.verify() {
+            Err(Error::Tampered(slots)) => assert_eq!(slots, [2]),
+            other => panic!("tampering not detected: {other:?}"),
+        }
+        drop(hook);
+        assert!(check.is_released());
+        assert!(check.verify().is_ok());
+    }
+}
+
+#[test]
+fn shared_copies_and_forks() {
+    let first = Fixture::new();
+    let second = Fixture::new();
+    unsafe {
+        // Point both objects at the same VTable.
+        *second.object = first.vtable();
+        let a = VTableHook::<_, SharedCopySwap>::with_backend_and_count(first.object, 3);
+        let b = VTableHook::<_, SharedCopySwap>::with_backend_and_count(second.object, 3);
+        assert_eq!(*first.object, *second.object);
+        assert_eq!(a.backend().sharers(), 2);
+
+        a.replace_method(0, 0x10);
+        assert_eq!(second.method(0), 0x10);
+
+        b.replace_method_own(1, 0x20);
+        assert!(!b.backend().is_shared());
+        assert_eq!((second.method(0), second.method(1)), (0x10, 0x20));
+        assert_eq!(first.method(1), 0x2000);
+        drop((a, b));
+        assert_eq!(*second.object, first.vtable());
+        *second.object = second.vtable();
+    }
+}
+
+#[test]
+fn in_place_patch_and_restore() {
+    let fixture = Fixture::new();
+    unsafe {
+        let hook = InPlaceVmtHook::new(fixture.table.add(2), 3);
+        hook.replace_method(1, 0x20).unwrap();
+        assert_eq!(fixture.method(1), 0x20);
+        hook.alias_method(2, 0).unwrap();
+        assert_eq!(fixture.method(2), 0x1000);
+        hook.restore_method(1).unwrap();
+        assert_eq!(fixture.method(1), 0x2000);
+        hook.verify().unwrap();
+        drop(hook);
+        assert_eq!(fixture.method(2), 0x3000);
+    }
+}
+
+#[test]
+fn in_place_unhook_verifies_and_frees() {
+    let fixture = Fixture::new();
+    unsafe {
+        let hook = InPlaceVmtHook::new(fixture.table.add(2), 3);
+        hook.replace_method(0, 0x10).unwrap();
+        hook.unhook().unwrap();
+        assert_eq!(fixture.method(0), 0x1000);
+    }
+}
+
+#[test]
+fn fixed_hook_keeps_table_inline() {
+    let fixture = Fixture::new();
+    let hook = Box::pin(vmt_hook::FixedVTableHook::<4>::new());
+    unsafe {
+        assert!(hook.as_ref().install(fixture.object.cast(), 3));
+        assert!(!hook.as_ref().install(fixture.object.cast(), 3));
+        assert!(hook.is_installed());
+        hook.replace_method(1, 0x20);
+        assert_eq!(fixture.method(1), 0x20);
+        assert_eq!(hook.get_original_method(1), 0x2000);
+        hook.alias_method(0, 2);
+        assert_eq!(fixture.method(0), 0x3000);
+
+        assert!(hook.uninstall());
+        assert_eq!(*fixture.object, fixture.vtable());
+        assert!(hook.as_ref().install(fixture.object.cast(), 2));
+        assert_eq!(fixture.method(1), 0x2000);
+        drop(hook);
+    }
+    assert_eq!(unsafe { *fixture.object }, fixture.vtable());
+}
+
+vmt_hook::static_hook! {
+    static STATIC_HOOK: 4;
+}
+
+#[test]
+fn static_hook_keeps_table_address() {
+    let fixture = Fixture::new();
+    unsafe {
+        assert!(STATIC_HOOK.install_static(fixture.object.cast(), 3));
+        let copy = *fixture.object;
+        assert!(STATIC_HOOK.uninstall());
+        assert!(STATIC_HOOK.install_static(fixture.object.cast(), 3));
+        assert_eq!(*fixture.object, copy);
+        assert!(STATIC_HOOK.uninstall());
+    }
+    assert_eq!(unsafe { *fixture.object }, fixture.vtable());
+}
+
+#[test]
+fn allocations_report_live_copies() {
+    use vmt_hook::allocations::{self, AllocationKind};
+
+    let fixture = Fixture::new();
+    let copy = unsafe {
+        let hook = VTableHook::with_count(fixture.object, 3);
+        let copy = *fixture.object as usize;
+        let live = allocations::report().into_iter().find(|allocation| allocation.address == copy).unwrap();
+        assert_eq!(live.kind, AllocationKind::ShadowTable);
+        assert_eq!((live.owner, live.vtable), (Some(fixture.object as usize), Some(fixture.vtable() as usize)));
+        drop(hook);
+        copy
+    };
+    assert!(allocations::report().iter().all(|allocation| allocation.address != copy));
+}
+
+#[test]
+fn lazy_copy_waits_for_first_replacement() {
+    let fixture = Fixture::new();
+    unsafe {
+        let hook = VTableHook::<_, LazyCopySwap>::with_backend_and_count(fixture.object, 3);
+        hook.restore_method(1);
+        assert!(!hook.backend().is_allocated());
+        assert_eq!(*fixture.object, fixture.vtable());
+        assert!(hook.is_installed());
+
+        hook.replace_method(1, 0x20);
+        assert!(hook.backend().is_allocated());
+        assert_ne!(*fixture.object, fixture.vtable());
+        assert_eq!((fixture.method(0), fixture.method(1)), (0x1000, 0x20));
+        drop(hook);
+    }
+    assert_eq!(unsafe { *fixture.object }, fixture.vtable());
+}
+
+#[test]
+fn writable_in_place_survives_rebuild() {
+    let fixture = Fixture::new();
+    unsafe {
+        let hook = InPlaceVmtHook::new_writable(fixture.table.add(2), 3).unwrap();
+        hook.replace_method(1, 0x20).unwrap();
+        assert_eq!(fixture.method(1), 0x20);
+
+        // The engine rebuilds the table with new addresses.
+        fixture.table.add(2).write(0x1100);
+        fixture.table.add(3).write(0x2200);
+        assert_eq!(hook.revalidate().unwrap(), [1]);
+        assert_eq!((fixture.method(0), fixture.method(1)), (0x1100, 0x20));
+        assert_eq!(hook.get_original_method(1), 0x2200);
+        hook.verify().unwrap();
+        drop(hook);
+        assert_eq!((fixture.method(0), fixture.method(1)), (0x1100, 0x2200));
+    }
+}
+
+#[test]
+fn try_new_probes_vtable() {
+    let fixture = Fixture::new();
+    unsafe {
+        let hook = VTableHook::try_new(fixture.object).unwrap();
+        assert_eq!(vmt_hook::HookBackend::count(hook.backend()), 3);
+        drop(hook);
+        let hook = VTableHook::try_with_count(fixture.object, 2).unwrap();
+        assert_eq!(vmt_hook::HookBackend::count(hook.backend()), 2);
+    }
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "probes unmapped memory")]
+fn try_new_rejects_non_objects() {
+    let mut not_an_object = 0x10usize;
+    assert!(matches!(unsafe { VTableHook::try_new(&mut not_an_object as *mut usize) }, Err(Error::Invalid(_))));
+    let hook = unsafe { VTableHook::try_with_count(&mut not_an_object as *mut usize, 1) };
+    assert!(matches!(hook, Err(Error::Invalid(_))));
+}
+
+#[test]
+fn dump_marks_replaced_slots() {
+    let fixture = Fixture::new();
+    unsafe {
+        let hook = VTableHook::with_count(fixture.object, 3);
+        hook.replace_method(1, 0x20);
+        let dump = hook.dump();
+        assert_eq!(dump.vtable, fixture.vtable() as usize);
+        assert_eq!(dump.object, Some(fixture.object as usize));
+        assert_eq!(dump.replaced().map(|slot| slot.id).collect::<Vec<_>>(), [1]);
+        let text = dump.to_string();
+        assert!(text.contains("   1: 0x2000 0x2000 -> 0x20 0x20 (replaced)"), "{text}");
+        assert!(text.contains("   2: 0x3000 0x3000\n"), "{text}");
+    }
+}
+
+#[test]
+#[cfg(feature = "serde")]
+fn dump_exports_json() {
+    let fixture = Fixture::new();
+    unsafe {
+        let hook = VTableHook::with_count(fixture.object, 3);
+        hook.replace_method(0, 0x10);
+        let json = hook.dump().export_json();
+        assert!(json.starts_with(r#"{"class":null,"object":"#), "{json}");
+        assert!(json.contains(r#""id":0,"original":{"address":4096"#), "{json}");
+        let current = r#""current":{"address":16,"module":null,"offset":0,"symbol":null},"method":null"#;
+        assert!(json.contains(current), "{json}");
+    }
+}
+
+#[test]
+fn dump_writes_analysis_scripts() {
+    let fixture = Fixture::new();
+    unsafe {
+        let hook = VTableHook::with_count(fixture.object, 3);
+        hook.replace_method(2, 0x30);
+        let dump = hook.dump();
+        let vtable = fixture.vtable() as usize;
+
+        let ida = dump.ida_script();
+        assert!(ida.contains("base = 0\n"), "{ida}");
+        let comment = format!("vtable of {vtable:x} (3 methods)\\n[0] 0x1000");
+        let table = format!("    ({vtable:#x}, u\"vtable_{vtable:x}\", u\"{comment}\"),");
+        assert!(ida.contains(&table), "{ida}");
+        let hooked = format!("    ({:#x}, None, u\"[2] 0x3000 (hooked: 0x30)\"),", vtable + 2 * size_of::<usize>());
+        assert!(ida.contains(&hooked), "{ida}");
+        assert!(dump.ghidra_script().contains(&hooked));
+    }
+}
+
+#[test]
+fn debug_lists_slots() {
+    let fixture = Fixture::new();
+    unsafe {
+        let hook = VTableHook::with_count(fixture.object, 3);
+        hook.replace_method(1, 0x20);
+        let debug = format!("{hook:?}");
+        let expected = format!(
+            "VTableHook {{ object: {:#x}, vtable: {:#x}, count: 3, \
+             slots: [0x1000, 0x2000 -> 0x20 (replaced), 0x3000] }}",
+            fixture.object as usize,
+            fixture.vtable() as usize,
+        );
+        assert_eq!(debug, expected);
+    }
+}
+
+static PRODUCT_A: AtomicUsize = AtomicUsize::new(0);
+static PRODUCT_B: AtomicUsize = AtomicUsize::new(0);
+
+unsafe extern "C" fn create_a(_: *mut std::ffi::c_void) -> usize {
+    PRODUCT_A.load(Ordering::SeqCst)
+}
+
+unsafe extern "C" fn create_b(_: *mut std::ffi::c_void) -> usize {
+    PRODUCT_B.load(Ordering::SeqCst)
+}
+
+#[test]
+#[cfg_attr(miri, ignore = "factory hooks keep objects as integers")]
+fn factory_routes_each_watched_slot() {
+    type CreateFn = unsafe extern "C" fn(*mut std::ffi::c_void) -> usize;
+    let (a, b) = (Fixture::new(), Fixture::new());
+    PRODUCT_A.store(a.object as usize, Ordering::SeqCst);
+    PRODUCT_B.store(b.object as usize, Ordering::SeqCst);
+    let table = [create_a as CreateFn as usize, create_b as CreateFn as usize, 0];
+    let factory = Box::into_raw(Box::new(table.as_ptr())).cast::<std::ffi::c_void>();
+    unsafe {
+        use vmt_hook::factory::{FactoryOutput, FactoryWatcher};
+        use vmt_hook::slot::Slot;
+
+        let watcher = FactoryWatcher::with_count(factory, 2);
+        watcher.replace_method(0, 0x77);
+        watcher.watch(Slot::<CreateFn>::new(0), FactoryOutput::Return).unwrap();
+        watcher.watch(Slot::<CreateFn>::new(1), FactoryOutput::Return).unwrap();
+        assert!(watcher.watch(Slot::<CreateFn>::new(1), FactoryOutput::Return).is_err());
+
+        let vtable = *factory.cast::<*const CreateFn>();
+        assert_eq!((*vtable)(factory), a.object as usize);
+        assert_eq!((*vtable.add(1))(factory), b.object as usize);
+        assert_eq!((a.method(0), b.method(0)), (0x77, 0x77));
+        drop(watcher);
+    }
+    assert_eq!((unsafe { a.method(0) }, unsafe { b.method(0) }), (0x1000, 0x1000));
+    drop(unsafe { Box::from_raw(factory.cast::<*const usize>()) });
+}
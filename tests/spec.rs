@@ -0,0 +1,88 @@
+//! Loading hook specs and installing them on synthetic objects.
+
+use vmt_hook::builder::VTableBuilder;
+use vmt_hook::spec::{Detours, HookSpec, SlotRef};
+use vmt_hook::Error;
+
+type Method = extern "C" fn(usize) -> usize;
+
+extern "C" fn first(this: usize) -> usize {
+    this + 1
+}
+
+extern "C" fn second(this: usize) -> usize {
+    this.wrapping_mul(31) ^ 7
+}
+
+extern "C" fn replacement(_this: usize) -> usize {
+    0
+}
+
+#[test]
+fn toml_spec_resolves_named_slots() {
+    let spec = HookSpec::from_toml(
+        r#"
+        [[hooks]]
+        class = "game::Player"
+        slots = { Think = 12 }
+        methods = [{ slot = "Think", hook = "think" }, { slot = 3, hook = "respawn" }]
+        "#,
+    )
+    .unwrap();
+    let class = &spec.hooks[0];
+    assert_eq!(class.methods[1].slot, SlotRef::Index(3));
+    unsafe {
+        let vtable = std::ptr::null();
+        assert_eq!(class.resolve_slot(&class.methods[0].slot, vtable, 20).unwrap(), 12);
+        assert!(matches!(class.resolve_slot(&SlotRef::Name("Render".into()), vtable, 20), Err(Error::Invalid(_))));
+        assert!(matches!(class.resolve_slot(&SlotRef::Index(20), vtable, 20), Err(Error::Invalid(_))));
+    }
+}
+
+#[test]
+fn json_spec_hooks_class_by_signature() {
+    let object = VTableBuilder::new().function(first as Method).function(second as Method).build();
+    let code = unsafe { std::slice::from_raw_parts(second as Method as usize as *const u8, 4) };
+    let pattern = code.iter().map(|byte| format!("{byte:02X}")).collect::<Vec<_>>().join(" ");
+    let signature = format!(r#""signature": {{"slot": 1, "pattern": "{pattern}"}}"#);
+    let methods = r#""methods": [{"slot": 1, "hook": "replacement"}]"#;
+    let spec = HookSpec::from_json(&format!(r#"{{"hooks": [{{{signature}, {methods}}}]}}"#)).unwrap();
+
+    let mut detours = Detours::new();
+    unsafe {
+        assert!(matches!(spec.install(object.as_ptr(), &detours), Err(Error::Invalid(_))));
+        assert_eq!(object.current_vtable(), object.vtable());
+
+        detours.register("replacement", replacement as Method);
+        let hook = spec.install(object.as_ptr(), &detours).unwrap().unwrap();
+        assert_eq!(object.method(0), first as Method as usize);
+        assert_eq!(object.method(1), replacement as Method as usize);
+        drop(hook);
+        assert_eq!(object.current_vtable(), object.vtable());
+    }
+}
+
+#[test]
+fn class_names_match_mangled_rtti_names() {
+    use vmt_hook::rtti::{demangle, is_named};
+
+    assert_eq!(demangle(".?AVPlayer@game@@").as_deref(), Some("game::Player"));
+    assert_eq!(demangle(".?AUIUnknown@@").as_deref(), Some("IUnknown"));
+    assert_eq!(demangle("N4game6PlayerE").as_deref(), Some("game::Player"));
+    assert_eq!(demangle("11CBasePlayer").as_deref(), Some("CBasePlayer"));
+    assert_eq!(demangle("4game6Player"), None);
+    assert!(is_named(".?AVCBasePlayer@@", "CBasePlayer"));
+    assert!(is_named("11CBasePlayer", "11CBasePlayer"));
+    assert!(!is_named("11CBasePlayer", "Player"));
+}
+
+#[test]
+fn bad_signature_pattern_is_rejected() {
+    let spec = HookSpec::from_toml(
+        r#"
+        [[hooks]]
+        signature = { slot = 0, pattern = "48 8B zz" }
+        "#,
+    );
+    assert!(matches!(spec, Err(Error::Invalid(_))));
+}
@@ -0,0 +1,280 @@
+//! Calls through the generated machine code of thunks, stubs, closures and proxies on synthetic objects.
+
+#![cfg(target_arch = "x86_64")]
+
+use std::ffi::c_void;
+use std::sync::{Arc, Mutex};
+
+use vmt_hook::builder::{SyntheticObject, VTableBuilder};
+use vmt_hook::proxy::ObjectProxy;
+use vmt_hook::thunk::{BypassThunk, CallCounter, CallTimer, HookBypass, Middleware};
+use vmt_hook::VTableHook;
+
+type Add = extern "C" fn(*mut c_void, usize, usize) -> usize;
+type Scale = extern "C" fn(*mut c_void, f64) -> f64;
+type Weigh = extern "C" fn(*mut c_void, usize, usize, usize, usize, usize, usize) -> usize;
+type Touch = extern "C" fn(*mut c_void);
+type This = extern "C" fn(*mut c_void) -> usize;
+
+const ADD: usize = 0;
+const SCALE: usize = 1;
+const WEIGH: usize = 2;
+const TOUCH: usize = 3;
+const THIS: usize = 4;
+
+/// Vector register of the float argument of `scale`, which follows `this`.
+const FLOAT: usize = if cfg!(windows) { 1 } else { 0 };
+
+extern "C" fn add(_this: *mut c_void, a: usize, b: usize) -> usize {
+    a + b
+}
+
+extern "C" fn scale(_this: *mut c_void, x: f64) -> f64 {
+    x * 2.0
+}
+
+/// Weighs every argument by its position, so that the seventh one is passed on the stack.
+extern "C" fn weigh(_this: *mut c_void, a: usize, b: usize, c: usize, d: usize, e: usize, f: usize) -> usize {
+    a + 2 * b + 3 * c + 4 * d + 5 * e + 6 * f
+}
+
+extern "C" fn touch(this: *mut c_void) {
+    unsafe { *(this as *mut usize).add(1) += 1 }
+}
+
+extern "C" fn this_of(this: *mut c_void) -> usize {
+    this as usize
+}
+
+extern "C" fn zero(_this: *mut c_void, _a: usize, _b: usize) -> usize {
+    0
+}
+
+fn fixture() -> SyntheticObject {
+    VTableBuilder::new()
+        .function(add as Add)
+        .function(scale as Scale)
+        .function(weigh as Weigh)
+        .function(touch as Touch)
+        .function(this_of as This)
+        .fields(1)
+        .build()
+}
+
+unsafe fn call_add(object: &SyntheticObject, a: usize, b: usize) -> usize {
+    object.get::<Add>(ADD)(object.as_ptr(), a, b)
+}
+
+unsafe fn call_scale(object: &SyntheticObject, x: f64) -> f64 {
+    object.get::<Scale>(SCALE)(object.as_ptr(), x)
+}
+
+unsafe fn call_weigh(object: &SyntheticObject) -> usize {
+    object.get::<Weigh>(WEIGH)(object.as_ptr(), 1, 2, 3, 4, 5, 6)
+}
+
+#[test]
+fn middleware_sees_arguments_and_results() {
+    let object = fixture();
+    let seen = Arc::new(Mutex::new(Vec::new()));
+    let floats = Arc::new(Mutex::new(Vec::new()));
+    unsafe {
+        let hook = VTableHook::with_count(object.as_ptr(), 5);
+        let (before, after) = (seen.clone(), seen.clone());
+        let _add = Middleware::install(
+            &hook,
+            ADD,
+            move |args| before.lock().unwrap().extend([args.slot(), args.this(), args.arg(1), args.arg(2)]),
+            move |result| after.lock().unwrap().extend([result.slot(), result.this(), result.value()]),
+        )
+        .unwrap();
+        let (before, after) = (floats.clone(), floats.clone());
+        let _scale = Middleware::install(
+            &hook,
+            SCALE,
+            move |args| before.lock().unwrap().push(args.float(FLOAT)),
+            move |result| after.lock().unwrap().push(result.float()),
+        )
+        .unwrap();
+        let stack = seen.clone();
+        let _weigh = Middleware::before(&hook, WEIGH, move |args| stack.lock().unwrap().push(args.arg(6))).unwrap();
+
+        assert_eq!(call_add(&object, 2, 3), 5);
+        assert_eq!(call_scale(&object, 1.5), 3.0);
+        assert_eq!(call_weigh(&object), 91);
+        drop(hook);
+    }
+    let this = object.as_ptr() as usize;
+    assert_eq!(*seen.lock().unwrap(), [ADD, this, 2, 3, ADD, this, 5, 6]);
+    assert_eq!(*floats.lock().unwrap(), [1.5, 3.0]);
+}
+
+#[test]
+fn middleware_transforms_arguments_and_results() {
+    let object = fixture();
+    unsafe {
+        let hook = VTableHook::with_count(object.as_ptr(), 5);
+        let _add = Middleware::transform_args(&hook, ADD, |args| args.set_arg(1, 10)).unwrap();
+        let _scale = Middleware::transform_args(&hook, SCALE, |args| args.set_float(FLOAT, 4.0)).unwrap();
+        let _weigh = Middleware::transform_args(&hook, WEIGH, |args| args.set_arg(6, 100)).unwrap();
+        assert_eq!(call_add(&object, 2, 3), 13);
+        assert_eq!(call_scale(&object, 1.5), 8.0);
+        assert_eq!(call_weigh(&object), 655);
+        hook.restore_all_methods();
+
+        let _add = Middleware::transform_return(&hook, ADD, |result| result.set_value(result.value() * 10)).unwrap();
+        let _scale = Middleware::transform_return(&hook, SCALE, |result| result.set_float(-result.float())).unwrap();
+        let _weigh = Middleware::transform_return(&hook, WEIGH, |result| result.set_value(result.value() + 1)).unwrap();
+        assert_eq!(call_add(&object, 2, 3), 50);
+        assert_eq!(call_scale(&object, 1.5), -3.0);
+        assert_eq!(call_weigh(&object), 92);
+        drop(hook);
+    }
+}
+
+#[test]
+fn nested_thunks_run_outermost_first() {
+    let object = fixture();
+    let order = Arc::new(Mutex::new(Vec::new()));
+    unsafe {
+        let hook = VTableHook::with_count(object.as_ptr(), 5);
+        let (before, after) = (order.clone(), order.clone());
+        let _inner = Middleware::install(
+            &hook,
+            ADD,
+            move |args| before.lock().unwrap().push(("inner", args.arg(1))),
+            move |result| after.lock().unwrap().push(("inner", result.value())),
+        )
+        .unwrap();
+        let _transform = Middleware::transform_args(&hook, ADD, |args| args.set_arg(1, args.arg(1) + 1)).unwrap();
+        let (before, after) = (order.clone(), order.clone());
+        let _outer = Middleware::install(
+            &hook,
+            ADD,
+            move |args| before.lock().unwrap().push(("outer", args.arg(1))),
+            move |result| after.lock().unwrap().push(("outer", result.value())),
+        )
+        .unwrap();
+
+        let counter = CallCounter::new(&hook);
+        counter.instrument(&hook, WEIGH).unwrap();
+        let timer = CallTimer::new(&hook);
+        timer.instrument(&hook, WEIGH).unwrap();
+        counter.instrument(&hook, WEIGH).unwrap();
+
+        assert_eq!(call_add(&object, 2, 3), 6);
+        assert_eq!(call_weigh(&object), 91);
+        assert_eq!(call_weigh(&object), 91);
+        assert_eq!(counter.count(WEIGH), 4);
+        assert_eq!(timer.timing(WEIGH).calls, 2);
+        drop(hook);
+    }
+    assert_eq!(*order.lock().unwrap(), [("outer", 2), ("inner", 3), ("inner", 6), ("outer", 6)]);
+}
+
+#[test]
+fn bypass_thunk_reaches_original_while_bypassed() {
+    let object = fixture();
+    unsafe {
+        let hook = VTableHook::with_count(object.as_ptr(), 5);
+        let bypass = BypassThunk::install(&hook, ADD, zero as Add as usize).unwrap();
+        assert_eq!(bypass.original(), add as Add as usize);
+        assert_eq!(call_add(&object, 2, 3), 0);
+        {
+            let _outer = HookBypass::for_current_thread();
+            let _inner = HookBypass::for_current_thread();
+            assert_eq!(call_add(&object, 2, 3), 5);
+        }
+        assert!(!HookBypass::is_active());
+        assert_eq!(call_add(&object, 2, 3), 0);
+        drop(hook);
+    }
+}
+
+#[test]
+fn stubs_return_constants() {
+    let object = fixture();
+    unsafe {
+        let hook = VTableHook::with_count(object.as_ptr(), 5);
+        hook.stub_method(ADD, 42).unwrap();
+        hook.nop_method(TOUCH).unwrap();
+        assert_eq!(call_add(&object, 2, 3), 42);
+        object.get::<Touch>(TOUCH)(object.as_ptr());
+        assert_eq!(*object.fields(), 0);
+
+        hook.restore_method(TOUCH);
+        object.get::<Touch>(TOUCH)(object.as_ptr());
+        assert_eq!(*object.fields(), 1);
+        drop(hook);
+    }
+}
+
+#[test]
+fn proxy_forwards_to_hooked_target() {
+    let object = fixture();
+    unsafe {
+        let proxy = ObjectProxy::new(object.as_ptr(), 5).unwrap();
+        let method = |id| *(*(proxy.as_ptr() as *const *const usize)).add(id);
+        assert_eq!(std::mem::transmute::<usize, This>(method(THIS))(proxy.as_ptr()), object.as_ptr() as usize);
+        assert_eq!(std::mem::transmute::<usize, Weigh>(method(WEIGH))(proxy.as_ptr(), 1, 2, 3, 4, 5, 6), 91);
+
+        let hook = VTableHook::with_count(object.as_ptr(), 5);
+        hook.replace_method(ADD, zero as Add as usize);
+        assert_eq!(std::mem::transmute::<usize, Add>(method(ADD))(proxy.as_ptr(), 2, 3), 0);
+        proxy.replace_method(ADD, add as Add as usize);
+        assert_eq!(std::mem::transmute::<usize, Add>(method(ADD))(proxy.as_ptr(), 2, 3), 5);
+        drop(hook);
+    }
+}
+
+#[cfg(feature = "closures")]
+mod closures {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use vmt_hook::closure::{ClosureHook, Fallback};
+    use vmt_hook::slot::Slot;
+
+    use super::*;
+
+    #[test]
+    fn closure_captures_state_and_original() {
+        let object = fixture();
+        let calls = Arc::new(AtomicUsize::new(0));
+        unsafe {
+            let hook = VTableHook::with_count(object.as_ptr(), 5);
+            let slot = Slot::<Add>::new(ADD);
+            let original = hook.get_original(slot);
+            let counted = calls.clone();
+            let _closure = ClosureHook::install(
+                &hook,
+                slot,
+                Box::new(move |this, a, b| {
+                    counted.fetch_add(1, Ordering::Relaxed);
+                    original(this, a, b) * 3
+                }),
+            )
+            .unwrap();
+            assert_eq!(call_add(&object, 2, 3), 15);
+            assert_eq!(call_add(&object, 1, 1), 6);
+            drop(hook);
+        }
+        assert_eq!(calls.load(Ordering::Relaxed), 2);
+    }
+
+    #[test]
+    fn guarded_closure_falls_back_after_panic() {
+        let object = fixture();
+        unsafe {
+            let hook = VTableHook::with_count(object.as_ptr(), 5);
+            let slot = Slot::<Add>::new(ADD);
+            let panicking = || Box::new(|_, _, _| panic!("hook"));
+            let _closure = ClosureHook::install_guarded(&hook, slot, panicking(), Fallback::Return(7)).unwrap();
+            assert_eq!(call_add(&object, 2, 3), 7);
+            hook.restore_method(ADD);
+
+            let _closure = ClosureHook::install_guarded(&hook, slot, panicking(), Fallback::CallOriginal).unwrap();
+            assert_eq!(call_add(&object, 2, 3), 5);
+            drop(hook);
+        }
+    }
+}
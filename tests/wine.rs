@@ -0,0 +1,38 @@
+//! Module lookups and region checks with the Wine workarounds forced on, which must agree with what Windows
+//! itself reports.
+
+#![cfg(windows)]
+
+use vmt_hook::detect::{detect_existing_hook, probe_code_count, set_wine_mode, WineMode};
+use vmt_hook::VTableHook;
+
+extern "C" fn first() {}
+extern "C" fn second() {}
+
+/// A table in read-only data: two methods, then a pointer into data like the RTTI pointer of the next MSVC table.
+#[repr(C)]
+struct Table {
+    methods: [extern "C" fn(); 2],
+    next: &'static u8,
+}
+
+static DATA: u8 = 0;
+static TABLE: Table = Table { methods: [first, second], next: &DATA };
+
+#[test]
+fn forced_wine_mode_matches_windows() {
+    let vtable = (&TABLE as *const Table).cast::<usize>();
+    for mode in [WineMode::Disable, WineMode::Force] {
+        set_wine_mode(mode);
+        assert_eq!(unsafe { probe_code_count(vtable) }, 2, "{mode:?}");
+
+        let mut object = vtable;
+        let object = &mut object as *mut *const usize;
+        assert_eq!(unsafe { detect_existing_hook(object) }, None, "{mode:?}");
+        let hook = unsafe { VTableHook::with_count(object, 2) };
+        let found = unsafe { detect_existing_hook(object) };
+        assert_eq!(found.map(|found| found.module), Some(None), "{mode:?}");
+        drop(hook);
+    }
+    set_wine_mode(WineMode::Detect);
+}